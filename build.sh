@@ -237,6 +237,7 @@ function build {
             libtalpid_openvpn_plugin.so
             mullvad-setup
             mullvad-exclude
+            mullvad-netns
         )
     elif [[ ("$(uname -s)" == "MINGW"*) ]]; then
         BINARIES=(
@@ -322,6 +323,10 @@ if [[ "$(uname -s)" == "Darwin" || "$(uname -s)" == "Linux" ]]; then
         cargo run --bin mullvad "${CARGO_ARGS[@]}" -- shell-completions "$sh" \
             "dist-assets/shell-completions/"
     done
+
+    log_info "Generating man page..."
+    mkdir -p "dist-assets/man"
+    cargo run --bin mullvad "${CARGO_ARGS[@]}" -- man-page "dist-assets/man/"
 fi
 
 log_info "Updating relays.json..."
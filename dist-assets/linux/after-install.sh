@@ -2,6 +2,7 @@
 set -eu
 
 chmod u+s "/usr/bin/mullvad-exclude"
+chmod u+s "/usr/bin/mullvad-netns"
 
 systemctl enable "/usr/lib/systemd/system/mullvad-daemon.service"
 systemctl start mullvad-daemon.service
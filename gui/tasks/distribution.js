@@ -189,12 +189,14 @@ const config = {
       distAssets(path.join(getLinuxTargetSubdir(), 'mullvad')) + '=/usr/bin/',
       distAssets(path.join(getLinuxTargetSubdir(), 'mullvad-daemon')) + '=/usr/bin/',
       distAssets(path.join(getLinuxTargetSubdir(), 'mullvad-exclude')) + '=/usr/bin/',
+      distAssets(path.join(getLinuxTargetSubdir(), 'mullvad-netns')) + '=/usr/bin/',
       distAssets('linux/problem-report-link') + '=/usr/bin/mullvad-problem-report',
       distAssets('shell-completions/mullvad.bash') +
         '=/usr/share/bash-completion/completions/mullvad',
       distAssets('shell-completions/_mullvad') + '=/usr/local/share/zsh/site-functions/_mullvad',
       distAssets('shell-completions/mullvad.fish') +
         '=/usr/share/fish/vendor_completions.d/mullvad.fish',
+      distAssets('man/mullvad.1') + '=/usr/share/man/man1/mullvad.1',
     ],
     afterInstall: distAssets('linux/after-install.sh'),
     afterRemove: distAssets('linux/after-remove.sh'),
@@ -214,12 +216,14 @@ const config = {
       distAssets(path.join(getLinuxTargetSubdir(), 'mullvad')) + '=/usr/bin/',
       distAssets(path.join(getLinuxTargetSubdir(), 'mullvad-daemon')) + '=/usr/bin/',
       distAssets(path.join(getLinuxTargetSubdir(), 'mullvad-exclude')) + '=/usr/bin/',
+      distAssets(path.join(getLinuxTargetSubdir(), 'mullvad-netns')) + '=/usr/bin/',
       distAssets('linux/problem-report-link') + '=/usr/bin/mullvad-problem-report',
       distAssets('shell-completions/mullvad.bash') +
         '=/usr/share/bash-completion/completions/mullvad',
       distAssets('shell-completions/_mullvad') + '=/usr/share/zsh/site-functions/_mullvad',
       distAssets('shell-completions/mullvad.fish') +
         '=/usr/share/fish/vendor_completions.d/mullvad.fish',
+      distAssets('man/mullvad.1') + '=/usr/share/man/man1/mullvad.1',
     ],
     afterInstall: distAssets('linux/after-install.sh'),
     afterRemove: distAssets('linux/after-remove.sh'),
@@ -0,0 +1,690 @@
+use crate::script::{Script, ScriptedResponse};
+use chrono::{DateTime, Duration, Utc};
+use hyper::{
+    header::{self, HeaderValue},
+    http::request::Parts,
+    Body, Method, Request, Response, StatusCode,
+};
+use rand::Rng;
+use serde::Deserialize;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    convert::Infallible,
+    hash::{Hash, Hasher},
+    net::SocketAddr,
+    sync::Mutex,
+};
+
+/// Error codes returned by the API. These match the ones in `mullvad_api`.
+const INVALID_ACCOUNT: &str = "INVALID_ACCOUNT";
+const INVALID_ACCESS_TOKEN: &str = "INVALID_ACCESS_TOKEN";
+const DEVICE_NOT_FOUND: &str = "DEVICE_NOT_FOUND";
+const MAX_DEVICES_REACHED: &str = "MAX_DEVICES_REACHED";
+const PUBKEY_IN_USE: &str = "PUBKEY_IN_USE";
+const INVALID_VOUCHER: &str = "INVALID_VOUCHER";
+const VOUCHER_USED: &str = "VOUCHER_USED";
+
+const MAX_DEVICES: usize = 5;
+
+/// Relay list served when no other list is provided.
+const DEFAULT_RELAY_LIST: &str = include_str!("relays.json");
+
+/// In-memory implementation of the API endpoints used by the app.
+pub struct MockApi {
+    listen_addr: SocketAddr,
+    accept_any_account: bool,
+    relay_list: String,
+    relay_list_etag: String,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    accounts: HashMap<String, Account>,
+    /// Maps access tokens to account numbers.
+    access_tokens: HashMap<String, String>,
+    vouchers: HashMap<String, u64>,
+    used_vouchers: HashSet<String>,
+    responses: Vec<ScriptedResponse>,
+    device_counter: u32,
+}
+
+struct Account {
+    expiry: DateTime<Utc>,
+    devices: Vec<Device>,
+}
+
+struct Device {
+    id: String,
+    name: String,
+    pubkey: String,
+    created: DateTime<Utc>,
+    address_index: u32,
+}
+
+impl Device {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+            "pubkey": self.pubkey,
+            "ipv4_address": format!(
+                "10.{}.{}.{}/32",
+                64 + (self.address_index >> 16) % 64,
+                (self.address_index >> 8) & 0xff,
+                self.address_index & 0xff,
+            ),
+            "ipv6_address": format!(
+                "fc00:bbbb:bbbb:bb01::{:x}/128",
+                self.address_index
+            ),
+            "ports": [],
+            "hijack_dns": false,
+            "created": self.created,
+        })
+    }
+}
+
+/// An error response, sent as `{"code": ...}`.
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &'static str) -> Self {
+        Self { status, code }
+    }
+
+    fn bad_request() -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "INVALID_REQUEST")
+    }
+}
+
+type ApiResult = Result<Response<Body>, ApiError>;
+
+impl MockApi {
+    pub fn new(
+        listen_addr: SocketAddr,
+        script: Script,
+        relay_list: Option<String>,
+        accept_any_account: bool,
+    ) -> Self {
+        let relay_list = relay_list.unwrap_or_else(|| DEFAULT_RELAY_LIST.to_owned());
+        let relay_list_etag = {
+            let mut hasher = DefaultHasher::new();
+            relay_list.hash(&mut hasher);
+            format!("\"{:x}\"", hasher.finish())
+        };
+
+        let state = State {
+            accounts: script
+                .accounts
+                .into_iter()
+                .map(|account| {
+                    (
+                        account.number,
+                        Account {
+                            expiry: account.expiry,
+                            devices: vec![],
+                        },
+                    )
+                })
+                .collect(),
+            vouchers: script
+                .vouchers
+                .into_iter()
+                .map(|voucher| (voucher.code, voucher.seconds))
+                .collect(),
+            responses: script.responses,
+            ..Default::default()
+        };
+
+        Self {
+            listen_addr,
+            accept_any_account,
+            relay_list,
+            relay_list_etag,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Handles a single request. Scripted responses take precedence over the default behavior.
+    pub async fn handle(&self, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let (parts, body) = request.into_parts();
+        log::debug!("{} {}", parts.method, parts.uri.path());
+
+        if let Some(scripted) = self.take_scripted_response(&parts.method, parts.uri.path()) {
+            if let Some(delay) = scripted.delay() {
+                tokio::time::sleep(delay).await;
+            }
+            let status =
+                StatusCode::from_u16(scripted.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            return Ok(match scripted.body() {
+                Some(body) => json_response(status, &body),
+                None => empty_response(status),
+            });
+        }
+
+        let body = match hyper::body::to_bytes(body).await {
+            Ok(body) => body,
+            Err(error) => {
+                log::error!("Failed to read request body: {}", error);
+                return Ok(empty_response(StatusCode::BAD_REQUEST));
+            }
+        };
+
+        let response = self.route(&parts, &body).unwrap_or_else(|error| {
+            log::debug!("Responding with error {}: {}", error.status, error.code);
+            json_response(error.status, &serde_json::json!({ "code": error.code }))
+        });
+        Ok(response)
+    }
+
+    fn take_scripted_response(&self, method: &Method, path: &str) -> Option<ScriptedResponse> {
+        let mut state = self.state.lock().unwrap();
+        let index = state
+            .responses
+            .iter()
+            .position(|response| response.matches(method, path))?;
+        match state.responses[index].count {
+            Some(count) if count <= 1 => Some(state.responses.remove(index)),
+            Some(count) => {
+                state.responses[index].count = Some(count - 1);
+                Some(state.responses[index].clone())
+            }
+            None => Some(state.responses[index].clone()),
+        }
+    }
+
+    fn route(&self, parts: &Parts, body: &[u8]) -> ApiResult {
+        let path = parts.uri.path().trim_matches('/');
+        let segments: Vec<&str> = path.split('/').collect();
+
+        match (&parts.method, segments.as_slice()) {
+            (&Method::POST, ["auth", "v1", "token"]) => self.create_access_token(body),
+
+            (&Method::POST, ["accounts", "v1", "accounts"]) => self.create_account(),
+            (&Method::GET, ["accounts", "v1", "accounts", "me"]) => {
+                self.get_account(&self.authenticate(parts)?)
+            }
+            (&Method::POST, ["accounts", "v1", "devices"]) => {
+                self.create_device(&self.authenticate(parts)?, body)
+            }
+            (&Method::GET, ["accounts", "v1", "devices"]) => {
+                self.list_devices(&self.authenticate(parts)?)
+            }
+            (&Method::GET, ["accounts", "v1", "devices", id]) => {
+                self.get_device(&self.authenticate(parts)?, id)
+            }
+            (&Method::DELETE, ["accounts", "v1", "devices", id]) => {
+                self.remove_device(&self.authenticate(parts)?, id)
+            }
+            (&Method::PUT, ["accounts", "v1", "devices", id, "pubkey"]) => {
+                self.replace_pubkey(&self.authenticate(parts)?, id, body)
+            }
+
+            (&Method::POST, ["app", "v1", "submit-voucher"]) => {
+                self.submit_voucher(&self.authenticate(parts)?, body)
+            }
+            (&Method::POST, ["app", "v1", "www-auth-token"]) => {
+                self.authenticate(parts)?;
+                Ok(json_response(
+                    StatusCode::OK,
+                    &serde_json::json!({ "auth_token": random_hex(16) }),
+                ))
+            }
+            (&Method::POST, ["app", "v1", "problem-report"]) => {
+                log::info!("Received problem report ({} bytes)", body.len());
+                Ok(empty_response(StatusCode::NO_CONTENT))
+            }
+            (&Method::GET, ["app", "v1", "releases", _platform, version]) => Ok(json_response(
+                StatusCode::OK,
+                &serde_json::json!({
+                    "supported": true,
+                    "latest": version,
+                    "latest_stable": version,
+                    "latest_beta": version,
+                }),
+            )),
+            (&Method::GET, ["app", "v1", "api-addrs"]) => Ok(json_response(
+                StatusCode::OK,
+                &serde_json::json!([self.listen_addr]),
+            )),
+            (&Method::GET, ["app", "v1", "relays"]) => Ok(self.get_relay_list(parts)),
+
+            (&Method::POST, ["mock", "v1", "responses"]) => {
+                let response: ScriptedResponse = parse_body(body)?;
+                self.state.lock().unwrap().responses.push(response);
+                Ok(empty_response(StatusCode::NO_CONTENT))
+            }
+            (&Method::DELETE, ["mock", "v1", "responses"]) => {
+                self.state.lock().unwrap().responses.clear();
+                Ok(empty_response(StatusCode::NO_CONTENT))
+            }
+
+            _ => Err(ApiError::new(StatusCode::NOT_FOUND, "NOT_FOUND")),
+        }
+    }
+
+    /// Returns the account number that the access token in the request belongs to.
+    fn authenticate(&self, parts: &Parts) -> Result<String, ApiError> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                INVALID_ACCESS_TOKEN,
+            ))?;
+        let state = self.state.lock().unwrap();
+        let account = state.access_tokens.get(token).ok_or(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            INVALID_ACCESS_TOKEN,
+        ))?;
+        if !state.accounts.contains_key(account) {
+            return Err(ApiError::new(StatusCode::UNAUTHORIZED, INVALID_ACCOUNT));
+        }
+        Ok(account.clone())
+    }
+
+    fn create_access_token(&self, body: &[u8]) -> ApiResult {
+        #[derive(Deserialize)]
+        struct AccessTokenRequest {
+            account_number: String,
+        }
+        let request: AccessTokenRequest = parse_body(body)?;
+
+        let mut state = self.state.lock().unwrap();
+        if !state.accounts.contains_key(&request.account_number) {
+            if !self.accept_any_account {
+                return Err(ApiError::new(StatusCode::BAD_REQUEST, INVALID_ACCOUNT));
+            }
+            state
+                .accounts
+                .insert(request.account_number.clone(), Account::new());
+        }
+
+        let access_token = format!("mock-{}", random_hex(16));
+        state
+            .access_tokens
+            .insert(access_token.clone(), request.account_number);
+        Ok(json_response(
+            StatusCode::OK,
+            &serde_json::json!({
+                "access_token": access_token,
+                "expiry": Utc::now() + Duration::hours(1),
+            }),
+        ))
+    }
+
+    fn create_account(&self) -> ApiResult {
+        let mut rng = rand::thread_rng();
+        let number: String = (0..16)
+            .map(|_| char::from(b'0' + rng.gen_range(0..10)))
+            .collect();
+        let account = Account::new();
+        let response = serde_json::json!({
+            "number": number,
+            "expiry": account.expiry,
+        });
+        self.state.lock().unwrap().accounts.insert(number, account);
+        Ok(json_response(StatusCode::CREATED, &response))
+    }
+
+    fn get_account(&self, account: &str) -> ApiResult {
+        let state = self.state.lock().unwrap();
+        let account = state.account(account)?;
+        Ok(json_response(
+            StatusCode::OK,
+            &serde_json::json!({ "expiry": account.expiry }),
+        ))
+    }
+
+    fn create_device(&self, account: &str, body: &[u8]) -> ApiResult {
+        #[derive(Deserialize)]
+        struct DeviceSubmission {
+            pubkey: String,
+        }
+        let submission: DeviceSubmission = parse_body(body)?;
+
+        let mut state = self.state.lock().unwrap();
+        if state.pubkey_in_use(&submission.pubkey) {
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, PUBKEY_IN_USE));
+        }
+        state.device_counter += 1;
+        let counter = state.device_counter;
+
+        let account = state.account_mut(account)?;
+        if account.devices.len() >= MAX_DEVICES {
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, MAX_DEVICES_REACHED));
+        }
+        let device = Device {
+            id: random_hex(16),
+            name: format!("mock device {}", counter),
+            pubkey: submission.pubkey,
+            created: Utc::now(),
+            address_index: counter + 1,
+        };
+        let response = device.to_json();
+        account.devices.push(device);
+        Ok(json_response(StatusCode::CREATED, &response))
+    }
+
+    fn list_devices(&self, account: &str) -> ApiResult {
+        let state = self.state.lock().unwrap();
+        let devices: Vec<_> = state
+            .account(account)?
+            .devices
+            .iter()
+            .map(Device::to_json)
+            .collect();
+        Ok(json_response(StatusCode::OK, &serde_json::json!(devices)))
+    }
+
+    fn get_device(&self, account: &str, id: &str) -> ApiResult {
+        let state = self.state.lock().unwrap();
+        let device = state.account(account)?.device(id)?;
+        Ok(json_response(StatusCode::OK, &device.to_json()))
+    }
+
+    fn remove_device(&self, account: &str, id: &str) -> ApiResult {
+        let mut state = self.state.lock().unwrap();
+        let account = state.account_mut(account)?;
+        let index = account
+            .devices
+            .iter()
+            .position(|device| device.id == id)
+            .ok_or(ApiError::new(StatusCode::NOT_FOUND, DEVICE_NOT_FOUND))?;
+        account.devices.remove(index);
+        Ok(empty_response(StatusCode::NO_CONTENT))
+    }
+
+    fn replace_pubkey(&self, account: &str, id: &str, body: &[u8]) -> ApiResult {
+        #[derive(Deserialize)]
+        struct RotateDevicePubkey {
+            pubkey: String,
+        }
+        let request: RotateDevicePubkey = parse_body(body)?;
+
+        let mut state = self.state.lock().unwrap();
+        if state.pubkey_in_use(&request.pubkey) {
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, PUBKEY_IN_USE));
+        }
+        let device = state.account_mut(account)?.device_mut(id)?;
+        device.pubkey = request.pubkey;
+        Ok(json_response(StatusCode::OK, &device.to_json()))
+    }
+
+    fn submit_voucher(&self, account: &str, body: &[u8]) -> ApiResult {
+        #[derive(Deserialize)]
+        struct VoucherSubmission {
+            voucher_code: String,
+        }
+        let submission: VoucherSubmission = parse_body(body)?;
+
+        let mut state = self.state.lock().unwrap();
+        if state.used_vouchers.contains(&submission.voucher_code) {
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, VOUCHER_USED));
+        }
+        let seconds = *state
+            .vouchers
+            .get(&submission.voucher_code)
+            .ok_or(ApiError::new(StatusCode::BAD_REQUEST, INVALID_VOUCHER))?;
+        state.used_vouchers.insert(submission.voucher_code);
+
+        let account = state.account_mut(account)?;
+        account.expiry = std::cmp::max(account.expiry, Utc::now())
+            + Duration::seconds(i64::try_from(seconds).unwrap_or(i64::MAX));
+        Ok(json_response(
+            StatusCode::OK,
+            &serde_json::json!({
+                "time_added": seconds,
+                "new_expiry": account.expiry,
+            }),
+        ))
+    }
+
+    fn get_relay_list(&self, parts: &Parts) -> Response<Body> {
+        let etag = HeaderValue::from_str(&self.relay_list_etag).expect("invalid etag");
+        if parts.headers.get(header::IF_NONE_MATCH) == Some(&etag) {
+            let mut response = empty_response(StatusCode::NOT_MODIFIED);
+            response.headers_mut().insert(header::ETAG, etag);
+            return response;
+        }
+        let mut response = Response::new(Body::from(self.relay_list.clone()));
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        headers.insert(header::ETAG, etag);
+        response
+    }
+}
+
+impl State {
+    fn account(&self, account: &str) -> Result<&Account, ApiError> {
+        self.accounts
+            .get(account)
+            .ok_or(ApiError::new(StatusCode::UNAUTHORIZED, INVALID_ACCOUNT))
+    }
+
+    fn account_mut(&mut self, account: &str) -> Result<&mut Account, ApiError> {
+        self.accounts
+            .get_mut(account)
+            .ok_or(ApiError::new(StatusCode::UNAUTHORIZED, INVALID_ACCOUNT))
+    }
+
+    fn pubkey_in_use(&self, pubkey: &str) -> bool {
+        self.accounts
+            .values()
+            .flat_map(|account| account.devices.iter())
+            .any(|device| device.pubkey == pubkey)
+    }
+}
+
+impl Account {
+    /// Returns a new account that expires in 30 days.
+    fn new() -> Self {
+        Self {
+            expiry: Utc::now() + Duration::days(30),
+            devices: vec![],
+        }
+    }
+
+    fn device(&self, id: &str) -> Result<&Device, ApiError> {
+        self.devices
+            .iter()
+            .find(|device| device.id == id)
+            .ok_or(ApiError::new(StatusCode::NOT_FOUND, DEVICE_NOT_FOUND))
+    }
+
+    fn device_mut(&mut self, id: &str) -> Result<&mut Device, ApiError> {
+        self.devices
+            .iter_mut()
+            .find(|device| device.id == id)
+            .ok_or(ApiError::new(StatusCode::NOT_FOUND, DEVICE_NOT_FOUND))
+    }
+}
+
+fn parse_body<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(body).map_err(|error| {
+        log::debug!("Failed to parse request body: {}", error);
+        ApiError::bad_request()
+    })
+}
+
+fn json_response(status: StatusCode, body: &serde_json::Value) -> Response<Body> {
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..bytes)
+        .map(|_| format!("{:02x}", rng.gen::<u8>()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ACCOUNT: &str = "1234123412341234";
+
+    fn mock_api() -> MockApi {
+        let script: Script = serde_json::from_value(serde_json::json!({
+            "accounts": [{ "number": ACCOUNT, "expiry": "2030-01-01T00:00:00Z" }],
+        }))
+        .unwrap();
+        MockApi::new("127.0.0.1:8080".parse().unwrap(), script, None, false)
+    }
+
+    fn request(
+        api: &MockApi,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut builder = Request::builder().method(method).uri(path);
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = builder.body(Body::from(body.to_string())).unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+        runtime.block_on(async {
+            let response = api.handle(request).await.unwrap();
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body = if body.is_empty() {
+                serde_json::Value::Null
+            } else {
+                serde_json::from_slice(&body).unwrap()
+            };
+            (status, body)
+        })
+    }
+
+    fn login(api: &MockApi) -> String {
+        let (status, body) = request(
+            api,
+            Method::POST,
+            "/auth/v1/token",
+            None,
+            serde_json::json!({ "account_number": ACCOUNT }),
+        );
+        assert_eq!(status, StatusCode::OK);
+        body["access_token"].as_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_invalid_account() {
+        let api = mock_api();
+        let (status, body) = request(
+            &api,
+            Method::POST,
+            "/auth/v1/token",
+            None,
+            serde_json::json!({ "account_number": "0000000000000000" }),
+        );
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], INVALID_ACCOUNT);
+    }
+
+    #[test]
+    fn test_device_flow() {
+        let api = mock_api();
+        let token = login(&api);
+
+        let (status, device) = request(
+            &api,
+            Method::POST,
+            "/accounts/v1/devices",
+            Some(&token),
+            serde_json::json!({ "pubkey": "key1", "hijack_dns": false }),
+        );
+        assert_eq!(status, StatusCode::CREATED);
+        let id = device["id"].as_str().unwrap();
+
+        let (status, devices) = request(
+            &api,
+            Method::GET,
+            "/accounts/v1/devices",
+            Some(&token),
+            serde_json::Value::Null,
+        );
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(devices.as_array().unwrap().len(), 1);
+
+        let (status, _) = request(
+            &api,
+            Method::DELETE,
+            &format!("/accounts/v1/devices/{}", id),
+            Some(&token),
+            serde_json::Value::Null,
+        );
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, body) = request(
+            &api,
+            Method::GET,
+            &format!("/accounts/v1/devices/{}", id),
+            Some(&token),
+            serde_json::Value::Null,
+        );
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], DEVICE_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_scripted_response() {
+        let api = mock_api();
+        let (status, _) = request(
+            &api,
+            Method::POST,
+            "/mock/v1/responses",
+            None,
+            serde_json::json!({
+                "method": "GET",
+                "path": "/app/v1/api-addrs",
+                "status": 503,
+                "count": 1,
+            }),
+        );
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, _) = request(
+            &api,
+            Method::GET,
+            "/app/v1/api-addrs",
+            None,
+            serde_json::Value::Null,
+        );
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        let (status, addrs) = request(
+            &api,
+            Method::GET,
+            "/app/v1/api-addrs",
+            None,
+            serde_json::Value::Null,
+        );
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(addrs, serde_json::json!(["127.0.0.1:8080"]));
+    }
+}
@@ -0,0 +1,144 @@
+//! A local mock of the Mullvad REST API, for testing the app without network access.
+//!
+//! Point a daemon built with the `api-override` feature at the mock by setting the following
+//! environment variables:
+//!
+//! ```text
+//! MULLVAD_API_HOST=localhost
+//! MULLVAD_API_ADDR=127.0.0.1:8080
+//! MULLVAD_API_DISABLE_TLS=1
+//! ```
+//!
+//! The initial state of the mock and any canned responses may be given in a script file. See
+//! [`script::Script`] for the format. Canned responses can also be added at runtime by posting
+//! them to `/mock/v1/responses`, and removed by sending `DELETE` to the same path.
+
+use clap::{crate_authors, crate_description, crate_name, crate_version, App, Arg};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Server,
+};
+use std::{convert::Infallible, io, net::SocketAddr, path::Path, process, sync::Arc};
+use talpid_types::ErrorExt;
+
+mod api;
+mod script;
+
+use api::MockApi;
+use script::Script;
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Failed to read script file")]
+    ReadScript(#[error(source)] io::Error),
+
+    #[error(display = "Failed to parse script file")]
+    ParseScript(#[error(source)] serde_json::Error),
+
+    #[error(display = "Failed to read relay list file")]
+    ReadRelayList(#[error(source)] io::Error),
+
+    #[error(display = "Failed to parse relay list file")]
+    ParseRelayList(#[error(source)] serde_json::Error),
+
+    #[error(display = "Failed to run the server")]
+    Serve(#[error(source)] hyper::Error),
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let matches = App::new(crate_name!())
+        .version(crate_version!())
+        .author(crate_authors!())
+        .about(crate_description!())
+        .arg(
+            Arg::new("listen")
+                .long("listen")
+                .help("Address to listen on")
+                .takes_value(true)
+                .default_value("127.0.0.1:8080"),
+        )
+        .arg(
+            Arg::new("script")
+                .long("script")
+                .help("JSON file describing accounts, vouchers, and canned responses")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("relay-list")
+                .long("relay-list")
+                .help("JSON file to serve as the relay list, in the format returned by the API")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("accept-any-account")
+                .long("accept-any-account")
+                .help("Create accounts on demand when logging in to an unknown account"),
+        )
+        .get_matches();
+
+    let listen_addr: SocketAddr = matches.value_of_t_or_exit("listen");
+    let result = run(
+        listen_addr,
+        matches.value_of("script").map(Path::new),
+        matches.value_of("relay-list").map(Path::new),
+        matches.is_present("accept-any-account"),
+    )
+    .await;
+
+    if let Err(error) = result {
+        eprintln!("{}", error.display_chain());
+        process::exit(1);
+    }
+}
+
+async fn run(
+    listen_addr: SocketAddr,
+    script_path: Option<&Path>,
+    relay_list_path: Option<&Path>,
+    accept_any_account: bool,
+) -> Result<(), Error> {
+    let script = match script_path {
+        Some(path) => {
+            let contents = tokio::fs::read(path).await.map_err(Error::ReadScript)?;
+            serde_json::from_slice(&contents).map_err(Error::ParseScript)?
+        }
+        None => Script::default(),
+    };
+    let relay_list = match relay_list_path {
+        Some(path) => {
+            let contents = tokio::fs::read_to_string(path)
+                .await
+                .map_err(Error::ReadRelayList)?;
+            // Only check that the file is valid JSON. It is served as is.
+            serde_json::from_str::<serde_json::Value>(&contents).map_err(Error::ParseRelayList)?;
+            Some(contents)
+        }
+        None => None,
+    };
+
+    let api = Arc::new(MockApi::new(
+        listen_addr,
+        script,
+        relay_list,
+        accept_any_account,
+    ));
+    let make_service = make_service_fn(move |_| {
+        let api = api.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let api = api.clone();
+                async move { api.handle(request).await }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&listen_addr)
+        .map_err(Error::Serve)?
+        .serve(make_service);
+    log::info!("Listening on {}", listen_addr);
+    server.await.map_err(Error::Serve)
+}
@@ -0,0 +1,137 @@
+use chrono::{DateTime, Utc};
+use hyper::Method;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Initial state and canned responses for the mock API, read from a JSON file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    /// Accounts that exist when the server starts.
+    #[serde(default)]
+    pub accounts: Vec<ScriptedAccount>,
+    /// Vouchers that may be submitted to any account.
+    #[serde(default)]
+    pub vouchers: Vec<ScriptedVoucher>,
+    /// Responses that replace the default behavior of the mock.
+    #[serde(default)]
+    pub responses: Vec<ScriptedResponse>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptedAccount {
+    pub number: String,
+    pub expiry: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptedVoucher {
+    pub code: String,
+    /// Time added to the account when the voucher is redeemed.
+    pub seconds: u64,
+}
+
+/// A response that is returned instead of the default one, for all requests that match `method`
+/// and `path`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptedResponse {
+    /// Request method to match. Any method matches if this is not set.
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Request path to match, e.g. `/app/v1/relays`. A trailing `*` matches any suffix.
+    pub path: String,
+    #[serde(default = "default_status")]
+    pub status: u16,
+    /// JSON body of the response.
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+    /// API error code, sent as `{"code": ...}`. Ignored if `body` is set.
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Number of requests to respond to. The response is used indefinitely if this is not set.
+    #[serde(default)]
+    pub count: Option<u32>,
+    /// Time to wait before responding, in milliseconds.
+    #[serde(default)]
+    pub delay_ms: Option<u64>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+impl ScriptedResponse {
+    pub fn matches(&self, method: &Method, path: &str) -> bool {
+        if let Some(expected_method) = &self.method {
+            if !expected_method.eq_ignore_ascii_case(method.as_str()) {
+                return false;
+            }
+        }
+        match self.path.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == self.path,
+        }
+    }
+
+    pub fn delay(&self) -> Option<Duration> {
+        self.delay_ms.map(Duration::from_millis)
+    }
+
+    /// Returns the body to send, if any.
+    pub fn body(&self) -> Option<serde_json::Value> {
+        self.body.clone().or_else(|| {
+            self.error
+                .as_ref()
+                .map(|code| serde_json::json!({ "code": code }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn response(method: Option<&str>, path: &str) -> ScriptedResponse {
+        ScriptedResponse {
+            method: method.map(str::to_owned),
+            path: path.to_owned(),
+            status: default_status(),
+            body: None,
+            error: None,
+            count: None,
+            delay_ms: None,
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let exact = response(Some("get"), "/app/v1/relays");
+        assert!(exact.matches(&Method::GET, "/app/v1/relays"));
+        assert!(!exact.matches(&Method::POST, "/app/v1/relays"));
+        assert!(!exact.matches(&Method::GET, "/app/v1/relays/extra"));
+
+        let prefix = response(None, "/accounts/v1/devices/*");
+        assert!(prefix.matches(&Method::DELETE, "/accounts/v1/devices/1234"));
+        assert!(prefix.matches(&Method::PUT, "/accounts/v1/devices/1234/pubkey"));
+        assert!(!prefix.matches(&Method::GET, "/accounts/v1/devices"));
+    }
+
+    #[test]
+    fn test_parse_script() {
+        let script: Script = serde_json::from_str(
+            r#"{
+                "accounts": [{ "number": "1234123412341234", "expiry": "2030-01-01T00:00:00Z" }],
+                "responses": [{ "path": "/auth/v1/token", "status": 400, "error": "INVALID_ACCOUNT", "count": 1 }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(script.accounts.len(), 1);
+        assert_eq!(
+            script.responses[0].body(),
+            Some(serde_json::json!({ "code": "INVALID_ACCOUNT" }))
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     abortable_stream::{AbortableStream, AbortableStreamHandle},
-    proxy::{ApiConnection, ApiConnectionMode, ProxyConfig},
+    proxy::{socks5, ApiConnection, ApiConnectionMode, ProxyConfig},
     tls_stream::TlsStream,
     AddressCache,
 };
@@ -33,7 +33,7 @@ use std::{
     task::{Context, Poll},
     time::Duration,
 };
-use talpid_types::ErrorExt;
+use talpid_types::{net::openvpn::RemoteProxySettings, ErrorExt};
 
 use tokio::{
     net::{TcpSocket, TcpStream},
@@ -73,8 +73,10 @@ enum HttpsConnectorRequest {
 enum InnerConnectionMode {
     /// Connect directly to the target.
     Direct,
-    /// Connect to the destination via a proxy.
-    Proxied(ParsedShadowsocksConfig),
+    /// Connect to the destination via a Shadowsocks proxy.
+    Shadowsocks(ParsedShadowsocksConfig),
+    /// Connect to the destination via a SOCKS5 proxy.
+    Socks5(RemoteProxySettings),
 }
 
 #[derive(Clone)]
@@ -103,13 +105,16 @@ impl TryFrom<ApiConnectionMode> for InnerConnectionMode {
         Ok(match config {
             ApiConnectionMode::Direct => InnerConnectionMode::Direct,
             ApiConnectionMode::Proxied(ProxyConfig::Shadowsocks(config)) => {
-                InnerConnectionMode::Proxied(ParsedShadowsocksConfig {
+                InnerConnectionMode::Shadowsocks(ParsedShadowsocksConfig {
                     peer: config.peer,
                     password: config.password,
                     cipher: CipherKind::from_str(&config.cipher)
                         .map_err(|_| ProxyConfigError::InvalidCipher(config.cipher))?,
                 })
             }
+            ApiConnectionMode::Proxied(ProxyConfig::Socks5(config)) => {
+                InnerConnectionMode::Socks5(config)
+            }
         })
     }
 }
@@ -319,7 +324,7 @@ impl Service<Uri> for HttpsConnectorWithSni {
                             let tls_stream = TlsStream::connect_https(socket, &hostname).await?;
                             Ok::<_, io::Error>(ApiConnection::new(Box::new(tls_stream)))
                         }
-                        InnerConnectionMode::Proxied(proxy_config) => {
+                        InnerConnectionMode::Shadowsocks(proxy_config) => {
                             let socket = Self::open_socket(
                                 proxy_config.peer,
                                 #[cfg(target_os = "android")]
@@ -340,6 +345,28 @@ impl Service<Uri> for HttpsConnectorWithSni {
                                 ))));
                             }
 
+                            let tls_stream = TlsStream::connect_https(proxy, &hostname).await?;
+                            Ok(ApiConnection::new(Box::new(tls_stream)))
+                        }
+                        InnerConnectionMode::Socks5(proxy_config) => {
+                            let socket = Self::open_socket(
+                                proxy_config.address,
+                                #[cfg(target_os = "android")]
+                                socket_bypass_tx.clone(),
+                            )
+                            .await?;
+                            let proxy = timeout(
+                                CONNECT_TIMEOUT,
+                                socks5::connect(socket, addr, proxy_config.auth.as_ref()),
+                            )
+                            .await
+                            .map_err(|err| io::Error::new(io::ErrorKind::TimedOut, err))??;
+
+                            #[cfg(feature = "api-override")]
+                            if API.disable_tls {
+                                return Ok(ApiConnection::new(Box::new(proxy)));
+                            }
+
                             let tls_stream = TlsStream::connect_https(proxy, &hostname).await?;
                             Ok(ApiConnection::new(Box::new(tls_stream)))
                         }
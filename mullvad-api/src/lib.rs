@@ -4,7 +4,7 @@ use chrono::{offset::Utc, DateTime};
 #[cfg(target_os = "android")]
 use futures::channel::mpsc;
 use futures::Stream;
-use hyper::Method;
+use hyper::{header, Method};
 use mullvad_types::{
     account::{AccountToken, VoucherSubmission},
     version::AppVersion,
@@ -27,6 +27,8 @@ pub mod rest;
 
 mod abortable_stream;
 mod https_client_with_sni;
+mod metrics;
+mod pinning;
 pub mod proxy;
 mod tls_stream;
 #[cfg(target_os = "android")]
@@ -111,6 +113,9 @@ pub struct ApiEndpoint {
     pub disable_tls: bool,
     #[cfg(feature = "api-override")]
     pub force_direct_connection: bool,
+    /// PEM file with root certificates to trust instead of the bundled one.
+    #[cfg(feature = "api-override")]
+    pub ca_bundle: Option<std::path::PathBuf>,
 }
 
 impl ApiEndpoint {
@@ -137,6 +142,7 @@ impl ApiEndpoint {
         let host_var = read_var("MULLVAD_API_HOST");
         let address_var = read_var("MULLVAD_API_ADDR");
         let disable_tls_var = read_var("MULLVAD_API_DISABLE_TLS");
+        let ca_bundle_var = read_var("MULLVAD_API_CA_BUNDLE");
 
         #[cfg_attr(not(feature = "api-override"), allow(unused_mut))]
         let mut api = ApiEndpoint {
@@ -148,6 +154,8 @@ impl ApiEndpoint {
             disable_tls: false,
             #[cfg(feature = "api-override")]
             force_direct_connection: false,
+            #[cfg(feature = "api-override")]
+            ca_bundle: None,
         };
 
         #[cfg(feature = "api-override")]
@@ -158,6 +166,9 @@ impl ApiEndpoint {
                 if disable_tls_var.is_some() {
                     log::warn!("MULLVAD_API_DISABLE_TLS is ignored since MULLVAD_API_HOST and MULLVAD_API_ADDR are not set");
                 }
+                if ca_bundle_var.is_some() {
+                    log::warn!("MULLVAD_API_CA_BUNDLE is ignored since MULLVAD_API_HOST and MULLVAD_API_ADDR are not set");
+                }
                 return api;
             }
 
@@ -183,13 +194,18 @@ impl ApiEndpoint {
                     .next()
                     .expect("API host yielded 0 addresses");
             }
+            api.ca_bundle = ca_bundle_var.map(std::path::PathBuf::from);
             api.disable_address_cache = true;
             api.force_direct_connection = true;
             log::debug!("Overriding API. Using {} at {scheme}{}", api.host, api.addr);
         }
         #[cfg(not(feature = "api-override"))]
-        if host_var.is_some() || address_var.is_some() || disable_tls_var.is_some() {
-            log::warn!("These variables are ignored in production builds: MULLVAD_API_HOST, MULLVAD_API_ADDR, MULLVAD_API_DISABLE_TLS");
+        if host_var.is_some()
+            || address_var.is_some()
+            || disable_tls_var.is_some()
+            || ca_bundle_var.is_some()
+        {
+            log::warn!("These variables are ignored in production builds: MULLVAD_API_HOST, MULLVAD_API_ADDR, MULLVAD_API_DISABLE_TLS, MULLVAD_API_CA_BUNDLE");
         }
         api
     }
@@ -200,6 +216,7 @@ pub struct Runtime {
     handle: tokio::runtime::Handle,
     pub address_cache: AddressCache,
     api_availability: availability::ApiAvailability,
+    metrics: metrics::ApiMetrics,
     #[cfg(target_os = "android")]
     socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
 }
@@ -214,6 +231,10 @@ pub enum Error {
 
     #[error(display = "API availability check failed")]
     ApiCheckError(#[error(source)] availability::Error),
+
+    #[cfg(feature = "api-override")]
+    #[error(display = "Failed to load the API CA bundle")]
+    CaBundleError(#[error(source)] tls_stream::CaBundleError),
 }
 
 /// Closure that receives the next API (real or proxy) endpoint to use for `api.mullvad.net`.
@@ -243,10 +264,14 @@ impl Runtime {
         handle: tokio::runtime::Handle,
         #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
     ) -> Result<Self, Error> {
+        #[cfg(feature = "api-override")]
+        tls_stream::load_ca_bundle()?;
+
         Ok(Runtime {
             handle,
             address_cache: AddressCache::new(None)?,
             api_availability: ApiAvailability::new(availability::State::default()),
+            metrics: metrics::ApiMetrics::default(),
             #[cfg(target_os = "android")]
             socket_bypass_tx,
         })
@@ -269,6 +294,13 @@ impl Runtime {
             );
         }
 
+        #[cfg(feature = "api-override")]
+        tls_stream::load_ca_bundle()?;
+
+        pinning::API_PINS
+            .load_from_cache(cache_dir, write_changes)
+            .await;
+
         let cache_file = cache_dir.join(API_IP_CACHE_FILENAME);
         let write_file = if write_changes {
             Some(cache_file.clone().into_boxed_path())
@@ -295,6 +327,7 @@ impl Runtime {
             handle,
             address_cache,
             api_availability: ApiAvailability::new(availability::State::default()),
+            metrics: metrics::ApiMetrics::default(),
             #[cfg(target_os = "android")]
             socket_bypass_tx,
         })
@@ -306,6 +339,7 @@ impl Runtime {
         sni_hostname: Option<String>,
         proxy_provider: T,
         new_address_callback: impl ApiEndpointUpdateCallback + Send + Sync + 'static,
+        metrics: metrics::ApiMetrics,
         #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
     ) -> rest::RequestServiceHandle {
         rest::RequestService::spawn(
@@ -314,6 +348,7 @@ impl Runtime {
             self.address_cache.clone(),
             proxy_provider,
             new_address_callback,
+            metrics,
             #[cfg(target_os = "android")]
             socket_bypass_tx,
         )
@@ -327,12 +362,43 @@ impl Runtime {
         &self,
         proxy_provider: T,
         new_address_callback: impl ApiEndpointUpdateCallback + Send + Sync + 'static,
+    ) -> rest::MullvadRestHandle {
+        self.new_mullvad_rest_handle(proxy_provider, new_address_callback, self.metrics.clone())
+            .await
+    }
+
+    /// Like [`Runtime::mullvad_rest_handle`], but the requests are left out of
+    /// [`Runtime::api_diagnostics`]. Used to test connection modes without skewing the
+    /// statistics of the connection modes in use.
+    pub async fn unmetered_mullvad_rest_handle<
+        T: Stream<Item = ApiConnectionMode> + Unpin + Send + 'static,
+    >(
+        &self,
+        proxy_provider: T,
+        new_address_callback: impl ApiEndpointUpdateCallback + Send + Sync + 'static,
+    ) -> rest::MullvadRestHandle {
+        self.new_mullvad_rest_handle(
+            proxy_provider,
+            new_address_callback,
+            metrics::ApiMetrics::default(),
+        )
+        .await
+    }
+
+    async fn new_mullvad_rest_handle<
+        T: Stream<Item = ApiConnectionMode> + Unpin + Send + 'static,
+    >(
+        &self,
+        proxy_provider: T,
+        new_address_callback: impl ApiEndpointUpdateCallback + Send + Sync + 'static,
+        metrics: metrics::ApiMetrics,
     ) -> rest::MullvadRestHandle {
         let service = self
             .new_request_service(
                 Some(API.host.clone()),
                 proxy_provider,
                 new_address_callback,
+                metrics,
                 #[cfg(target_os = "android")]
                 self.socket_bypass_tx.clone(),
             )
@@ -353,6 +419,7 @@ impl Runtime {
             None,
             ApiConnectionMode::Direct.into_repeat(),
             |_| async { true },
+            self.metrics.clone(),
             #[cfg(target_os = "android")]
             None,
         )
@@ -366,6 +433,11 @@ impl Runtime {
     pub fn availability_handle(&self) -> ApiAvailabilityHandle {
         self.api_availability.handle()
     }
+
+    /// Returns statistics about the requests made by all request services of this runtime.
+    pub fn api_diagnostics(&self) -> mullvad_types::api_diagnostics::ApiDiagnostics {
+        self.metrics.diagnostics()
+    }
 }
 
 #[derive(Clone)]
@@ -612,4 +684,26 @@ impl ApiProxy {
 
         rest::deserialize_body(response).await
     }
+
+    /// Returns the time reported by the API server in the `Date` header of a response, if any.
+    pub async fn get_server_time(&self) -> Result<Option<DateTime<Utc>>, rest::Error> {
+        let service = self.handle.service.clone();
+
+        let response = rest::send_request(
+            &self.handle.factory,
+            service,
+            &format!("{}/api-addrs", APP_URL_PREFIX),
+            Method::GET,
+            None,
+            &[StatusCode::OK],
+        )
+        .await?;
+
+        Ok(response
+            .headers()
+            .get(header::DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+            .map(|date| date.with_timezone(&Utc)))
+    }
 }
@@ -0,0 +1,186 @@
+//! Counters for requests made by the REST service.
+
+use crate::proxy::ApiConnectionMode;
+use mullvad_types::api_diagnostics::{ApiDiagnostics, EndpointMetrics, LatencyBucket};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Upper bounds of the latency histogram buckets, in milliseconds. Requests slower than the last
+/// bound are counted in an additional bucket.
+const LATENCY_BUCKETS_MS: &[u64] = &[100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// The result of a request, as far as the metrics are concerned.
+pub(crate) enum Outcome<'a> {
+    Response(u16),
+    Error(&'a crate::rest::Error),
+}
+
+/// Shared handle used to record and read request metrics.
+#[derive(Clone, Default)]
+pub struct ApiMetrics {
+    inner: Arc<Mutex<MetricsInner>>,
+}
+
+#[derive(Default)]
+struct MetricsInner {
+    connection_mode: String,
+    connection_mode_changes: u64,
+    endpoints: BTreeMap<String, EndpointCounters>,
+}
+
+#[derive(Default)]
+struct EndpointCounters {
+    request_count: u64,
+    latency_histogram: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    status_codes: BTreeMap<u16, u64>,
+    errors: BTreeMap<String, u64>,
+    connection_modes: BTreeMap<String, u64>,
+}
+
+impl ApiMetrics {
+    /// Returns a snapshot of all metrics.
+    pub fn diagnostics(&self) -> ApiDiagnostics {
+        let inner = self.inner.lock().unwrap();
+        ApiDiagnostics {
+            connection_mode: inner.connection_mode.clone(),
+            connection_mode_changes: inner.connection_mode_changes,
+            endpoints: inner
+                .endpoints
+                .iter()
+                .map(|(endpoint, counters)| EndpointMetrics {
+                    endpoint: endpoint.clone(),
+                    request_count: counters.request_count,
+                    latency_histogram: counters
+                        .latency_histogram
+                        .iter()
+                        .enumerate()
+                        .map(|(i, count)| LatencyBucket {
+                            upper_bound_ms: LATENCY_BUCKETS_MS.get(i).copied(),
+                            count: *count,
+                        })
+                        .collect(),
+                    status_codes: counters.status_codes.clone(),
+                    errors: counters.errors.clone(),
+                    connection_modes: counters.connection_modes.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Records that a request service switched to a new connection mode.
+    pub(crate) fn record_connection_mode_change(&self, connection_mode: &ApiConnectionMode) {
+        log::debug!("API connection mode changed to \"{}\"", connection_mode);
+        self.inner.lock().unwrap().connection_mode_changes += 1;
+    }
+
+    /// Records a completed request and logs it.
+    pub(crate) fn record(
+        &self,
+        endpoint: String,
+        connection_mode: &str,
+        outcome: Outcome<'_>,
+        latency: Duration,
+    ) {
+        let latency_ms = u64::try_from(latency.as_millis()).unwrap_or(u64::MAX);
+        let outcome_label = match outcome {
+            Outcome::Response(status) => status.to_string(),
+            Outcome::Error(error) => error_kind(error).to_owned(),
+        };
+        log::debug!(
+            "API request: endpoint=\"{}\" mode=\"{}\" outcome={} latency_ms={}",
+            endpoint,
+            connection_mode,
+            outcome_label,
+            latency_ms
+        );
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.connection_mode = connection_mode.to_owned();
+        let counters = inner.endpoints.entry(endpoint).or_default();
+        counters.request_count += 1;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| latency_ms < *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        counters.latency_histogram[bucket] += 1;
+        match outcome {
+            Outcome::Response(status) => *counters.status_codes.entry(status).or_default() += 1,
+            Outcome::Error(_) => *counters.errors.entry(outcome_label).or_default() += 1,
+        }
+        *counters
+            .connection_modes
+            .entry(connection_mode.to_owned())
+            .or_default() += 1;
+    }
+}
+
+/// Returns the method and path of a request, with identifiers in the path replaced by `{id}`.
+pub(crate) fn endpoint_name(method: &hyper::Method, uri: &hyper::Uri) -> String {
+    let path: Vec<&str> = uri
+        .path()
+        .split('/')
+        .map(|segment| {
+            let is_api_version = segment.starts_with('v')
+                && segment.len() > 1
+                && segment[1..].bytes().all(|b| b.is_ascii_digit());
+            if !is_api_version && segment.bytes().any(|b| b.is_ascii_digit()) {
+                "{id}"
+            } else {
+                segment
+            }
+        })
+        .collect();
+    format!("{} {}", method, path.join("/"))
+}
+
+fn error_kind(error: &crate::rest::Error) -> &'static str {
+    use crate::rest::Error;
+    match error {
+        Error::Aborted => "aborted",
+        Error::TimeoutError(_) => "timeout",
+        Error::HyperError(_) => "network",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_name() {
+        let uri: hyper::Uri = "https://api.mullvad.net/accounts/v1/devices/8a3f2b91/pubkey"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            endpoint_name(&hyper::Method::PUT, &uri),
+            "PUT /accounts/v1/devices/{id}/pubkey"
+        );
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let metrics = ApiMetrics::default();
+        for latency_ms in [50, 120, 20_000] {
+            metrics.record(
+                "GET /app/v1/relays".to_owned(),
+                "unproxied",
+                Outcome::Response(200),
+                Duration::from_millis(latency_ms),
+            );
+        }
+
+        let diagnostics = metrics.diagnostics();
+        let endpoint = &diagnostics.endpoints[0];
+        assert_eq!(endpoint.request_count, 3);
+        assert_eq!(endpoint.latency_histogram[0].count, 1);
+        assert_eq!(endpoint.latency_histogram[1].count, 1);
+        let last = endpoint.latency_histogram.last().unwrap();
+        assert_eq!(last.upper_bound_ms, None);
+        assert_eq!(last.count, 1);
+        assert_eq!(endpoint.status_codes.get(&200), Some(&3));
+    }
+}
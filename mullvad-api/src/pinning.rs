@@ -0,0 +1,514 @@
+//! SPKI pinning for the API. After the certificate chain has been validated against the trust
+//! store, at least one certificate presented by the server must have a public key whose SHA-256
+//! hash is in the current pin set. Until a pin set has been received, [`DEFAULT_PINS`] apply.
+//!
+//! Pin sets are delivered as part of the relay list and must be signed with
+//! [`PIN_SET_SIGNING_KEY`]. A pin set is only accepted if its version is greater than that of the
+//! current set and it has not expired. Once accepted, a set keeps applying until a newer one is
+//! accepted, even after it expires, so that blocking updates cannot turn pinning off. The most
+//! recent set is stored in the cache directory.
+
+use crate::fs::AtomicFile;
+use once_cell::sync::Lazy;
+use ring::{digest, signature};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use talpid_types::ErrorExt;
+use tokio::io::AsyncWriteExt;
+use tokio_rustls::rustls::{
+    self,
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, RootCertStore, ServerName,
+};
+
+pub const API_PINS_FILENAME: &str = "api-pins.json";
+
+/// Base64-encoded Ed25519 public key used to verify pin sets delivered with the relay list.
+const PIN_SET_SIGNING_KEY: &str = include_str!("../pin_set_signing_key.pub");
+
+/// Pins that apply until a signed pin set has been accepted. These are the keys of the Let's
+/// Encrypt intermediates that issue the API certificate.
+const DEFAULT_PINS: [&str; 2] = [
+    // Let's Encrypt R3
+    "jQJTbIh0grw0/1TkHSumWb+Fs0Ggogr621gT3PvPKG0=",
+    // Let's Encrypt E1
+    "J2/oqMTsdhFWW/n85tys6b4yDBtb6idZayIEBx7QTxA=",
+];
+
+/// Pin sets that are valid for longer than this are rejected, to limit how long a bad set can
+/// lock out the API.
+const MAX_PIN_SET_VALIDITY: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+/// The current pin set for the API.
+pub static API_PINS: Lazy<PinStore> = Lazy::new(PinStore::new);
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Invalid SPKI pin: {}", _0)]
+    InvalidPin(String),
+
+    #[error(display = "The pin set is empty")]
+    EmptyPinSet,
+
+    #[error(display = "The pin set signature is invalid")]
+    InvalidSignature,
+
+    #[error(display = "The pin set has expired")]
+    Expired,
+
+    #[error(display = "The pin set is valid for too long")]
+    ExpiryTooFarAhead,
+
+    #[error(display = "The pin set version {} is not newer than {}", _0, _1)]
+    OldVersion(u64, u64),
+
+    #[error(display = "Failed to read the pin set file")]
+    Read(#[error(source)] io::Error),
+
+    #[error(display = "Failed to parse the pin set file")]
+    Parse(#[error(source)] serde_json::Error),
+
+    #[error(display = "Failed to write the pin set file")]
+    Write(#[error(source)] io::Error),
+}
+
+/// SHA-256 hash of a DER-encoded `SubjectPublicKeyInfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpkiPin([u8; 32]);
+
+impl SpkiPin {
+    /// Parses a base64-encoded SHA-256 hash, as used by `pin-sha256` in HPKP.
+    pub fn from_base64(pin: &str) -> Result<Self, Error> {
+        base64::decode(pin)
+            .ok()
+            .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+            .map(SpkiPin)
+            .ok_or_else(|| Error::InvalidPin(pin.to_owned()))
+    }
+
+    /// Returns the pin of the public key in a DER-encoded certificate.
+    pub fn from_certificate(cert: &[u8]) -> Option<Self> {
+        let spki = der::subject_public_key_info(cert)?;
+        let hash = digest::digest(&digest::SHA256, spki);
+        Some(SpkiPin(hash.as_ref().try_into().ok()?))
+    }
+}
+
+/// A versioned pin set, together with an Ed25519 signature of the version, the expiry and the
+/// pins, joined by newlines.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SignedPinSet {
+    version: u64,
+    /// Unix timestamp after which the pin set no longer applies.
+    expires: u64,
+    pins: Vec<String>,
+    signature: String,
+}
+
+impl SignedPinSet {
+    fn signed_message(&self) -> String {
+        let mut lines = vec![self.version.to_string(), self.expires.to_string()];
+        lines.extend(self.pins.iter().cloned());
+        lines.join("\n")
+    }
+
+    fn expiry(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.expires)
+    }
+
+    /// Returns the pin set if the signature was made by `public_key`. Unless `now` is `None`, the
+    /// set must also not have expired.
+    fn verify(&self, public_key: &[u8], now: Option<SystemTime>) -> Result<PinSet, Error> {
+        if self.pins.is_empty() {
+            return Err(Error::EmptyPinSet);
+        }
+        let signature = base64::decode(&self.signature).map_err(|_| Error::InvalidSignature)?;
+        signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+            .verify(self.signed_message().as_bytes(), &signature)
+            .map_err(|_| Error::InvalidSignature)?;
+        if let Some(now) = now {
+            if self.expiry() <= now {
+                return Err(Error::Expired);
+            }
+            if self.expiry() > now + MAX_PIN_SET_VALIDITY {
+                return Err(Error::ExpiryTooFarAhead);
+            }
+        }
+        Ok(PinSet {
+            version: self.version,
+            pins: self
+                .pins
+                .iter()
+                .map(|pin| SpkiPin::from_base64(pin))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct PinSet {
+    version: u64,
+    pins: Vec<SpkiPin>,
+}
+
+impl PinSet {
+    /// Returns the pin set that applies until a signed set has been accepted.
+    fn default_pins() -> Self {
+        PinSet {
+            version: 0,
+            pins: DEFAULT_PINS
+                .iter()
+                .map(|pin| SpkiPin::from_base64(pin).expect("Invalid default pin"))
+                .collect(),
+        }
+    }
+}
+
+pub struct PinStore {
+    /// The most recently accepted pin set. It keeps applying after it expires.
+    current: RwLock<PinSet>,
+    write_path: Mutex<Option<PathBuf>>,
+}
+
+impl PinStore {
+    fn new() -> Self {
+        Self {
+            current: RwLock::new(PinSet::default_pins()),
+            write_path: Mutex::new(None),
+        }
+    }
+
+    /// Loads the pin set stored in `cache_dir`, if there is one, and stores future updates there.
+    /// The stored set was valid when it was received, so it is loaded even if it has expired.
+    pub async fn load_from_cache(&self, cache_dir: &Path, write_changes: bool) {
+        let path = cache_dir.join(API_PINS_FILENAME);
+        if write_changes {
+            *self.write_path.lock().unwrap() = Some(path.clone());
+        }
+        let result = async {
+            let contents = tokio::fs::read(&path).await.map_err(Error::Read)?;
+            let pin_set: SignedPinSet = serde_json::from_slice(&contents).map_err(Error::Parse)?;
+            self.set_pins(&pin_set, None)
+        }
+        .await;
+        match result {
+            Ok(()) => log::debug!("Loaded API pins from {}", path.display()),
+            Err(Error::Read(ref error)) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to load API pins. Using the default pins")
+            ),
+        }
+    }
+
+    /// Replaces the current pins if the pin set is valid and newer than the current one, and
+    /// saves it to disk.
+    pub async fn update(&self, pin_set: SignedPinSet) -> Result<(), Error> {
+        if self.version() == pin_set.version {
+            return Ok(());
+        }
+        self.set_pins(&pin_set, Some(SystemTime::now()))?;
+        log::debug!("Updated API pins to version {}", pin_set.version);
+
+        let write_path = self.write_path.lock().unwrap().clone();
+        if let Some(write_path) = write_path {
+            let contents = serde_json::to_vec(&pin_set).map_err(Error::Parse)?;
+            let mut file = AtomicFile::new(write_path).await.map_err(Error::Write)?;
+            file.write_all(&contents).await.map_err(Error::Write)?;
+            file.finalize().await.map_err(Error::Write)?;
+        }
+        Ok(())
+    }
+
+    fn version(&self) -> u64 {
+        self.current.read().unwrap().version
+    }
+
+    fn set_pins(&self, pin_set: &SignedPinSet, now: Option<SystemTime>) -> Result<(), Error> {
+        let public_key =
+            base64::decode(PIN_SET_SIGNING_KEY.trim()).expect("Invalid pin set signing key");
+        self.set_verified_pins(pin_set, &public_key, now)
+    }
+
+    fn set_verified_pins(
+        &self,
+        pin_set: &SignedPinSet,
+        public_key: &[u8],
+        now: Option<SystemTime>,
+    ) -> Result<(), Error> {
+        let new_set = pin_set.verify(public_key, now)?;
+        let mut current = self.current.write().unwrap();
+        if new_set.version <= current.version {
+            return Err(Error::OldVersion(new_set.version, current.version));
+        }
+        *current = new_set;
+        Ok(())
+    }
+
+    /// Returns whether any of `candidates` is in the current pin set.
+    fn matches_any<'a>(&self, mut candidates: impl Iterator<Item = &'a SpkiPin>) -> bool {
+        let current = self.current.read().unwrap();
+        candidates.any(|candidate| current.pins.contains(candidate))
+    }
+}
+
+/// Validates the certificate chain against a trust store, then checks it against [`API_PINS`].
+pub struct PinningVerifier {
+    inner: WebPkiVerifier,
+}
+
+impl PinningVerifier {
+    /// Creates a verifier that trusts `roots`.
+    pub fn new(roots: RootCertStore) -> Self {
+        Self {
+            inner: WebPkiVerifier::new(roots, None),
+        }
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+
+        // Only the certificates presented by the server are checked. The trust anchor is not,
+        // since it would match any chain that the trust store accepts.
+        let chain_pins: Vec<SpkiPin> = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|cert| SpkiPin::from_certificate(&cert.0))
+            .collect();
+        if API_PINS.matches_any(chain_pins.iter()) {
+            Ok(verified)
+        } else {
+            log::error!("The API certificate chain does not match any pinned public key");
+            Err(rustls::Error::General(
+                "certificate chain does not match any pinned public key".to_owned(),
+            ))
+        }
+    }
+}
+
+/// Just enough DER parsing to find the public key of an X.509 certificate.
+mod der {
+    const TAG_SEQUENCE: u8 = 0x30;
+    const TAG_INTEGER: u8 = 0x02;
+    const TAG_EXPLICIT_VERSION: u8 = 0xa0;
+
+    /// Splits `input` into the first element, its contents, and the remaining input.
+    fn next_element(input: &[u8]) -> Option<(u8, &[u8], &[u8], &[u8])> {
+        let tag = *input.first()?;
+        let first_len_byte = *input.get(1)?;
+        let (header_len, content_len) = if first_len_byte < 0x80 {
+            (2, usize::from(first_len_byte))
+        } else {
+            let num_len_bytes = usize::from(first_len_byte & 0x7f);
+            if num_len_bytes == 0 || num_len_bytes > 4 {
+                return None;
+            }
+            let len_bytes = input.get(2..2 + num_len_bytes)?;
+            let len = len_bytes
+                .iter()
+                .fold(0usize, |len, byte| (len << 8) | usize::from(*byte));
+            (2 + num_len_bytes, len)
+        };
+        let end = header_len.checked_add(content_len)?;
+        let element = input.get(..end)?;
+        Some((tag, element, &element[header_len..], &input[end..]))
+    }
+
+    fn expect_element(input: &[u8], expected_tag: u8) -> Option<(&[u8], &[u8], &[u8])> {
+        let (tag, element, contents, rest) = next_element(input)?;
+        (tag == expected_tag).then(|| (element, contents, rest))
+    }
+
+    /// Returns the DER encoding of the `SubjectPublicKeyInfo` of a certificate.
+    pub fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+        let (_, cert, _) = expect_element(cert, TAG_SEQUENCE)?;
+        let (_, tbs_certificate, _) = expect_element(cert, TAG_SEQUENCE)?;
+
+        let mut fields = tbs_certificate;
+        if fields.first() == Some(&TAG_EXPLICIT_VERSION) {
+            fields = next_element(fields)?.3;
+        }
+        // serialNumber
+        fields = expect_element(fields, TAG_INTEGER)?.2;
+        // signature, issuer, validity, subject
+        for _ in 0..4 {
+            fields = expect_element(fields, TAG_SEQUENCE)?.2;
+        }
+        let (spki, _, _) = expect_element(fields, TAG_SEQUENCE)?;
+        Some(spki)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::{rand::SystemRandom, signature::KeyPair};
+
+    const PINS: [&str; 2] = [
+        "ypeBEsobvcr6wjGzmiPcTaeG7/gUfE5yuYB3ha/uSLs=",
+        "PiPoFgA5WUoziU9lZOGxNIu9egCI1CxKy3PurtWcAJ0=",
+    ];
+
+    fn new_key() -> signature::Ed25519KeyPair {
+        let pkcs8 = signature::Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    fn signed_pin_set(key: &signature::Ed25519KeyPair, version: u64, expires: u64) -> SignedPinSet {
+        let mut pin_set = SignedPinSet {
+            version,
+            expires,
+            pins: PINS.iter().map(|pin| pin.to_string()).collect(),
+            signature: String::new(),
+        };
+        pin_set.signature = base64::encode(key.sign(pin_set.signed_message().as_bytes()));
+        pin_set
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_spki_pin_of_bundled_root() {
+        let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(
+            crate::tls_stream::LE_ROOT_CERT,
+        ))
+        .unwrap();
+        // ISRG Root X1
+        assert_eq!(
+            SpkiPin::from_certificate(&certs[0]),
+            Some(SpkiPin::from_base64("C5+lpZ7tcVwmwQIMcRtPbsQtWLABXhQzejna0wHFr8M=").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_verify_pin_set() {
+        let key = new_key();
+        let public_key = key.public_key().as_ref();
+        let pin_set = signed_pin_set(&key, 1, 2000);
+        assert_eq!(
+            pin_set
+                .verify(public_key, Some(at(1000)))
+                .unwrap()
+                .pins
+                .len(),
+            2
+        );
+
+        assert!(matches!(
+            pin_set.verify(new_key().public_key().as_ref(), Some(at(1000))),
+            Err(Error::InvalidSignature)
+        ));
+
+        let mut tampered = pin_set.clone();
+        tampered.pins.pop();
+        assert!(matches!(
+            tampered.verify(public_key, Some(at(1000))),
+            Err(Error::InvalidSignature)
+        ));
+
+        let mut tampered = pin_set.clone();
+        tampered.expires += 1;
+        assert!(matches!(
+            tampered.verify(public_key, Some(at(1000))),
+            Err(Error::InvalidSignature)
+        ));
+
+        assert!(matches!(
+            pin_set.verify(public_key, Some(at(2000))),
+            Err(Error::Expired)
+        ));
+
+        let long_lived = signed_pin_set(&key, 1, 1000 + MAX_PIN_SET_VALIDITY.as_secs() + 1);
+        assert!(matches!(
+            long_lived.verify(public_key, Some(at(1000))),
+            Err(Error::ExpiryTooFarAhead)
+        ));
+    }
+
+    #[test]
+    fn test_pin_set_version_is_monotonic() {
+        let key = new_key();
+        let public_key = key.public_key().as_ref();
+        let store = PinStore::new();
+
+        store
+            .set_verified_pins(&signed_pin_set(&key, 2, 2000), public_key, Some(at(1000)))
+            .unwrap();
+        assert!(matches!(
+            store.set_verified_pins(&signed_pin_set(&key, 2, 2000), public_key, Some(at(1000))),
+            Err(Error::OldVersion(2, 2))
+        ));
+        assert!(matches!(
+            store.set_verified_pins(&signed_pin_set(&key, 1, 3000), public_key, Some(at(1000))),
+            Err(Error::OldVersion(1, 2))
+        ));
+        store
+            .set_verified_pins(&signed_pin_set(&key, 3, 3000), public_key, Some(at(1000)))
+            .unwrap();
+        assert_eq!(store.version(), 3);
+    }
+
+    #[test]
+    fn test_default_pins_apply() {
+        let store = PinStore::new();
+        assert_eq!(store.version(), 0);
+        let default_pin = SpkiPin::from_base64(DEFAULT_PINS[0]).unwrap();
+        assert!(store.matches_any([default_pin].iter()));
+        assert!(!store.matches_any([SpkiPin([0; 32])].iter()));
+    }
+
+    #[test]
+    fn test_pins_apply_after_expiry() {
+        let key = new_key();
+        let public_key = key.public_key().as_ref();
+        let store = PinStore::new();
+        let unpinned = SpkiPin([0; 32]);
+        let pinned = SpkiPin::from_base64(PINS[0]).unwrap();
+
+        store
+            .set_verified_pins(&signed_pin_set(&key, 1, 2000), public_key, Some(at(1000)))
+            .unwrap();
+        assert!(store.matches_any([pinned].iter()));
+        assert!(!store.matches_any([unpinned].iter()));
+
+        // An expired set is not accepted as an update, but a stored one is still loaded
+        let expired = signed_pin_set(&key, 2, 2000);
+        assert!(matches!(
+            store.set_verified_pins(&expired, public_key, Some(at(2000))),
+            Err(Error::Expired)
+        ));
+        store.set_verified_pins(&expired, public_key, None).unwrap();
+        assert_eq!(store.version(), 2);
+        assert!(store.matches_any([pinned].iter()));
+        assert!(!store.matches_any([unpinned].iter()));
+    }
+
+    #[test]
+    fn test_signing_key() {
+        let public_key = base64::decode(PIN_SET_SIGNING_KEY.trim()).unwrap();
+        assert_eq!(public_key.len(), 32);
+    }
+}
@@ -8,12 +8,17 @@ use std::{
     pin::Pin,
     task::{self, Poll},
 };
-use talpid_types::{net::openvpn::ShadowsocksProxySettings, ErrorExt};
+use talpid_types::{
+    net::openvpn::{RemoteProxySettings, ShadowsocksProxySettings},
+    ErrorExt,
+};
 use tokio::{
     fs,
     io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf},
 };
 
+pub mod socks5;
+
 const CURRENT_CONFIG_FILENAME: &str = "api-endpoint.json";
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -36,6 +41,8 @@ impl fmt::Display for ApiConnectionMode {
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum ProxyConfig {
     Shadowsocks(ShadowsocksProxySettings),
+    /// A SOCKS5 proxy, with optional username/password authentication.
+    Socks5(RemoteProxySettings),
 }
 
 impl fmt::Display for ProxyConfig {
@@ -43,6 +50,7 @@ impl fmt::Display for ProxyConfig {
         match self {
             // TODO: Do not hardcode TCP
             ProxyConfig::Shadowsocks(ss) => write!(f, "Shadowsocks {}/TCP", ss.peer),
+            ProxyConfig::Socks5(socks) => write!(f, "SOCKS5 {}/TCP", socks.address),
         }
     }
 }
@@ -112,6 +120,7 @@ impl ApiConnectionMode {
     pub fn get_endpoint(&self) -> Option<SocketAddr> {
         match self {
             ApiConnectionMode::Proxied(ProxyConfig::Shadowsocks(ss)) => Some(ss.peer),
+            ApiConnectionMode::Proxied(ProxyConfig::Socks5(socks)) => Some(socks.address),
             ApiConnectionMode::Direct => None,
         }
     }
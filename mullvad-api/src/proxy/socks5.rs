@@ -0,0 +1,306 @@
+//! A minimal SOCKS5 client (RFC 1928), supporting the `CONNECT` command and optional
+//! username/password authentication (RFC 1929).
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
+use talpid_types::net::openvpn::ProxyAuth;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const SOCKS_VERSION: u8 = 0x05;
+const AUTH_VERSION: u8 = 0x01;
+
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const METHOD_NOT_ACCEPTABLE: u8 = 0xff;
+
+const COMMAND_CONNECT: u8 = 0x01;
+
+const ADDRESS_TYPE_IPV4: u8 = 0x01;
+const ADDRESS_TYPE_DOMAIN: u8 = 0x03;
+const ADDRESS_TYPE_IPV6: u8 = 0x04;
+
+const REPLY_SUCCEEDED: u8 = 0x00;
+
+/// Asks the SOCKS5 server at the other end of `stream` to connect to `target`. On success, the
+/// stream may be used to communicate with `target`.
+pub async fn connect<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    target: SocketAddr,
+    auth: Option<&ProxyAuth>,
+) -> io::Result<S> {
+    let method = if auth.is_some() {
+        METHOD_USERNAME_PASSWORD
+    } else {
+        METHOD_NO_AUTH
+    };
+    stream.write_all(&[SOCKS_VERSION, 1, method]).await?;
+
+    let mut response = [0u8; 2];
+    stream.read_exact(&mut response).await?;
+    check_version(response[0], SOCKS_VERSION)?;
+    match (response[1], auth) {
+        (METHOD_NO_AUTH, None) => (),
+        (METHOD_USERNAME_PASSWORD, Some(auth)) => authenticate(&mut stream, auth).await?,
+        (METHOD_NOT_ACCEPTABLE, _) => {
+            return Err(proxy_error(
+                "SOCKS5 server rejected the authentication method",
+            ))
+        }
+        _ => return Err(proxy_error("SOCKS5 server selected an unexpected method")),
+    }
+
+    let mut request = vec![SOCKS_VERSION, COMMAND_CONNECT, 0x00];
+    match target.ip() {
+        IpAddr::V4(addr) => {
+            request.push(ADDRESS_TYPE_IPV4);
+            request.extend_from_slice(&addr.octets());
+        }
+        IpAddr::V6(addr) => {
+            request.push(ADDRESS_TYPE_IPV6);
+            request.extend_from_slice(&addr.octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    check_version(reply[0], SOCKS_VERSION)?;
+    if reply[1] != REPLY_SUCCEEDED {
+        return Err(proxy_error(&format!(
+            "SOCKS5 server failed to connect to the target: {}",
+            reply_message(reply[1])
+        )));
+    }
+
+    // Skip the bound address and port
+    let address_len = match reply[3] {
+        ADDRESS_TYPE_IPV4 => 4,
+        ADDRESS_TYPE_IPV6 => 16,
+        ADDRESS_TYPE_DOMAIN => usize::from(stream.read_u8().await?),
+        _ => {
+            return Err(proxy_error(
+                "SOCKS5 server replied with an invalid address type",
+            ))
+        }
+    };
+    let mut bound_address = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound_address).await?;
+
+    Ok(stream)
+}
+
+async fn authenticate<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    auth: &ProxyAuth,
+) -> io::Result<()> {
+    let username = auth.username.as_bytes();
+    let password = auth.password.as_bytes();
+    let username_len =
+        u8::try_from(username.len()).map_err(|_| invalid_input("SOCKS5 username is too long"))?;
+    let password_len =
+        u8::try_from(password.len()).map_err(|_| invalid_input("SOCKS5 password is too long"))?;
+
+    let mut request = vec![AUTH_VERSION, username_len];
+    request.extend_from_slice(username);
+    request.push(password_len);
+    request.extend_from_slice(password);
+    stream.write_all(&request).await?;
+
+    let mut response = [0u8; 2];
+    stream.read_exact(&mut response).await?;
+    check_version(response[0], AUTH_VERSION)?;
+    if response[1] != REPLY_SUCCEEDED {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "SOCKS5 authentication failed",
+        ));
+    }
+    Ok(())
+}
+
+fn check_version(version: u8, expected: u8) -> io::Result<()> {
+    if version != expected {
+        return Err(proxy_error(&format!(
+            "Unexpected SOCKS version in response: {}",
+            version
+        )));
+    }
+    Ok(())
+}
+
+fn reply_message(reply: u8) -> &'static str {
+    match reply {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+fn proxy_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, msg)
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
+
+    const TARGET: &str = "10.0.0.1:443";
+
+    /// Accepts a single client and performs the server side of the SOCKS5 handshake. The
+    /// connection is then used to echo a single message.
+    async fn run_proxy_stand_in(listener: TcpListener, auth: Option<ProxyAuth>) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        let mut greeting = [0u8; 2];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting[0], SOCKS_VERSION);
+        let mut methods = vec![0u8; usize::from(greeting[1])];
+        stream.read_exact(&mut methods).await.unwrap();
+
+        match auth {
+            Some(auth) => {
+                assert!(methods.contains(&METHOD_USERNAME_PASSWORD));
+                stream
+                    .write_all(&[SOCKS_VERSION, METHOD_USERNAME_PASSWORD])
+                    .await
+                    .unwrap();
+
+                assert_eq!(stream.read_u8().await.unwrap(), AUTH_VERSION);
+                let mut username = vec![0u8; usize::from(stream.read_u8().await.unwrap())];
+                stream.read_exact(&mut username).await.unwrap();
+                let mut password = vec![0u8; usize::from(stream.read_u8().await.unwrap())];
+                stream.read_exact(&mut password).await.unwrap();
+
+                let accepted =
+                    username == auth.username.as_bytes() && password == auth.password.as_bytes();
+                stream
+                    .write_all(&[AUTH_VERSION, if accepted { 0x00 } else { 0x01 }])
+                    .await
+                    .unwrap();
+                if !accepted {
+                    return;
+                }
+            }
+            None => {
+                assert!(methods.contains(&METHOD_NO_AUTH));
+                stream
+                    .write_all(&[SOCKS_VERSION, METHOD_NO_AUTH])
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let mut request = [0u8; 10];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(
+            request,
+            [
+                SOCKS_VERSION,
+                COMMAND_CONNECT,
+                0x00,
+                ADDRESS_TYPE_IPV4,
+                10,
+                0,
+                0,
+                1,
+                0x01,
+                0xbb
+            ]
+        );
+        stream
+            .write_all(&[
+                SOCKS_VERSION,
+                REPLY_SUCCEEDED,
+                0x00,
+                ADDRESS_TYPE_IPV4,
+                127,
+                0,
+                0,
+                1,
+                0x00,
+                0x00,
+            ])
+            .await
+            .unwrap();
+
+        let mut message = [0u8; 4];
+        stream.read_exact(&mut message).await.unwrap();
+        stream.write_all(&message).await.unwrap();
+    }
+
+    async fn connect_through_stand_in(
+        server_auth: Option<ProxyAuth>,
+        client_auth: Option<ProxyAuth>,
+    ) -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(run_proxy_stand_in(listener, server_auth));
+
+        let socket = TcpStream::connect(proxy_addr).await.unwrap();
+        let result = connect(socket, TARGET.parse().unwrap(), client_auth.as_ref()).await;
+        let result = match result {
+            Ok(mut stream) => {
+                stream.write_all(b"ping").await.unwrap();
+                let mut response = [0u8; 4];
+                stream.read_exact(&mut response).await.unwrap();
+                assert_eq!(&response, b"ping");
+                Ok(())
+            }
+            Err(error) => Err(error),
+        };
+        server.await.unwrap();
+        result
+    }
+
+    fn test_auth(password: &str) -> ProxyAuth {
+        ProxyAuth {
+            username: "user".to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_connect_without_auth() {
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+        runtime
+            .block_on(connect_through_stand_in(None, None))
+            .expect("SOCKS5 handshake failed");
+    }
+
+    #[test]
+    fn test_connect_with_auth() {
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+        runtime
+            .block_on(connect_through_stand_in(
+                Some(test_auth("secret")),
+                Some(test_auth("secret")),
+            ))
+            .expect("SOCKS5 handshake failed");
+    }
+
+    #[test]
+    fn test_connect_with_invalid_auth() {
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+        let error = runtime
+            .block_on(connect_through_stand_in(
+                Some(test_auth("secret")),
+                Some(test_auth("wrong")),
+            ))
+            .expect_err("SOCKS5 handshake should fail");
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
@@ -1,10 +1,10 @@
 //! A module dedicated to retrieving the relay list from the Mullvad API.
 
-use crate::rest;
+use crate::{pinning, rest};
 
 use hyper::{header, Method, StatusCode};
 use mullvad_types::{location, relay_list};
-use talpid_types::net::wireguard;
+use talpid_types::{net::wireguard, ErrorExt};
 
 use std::{
     collections::BTreeMap,
@@ -62,11 +62,16 @@ impl RelayListProxy {
                     }
                 });
 
-            Ok(Some(
-                rest::deserialize_body::<ServerRelayList>(response)
-                    .await?
-                    .into_relay_list(etag),
-            ))
+            let mut relay_list = rest::deserialize_body::<ServerRelayList>(response).await?;
+            if let Some(api_pins) = relay_list.api_pins.take() {
+                if let Err(error) = pinning::API_PINS.update(api_pins).await {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to update API pins")
+                    );
+                }
+            }
+            Ok(Some(relay_list.into_relay_list(etag)))
         }
     }
 }
@@ -77,6 +82,9 @@ struct ServerRelayList {
     openvpn: OpenVpn,
     wireguard: Wireguard,
     bridge: Bridges,
+    /// Signed SPKI pins for the API.
+    #[serde(default)]
+    api_pins: Option<pinning::SignedPinSet>,
 }
 
 impl ServerRelayList {
@@ -87,6 +95,7 @@ impl ServerRelayList {
             openvpn,
             wireguard,
             bridge,
+            ..
         } = self;
 
         for (code, location) in locations.into_iter() {
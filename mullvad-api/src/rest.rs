@@ -5,6 +5,7 @@ use crate::{
     address_cache::AddressCache,
     availability::ApiAvailabilityHandle,
     https_client_with_sni::{HttpsConnectorWithSni, HttpsConnectorWithSniHandle},
+    metrics::{self, ApiMetrics},
     proxy::ApiConnectionMode,
 };
 use futures::{
@@ -22,7 +23,7 @@ use std::{
     future::Future,
     str::FromStr,
     sync::{Arc, Weak},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use talpid_types::ErrorExt;
 
@@ -125,6 +126,9 @@ pub(crate) struct RequestService<
     new_address_callback: F,
     address_cache: AddressCache,
     api_availability: ApiAvailabilityHandle,
+    metrics: ApiMetrics,
+    /// Description of the current connection mode, for metrics.
+    connection_mode: String,
 }
 
 impl<
@@ -139,6 +143,7 @@ impl<
         address_cache: AddressCache,
         mut proxy_config_provider: T,
         new_address_callback: F,
+        metrics: ApiMetrics,
         #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
     ) -> RequestServiceHandle {
         let (connector, connector_handle) = HttpsConnectorWithSni::new(
@@ -153,9 +158,11 @@ impl<
         #[cfg(not(feature = "api-override"))]
         let force_direct_connection = false;
 
+        let mut connection_mode = ApiConnectionMode::Direct.to_string();
         if force_direct_connection {
             log::debug!("API proxies are disabled");
         } else if let Some(config) = proxy_config_provider.next().await {
+            connection_mode = config.to_string();
             connector_handle.set_connection_mode(config);
         }
 
@@ -173,6 +180,8 @@ impl<
             new_address_callback,
             address_cache,
             api_availability,
+            metrics,
+            connection_mode,
         };
         let handle = RequestServiceHandle { tx: command_tx };
         tokio::spawn(service.into_future());
@@ -184,6 +193,9 @@ impl<
             RequestCommand::NewRequest(request, completion_tx) => {
                 let tx = self.command_tx.upgrade();
                 let timeout = request.timeout();
+                let endpoint = metrics::endpoint_name(request.request.method(), request.uri());
+                let connection_mode = self.connection_mode.clone();
+                let metrics = self.metrics.clone();
 
                 let hyper_request = request.into_request();
 
@@ -197,12 +209,19 @@ impl<
                 };
 
                 let future = async move {
+                    let start = Instant::now();
                     let response = tokio::time::timeout(timeout, request_future)
                         .await
                         .map_err(Error::TimeoutError);
 
                     let response = flatten_result(response).map_err(|error| error.map_aborted());
 
+                    let outcome = match &response {
+                        Ok(response) => metrics::Outcome::Response(response.status().as_u16()),
+                        Err(error) => metrics::Outcome::Error(error),
+                    };
+                    metrics.record(endpoint, &connection_mode, outcome, start.elapsed());
+
                     if let Err(err) = &response {
                         if err.is_network_error() && !api_availability.get_state().is_offline() {
                             log::error!("{}", err.display_chain_with_msg("HTTP request failed"));
@@ -237,6 +256,8 @@ impl<
                     };
                     // Switch to new connection mode unless rejected by address change callback
                     if (self.new_address_callback)(endpoint).await {
+                        self.metrics.record_connection_mode_change(&new_config);
+                        self.connection_mode = new_config.to_string();
                         self.connector_handle.set_connection_mode(new_config);
                     }
                 }
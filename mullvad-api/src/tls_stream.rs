@@ -1,4 +1,5 @@
-//! Provides a TLS 1.3 stream with SNI and LE root cert only.
+//! Provides a TLS 1.3 stream with SNI and LE root cert only. The certificate chain must also match
+//! the SPKI pins in [`crate::pinning`].
 use std::{
     io::{self, ErrorKind},
     pin::Pin,
@@ -6,6 +7,7 @@ use std::{
     task::{self, Poll},
 };
 
+use crate::pinning::PinningVerifier;
 use hyper::client::connect::{Connected, Connection};
 use once_cell::sync::Lazy;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
@@ -14,7 +16,7 @@ use tokio_rustls::{
     TlsConnector,
 };
 
-const LE_ROOT_CERT: &[u8] = include_bytes!("../le_root_cert.pem");
+pub(crate) const LE_ROOT_CERT: &[u8] = include_bytes!("../le_root_cert.pem");
 
 pub struct TlsStream<S: AsyncRead + AsyncWrite + Unpin> {
     stream: tokio_rustls::client::TlsStream<S>,
@@ -26,14 +28,30 @@ where
 {
     pub async fn connect_https(stream: S, domain: &str) -> io::Result<TlsStream<S>> {
         static TLS_CONFIG: Lazy<Arc<ClientConfig>> = Lazy::new(|| {
-            let config = ClientConfig::builder()
+            let builder = ClientConfig::builder()
                 .with_safe_default_cipher_suites()
                 .with_safe_default_kx_groups()
                 .with_protocol_versions(&[&rustls::version::TLS13])
-                .unwrap()
-                .with_root_certificates(read_cert_store())
-                .with_no_client_auth();
-            Arc::new(config)
+                .unwrap();
+
+            #[cfg(feature = "api-override")]
+            if let Some(cert_store) = CA_BUNDLE.get() {
+                log::warn!(
+                    "Trusting custom CA bundle for the API. Certificate pinning is disabled"
+                );
+                return Arc::new(
+                    builder
+                        .with_root_certificates(cert_store.clone())
+                        .with_no_client_auth(),
+                );
+            }
+
+            let verifier = PinningVerifier::new(read_cert_store());
+            Arc::new(
+                builder
+                    .with_custom_certificate_verifier(Arc::new(verifier))
+                    .with_no_client_auth(),
+            )
         });
 
         let connector = TlsConnector::from(TLS_CONFIG.clone());
@@ -54,6 +72,7 @@ where
     }
 }
 
+/// Returns a cert store containing the bundled root certificate.
 fn read_cert_store() -> rustls::RootCertStore {
     let mut cert_store = rustls::RootCertStore::empty();
 
@@ -67,6 +86,56 @@ fn read_cert_store() -> rustls::RootCertStore {
     cert_store
 }
 
+/// Root certificates from `MULLVAD_API_CA_BUNDLE`, trusted instead of the bundled root.
+#[cfg(feature = "api-override")]
+static CA_BUNDLE: once_cell::sync::OnceCell<rustls::RootCertStore> =
+    once_cell::sync::OnceCell::new();
+
+#[cfg(feature = "api-override")]
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum CaBundleError {
+    #[error(display = "Failed to read CA bundle {}", _0)]
+    Read(String, #[error(source)] io::Error),
+
+    #[error(display = "CA bundle {} contains no valid certificates", _0)]
+    NoCertificates(String),
+}
+
+/// Reads the CA bundle in [`crate::ApiEndpoint::ca_bundle`], if one is set, so that it is used
+/// for all TLS connections to the API.
+#[cfg(feature = "api-override")]
+pub(crate) fn load_ca_bundle() -> Result<(), CaBundleError> {
+    let path = match &crate::API.ca_bundle {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    if CA_BUNDLE.get().is_some() {
+        return Ok(());
+    }
+
+    let display_path = path.display().to_string();
+    let file = std::fs::File::open(path)
+        .map_err(|error| CaBundleError::Read(display_path.clone(), error))?;
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(file))
+        .map_err(|error| CaBundleError::Read(display_path.clone(), error))?;
+
+    let mut cert_store = rustls::RootCertStore::empty();
+    let (num_certs_added, num_failures) = cert_store.add_parsable_certificates(&certs);
+    if num_certs_added == 0 {
+        return Err(CaBundleError::NoCertificates(display_path));
+    }
+    if num_failures > 0 {
+        log::warn!(
+            "Ignoring {} invalid certificates in {}",
+            num_failures,
+            display_path
+        );
+    }
+    let _ = CA_BUNDLE.set(cert_store);
+    Ok(())
+}
+
 impl<S> AsyncRead for TlsStream<S>
 where
     S: AsyncRead + AsyncWrite + Unpin,
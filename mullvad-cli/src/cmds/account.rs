@@ -1,10 +1,13 @@
-use crate::{new_rpc_client, Command, Error, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
 use itertools::Itertools;
 use mullvad_management_interface::{
     types::{self, Timestamp},
     Code, ManagementServiceClient, Status,
 };
-use mullvad_types::{account::AccountToken, device::Device};
+use mullvad_types::{
+    account::AccountToken,
+    device::{AccountProfile, Device, DeviceState},
+};
 use std::io::{self, Write};
 
 const NOT_LOGGED_IN_MESSAGE: &str = "Not logged in on any account";
@@ -85,6 +88,57 @@ impl Command for Account {
                         .required(true),
                 ),
             )
+            .subcommand(
+                clap::App::new("switch")
+                    .about("Switch to the device of another account profile")
+                    .arg(
+                        clap::Arg::new("profile")
+                            .help("Name of the profile to switch to")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::App::new("profile")
+                    .about("Manage named account profiles, each with its own device")
+                    .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(clap::App::new("list").about("List all account profiles"))
+                    .subcommand(
+                        clap::App::new("save")
+                            .about("Save the current account and device as a new profile")
+                            .arg(
+                                clap::Arg::new("profile")
+                                    .help("Name of the new profile")
+                                    .required(true),
+                            ),
+                    )
+                    .subcommand(
+                        clap::App::new("remove")
+                            .about("Remove a profile and revoke its device")
+                            .arg(
+                                clap::Arg::new("profile")
+                                    .help("Name of the profile to remove")
+                                    .required(true),
+                            ),
+                    ),
+            )
+            .subcommand(
+                clap::App::new("expiry-reminders")
+                    .about("Configure when to be reminded that the account is about to expire")
+                    .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        clap::App::new("get").about("Display the current reminder thresholds"),
+                    )
+                    .subcommand(
+                        clap::App::new("set")
+                            .about("Set the number of days before expiry at which to remind")
+                            .arg(
+                                clap::Arg::new("days")
+                                    .multiple_occurrences(true)
+                                    .help("Days before expiry. Pass none to disable reminders")
+                                    .required(false),
+                            ),
+                    ),
+            )
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -104,6 +158,35 @@ impl Command for Account {
         } else if let Some(matches) = matches.subcommand_matches("redeem") {
             let voucher = matches.value_of_t_or_exit("voucher");
             self.redeem_voucher(voucher).await
+        } else if let Some(matches) = matches.subcommand_matches("switch") {
+            let profile = matches.value_of_t_or_exit("profile");
+            self.switch_profile(profile).await
+        } else if let Some(matches) = matches.subcommand_matches("profile") {
+            match matches.subcommand() {
+                Some(("list", _)) => self.list_profiles().await,
+                Some(("save", matches)) => {
+                    let profile = matches.value_of_t_or_exit("profile");
+                    self.save_profile(profile).await
+                }
+                Some(("remove", matches)) => {
+                    let profile = matches.value_of_t_or_exit("profile");
+                    self.remove_profile(profile).await
+                }
+                _ => unreachable!("unhandled command"),
+            }
+        } else if let Some(matches) = matches.subcommand_matches("expiry-reminders") {
+            match matches.subcommand() {
+                Some(("get", _)) => self.get_expiry_reminders().await,
+                Some(("set", matches)) => {
+                    let days = match matches.values_of_t::<u32>("days") {
+                        Ok(days) => days,
+                        Err(e) if e.kind == clap::ErrorKind::ArgumentNotFound => vec![],
+                        Err(e) => e.exit(),
+                    };
+                    self.set_expiry_reminders(days).await
+                }
+                _ => unreachable!("unhandled command"),
+            }
         } else {
             unreachable!("No account command given");
         }
@@ -114,7 +197,7 @@ impl Account {
     async fn create(&self) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.create_new_account(()).await.map_err(map_device_error)?;
-        println!("New account created!");
+        println_text!("New account created!");
         self.get(false).await
     }
 
@@ -123,14 +206,14 @@ impl Account {
         rpc.login_account(token.clone())
             .await
             .map_err(map_device_error)?;
-        println!("Mullvad account \"{}\" set", token);
+        println_text!("Mullvad account \"{}\" set", token);
         Ok(())
     }
 
     async fn logout(&self) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.logout_account(()).await?;
-        println!("Removed device from Mullvad account");
+        println_text!("Removed device from Mullvad account");
         Ok(())
     }
 
@@ -145,6 +228,10 @@ impl Account {
             .map_err(map_device_error)?
             .into_inner();
 
+        if format::json_output() {
+            return Self::print_json_device(&mut rpc, state).await;
+        }
+
         use types::device_state::State;
 
         match State::from_i32(state.state).unwrap() {
@@ -185,6 +272,27 @@ impl Account {
         Ok(())
     }
 
+    /// Prints the device state and the expiry of the account as JSON.
+    async fn print_json_device(
+        rpc: &mut ManagementServiceClient,
+        state: types::DeviceState,
+    ) -> Result<()> {
+        let state = DeviceState::try_from(state).expect("invalid device state");
+        let expiry = match state {
+            DeviceState::LoggedIn(ref device) => {
+                let account_data = rpc
+                    .get_account_data(device.account_token.clone())
+                    .await
+                    .map_err(|error| Error::RpcFailedExt("Failed to fetch account data", error))?
+                    .into_inner();
+                account_data.expiry.map(|expiry| expiry.seconds)
+            }
+            DeviceState::LoggedOut | DeviceState::Revoked => None,
+        };
+        format::print_json(&serde_json::json!({ "device": state, "expiry": expiry }));
+        Ok(())
+    }
+
     async fn list_devices(&self, matches: &clap::ArgMatches) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let token = self.parse_account_else_current(&mut rpc, matches).await?;
@@ -196,10 +304,20 @@ impl Account {
 
         let verbose = matches.is_present("verbose");
 
-        println!("Devices on the account:");
         device_list
             .devices
             .sort_unstable_by_key(|dev| dev.created.as_ref().map(|dt| dt.seconds).unwrap_or(0));
+        if format::json_output() {
+            let devices: Vec<_> = device_list
+                .devices
+                .into_iter()
+                .map(|device| Device::try_from(device).unwrap())
+                .collect();
+            format::print_json(&devices);
+            return Ok(());
+        }
+
+        println!("Devices on the account:");
         for device in device_list.devices {
             let device = Device::try_from(device.clone()).unwrap();
             if verbose {
@@ -249,7 +367,7 @@ impl Account {
         })
         .await
         .map_err(map_device_error)?;
-        println!("Removed device");
+        println_text!("Removed device");
         Ok(())
     }
 
@@ -281,6 +399,13 @@ impl Account {
         match rpc.submit_voucher(voucher).await {
             Ok(submission) => {
                 let submission = submission.into_inner();
+                if format::json_output() {
+                    format::print_json(&serde_json::json!({
+                        "seconds_added": submission.seconds_added,
+                        "new_expiry": submission.new_expiry.map(|expiry| expiry.seconds),
+                    }));
+                    return Ok(());
+                }
                 println!(
                     "Added {} to the account",
                     Self::format_duration(submission.seconds_added)
@@ -293,6 +418,9 @@ impl Account {
             }
             Err(err) => {
                 match err.code() {
+                    Code::NotFound | Code::ResourceExhausted if format::json_output() => {
+                        return Err(Error::RpcFailedExt("Failed to submit voucher", err));
+                    }
                     Code::NotFound | Code::ResourceExhausted => {
                         eprintln!("Failed to submit voucher: {}", err.message());
                     }
@@ -316,6 +444,91 @@ impl Account {
         }
     }
 
+    async fn switch_profile(&self, profile: String) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.switch_account_profile(profile.clone())
+            .await
+            .map_err(map_profile_error)?;
+        println_text!("Switched to account profile \"{}\"", profile);
+        Ok(())
+    }
+
+    async fn list_profiles(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let profiles = rpc.list_account_profiles(()).await?.into_inner().profiles;
+        if format::json_output() {
+            let profiles: Vec<_> = profiles
+                .into_iter()
+                .map(|profile| AccountProfile::try_from(profile).unwrap())
+                .collect();
+            format::print_json(&profiles);
+            return Ok(());
+        }
+        if profiles.is_empty() {
+            println!("No account profiles");
+        }
+        for profile in profiles {
+            let profile = AccountProfile::try_from(profile).unwrap();
+            println!(
+                "{} {}: {} ({})",
+                if profile.active { "*" } else { " " },
+                profile.name,
+                profile.account_token,
+                profile.device.pretty_name()
+            );
+        }
+        Ok(())
+    }
+
+    async fn save_profile(&self, profile: String) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.save_account_profile(profile.clone())
+            .await
+            .map_err(map_profile_error)?;
+        println_text!(
+            "Saved the current device as account profile \"{}\"",
+            profile
+        );
+        Ok(())
+    }
+
+    async fn remove_profile(&self, profile: String) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.remove_account_profile(profile.clone())
+            .await
+            .map_err(map_profile_error)?;
+        println_text!("Removed account profile \"{}\"", profile);
+        Ok(())
+    }
+
+    async fn get_expiry_reminders(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        let mut days = settings.expiry_reminder_days;
+        days.sort_unstable_by(|a, b| b.cmp(a));
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "expiry_reminder_days": days }));
+            return Ok(());
+        }
+        if days.is_empty() {
+            println!("Expiry reminders are disabled");
+        } else {
+            println!(
+                "Reminding {} days before the account expires",
+                days.iter().join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    async fn set_expiry_reminders(&self, days: Vec<u32>) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_expiry_reminder_days(types::ExpiryReminderDays { days })
+            .await?;
+        println_text!("Updated expiry reminders");
+        Ok(())
+    }
+
     fn format_expiry(expiry: &Timestamp) -> String {
         let ndt = chrono::NaiveDateTime::from_timestamp(expiry.seconds, expiry.nanos as u32);
         let utc = chrono::DateTime::<chrono::Utc>::from_utc(ndt, chrono::Utc);
@@ -333,6 +546,18 @@ fn map_device_error(error: Status) -> Error {
     }
 }
 
+fn map_profile_error(error: Status) -> Error {
+    match error.code() {
+        Code::NotFound | Code::AlreadyExists | Code::FailedPrecondition
+            if !format::json_output() =>
+        {
+            eprintln!("{}", error.message());
+            std::process::exit(1);
+        }
+        _other => Error::RpcFailed(error),
+    }
+}
+
 fn parse_token_else_stdin(matches: &clap::ArgMatches) -> String {
     parse_from_match_else_stdin("Enter account number: ", "account", matches)
         .split_whitespace()
@@ -0,0 +1,297 @@
+use crate::{format, new_rpc_client, Command, Error, Result};
+
+use mullvad_management_interface::types;
+use mullvad_types::{
+    access_method::{AccessMethod, ApiAccessMethod},
+    api_diagnostics::ApiDiagnostics,
+};
+use talpid_types::net::openvpn::{self, SHADOWSOCKS_CIPHERS};
+
+use std::{convert::TryFrom, net::SocketAddr};
+
+pub struct ApiAccess;
+
+#[mullvad_management_interface::async_trait]
+impl Command for ApiAccess {
+    fn name(&self) -> &'static str {
+        "api-access"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Manage the methods used to reach the Mullvad API. When the API cannot be \
+                reached, the enabled methods are tried in the order that they are listed.",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(clap::App::new("list").about("List all API access methods"))
+            .subcommand(create_add_subcommand())
+            .subcommand(
+                clap::App::new("remove")
+                    .about("Remove a custom API access method")
+                    .arg(name_arg()),
+            )
+            .subcommand(
+                clap::App::new("enable")
+                    .about("Enable an API access method")
+                    .arg(name_arg()),
+            )
+            .subcommand(
+                clap::App::new("disable")
+                    .about("Disable an API access method")
+                    .arg(name_arg()),
+            )
+            .subcommand(
+                clap::App::new("test")
+                    .about(
+                        "Check whether the API can be reached using an API access method. \
+                        The method does not have to be enabled.",
+                    )
+                    .arg(name_arg()),
+            )
+            .subcommand(
+                clap::App::new("diagnostics").about(
+                    "Show statistics about requests made to the API since the daemon started",
+                ),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("list", _)) => Self::list().await,
+            Some(("add", add_matches)) => Self::add(add_matches).await,
+            Some(("remove", remove_matches)) => {
+                Self::remove(remove_matches.value_of_t_or_exit("name")).await
+            }
+            Some(("enable", enable_matches)) => {
+                Self::set_enabled(enable_matches.value_of_t_or_exit("name"), true).await
+            }
+            Some(("disable", disable_matches)) => {
+                Self::set_enabled(disable_matches.value_of_t_or_exit("name"), false).await
+            }
+            Some(("test", test_matches)) => {
+                Self::test(test_matches.value_of_t_or_exit("name")).await
+            }
+            Some(("diagnostics", _)) => Self::diagnostics().await,
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+fn name_arg() -> clap::Arg<'static> {
+    clap::Arg::new("name")
+        .help("Name of the API access method")
+        .required(true)
+}
+
+fn create_add_subcommand() -> clap::App<'static> {
+    clap::App::new("add")
+        .about("Add a custom API access method. It is tried after all existing methods")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::App::new("shadowsocks")
+                .about("Reach the API through a Shadowsocks server")
+                .arg(name_arg().index(1))
+                .arg(
+                    clap::Arg::new("remote-ip")
+                        .help("Specifies the IP of the remote Shadowsocks server")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    clap::Arg::new("remote-port")
+                        .help("Specifies the port of the remote Shadowsocks server")
+                        .required(true)
+                        .index(3),
+                )
+                .arg(
+                    clap::Arg::new("password")
+                        .help("Specifies the password on the remote Shadowsocks server")
+                        .required(true)
+                        .index(4),
+                )
+                .arg(
+                    clap::Arg::new("cipher")
+                        .help("Specifies the cipher to use")
+                        .default_value("aes-256-gcm")
+                        .possible_values(SHADOWSOCKS_CIPHERS)
+                        .index(5),
+                ),
+        )
+        .subcommand(
+            clap::App::new("socks5")
+                .about("Reach the API through a SOCKS5 proxy")
+                .arg(name_arg().index(1))
+                .arg(
+                    clap::Arg::new("remote-ip")
+                        .help("Specifies the IP of the SOCKS5 proxy server")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    clap::Arg::new("remote-port")
+                        .help("Specifies the port the SOCKS5 proxy server is listening on")
+                        .required(true)
+                        .index(3),
+                )
+                .arg(
+                    clap::Arg::new("username")
+                        .help("Specifies the username for authentication")
+                        .long("username")
+                        .takes_value(true)
+                        .requires("password"),
+                )
+                .arg(
+                    clap::Arg::new("password")
+                        .help("Specifies the password for authentication")
+                        .long("password")
+                        .takes_value(true)
+                        .requires("username"),
+                ),
+        )
+}
+
+impl ApiAccess {
+    async fn list() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let settings = rpc.get_settings(()).await?.into_inner();
+        if format::json_output() {
+            let methods: Vec<_> = settings
+                .api_access_methods
+                .into_iter()
+                .map(|method| ApiAccessMethod::try_from(method).unwrap())
+                .collect();
+            format::print_json(&methods);
+            return Ok(());
+        }
+        for (index, method) in settings.api_access_methods.into_iter().enumerate() {
+            let method = ApiAccessMethod::try_from(method).unwrap();
+            println!(
+                "{}. {} ({})",
+                index + 1,
+                method.name,
+                if method.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+            match &method.access_method {
+                AccessMethod::Shadowsocks(settings) => {
+                    println!("    Shadowsocks");
+                    println!("    peer address: {}", settings.peer);
+                    println!("    password: {}", settings.password);
+                    println!("    cipher: {}", settings.cipher);
+                }
+                AccessMethod::Socks5(settings) => {
+                    println!("    SOCKS5");
+                    println!("    server address: {}", settings.address);
+                    if let Some(ref auth) = settings.auth {
+                        println!("    auth username: {}", auth.username);
+                        println!("    auth password: {}", auth.password);
+                    }
+                }
+                AccessMethod::Direct | AccessMethod::Bridges => (),
+            }
+        }
+        Ok(())
+    }
+
+    async fn add(matches: &clap::ArgMatches) -> Result<()> {
+        let (name, access_method) = match matches.subcommand() {
+            Some(("shadowsocks", args)) => {
+                let remote_ip = args.value_of_t_or_exit("remote-ip");
+                let remote_port = args.value_of_t_or_exit("remote-port");
+                let settings = openvpn::ShadowsocksProxySettings {
+                    peer: SocketAddr::new(remote_ip, remote_port),
+                    password: args.value_of_t_or_exit("password"),
+                    cipher: args.value_of_t_or_exit("cipher"),
+                    #[cfg(target_os = "linux")]
+                    fwmark: None,
+                };
+                (
+                    args.value_of_t_or_exit("name"),
+                    AccessMethod::Shadowsocks(settings),
+                )
+            }
+            Some(("socks5", args)) => {
+                let remote_ip = args.value_of_t_or_exit("remote-ip");
+                let remote_port = args.value_of_t_or_exit("remote-port");
+                let auth = match (args.value_of("username"), args.value_of("password")) {
+                    (Some(username), Some(password)) => Some(openvpn::ProxyAuth {
+                        username: username.to_owned(),
+                        password: password.to_owned(),
+                    }),
+                    _ => None,
+                };
+                let settings = openvpn::RemoteProxySettings {
+                    address: SocketAddr::new(remote_ip, remote_port),
+                    auth,
+                };
+                (
+                    args.value_of_t_or_exit("name"),
+                    AccessMethod::Socks5(settings),
+                )
+            }
+            _ => unreachable!("unhandled access method type"),
+        };
+        let access_method = ApiAccessMethod::new(name, access_method);
+
+        let mut rpc = new_rpc_client().await?;
+        rpc.add_api_access_method(types::ApiAccessMethod::from(&access_method))
+            .await?;
+        println_text!("Added API access method \"{}\"", access_method.name);
+        Ok(())
+    }
+
+    async fn remove(name: String) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.remove_api_access_method(name.clone()).await?;
+        println_text!("Removed API access method \"{}\"", name);
+        Ok(())
+    }
+
+    async fn set_enabled(name: String, enabled: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_api_access_method_enabled(types::ApiAccessMethodToggle {
+            name: name.clone(),
+            enabled,
+        })
+        .await?;
+        println_text!(
+            "{} API access method \"{}\"",
+            if enabled { "Enabled" } else { "Disabled" },
+            name
+        );
+        Ok(())
+    }
+
+    async fn test(name: String) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        println_text!("Testing API access method \"{}\"...", name);
+        let reachable = rpc.test_api_access_method(name).await?.into_inner();
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "reachable": reachable }));
+        }
+        if reachable {
+            println_text!("The API is reachable");
+            Ok(())
+        } else {
+            Err(Error::CommandFailed(
+                "Failed to reach the API using the access method",
+            ))
+        }
+    }
+
+    async fn diagnostics() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let diagnostics = rpc.get_api_diagnostics(()).await?.into_inner();
+        let diagnostics = ApiDiagnostics::from(diagnostics);
+        if format::json_output() {
+            format::print_json(&diagnostics);
+        } else {
+            print!("{}", diagnostics);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{format, new_rpc_client, Command, Error, Result};
+use mullvad_types::audit_log::AuditLogEntry;
+
+pub struct AuditLog;
+
+#[mullvad_management_interface::async_trait]
+impl Command for AuditLog {
+    fn name(&self) -> &'static str {
+        "audit-log"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about("Show the commands that clients have sent to the daemon")
+            .arg(
+                clap::Arg::new("limit")
+                    .help("Only show this many of the most recent entries")
+                    .long("limit")
+                    .short('n')
+                    .takes_value(true),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        let limit = if matches.is_present("limit") {
+            matches.value_of_t_or_exit::<u32>("limit")
+        } else {
+            0
+        };
+        let mut rpc = new_rpc_client().await?;
+        let entries = rpc
+            .get_audit_log(limit)
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to read the audit log", error))?
+            .into_inner()
+            .entries;
+        let entries = entries
+            .into_iter()
+            .map(|entry| AuditLogEntry::try_from(entry).expect("invalid audit log entry"));
+        if format::json_output() {
+            format::print_json(&entries.collect::<Vec<_>>());
+            return Ok(());
+        }
+        let entries: Vec<_> = entries.collect();
+        if entries.is_empty() {
+            println!("The audit log is empty");
+        }
+        for entry in entries {
+            print_entry(&entry);
+        }
+        Ok(())
+    }
+}
+
+fn print_entry(entry: &AuditLogEntry) {
+    let uid = entry
+        .uid
+        .map(|uid| uid.to_string())
+        .unwrap_or_else(|| "unknown".to_owned());
+    let pid = entry
+        .pid
+        .map(|pid| pid.to_string())
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!(
+        "{} uid={} pid={} {}",
+        entry
+            .timestamp
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S"),
+        uid,
+        pid,
+        entry.command
+    );
+    for change in &entry.changes {
+        println!(
+            "    {}: {} -> {}",
+            change.setting, change.old_value, change.new_value
+        );
+    }
+}
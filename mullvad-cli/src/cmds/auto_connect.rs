@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
 
 pub struct AutoConnect;
 
@@ -40,13 +40,17 @@ impl AutoConnect {
     async fn set(&self, auto_connect: bool) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.set_auto_connect(auto_connect).await?;
-        println!("Changed auto-connect setting");
+        println_text!("Changed auto-connect setting");
         Ok(())
     }
 
     async fn get(&self) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let auto_connect = rpc.get_settings(()).await?.into_inner().auto_connect;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "auto_connect": auto_connect }));
+            return Ok(());
+        }
         println!("Autoconnect: {}", if auto_connect { "on" } else { "off" });
         Ok(())
     }
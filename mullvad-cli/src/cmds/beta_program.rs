@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Error, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
 
 pub struct BetaProgram;
 
@@ -29,6 +29,12 @@ impl Command for BetaProgram {
             Some(("get", _)) => {
                 let mut rpc = new_rpc_client().await?;
                 let settings = rpc.get_settings(()).await?.into_inner();
+                if format::json_output() {
+                    format::print_json(&serde_json::json!({
+                        "show_beta_releases": settings.show_beta_releases
+                    }));
+                    return Ok(());
+                }
                 let enabled_str = if settings.show_beta_releases {
                     "on"
                 } else {
@@ -50,7 +56,7 @@ impl Command for BetaProgram {
                 let mut rpc = new_rpc_client().await?;
                 rpc.set_show_beta_releases(enable).await?;
 
-                println!("Beta program: {}", enable_str);
+                println_text!("Beta program: {}", enable_str);
                 Ok(())
             }
             _ => {
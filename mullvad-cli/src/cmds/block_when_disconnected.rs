@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
 
 pub struct BlockWhenDisconnected;
 
@@ -44,7 +44,7 @@ impl BlockWhenDisconnected {
         let mut rpc = new_rpc_client().await?;
         rpc.set_block_when_disconnected(block_when_disconnected)
             .await?;
-        println!("Changed lockdown mode setting");
+        println_text!("Changed lockdown mode setting");
         Ok(())
     }
 
@@ -55,6 +55,12 @@ impl BlockWhenDisconnected {
             .await?
             .into_inner()
             .block_when_disconnected;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({
+                "block_when_disconnected": block_when_disconnected
+            }));
+            return Ok(());
+        }
         println!(
             "Network traffic will be {} when the VPN is disconnected",
             if block_when_disconnected {
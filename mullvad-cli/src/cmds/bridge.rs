@@ -1,8 +1,11 @@
-use crate::{location, new_rpc_client, Command, Error, Result};
+use crate::{format, location, new_rpc_client, Command, Error, Result};
 
 use mullvad_management_interface::types;
-use mullvad_types::relay_constraints::{
-    BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
+use mullvad_types::{
+    relay_constraints::{
+        BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
+    },
+    relay_list::RelayListCountry,
 };
 use talpid_types::net::openvpn::{self, SHADOWSOCKS_CIPHERS};
 
@@ -214,10 +217,15 @@ impl Bridge {
         let mut rpc = new_rpc_client().await?;
         let settings = rpc.get_settings(()).await?.into_inner();
         let bridge_settings = BridgeSettings::try_from(settings.bridge_settings.unwrap()).unwrap();
-        println!(
-            "Bridge state: {}",
-            BridgeState::try_from(settings.bridge_state.unwrap()).unwrap()
-        );
+        let bridge_state = BridgeState::try_from(settings.bridge_state.unwrap()).unwrap();
+        if format::json_output() {
+            format::print_json(&serde_json::json!({
+                "bridge_state": bridge_state,
+                "bridge_settings": bridge_settings,
+            }));
+            return Ok(());
+        }
+        println!("Bridge state: {}", bridge_state);
         match bridge_settings {
             BridgeSettings::Custom(proxy) => match proxy {
                 openvpn::ProxySettings::Local(local_proxy) => Self::print_local_proxy(&local_proxy),
@@ -400,7 +408,7 @@ impl Bridge {
             unreachable!("unhandled proxy type");
         }
 
-        println!("proxy details have been updated");
+        println_text!("proxy details have been updated");
         Ok(())
     }
 
@@ -461,6 +469,15 @@ impl Bridge {
         }
 
         countries.sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
+        if format::json_output() {
+            let countries: Vec<_> = countries
+                .into_iter()
+                .map(RelayListCountry::try_from)
+                .collect::<std::result::Result<_, _>>()
+                .expect("invalid relay list");
+            format::print_json(&countries);
+            return Ok(());
+        }
         for mut country in countries {
             country
                 .cities
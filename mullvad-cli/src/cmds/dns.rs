@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
 use mullvad_management_interface::types;
 use mullvad_types::settings::{DnsOptions, DnsState};
 use std::{convert::TryInto, net::IpAddr};
@@ -121,7 +121,7 @@ impl Dns {
             ..settings.tunnel_options.unwrap().dns_options.unwrap()
         })
         .await?;
-        println!("Updated DNS settings");
+        println_text!("Updated DNS settings");
         Ok(())
     }
 
@@ -140,7 +140,7 @@ impl Dns {
             ..settings.tunnel_options.unwrap().dns_options.unwrap()
         })
         .await?;
-        println!("Updated DNS settings");
+        println_text!("Updated DNS settings");
         Ok(())
     }
 
@@ -157,6 +157,11 @@ impl Dns {
             .try_into()
             .unwrap();
 
+        if format::json_output() {
+            format::print_json(&options);
+            return Ok(());
+        }
+
         match options.state {
             DnsState::Default => {
                 println!("Custom DNS: no");
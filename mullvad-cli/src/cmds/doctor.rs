@@ -0,0 +1,57 @@
+use crate::{format, new_rpc_client, Command, Error, Result};
+use mullvad_types::diagnostics::{CheckStatus, DiagnosticCheck};
+
+pub struct Doctor;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Doctor {
+    fn name(&self) -> &'static str {
+        "doctor"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about("Check the system for common problems with the tunnel")
+            .long_about(
+                "Check the firewall, routing, DNS, split tunneling, WireGuard, API access \
+                 methods and the system clock for common problems, and suggest how to fix \
+                 them. The exit code is 1 if any check fails.",
+            )
+    }
+
+    async fn run(&self, _: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let checks: Vec<_> = rpc
+            .run_diagnostics(())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to run diagnostics", error))?
+            .into_inner()
+            .checks
+            .into_iter()
+            .map(|check| DiagnosticCheck::try_from(check).expect("invalid diagnostic check"))
+            .collect();
+        if format::json_output() {
+            format::print_json(&checks);
+        } else {
+            for check in &checks {
+                print_check(check);
+            }
+        }
+        if checks.iter().any(|check| check.status == CheckStatus::Fail) {
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+}
+
+fn print_check(check: &DiagnosticCheck) {
+    let status = match check.status {
+        CheckStatus::Pass => "PASS",
+        CheckStatus::Fail => "FAIL",
+        CheckStatus::Skipped => "SKIP",
+    };
+    println!("[{}] {}: {}", status, check.name, check.details);
+    if let Some(remediation) = &check.remediation {
+        println!("       {}", remediation);
+    }
+}
@@ -0,0 +1,48 @@
+use super::tunnel::write_private_file;
+use crate::{new_rpc_client, Command, Error, Result};
+use std::path::Path;
+
+pub struct ExportSettings;
+
+#[mullvad_management_interface::async_trait]
+impl Command for ExportSettings {
+    fn name(&self) -> &'static str {
+        "export-settings"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about("Export the settings as a bundle that can be imported on another machine")
+            .arg(
+                clap::Arg::new("include-secrets")
+                    .help(
+                        "Include custom relays, custom bridges and API access methods that \
+                        contain passwords or keys",
+                    )
+                    .long("include-secrets"),
+            )
+            .arg(
+                clap::Arg::new("file")
+                    .help("Write the bundle to this file instead of standard output")
+                    .allow_invalid_utf8(true),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        let include_secrets = matches.is_present("include-secrets");
+        let mut rpc = new_rpc_client().await?;
+        let bundle = rpc
+            .export_settings(include_secrets)
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to export settings", error))?
+            .into_inner();
+        match matches.value_of_os("file") {
+            Some(path) => {
+                write_private_file(path, &bundle).map_err(Error::WriteSettingsBundle)?;
+                println_text!("Wrote settings to {}", Path::new(path).display());
+            }
+            None => println!("{}", bundle),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use std::{
+    fs,
+    io::{self, Read},
+};
+
+pub struct ImportSettings;
+
+#[mullvad_management_interface::async_trait]
+impl Command for ImportSettings {
+    fn name(&self) -> &'static str {
+        "import-settings"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about("Replace the settings with ones created by export-settings")
+            .arg(
+                clap::Arg::new("file")
+                    .help("File to read the bundle from. Use '-' to read from standard input")
+                    .required(true)
+                    .allow_invalid_utf8(true),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        let path = matches.value_of_os("file").unwrap();
+        let bundle = if path == "-" {
+            let mut bundle = String::new();
+            io::stdin()
+                .read_to_string(&mut bundle)
+                .map_err(Error::ReadSettingsBundle)?;
+            bundle
+        } else {
+            fs::read_to_string(path).map_err(Error::ReadSettingsBundle)?
+        };
+
+        let mut rpc = new_rpc_client().await?;
+        rpc.import_settings(bundle)
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to import settings", error))?;
+        println_text!("Imported settings");
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
 
 pub struct Lan;
 
@@ -40,13 +40,17 @@ impl Lan {
     async fn set(&self, allow_lan: bool) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.set_allow_lan(allow_lan).await?;
-        println!("Changed local network sharing setting");
+        println_text!("Changed local network sharing setting");
         Ok(())
     }
 
     async fn get(&self) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let allow_lan = rpc.get_settings(()).await?.into_inner().allow_lan;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "allow_lan": allow_lan }));
+            return Ok(());
+        }
         println!(
             "Local network sharing setting: {}",
             if allow_lan { "allow" } else { "block" }
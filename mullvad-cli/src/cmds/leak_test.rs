@@ -0,0 +1,65 @@
+use crate::{format, new_rpc_client, Command, Error, Result};
+use talpid_types::leak_test::{ProbeOutcome, ProbeResult};
+
+pub struct LeakTest;
+
+#[mullvad_management_interface::async_trait]
+impl Command for LeakTest {
+    fn name(&self) -> &'static str {
+        "leak-test"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about("Check that the firewall blocks traffic outside the tunnel")
+            .long_about(
+                "Send DNS, IPv4 and IPv6 probes out of the physical interface, bypassing the \
+                 tunnel, and check that the firewall blocks them. A probe is also sent to the \
+                 default gateway, which should only get through if local network sharing is \
+                 enabled. Only works while connected. The exit code is 1 if a probe was not \
+                 handled as expected.",
+            )
+    }
+
+    async fn run(&self, _: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let probes: Vec<_> = rpc
+            .run_leak_test(())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to run the leak test", error))?
+            .into_inner()
+            .probes
+            .into_iter()
+            .map(|probe| ProbeResult::try_from(probe).expect("invalid leak test probe"))
+            .collect();
+        if format::json_output() {
+            format::print_json(&probes);
+        } else {
+            for probe in &probes {
+                print_probe(probe);
+            }
+        }
+        if probes.iter().any(|probe| probe.passed() == Some(false)) {
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+}
+
+fn print_probe(probe: &ProbeResult) {
+    let status = match probe.passed() {
+        Some(true) => "PASS",
+        Some(false) if probe.should_block => "LEAK",
+        Some(false) => "FAIL",
+        None => "SKIP",
+    };
+    let outcome = match &probe.outcome {
+        ProbeOutcome::Blocked => "blocked".to_owned(),
+        ProbeOutcome::Sent => "sent".to_owned(),
+        ProbeOutcome::Error(error) => format!("not sent: {}", error),
+    };
+    println!(
+        "[{}] {} probe to {}: {}",
+        status, probe.kind, probe.target, outcome
+    );
+}
@@ -0,0 +1,62 @@
+use crate::{format, new_rpc_client, Command, Result};
+
+pub struct Lockdown;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Lockdown {
+    fn name(&self) -> &'static str {
+        "lockdown"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Control if network access should remain blocked while the system service is \
+                not running, for example after a crash or during an upgrade. The block is only \
+                lifted by turning this off",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(clap::App::new("on").about("Enable persistent lockdown"))
+            .subcommand(
+                clap::App::new("off")
+                    .about("Disable persistent lockdown and clear the persistent firewall block"),
+            )
+            .subcommand(clap::App::new("get").about("Display the current lockdown setting"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("on", _)) => self.set(true).await,
+            Some(("off", _)) => self.set(false).await,
+            Some(("get", _)) => self.get().await,
+            _ => unreachable!("No lockdown command given"),
+        }
+    }
+}
+
+impl Lockdown {
+    async fn set(&self, persistent_lockdown: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_persistent_lockdown(persistent_lockdown).await?;
+        println_text!("Changed lockdown setting");
+        Ok(())
+    }
+
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let persistent_lockdown = rpc.get_settings(()).await?.into_inner().persistent_lockdown;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "persistent_lockdown": persistent_lockdown }));
+            return Ok(());
+        }
+        println!(
+            "Network traffic will be {} while the system service is stopped",
+            if persistent_lockdown {
+                "blocked"
+            } else {
+                "allowed"
+            }
+        );
+        Ok(())
+    }
+}
@@ -4,6 +4,12 @@ use std::collections::HashMap;
 mod account;
 pub use self::account::Account;
 
+mod api_access;
+pub use self::api_access::ApiAccess;
+
+mod audit_log;
+pub use self::audit_log::AuditLog;
+
 mod auto_connect;
 pub use self::auto_connect::AutoConnect;
 
@@ -25,9 +31,26 @@ pub use self::disconnect::Disconnect;
 mod dns;
 pub use self::dns::Dns;
 
+mod doctor;
+pub use self::doctor::Doctor;
+
+mod export_settings;
+pub use self::export_settings::ExportSettings;
+
+mod import_settings;
+pub use self::import_settings::ImportSettings;
+
 mod lan;
 pub use self::lan::Lan;
 
+#[cfg(target_os = "linux")]
+mod leak_test;
+#[cfg(target_os = "linux")]
+pub use self::leak_test::LeakTest;
+
+mod lockdown;
+pub use self::lockdown::Lockdown;
+
 mod obfuscation;
 pub use self::obfuscation::Obfuscation;
 
@@ -58,6 +81,8 @@ pub use self::version::Version;
 pub fn get_commands() -> HashMap<&'static str, Box<dyn Command>> {
     let commands: Vec<Box<dyn Command>> = vec![
         Box::new(Account),
+        Box::new(ApiAccess),
+        Box::new(AuditLog),
         Box::new(AutoConnect),
         Box::new(BetaProgram),
         Box::new(BlockWhenDisconnected),
@@ -65,8 +90,14 @@ pub fn get_commands() -> HashMap<&'static str, Box<dyn Command>> {
         Box::new(Connect),
         Box::new(Disconnect),
         Box::new(Dns),
+        Box::new(Doctor),
+        Box::new(ExportSettings),
+        Box::new(ImportSettings),
         Box::new(Reconnect),
         Box::new(Lan),
+        #[cfg(target_os = "linux")]
+        Box::new(LeakTest),
+        Box::new(Lockdown),
         Box::new(Obfuscation),
         Box::new(Relay),
         Box::new(Reset),
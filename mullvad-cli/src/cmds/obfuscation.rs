@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
 
 use mullvad_management_interface::{types as grpc_types, ManagementServiceClient};
 
@@ -71,6 +71,10 @@ impl Obfuscation {
     async fn handle_get() -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let obfuscation_settings = Self::get_obfuscation_settings(&mut rpc).await?;
+        if format::json_output() {
+            format::print_json(&obfuscation_settings);
+            return Ok(());
+        }
         println!(
             "Obfuscation mode: {}",
             obfuscation_settings.selected_obfuscation
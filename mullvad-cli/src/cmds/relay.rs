@@ -1,15 +1,20 @@
-use crate::{location, new_rpc_client, Command, Error, Result};
+use crate::{format, location, new_rpc_client, relay_picker, Command, Error, Result};
 use itertools::Itertools;
 use std::{
     convert::TryFrom,
+    fs,
     io::{self, BufRead},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     str::FromStr,
 };
 
 use mullvad_management_interface::{types, ManagementServiceClient};
-use mullvad_types::relay_constraints::{Constraint, RelaySettings};
-use talpid_types::net::all_of_the_internet;
+use mullvad_types::{
+    relay_constraints::{Constraint, Ownership, Providers, RelayConstraints, RelaySettings},
+    relay_list::{RelayList, RelayListCountry},
+    wg_quick,
+};
+use talpid_types::net::{all_of_the_internet, TunnelType};
 
 pub struct Relay;
 
@@ -37,27 +42,27 @@ impl Command for Relay {
                                 .arg(
                                     clap::Arg::new("host")
                                         .help("Hostname or IP")
-                                        .required(true),
+                                        .required_unless_present("from-file"),
                                 )
                                 .arg(
                                     clap::Arg::new("port")
                                         .help("Remote network port")
-                                        .required(true),
+                                        .required_unless_present("from-file"),
                                 )
                                 .arg(
                                     clap::Arg::new("peer-pubkey")
                                         .help("Base64 encoded peer public key")
-                                        .required(true),
+                                        .required_unless_present("from-file"),
                                 )
                                 .arg(
                                     clap::Arg::new("v4-gateway")
                                         .help("IPv4 gateway address")
-                                        .required(true),
+                                        .required_unless_present("from-file"),
                                 )
                                 .arg(
                                     clap::Arg::new("addr")
                                         .help("Local address of wireguard tunnel")
-                                        .required(true)
+                                        .required_unless_present("from-file")
                                         .multiple_values(true),
                                 )
                                 .arg(
@@ -66,6 +71,18 @@ impl Command for Relay {
                                         .long("v6-gateway")
                                         .takes_value(true),
                                 )
+                                .arg(
+                                    clap::Arg::new("from-file")
+                                        .help("Read the relay, DNS and MTU settings from a \
+                                               wg-quick configuration file. The IPv4 gateway is \
+                                               assumed to be the first IPv4 DNS server.")
+                                        .long("from-file")
+                                        .takes_value(true)
+                                        .allow_invalid_utf8(true)
+                                        .conflicts_with_all(&[
+                                            "host", "port", "peer-pubkey", "v4-gateway", "addr",
+                                        ]),
+                                )
                             )
                             .subcommand(clap::App::new("openvpn")
                                 .arg(
@@ -201,6 +218,34 @@ impl Command for Relay {
                 clap::App::new("update")
                     .about("Update the list of available countries and cities"),
             )
+            .subcommand(
+                clap::App::new("pick")
+                    .about("Interactively search for a country, city or relay and select it. \
+                           Relays are filtered by the current provider, ownership and tunnel \
+                           protocol constraints, unless other filters are given. Given filters \
+                           are also saved as constraints.")
+                    .arg(
+                        clap::Arg::new("provider")
+                            .help("Only show relays hosted by these providers, or 'any'")
+                            .long("provider")
+                            .takes_value(true)
+                            .multiple_values(true),
+                    )
+                    .arg(
+                        clap::Arg::new("ownership")
+                            .help("Only show relays with this ownership")
+                            .long("ownership")
+                            .possible_values(["any", "owned", "rented"])
+                            .takes_value(true),
+                    )
+                    .arg(
+                        clap::Arg::new("tunnel protocol")
+                            .help("Only show relays that support this tunnel protocol")
+                            .long("tunnel-protocol")
+                            .possible_values(["any", "wireguard", "openvpn"])
+                            .takes_value(true),
+                    ),
+            )
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -212,6 +257,8 @@ impl Command for Relay {
             self.list().await
         } else if matches.subcommand_matches("update").is_some() {
             self.update().await
+        } else if let Some(pick_matches) = matches.subcommand_matches("pick") {
+            self.pick(pick_matches).await
         } else {
             unreachable!("No relay command given");
         }
@@ -224,7 +271,7 @@ impl Relay {
         rpc.update_relay_settings(update)
             .await
             .map_err(|error| Error::RpcFailedExt("Failed to update relay settings", error))?;
-        println!("Relay constraints updated");
+        println_text!("Relay constraints updated");
         Ok(())
     }
 
@@ -257,6 +304,9 @@ impl Relay {
     async fn set_custom(&self, matches: &clap::ArgMatches) -> Result<()> {
         let custom_endpoint = match matches.subcommand() {
             Some(("openvpn", openvpn_matches)) => Self::read_custom_openvpn_relay(openvpn_matches),
+            Some(("wireguard", wg_matches)) if wg_matches.is_present("from-file") => {
+                return self.set_custom_wireguard_from_file(wg_matches).await;
+            }
             Some(("wireguard", wg_matches)) => Self::read_custom_wireguard_relay(wg_matches),
             _ => unreachable!("No set relay command given"),
         };
@@ -267,6 +317,64 @@ impl Relay {
         .await
     }
 
+    async fn set_custom_wireguard_from_file(&self, matches: &clap::ArgMatches) -> Result<()> {
+        let path = matches.value_of_os("from-file").unwrap();
+        let ipv6_gateway = match matches.value_of_t::<Ipv6Addr>("v6-gateway") {
+            Ok(gateway) => Some(gateway),
+            Err(e) => match e.kind {
+                clap::ErrorKind::ArgumentNotFound => None,
+                _ => e.exit(),
+            },
+        };
+
+        let contents = fs::read_to_string(path).map_err(Error::ReadWireguardConfig)?;
+        let config = wg_quick::parse(&contents, None, ipv6_gateway)?;
+        let endpoint = config.custom_tunnel_endpoint();
+
+        let mut rpc = new_rpc_client().await?;
+        let dns_options = if config.dns_servers.is_empty() {
+            None
+        } else {
+            let settings = rpc.get_settings(()).await?.into_inner();
+            Some(types::DnsOptions {
+                state: types::dns_options::DnsState::Custom as i32,
+                custom_options: Some(types::CustomDnsOptions {
+                    addresses: config
+                        .dns_servers
+                        .iter()
+                        .map(|addr| addr.to_string())
+                        .collect(),
+                }),
+                ..settings.tunnel_options.unwrap().dns_options.unwrap()
+            })
+        };
+
+        // Apply everything in one update, so that a failure cannot leave half of it applied
+        rpc.apply_settings_patch(types::SettingsPatch {
+            relay_settings: Some(types::RelaySettingsUpdate {
+                r#type: Some(types::relay_settings_update::Type::Custom(
+                    types::CustomRelaySettings {
+                        host: endpoint.host,
+                        config: Some(types::ConnectionConfig::from(endpoint.config)),
+                    },
+                )),
+            }),
+            dns_options,
+            wireguard_mtu: config.mtu.map(u32::from),
+            ..Default::default()
+        })
+        .await
+        .map_err(|error| Error::RpcFailedExt("Failed to import the WireGuard config", error))?;
+        println_text!("Relay constraints updated");
+        if config.mtu.is_some() {
+            println_text!("Wireguard MTU has been updated");
+        }
+        if !config.dns_servers.is_empty() {
+            println_text!("Updated DNS settings");
+        }
+        Ok(())
+    }
+
     fn read_custom_openvpn_relay(matches: &clap::ArgMatches) -> types::CustomRelaySettings {
         let host = matches.value_of_t_or_exit("host");
         let port = matches.value_of_t_or_exit("port");
@@ -308,7 +416,7 @@ impl Relay {
             },
         };
         let mut private_key_str = String::new();
-        println!("Reading private key from standard input");
+        println_text!("Reading private key from standard input");
         let _ = io::stdin().lock().read_line(&mut private_key_str);
         if private_key_str.trim().is_empty() {
             eprintln!("Expected to read private key from standard input");
@@ -336,6 +444,7 @@ impl Relay {
                                 .collect(),
                             endpoint: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port)
                                 .to_string(),
+                            psk: vec![],
                         }),
                         ipv4_gateway: ipv4_gateway.to_string(),
                         ipv6_gateway: ipv6_gateway
@@ -401,9 +510,11 @@ impl Relay {
         };
 
         if let Some(location) = find_relay() {
-            println!(
+            println_text!(
                 "Setting location constraint to {} in {}, {}",
-                location.hostname, location.city, location.country
+                location.hostname,
+                location.city,
+                location.country
             );
 
             self.update_constraints(types::RelaySettingsUpdate {
@@ -546,7 +657,7 @@ impl Relay {
                 Ok(settings.openvpn_constraints.unwrap())
             }
             types::relay_settings::Endpoint::Custom(_settings) => {
-                println!("Clearing custom tunnel constraints");
+                println_text!("Clearing custom tunnel constraints");
                 Ok(types::OpenvpnConstraints::default())
             }
         }
@@ -592,22 +703,6 @@ impl Relay {
         if let Some(entry) = matches.values_of("entry location") {
             wireguard_constraints.entry_location = parse_entry_location_constraint(entry);
             let use_multihop = wireguard_constraints.entry_location.is_some();
-            if use_multihop {
-                let use_pq_safe_psk = rpc
-                    .get_settings(())
-                    .await?
-                    .into_inner()
-                    .tunnel_options
-                    .unwrap()
-                    .wireguard
-                    .unwrap()
-                    .use_pq_safe_psk;
-                if use_pq_safe_psk {
-                    return Err(Error::CommandFailed(
-                        "Quantum resistant tunnels do not work when multihop is enabled",
-                    ));
-                }
-            }
             wireguard_constraints.use_multihop = use_multihop;
         }
 
@@ -639,7 +734,7 @@ impl Relay {
                 Ok(settings.wireguard_constraints.unwrap())
             }
             types::relay_settings::Endpoint::Custom(_settings) => {
-                println!("Clearing custom tunnel constraints");
+                println_text!("Clearing custom tunnel constraints");
                 Ok(types::WireguardConstraints::default())
             }
         }
@@ -676,10 +771,12 @@ impl Relay {
             .relay_settings
             .unwrap();
 
-        println!(
-            "Current constraints: {}",
-            RelaySettings::try_from(relay_settings).unwrap()
-        );
+        let relay_settings = RelaySettings::try_from(relay_settings).unwrap();
+        if format::json_output() {
+            format::print_json(&relay_settings);
+            return Ok(());
+        }
+        println!("Current constraints: {}", relay_settings);
 
         Ok(())
     }
@@ -687,6 +784,15 @@ impl Relay {
     async fn list(&self) -> Result<()> {
         let mut countries = Self::get_filtered_relays().await?;
         countries.sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
+        if format::json_output() {
+            let countries: Vec<_> = countries
+                .into_iter()
+                .map(RelayListCountry::try_from)
+                .collect::<std::result::Result<_, _>>()
+                .expect("invalid relay list");
+            format::print_json(&countries);
+            return Ok(());
+        }
         for mut country in countries {
             country
                 .cities
@@ -730,10 +836,112 @@ impl Relay {
 
     async fn update(&self) -> Result<()> {
         new_rpc_client().await?.update_relay_locations(()).await?;
-        println!("Updating relay list in the background...");
+        println_text!("Updating relay list in the background...");
         Ok(())
     }
 
+    async fn pick(&self, matches: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let relay_settings = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .relay_settings
+            .unwrap();
+        let constraints = match RelaySettings::try_from(relay_settings).unwrap() {
+            RelaySettings::Normal(constraints) => constraints,
+            RelaySettings::CustomTunnelEndpoint(_) => RelayConstraints::default(),
+        };
+        let mut update = types::NormalRelaySettingsUpdate::default();
+        let mut filters = relay_picker::Filters {
+            providers: constraints.providers,
+            ownership: constraints.ownership,
+            tunnel_type: constraints.tunnel_protocol,
+        };
+
+        if let Some(providers) = matches.values_of("provider") {
+            let providers: Vec<String> = providers
+                .filter(|provider| *provider != "any")
+                .map(String::from)
+                .collect();
+            filters.providers = match Providers::new(providers.clone().into_iter()) {
+                Ok(providers) => Constraint::Only(providers),
+                Err(_) => Constraint::Any,
+            };
+            update.providers = Some(types::ProviderUpdate { providers });
+        }
+        if let Some(ownership) = matches.value_of("ownership") {
+            filters.ownership = match ownership {
+                "owned" => Constraint::Only(Ownership::MullvadOwned),
+                "rented" => Constraint::Only(Ownership::Rented),
+                _ => Constraint::Any,
+            };
+            update.ownership = Some(types::OwnershipUpdate {
+                ownership: parse_ownership_constraint(ownership) as i32,
+            });
+        }
+        if let Some(tunnel_protocol) = matches.value_of("tunnel protocol") {
+            let (tunnel_type, proto_tunnel_type) = match tunnel_protocol {
+                "wireguard" => (
+                    Constraint::Only(TunnelType::Wireguard),
+                    Some(types::TunnelType::Wireguard),
+                ),
+                "openvpn" => (
+                    Constraint::Only(TunnelType::OpenVpn),
+                    Some(types::TunnelType::Openvpn),
+                ),
+                _ => (Constraint::Any, None),
+            };
+            filters.tunnel_type = tunnel_type;
+            update.tunnel_type = Some(types::TunnelTypeUpdate {
+                tunnel_type: proto_tunnel_type.map(|tunnel_type| types::TunnelTypeConstraint {
+                    tunnel_type: tunnel_type as i32,
+                }),
+            });
+        }
+
+        let relay_list = rpc
+            .get_relay_locations(())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to obtain relay locations", error))?
+            .into_inner();
+        let mut countries = RelayList::try_from(relay_list)
+            .expect("invalid relay list")
+            .countries;
+        countries.sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
+        for country in &mut countries {
+            country
+                .cities
+                .sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
+            for city in &mut country.cities {
+                city.relays
+                    .sort_by(|r1, r2| natord::compare_ignore_case(&r1.hostname, &r2.hostname));
+            }
+        }
+
+        let entries = relay_picker::entries(&countries, &filters);
+        if entries.is_empty() {
+            return Err(Error::CommandFailed("No relays match the filters"));
+        }
+        let entry = match relay_picker::pick(&entries).map_err(Error::RelayPicker)? {
+            Some(entry) => entry,
+            None => {
+                println_text!("No location was selected");
+                return Ok(());
+            }
+        };
+        if !entry.active {
+            eprintln!("Warning: The selected location has no active relays.");
+        }
+        println_text!("Setting location constraint to {}", entry.location);
+        update.location = Some(types::RelayLocation::from(entry.location));
+
+        self.update_constraints(types::RelaySettingsUpdate {
+            r#type: Some(types::relay_settings_update::Type::Normal(update)),
+        })
+        .await
+    }
+
     async fn get_filtered_relays() -> Result<Vec<types::RelayListCountry>> {
         let mut rpc = new_rpc_client().await?;
         let relay_list = rpc
@@ -844,7 +1052,7 @@ fn parse_transport_port(
     };
     if port.is_only() && protocol.is_any() && !matches.is_present("port") {
         // Reset the port if the transport protocol is set to any.
-        println!("The port constraint was set to 'any'");
+        println_text!("The port constraint was set to 'any'");
         port = Constraint::Any;
     }
     match (port, protocol) {
@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Error, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
 use std::io::stdin;
 
 pub struct Reset;
@@ -19,7 +19,7 @@ impl Command for Reset {
                 .await
                 .map_err(|error| Error::RpcFailedExt("FAILED TO PERFORM FACTORY RESET", error))?;
             #[cfg(target_os = "linux")]
-            println!("If you're running systemd, to remove all logs, you must use journalctl");
+            println_text!("If you're running systemd, to remove all logs, you must use journalctl");
         }
         Ok(())
     }
@@ -27,7 +27,7 @@ impl Command for Reset {
 
 impl Reset {
     fn receive_confirmation() -> bool {
-        println!("Are you sure you want to disconnect, log out, delete all settings, logs and cache files for the Mullvad VPN system service? [Yes/No (default)]");
+        Self::prompt("Are you sure you want to disconnect, log out, delete all settings, logs and cache files for the Mullvad VPN system service? [Yes/No (default)]");
         loop {
             let mut buf = String::new();
             if let Err(e) = stdin().read_line(&mut buf) {
@@ -37,8 +37,18 @@ impl Reset {
             match buf.trim() {
                 "Yes" => return true,
                 "No" | "no" | "" => return false,
-                _ => println!("Unexpected response. Please enter \"Yes\" or \"No\""),
+                _ => Self::prompt("Unexpected response. Please enter \"Yes\" or \"No\""),
             }
         }
     }
+
+    /// Prints a question for the user. With `--json`, it is printed to stderr so that stdout
+    /// only contains JSON.
+    fn prompt(message: &str) {
+        if format::json_output() {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
 }
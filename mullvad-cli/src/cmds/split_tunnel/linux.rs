@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
 
 pub struct SplitTunnel;
 
@@ -68,6 +68,14 @@ impl SplitTunnel {
                     .get_split_tunnel_processes(())
                     .await?
                     .into_inner();
+                if format::json_output() {
+                    let mut pids = vec![];
+                    while let Some(pid) = pids_stream.message().await? {
+                        pids.push(pid);
+                    }
+                    format::print_json(&pids);
+                    return Ok(());
+                }
                 println!("Excluded PIDs:");
 
                 while let Some(pid) = pids_stream.message().await? {
@@ -1,6 +1,6 @@
 use std::{ffi::OsStr, path::Path};
 
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
 
 pub struct SplitTunnel;
 
@@ -77,6 +77,10 @@ impl SplitTunnel {
                     .unwrap()
                     .apps;
 
+                if format::json_output() {
+                    format::print_json(&paths);
+                    return Ok(());
+                }
                 println!("Excluded applications:");
                 for path in &paths {
                     println!("    {}", path);
@@ -114,6 +118,21 @@ impl SplitTunnel {
                     .await?
                     .into_inner();
 
+                if format::json_output() {
+                    let processes: Vec<_> = processes
+                        .processes
+                        .iter()
+                        .map(|process| {
+                            serde_json::json!({
+                                "pid": process.pid,
+                                "image": process.image,
+                                "inherited": process.inherited,
+                            })
+                        })
+                        .collect();
+                    format::print_json(&processes);
+                    return Ok(());
+                }
                 for process in &processes.processes {
                     let subproc = if process.inherited { "subprocess" } else { "" };
                     println!(
@@ -135,7 +154,7 @@ impl SplitTunnel {
     async fn set(&self, enabled: bool) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.set_split_tunnel_state(enabled).await?;
-        println!("Changed split tunnel setting");
+        println_text!("Changed split tunnel setting");
         Ok(())
     }
 
@@ -148,6 +167,10 @@ impl SplitTunnel {
             .split_tunnel
             .unwrap()
             .enable_exclusions;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "enable_exclusions": enabled }));
+            return Ok(());
+        }
         println!(
             "Split tunnel status: {}",
             if enabled { "on" } else { "off" }
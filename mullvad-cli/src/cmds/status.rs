@@ -2,7 +2,14 @@ use crate::{format, new_rpc_client, Command, Error, Result};
 use mullvad_management_interface::{
     types::daemon_event::Event as EventType, ManagementServiceClient,
 };
-use mullvad_types::{location::GeoIpLocation, states::TunnelState};
+use mullvad_types::{
+    account::AccountExpiryEvent,
+    device::{DeviceEvent, RemoveDeviceEvent},
+    location::GeoIpLocation,
+    relay_list::RelayList,
+    settings::Settings,
+    states::TunnelState,
+};
 
 pub struct Status;
 
@@ -43,6 +50,16 @@ impl Command for Status {
         let mut rpc = new_rpc_client().await?;
         let state = rpc.get_tunnel_state(()).await?.into_inner();
 
+        if format::json_output() {
+            return print_json_status(
+                &mut rpc,
+                state,
+                show_full_location,
+                matches.subcommand_matches("listen").is_some(),
+            )
+            .await;
+        }
+
         if debug {
             println!("Tunnel state: {:#?}", state);
         } else {
@@ -54,6 +71,8 @@ impl Command for Status {
             print_location(&mut rpc).await?;
         }
 
+        print_account_expiry(&mut rpc).await?;
+
         if matches.subcommand_matches("listen").is_some() {
             let mut events = rpc.events_listen(()).await?.into_inner();
 
@@ -103,6 +122,15 @@ impl Command for Status {
                             println!("Remove device event: {:#?}", device);
                         }
                     }
+                    EventType::AccountExpiry(event) => {
+                        if debug {
+                            println!("Account expiry event: {:#?}", event);
+                        } else {
+                            let event = AccountExpiryEvent::try_from(event)
+                                .expect("invalid account expiry event");
+                            println!("{}", event);
+                        }
+                    }
                 }
             }
         }
@@ -111,16 +139,104 @@ impl Command for Status {
     }
 }
 
-async fn print_location(rpc: &mut ManagementServiceClient) -> Result<()> {
-    let location = match rpc.get_current_location(()).await {
-        Ok(response) => GeoIpLocation::try_from(response.into_inner()).expect("invalid geoip data"),
-        Err(status) => {
-            if status.code() == mullvad_management_interface::Code::NotFound {
-                println!("Location data unavailable");
-                return Ok(());
-            } else {
-                return Err(Error::RpcFailed(status));
+/// Prints the status as a JSON object. If `listen` is set, a JSON object is then printed for every
+/// event.
+async fn print_json_status(
+    rpc: &mut ManagementServiceClient,
+    state: mullvad_management_interface::types::TunnelState,
+    show_full_location: bool,
+    listen: bool,
+) -> Result<()> {
+    let state = TunnelState::try_from(state).expect("invalid tunnel state");
+    let mut status = serde_json::json!({
+        "tunnel_state": state,
+        "account_expiry": get_account_expiry(rpc).await?,
+    });
+    if show_full_location {
+        status["location"] = serde_json::json!(get_location(rpc).await?);
+    }
+    format::print_json(&status);
+
+    if !listen {
+        return Ok(());
+    }
+
+    let mut events = rpc.events_listen(()).await?.into_inner();
+    while let Some(event) = events.message().await? {
+        let (event, data) = match event.event.unwrap() {
+            EventType::TunnelState(new_state) => (
+                "tunnel_state",
+                serde_json::json!(TunnelState::try_from(new_state).expect("invalid tunnel state")),
+            ),
+            EventType::Settings(settings) => (
+                "settings",
+                serde_json::json!(Settings::try_from(settings).expect("invalid settings")),
+            ),
+            EventType::RelayList(relay_list) => (
+                "relay_list",
+                serde_json::json!(RelayList::try_from(relay_list).expect("invalid relay list")),
+            ),
+            EventType::VersionInfo(app_version_info) => {
+                ("version_info", format::version_info_json(&app_version_info))
             }
+            EventType::Device(device) => (
+                "device",
+                serde_json::json!(DeviceEvent::try_from(device).expect("invalid device event")),
+            ),
+            EventType::RemoveDevice(device) => (
+                "remove_device",
+                serde_json::json!(
+                    RemoveDeviceEvent::try_from(device).expect("invalid remove device event")
+                ),
+            ),
+            EventType::AccountExpiry(event) => (
+                "account_expiry",
+                serde_json::json!(
+                    AccountExpiryEvent::try_from(event).expect("invalid account expiry event")
+                ),
+            ),
+        };
+        format::print_json(&serde_json::json!({ "event": event, "data": data }));
+    }
+    Ok(())
+}
+
+async fn get_account_expiry(
+    rpc: &mut ManagementServiceClient,
+) -> Result<Option<AccountExpiryEvent>> {
+    match rpc.get_account_expiry_event(()).await {
+        Ok(response) => Ok(Some(
+            AccountExpiryEvent::try_from(response.into_inner())
+                .expect("invalid account expiry event"),
+        )),
+        Err(status) if status.code() == mullvad_management_interface::Code::NotFound => Ok(None),
+        Err(status) => Err(Error::RpcFailed(status)),
+    }
+}
+
+async fn print_account_expiry(rpc: &mut ManagementServiceClient) -> Result<()> {
+    if let Some(event) = get_account_expiry(rpc).await? {
+        println!("{}", event);
+    }
+    Ok(())
+}
+
+async fn get_location(rpc: &mut ManagementServiceClient) -> Result<Option<GeoIpLocation>> {
+    match rpc.get_current_location(()).await {
+        Ok(response) => Ok(Some(
+            GeoIpLocation::try_from(response.into_inner()).expect("invalid geoip data"),
+        )),
+        Err(status) if status.code() == mullvad_management_interface::Code::NotFound => Ok(None),
+        Err(status) => Err(Error::RpcFailed(status)),
+    }
+}
+
+async fn print_location(rpc: &mut ManagementServiceClient) -> Result<()> {
+    let location = match get_location(rpc).await? {
+        Some(location) => location,
+        None => {
+            println!("Location data unavailable");
+            return Ok(());
         }
     };
     if let Some(ipv4) = location.ipv4 {
@@ -1,7 +1,14 @@
-use crate::{new_rpc_client, Command, Error, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
 use mullvad_management_interface::types::{self, Timestamp, TunnelOptions};
 use mullvad_types::wireguard::DEFAULT_ROTATION_INTERVAL;
-use std::{convert::TryFrom, time::Duration};
+use std::{
+    convert::TryFrom,
+    ffi::OsStr,
+    fs,
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
 
 pub struct Tunnel;
 
@@ -38,12 +45,19 @@ fn create_wireguard_subcommand() -> clap::App<'static> {
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(create_wireguard_mtu_subcommand())
         .subcommand(create_wireguard_quantum_resistant_tunnel_subcommand())
-        .subcommand(create_wireguard_keys_subcommand());
+        .subcommand(create_wireguard_keys_subcommand())
+        .subcommand(create_wireguard_export_config_subcommand());
     #[cfg(windows)]
     {
         subcmd.subcommand(create_wireguard_use_wg_nt_subcommand())
     }
-    #[cfg(not(windows))]
+    #[cfg(target_os = "linux")]
+    {
+        subcmd
+            .subcommand(create_wireguard_use_boringtun_subcommand())
+            .subcommand(create_wireguard_network_namespace_subcommand())
+    }
+    #[cfg(not(any(windows, target_os = "linux")))]
     {
         subcmd
     }
@@ -64,6 +78,19 @@ fn create_wireguard_quantum_resistant_tunnel_subcommand() -> clap::App<'static>
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(clap::App::new("get"))
         .subcommand(clap::App::new("set").arg(clap::Arg::new("policy").required(true)))
+        .subcommand(
+            clap::App::new("rotation-interval")
+                .about(
+                    "Manage renegotiation of the quantum-resistant PSK while connected (given in \
+                    minutes)",
+                )
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(clap::App::new("get"))
+                .subcommand(
+                    clap::App::new("reset").about("Only negotiate the PSK once per connection"),
+                )
+                .subcommand(clap::App::new("set").arg(clap::Arg::new("interval").required(true))),
+        )
 }
 
 fn create_wireguard_keys_subcommand() -> clap::App<'static> {
@@ -75,6 +102,22 @@ fn create_wireguard_keys_subcommand() -> clap::App<'static> {
         .subcommand(create_wireguard_keys_rotation_interval_subcommand())
 }
 
+fn create_wireguard_export_config_subcommand() -> clap::App<'static> {
+    clap::App::new("export-config")
+        .about(
+            "Print the current WireGuard connection as a wg-quick config. This includes the \
+            private key of the device, so it must be run as root or as an administrator",
+        )
+        .arg(
+            clap::Arg::new("output")
+                .help("Write the config to this file instead of standard output")
+                .long("output")
+                .short('o')
+                .takes_value(true)
+                .allow_invalid_utf8(true),
+        )
+}
+
 #[cfg(windows)]
 fn create_wireguard_use_wg_nt_subcommand() -> clap::App<'static> {
     clap::App::new("use-wireguard-nt")
@@ -91,6 +134,44 @@ fn create_wireguard_use_wg_nt_subcommand() -> clap::App<'static> {
         )
 }
 
+#[cfg(target_os = "linux")]
+fn create_wireguard_network_namespace_subcommand() -> clap::App<'static> {
+    clap::App::new("network-namespace")
+        .about(
+            "Place the WireGuard interface in a separate network namespace. Only programs started \
+            using mullvad-netns can then reach the tunnel",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("get"))
+        .subcommand(
+            clap::App::new("set").arg(
+                clap::Arg::new("policy")
+                    .required(true)
+                    .takes_value(true)
+                    .possible_values(&["on", "off"]),
+            ),
+        )
+}
+
+#[cfg(target_os = "linux")]
+fn create_wireguard_use_boringtun_subcommand() -> clap::App<'static> {
+    clap::App::new("use-boringtun")
+        .about(
+            "Use the built-in userspace WireGuard implementation instead of the kernel module or \
+            wireguard-go",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("get"))
+        .subcommand(
+            clap::App::new("set").arg(
+                clap::Arg::new("policy")
+                    .required(true)
+                    .takes_value(true)
+                    .possible_values(&["on", "off"]),
+            ),
+        )
+}
+
 fn create_wireguard_keys_rotation_interval_subcommand() -> clap::App<'static> {
     clap::App::new("rotation-interval")
         .about("Manage automatic key rotation (given in hours)")
@@ -172,11 +253,25 @@ impl Tunnel {
                 _ => unreachable!("unhandled command"),
             },
 
+            Some(("export-config", matches)) => {
+                Self::process_wireguard_export_config(matches).await
+            }
+
             Some(("quantum-resistant-tunnel", matches)) => match matches.subcommand() {
                 Some(("get", _)) => Self::process_wireguard_quantum_resistant_tunnel_get().await,
                 Some(("set", matches)) => {
                     Self::process_wireguard_quantum_resistant_tunnel_set(matches).await
                 }
+                Some(("rotation-interval", matches)) => match matches.subcommand() {
+                    Some(("get", _)) => Self::process_wireguard_psk_rotation_interval_get().await,
+                    Some(("set", matches)) => {
+                        Self::process_wireguard_psk_rotation_interval_set(matches).await
+                    }
+                    Some(("reset", _)) => {
+                        Self::process_wireguard_psk_rotation_interval_reset().await
+                    }
+                    _ => unreachable!("unhandled command"),
+                },
                 _ => unreachable!("unhandled command"),
             },
 
@@ -187,6 +282,22 @@ impl Tunnel {
                 _ => unreachable!("unhandled command"),
             },
 
+            #[cfg(target_os = "linux")]
+            Some(("use-boringtun", matches)) => match matches.subcommand() {
+                Some(("get", _)) => Self::process_wireguard_use_boringtun_get().await,
+                Some(("set", matches)) => Self::process_wireguard_use_boringtun_set(matches).await,
+                _ => unreachable!("unhandled command"),
+            },
+
+            #[cfg(target_os = "linux")]
+            Some(("network-namespace", matches)) => match matches.subcommand() {
+                Some(("get", _)) => Self::process_wireguard_network_namespace_get().await,
+                Some(("set", matches)) => {
+                    Self::process_wireguard_network_namespace_set(matches).await
+                }
+                _ => unreachable!("unhandled command"),
+            },
+
             _ => unreachable!("unhandled command"),
         }
     }
@@ -194,6 +305,10 @@ impl Tunnel {
     async fn process_wireguard_mtu_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
         let mtu = tunnel_options.wireguard.unwrap().mtu;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "mtu": Some(mtu).filter(|mtu| *mtu != 0) }));
+            return Ok(());
+        }
         println!(
             "mtu: {}",
             if mtu != 0 {
@@ -209,20 +324,25 @@ impl Tunnel {
         let mtu = matches.value_of_t_or_exit::<u16>("mtu");
         let mut rpc = new_rpc_client().await?;
         rpc.set_wireguard_mtu(mtu as u32).await?;
-        println!("Wireguard MTU has been updated");
+        println_text!("Wireguard MTU has been updated");
         Ok(())
     }
 
     async fn process_wireguard_mtu_unset() -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.set_wireguard_mtu(0).await?;
-        println!("Wireguard MTU has been unset");
+        println_text!("Wireguard MTU has been unset");
         Ok(())
     }
 
     async fn process_wireguard_quantum_resistant_tunnel_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
-        if tunnel_options.wireguard.unwrap().use_pq_safe_psk {
+        let use_pq_safe_psk = tunnel_options.wireguard.unwrap().use_pq_safe_psk;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "quantum_resistant": use_pq_safe_psk }));
+            return Ok(());
+        }
+        if use_pq_safe_psk {
             println!("enabled");
         } else {
             println!("disabled");
@@ -235,36 +355,59 @@ impl Tunnel {
     ) -> Result<()> {
         let use_pq_safe_psk = matches.value_of("policy").unwrap() == "on";
         let mut rpc = new_rpc_client().await?;
-        let settings = rpc.get_settings(()).await?;
-        if use_pq_safe_psk {
-            let multihop_is_enabled = settings
-                .into_inner()
-                .relay_settings
-                .unwrap()
-                .endpoint
-                .and_then(|endpoint| {
-                    if let types::relay_settings::Endpoint::Normal(settings) = endpoint {
-                        Some(settings.wireguard_constraints.unwrap().use_multihop)
-                    } else {
-                        None
-                    }
-                })
-                .unwrap_or(false);
-            if multihop_is_enabled {
-                return Err(Error::CommandFailed(
-                    "Quantum resistant tunnels do not work when multihop is enabled",
-                ));
+        rpc.set_quantum_resistant_tunnel(use_pq_safe_psk).await?;
+        println_text!("Updated quantum resistant tunnel setting");
+        Ok(())
+    }
+
+    async fn process_wireguard_psk_rotation_interval_get() -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options().await?;
+        let interval = tunnel_options.wireguard.unwrap().pq_psk_rotation_interval;
+        if format::json_output() {
+            let minutes =
+                interval.map(|interval| Duration::try_from(interval).unwrap().as_secs() / 60);
+            format::print_json(&serde_json::json!({ "psk_rotation_interval_minutes": minutes }));
+            return Ok(());
+        }
+        match interval {
+            Some(interval) => {
+                let minutes = Duration::try_from(interval).unwrap().as_secs() / 60;
+                println!("PSK rotation interval: {} minute(s)", minutes);
             }
+            None => println!("PSK rotation interval: none"),
         }
-        rpc.set_quantum_resistant_tunnel(use_pq_safe_psk).await?;
-        println!("Updated quantum resistant tunnel setting");
+        Ok(())
+    }
+
+    async fn process_wireguard_psk_rotation_interval_set(matches: &clap::ArgMatches) -> Result<()> {
+        let rotate_interval = matches.value_of_t_or_exit::<u64>("interval");
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_quantum_resistant_psk_rotation_interval(
+            types::Duration::try_from(Duration::from_secs(60 * rotate_interval))
+                .expect("Failed to convert rotation interval to prost_types::Duration"),
+        )
+        .await?;
+        println_text!("Set PSK rotation interval: {} minute(s)", rotate_interval);
+        Ok(())
+    }
+
+    async fn process_wireguard_psk_rotation_interval_reset() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.reset_quantum_resistant_psk_rotation_interval(())
+            .await?;
+        println_text!("Set PSK rotation interval: none");
         Ok(())
     }
 
     #[cfg(windows)]
     async fn process_wireguard_use_wg_nt_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
-        if tunnel_options.wireguard.unwrap().use_wireguard_nt {
+        let enabled = tunnel_options.wireguard.unwrap().use_wireguard_nt;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "use_wireguard_nt": enabled }));
+            return Ok(());
+        }
+        if enabled {
             println!("enabled");
         } else {
             println!("disabled");
@@ -277,7 +420,57 @@ impl Tunnel {
         let new_state = matches.value_of("policy").unwrap() == "on";
         let mut rpc = new_rpc_client().await?;
         rpc.set_use_wireguard_nt(new_state).await?;
-        println!("Updated wireguard-nt setting");
+        println_text!("Updated wireguard-nt setting");
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn process_wireguard_use_boringtun_get() -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options().await?;
+        let enabled = tunnel_options.wireguard.unwrap().use_boringtun;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "use_boringtun": enabled }));
+            return Ok(());
+        }
+        if enabled {
+            println!("enabled");
+        } else {
+            println!("disabled");
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn process_wireguard_use_boringtun_set(matches: &clap::ArgMatches) -> Result<()> {
+        let new_state = matches.value_of("policy").unwrap() == "on";
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_use_boringtun(new_state).await?;
+        println_text!("Updated boringtun setting");
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn process_wireguard_network_namespace_get() -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options().await?;
+        let enabled = tunnel_options.wireguard.unwrap().use_network_namespace;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "use_network_namespace": enabled }));
+            return Ok(());
+        }
+        if enabled {
+            println!("enabled");
+        } else {
+            println!("disabled");
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn process_wireguard_network_namespace_set(matches: &clap::ArgMatches) -> Result<()> {
+        let new_state = matches.value_of("policy").unwrap() == "on";
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_use_network_namespace(new_state).await?;
+        println_text!("Updated network namespace setting");
         Ok(())
     }
 
@@ -294,6 +487,16 @@ impl Tunnel {
                 }
             }
         };
+        if format::json_output() {
+            let key = key.map(|key| {
+                serde_json::json!({
+                    "public_key": base64::encode(&key.key),
+                    "created": key.created.map(|created| created.seconds),
+                })
+            });
+            format::print_json(&serde_json::json!({ "key": key }));
+            return Ok(());
+        }
         if let Some(key) = key {
             println!("Current key    : {}", base64::encode(&key.key));
             println!(
@@ -307,16 +510,44 @@ impl Tunnel {
         Ok(())
     }
 
+    async fn process_wireguard_export_config(matches: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let config = rpc
+            .export_wireguard_config(())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to export WireGuard config", error))?
+            .into_inner();
+        match matches.value_of_os("output") {
+            Some(path) => {
+                write_private_file(path, &config).map_err(Error::WriteWireguardConfig)?;
+                println_text!("Wrote WireGuard config to {}", Path::new(path).display());
+            }
+            None => print!("{}", config),
+        }
+        Ok(())
+    }
+
     async fn process_wireguard_key_generate() -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.rotate_wireguard_key(()).await?;
-        println!("Rotated WireGuard key");
+        println_text!("Rotated WireGuard key");
         Ok(())
     }
 
     async fn process_wireguard_rotation_interval_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
-        match tunnel_options.wireguard.unwrap().rotation_interval {
+        let interval = tunnel_options.wireguard.unwrap().rotation_interval;
+        if format::json_output() {
+            let hours = interval
+                .map(|interval| duration_hours(&Duration::try_from(interval).unwrap()))
+                .unwrap_or_else(|| duration_hours(&DEFAULT_ROTATION_INTERVAL));
+            format::print_json(&serde_json::json!({
+                "rotation_interval_hours": hours,
+                "default": interval.is_none(),
+            }));
+            return Ok(());
+        }
+        match interval {
             Some(interval) => {
                 let hours = duration_hours(&Duration::try_from(interval).unwrap());
                 println!("Rotation interval: {} hour(s)", hours);
@@ -337,14 +568,14 @@ impl Tunnel {
                 .expect("Failed to convert rotation interval to prost_types::Duration"),
         )
         .await?;
-        println!("Set key rotation interval: {} hour(s)", rotate_interval);
+        println_text!("Set key rotation interval: {} hour(s)", rotate_interval);
         Ok(())
     }
 
     async fn process_wireguard_rotation_interval_reset() -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.reset_wireguard_rotation_interval(()).await?;
-        println!(
+        println_text!(
             "Set key rotation interval: default ({} hours)",
             duration_hours(&DEFAULT_ROTATION_INTERVAL)
         );
@@ -364,6 +595,12 @@ impl Tunnel {
     async fn process_openvpn_mssfix_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
         let mssfix = tunnel_options.openvpn.unwrap().mssfix;
+        if format::json_output() {
+            format::print_json(
+                &serde_json::json!({ "mssfix": Some(mssfix).filter(|mssfix| *mssfix != 0) }),
+            );
+            return Ok(());
+        }
         println!(
             "mssfix: {}",
             if mssfix != 0 {
@@ -388,7 +625,7 @@ impl Tunnel {
     async fn process_openvpn_mssfix_unset() -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         rpc.set_openvpn_mssfix(0).await?;
-        println!("mssfix parameter has been unset");
+        println_text!("mssfix parameter has been unset");
         Ok(())
     }
 
@@ -396,20 +633,18 @@ impl Tunnel {
         let new_value = matches.value_of_t_or_exit::<u16>("mssfix");
         let mut rpc = new_rpc_client().await?;
         rpc.set_openvpn_mssfix(new_value as u32).await?;
-        println!("mssfix parameter has been updated");
+        println_text!("mssfix parameter has been updated");
         Ok(())
     }
 
     async fn process_ipv6_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
-        println!(
-            "IPv6: {}",
-            if tunnel_options.generic.unwrap().enable_ipv6 {
-                "on"
-            } else {
-                "off"
-            }
-        );
+        let enable_ipv6 = tunnel_options.generic.unwrap().enable_ipv6;
+        if format::json_output() {
+            format::print_json(&serde_json::json!({ "enable_ipv6": enable_ipv6 }));
+            return Ok(());
+        }
+        println!("IPv6: {}", if enable_ipv6 { "on" } else { "off" });
         Ok(())
     }
 
@@ -419,9 +654,9 @@ impl Tunnel {
         let mut rpc = new_rpc_client().await?;
         rpc.set_enable_ipv6(enabled).await?;
         if enabled {
-            println!("Enabled IPv6");
+            println_text!("Enabled IPv6");
         } else {
-            println!("Disabled IPv6");
+            println_text!("Disabled IPv6");
         }
         Ok(())
     }
@@ -433,6 +668,18 @@ impl Tunnel {
     }
 }
 
+/// Writes `contents` to a file that is only readable by its owner, since it contains a secret.
+pub(super) fn write_private_file(path: &OsStr, contents: &str) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
 fn duration_hours(duration: &Duration) -> u64 {
     duration.as_secs() / 60 / 60
 }
@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Error, Result};
+use crate::{format, new_rpc_client, Command, Error, Result};
 
 pub struct Version;
 
@@ -20,12 +20,18 @@ impl Command for Version {
             .await
             .map_err(|error| Error::RpcFailedExt("Failed to obtain current version", error))?
             .into_inner();
-        println!("{:21}: {}", "Current version", current_version);
         let version_info = rpc
             .get_version_info(())
             .await
             .map_err(|error| Error::RpcFailedExt("Failed to obtain version info", error))?
             .into_inner();
+        if format::json_output() {
+            let mut output = format::version_info_json(&version_info);
+            output["current_version"] = serde_json::json!(current_version);
+            format::print_json(&output);
+            return Ok(());
+        }
+        println!("{:21}: {}", "Current version", current_version);
         println!("{:21}: {}", "Is supported", version_info.supported);
 
         if !version_info.suggested_upgrade.is_empty() {
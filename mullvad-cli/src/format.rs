@@ -1,12 +1,51 @@
+use mullvad_management_interface::types;
 use mullvad_types::{auth_failed::AuthFailed, location::GeoIpLocation, states::TunnelState};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
 use talpid_types::{
     net::{Endpoint, TunnelEndpoint},
     tunnel::ErrorState,
 };
 
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Makes all commands print JSON instead of text. See `docs/cli-json-output.md` for the format.
+pub fn enable_json_output() {
+    JSON_OUTPUT.store(true, Ordering::Relaxed);
+}
+
+pub fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Prints a value as a single line of JSON.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) {
+    println!(
+        "{}",
+        serde_json::to_string(value).expect("failed to serialize output")
+    );
+}
+
+/// Returns the JSON representation of the version info. This is used instead of the serialized
+/// `AppVersionInfo`, which contains fields that are not sent to clients.
+pub fn version_info_json(version_info: &types::AppVersionInfo) -> serde_json::Value {
+    serde_json::json!({
+        "supported": version_info.supported,
+        "latest_stable": version_info.latest_stable,
+        "latest_beta": version_info.latest_beta,
+        "suggested_upgrade": Some(&version_info.suggested_upgrade).filter(|v| !v.is_empty()),
+    })
+}
+
+/// Prints a tunnel state. In JSON mode, the state is printed as a JSON object.
 pub fn print_state(state: &TunnelState, verbose: bool) {
     use TunnelState::*;
 
+    if json_output() {
+        print_json(state);
+        return;
+    }
+
     match state {
         Error(error) => print_error_state(error),
         Connected { endpoint, location } => {
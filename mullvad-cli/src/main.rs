@@ -2,16 +2,31 @@
 
 use clap::{crate_authors, crate_description};
 #[cfg(all(unix, not(target_os = "android")))]
-use clap_complete::{generator::generate_to, Shell};
+use clap_complete::{generate, generator::generate_to, Shell};
 use mullvad_management_interface::async_trait;
 use std::{collections::HashMap, io};
+#[cfg(all(unix, not(target_os = "android")))]
+use std::{fs, path::Path};
 use talpid_types::ErrorExt;
 
 pub use mullvad_management_interface::{self, new_rpc_client};
 
+/// Like `println!`, but prints nothing when JSON output is enabled. Used for messages that are not
+/// part of the JSON output, such as confirmations of changed settings.
+macro_rules! println_text {
+    ($($arg:tt)*) => {
+        if !$crate::format::json_output() {
+            println!($($arg)*);
+        }
+    };
+}
+
 mod cmds;
 mod format;
 mod location;
+#[cfg(all(unix, not(target_os = "android")))]
+mod man_page;
+mod relay_picker;
 mod state;
 
 pub const BIN_NAME: &str = "mullvad";
@@ -45,10 +60,31 @@ pub enum Error {
     #[error(display = "Failed to listen for status updates")]
     StatusListenerFailed,
 
+    #[error(display = "Failed to read WireGuard config file")]
+    ReadWireguardConfig(#[error(source, no_from)] io::Error),
+
+    #[error(display = "Invalid WireGuard config file")]
+    InvalidWireguardConfig(#[error(source)] mullvad_types::wg_quick::Error),
+
+    #[error(display = "Failed to write WireGuard config file")]
+    WriteWireguardConfig(#[error(source, no_from)] io::Error),
+
+    #[error(display = "Failed to read settings bundle")]
+    ReadSettingsBundle(#[error(source, no_from)] io::Error),
+
+    #[error(display = "Failed to write settings bundle")]
+    WriteSettingsBundle(#[error(source, no_from)] io::Error),
+
     //#[cfg(all(unix, not(target_os = "android"))
     #[error(display = "Failed to generate shell completions")]
     CompletionsError(#[error(source, no_from)] io::Error),
 
+    #[error(display = "Failed to generate man page")]
+    ManPageError(#[error(source, no_from)] io::Error),
+
+    #[error(display = "Failed to run the relay picker")]
+    RelayPicker(#[error(source, no_from)] io::Error),
+
     #[error(display = "{}", _0)]
     Other(&'static str),
 }
@@ -57,6 +93,10 @@ pub enum Error {
 async fn main() {
     let exit_code = match run().await {
         Ok(_) => 0,
+        Err(error) if format::json_output() => {
+            print_json_error(&error);
+            1
+        }
         Err(error) => {
             match &error {
                 Error::RpcFailed(status) => {
@@ -76,6 +116,24 @@ async fn main() {
     std::process::exit(exit_code);
 }
 
+/// Prints an error as JSON to stderr. `code` is the gRPC status code if the error was returned by
+/// the daemon.
+fn print_json_error(error: &Error) {
+    let (message, status) = match error {
+        Error::RpcFailed(status) => (error.to_string(), Some(status)),
+        Error::RpcFailedExt(message, status) => (message.to_string(), Some(status)),
+        error => (error.display_chain(), None),
+    };
+    let error = serde_json::json!({
+        "error": {
+            "message": message,
+            "code": status.map(|status| format!("{:?}", status.code())),
+            "details": status.map(|status| status.message()),
+        }
+    });
+    eprintln!("{}", error);
+}
+
 async fn run() -> Result<()> {
     env_logger::init();
 
@@ -92,16 +150,27 @@ async fn run() -> Result<()> {
                     .possible_values(Shell::possible_values())
                     .help("The shell to generate the script for"),
             )
-            .arg(
-                clap::Arg::new("DIR")
-                    .allow_invalid_utf8(true)
-                    .default_value("./")
-                    .help("Output directory where the shell completions are written"),
-            )
+            .arg(clap::Arg::new("DIR").allow_invalid_utf8(true).help(
+                "Output directory where the shell completions are written. \
+                        If omitted, the script is printed to stdout",
+            )),
+    );
+
+    #[cfg(all(unix, not(target_os = "android")))]
+    let app = app.subcommand(
+        clap::App::new("man-page")
+            .about("Generates a man page that documents all commands")
+            .arg(clap::Arg::new("DIR").allow_invalid_utf8(true).help(
+                "Output directory where the man page is written. \
+                        If omitted, the man page is printed to stdout",
+            ))
             .setting(clap::AppSettings::Hidden),
     );
 
     let app_matches = app.get_matches();
+    if app_matches.is_present("json") {
+        format::enable_json_output();
+    }
     match app_matches.subcommand() {
         #[cfg(all(unix, not(target_os = "android")))]
         Some(("shell-completions", sub_matches)) => {
@@ -110,11 +179,28 @@ async fn run() -> Result<()> {
                 .unwrap()
                 .parse()
                 .expect("Invalid shell");
-            let out_dir = sub_matches.value_of_os("DIR").unwrap();
             let mut app = build_cli(&commands);
-            generate_to(shell, &mut app, BIN_NAME, out_dir)
-                .map(|_output_file| ())
-                .map_err(Error::CompletionsError)
+            match sub_matches.value_of_os("DIR") {
+                Some(out_dir) => generate_to(shell, &mut app, BIN_NAME, out_dir)
+                    .map(|_output_file| ())
+                    .map_err(Error::CompletionsError),
+                None => {
+                    generate(shell, &mut app, BIN_NAME, &mut io::stdout());
+                    Ok(())
+                }
+            }
+        }
+        #[cfg(all(unix, not(target_os = "android")))]
+        Some(("man-page", sub_matches)) => {
+            let app = build_cli(&commands);
+            let result = match sub_matches.value_of_os("DIR") {
+                Some(out_dir) => {
+                    fs::File::create(Path::new(out_dir).join(format!("{}.1", BIN_NAME)))
+                        .and_then(|mut file| man_page::generate(&app, &mut file))
+                }
+                None => man_page::generate(&app, &mut io::stdout()),
+            };
+            result.map_err(Error::ManPageError)
         }
         Some((sub_name, sub_matches)) => {
             if let Some(cmd) = commands.get(sub_name) {
@@ -137,6 +223,12 @@ fn build_cli(commands: &HashMap<&'static str, Box<dyn Command>>) -> clap::App<'s
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .global_setting(clap::AppSettings::DisableHelpSubcommand)
         .global_setting(clap::AppSettings::DisableVersionFlag)
+        .arg(
+            clap::Arg::new("json")
+                .long("json")
+                .global(true)
+                .help("Print the output as JSON. See the documentation for the format"),
+        )
         .subcommands(commands.values().map(|cmd| cmd.clap_subcommand()))
 }
 
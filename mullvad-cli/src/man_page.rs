@@ -0,0 +1,173 @@
+//! Generates a man page for the CLI from the same clap command tree that is used to parse the
+//! arguments, so that every command in `cmds` is documented.
+
+use std::io::{self, Write};
+
+/// Writes a man page in roff format, which documents `app` and all of its visible subcommands.
+pub fn generate(app: &clap::App<'_>, out: &mut dyn Write) -> io::Result<()> {
+    let name = app.get_name();
+    writeln!(
+        out,
+        ".TH {} 1 \"\" \"{} {}\" \"User Commands\"",
+        name.to_uppercase(),
+        name,
+        app.get_version().unwrap_or_default()
+    )?;
+    writeln!(out, ".SH NAME")?;
+    writeln!(
+        out,
+        "{} \\- {}",
+        name,
+        escape(app.get_about().unwrap_or_default())
+    )?;
+    writeln!(out, ".SH SYNOPSIS")?;
+    writeln!(out, "\\fB{}\\fR [\\fIOPTIONS\\fR] \\fICOMMAND\\fR", name)?;
+    writeln!(out, ".SH OPTIONS")?;
+    write_arguments(app, out)?;
+    writeln!(out, ".SH COMMANDS")?;
+    for subcommand in visible_subcommands(app) {
+        write_command(subcommand, name, out)?;
+    }
+    Ok(())
+}
+
+/// Writes a section for a command, followed by sections for each of its subcommands. Sections
+/// are named by the full command line, e.g. `mullvad relay set tunnel wireguard`.
+fn write_command(app: &clap::App<'_>, parent: &str, out: &mut dyn Write) -> io::Result<()> {
+    let command_line = format!("{} {}", parent, app.get_name());
+    writeln!(out, ".SS \"{}\"", command_line)?;
+    if let Some(about) = app.get_long_about().or_else(|| app.get_about()) {
+        writeln!(out, "{}", escape(about))?;
+    }
+    write_arguments(app, out)?;
+    for subcommand in visible_subcommands(app) {
+        write_command(subcommand, &command_line, out)?;
+    }
+    Ok(())
+}
+
+fn write_arguments(app: &clap::App<'_>, out: &mut dyn Write) -> io::Result<()> {
+    let arguments = app
+        .get_arguments()
+        .filter(|arg| !arg.is_set(clap::ArgSettings::Hidden));
+    for arg in arguments {
+        writeln!(out, ".TP")?;
+        writeln!(out, "{}", format_argument(arg))?;
+        if let Some(help) = arg.get_long_help().or_else(|| arg.get_help()) {
+            writeln!(out, "{}", escape(help))?;
+        }
+        if let Some(values) = arg.get_possible_values() {
+            let names: Vec<_> = values.iter().map(|value| value.get_name()).collect();
+            writeln!(out, ".br")?;
+            writeln!(out, "Possible values: {}", escape(&names.join(", ")))?;
+        }
+    }
+    Ok(())
+}
+
+/// Formats the flags of an option, e.g. `-n, --limit <limit>`, or the name of a positional
+/// argument.
+fn format_argument(arg: &clap::Arg<'_>) -> String {
+    let value_name = arg
+        .get_value_names()
+        .and_then(|names| names.first().copied())
+        .unwrap_or_else(|| arg.get_name());
+    let mut flags = vec![];
+    if let Some(short) = arg.get_short() {
+        flags.push(format!("\\fB\\-{}\\fR", short));
+    }
+    if let Some(long) = arg.get_long() {
+        flags.push(format!("\\fB\\-\\-{}\\fR", long.replace('-', "\\-")));
+    }
+    if flags.is_empty() {
+        return format!("\\fI<{}>\\fR", value_name);
+    }
+    let mut formatted = flags.join(", ");
+    if arg.is_set(clap::ArgSettings::TakesValue) {
+        formatted.push_str(&format!(" \\fI<{}>\\fR", value_name));
+    }
+    formatted
+}
+
+fn visible_subcommands<'a, 'help>(
+    app: &'a clap::App<'help>,
+) -> impl Iterator<Item = &'a clap::App<'help>> {
+    app.get_subcommands()
+        .filter(|subcommand| !subcommand.is_set(clap::AppSettings::Hidden))
+}
+
+/// Escapes text so that it is not interpreted as roff requests or escape sequences.
+fn escape(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.trim().replace('\\', "\\e");
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn generate_for_cli() -> String {
+        let app = crate::build_cli(&crate::cmds::get_commands());
+        let mut out = vec![];
+        generate(&app, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Returns the section names of `app` and all of its visible subcommands.
+    fn command_lines(app: &clap::App<'_>, parent: &str) -> Vec<String> {
+        let mut lines = vec![];
+        for subcommand in visible_subcommands(app) {
+            let command_line = format!("{} {}", parent, subcommand.get_name());
+            lines.extend(command_lines(subcommand, &command_line));
+            lines.push(command_line);
+        }
+        lines
+    }
+
+    #[test]
+    fn test_all_commands_documented() {
+        let man_page = generate_for_cli();
+        let commands = crate::cmds::get_commands();
+        assert!(!commands.is_empty());
+
+        for command in commands.values() {
+            let app = command.clap_subcommand();
+            if app.is_set(clap::AppSettings::Hidden) {
+                continue;
+            }
+            let command_line = format!("{} {}", crate::BIN_NAME, app.get_name());
+            let mut expected = command_lines(&app, &command_line);
+            expected.push(command_line);
+            for command_line in expected {
+                assert!(
+                    man_page.contains(&format!(".SS \"{}\"\n", command_line)),
+                    "{} is missing from the man page",
+                    command_line
+                );
+            }
+        }
+        assert!(man_page.contains("\\fB\\-\\-json\\fR"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("plain text"), "plain text");
+        assert_eq!(escape(".TH injected"), "\\&.TH injected");
+        assert_eq!(escape("'br"), "\\&'br");
+        assert_eq!(escape("\\fBbold"), "\\efBbold");
+        assert_eq!(escape("C:\\path"), "C:\\epath");
+        assert_eq!(
+            escape("first line\n    .second line"),
+            "first line\n\\&.second line"
+        );
+    }
+}
@@ -0,0 +1,514 @@
+//! Interactive picker for selecting a relay location in the terminal. Countries, cities and relays
+//! are searched using fuzzy matching on their names, codes and hostnames.
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyModifiers},
+    queue,
+    style::{self, Stylize},
+    terminal,
+    tty::IsTty,
+};
+use mullvad_types::{
+    relay_constraints::{Constraint, LocationConstraint, Match, Ownership, Providers},
+    relay_list::{Relay, RelayEndpointData, RelayListCountry},
+};
+use std::io::{self, Write};
+use talpid_types::net::TunnelType;
+
+/// Relays that do not match these filters are not shown. They are matched in the same way as by
+/// the relay selector in the daemon.
+pub struct Filters {
+    pub providers: Constraint<Providers>,
+    pub ownership: Constraint<Ownership>,
+    pub tunnel_type: Constraint<TunnelType>,
+}
+
+impl Filters {
+    fn matches(&self, relay: &Relay) -> bool {
+        let tunnel_type = match relay.endpoint_data {
+            RelayEndpointData::Openvpn => TunnelType::OpenVpn,
+            RelayEndpointData::Wireguard(_) => TunnelType::Wireguard,
+            RelayEndpointData::Bridge => return false,
+        };
+        self.providers.matches(relay)
+            && self.ownership.matches(relay)
+            && self.tunnel_type.matches_eq(&tunnel_type)
+    }
+}
+
+/// A location that can be picked.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Text that is shown and searched.
+    pub label: String,
+    pub location: LocationConstraint,
+    /// Whether any relay at the location is active.
+    pub active: bool,
+}
+
+/// Returns an entry for every country, city and relay that has relays matching `filters`.
+/// Inactive relays are included, but marked as such.
+pub fn entries(countries: &[RelayListCountry], filters: &Filters) -> Vec<Entry> {
+    let mut entries = vec![];
+    for country in countries {
+        let mut country_entries = vec![];
+        for city in &country.cities {
+            let relays: Vec<_> = city
+                .relays
+                .iter()
+                .filter(|relay| filters.matches(relay))
+                .collect();
+            if relays.is_empty() {
+                continue;
+            }
+            country_entries.push(Entry {
+                label: format!(
+                    "{}, {} ({} {})",
+                    city.name, country.name, country.code, city.code
+                ),
+                location: LocationConstraint::City(country.code.clone(), city.code.clone()),
+                active: relays.iter().any(|relay| relay.active),
+            });
+            for relay in relays {
+                country_entries.push(Entry {
+                    label: format!(
+                        "{} - {}, {} - {}, hosted by {}{}",
+                        relay.hostname,
+                        city.name,
+                        country.name,
+                        if let RelayEndpointData::Openvpn = relay.endpoint_data {
+                            "OpenVPN"
+                        } else {
+                            "WireGuard"
+                        },
+                        relay.provider,
+                        if relay.owned { " (Mullvad-owned)" } else { "" },
+                    ),
+                    location: LocationConstraint::Hostname(
+                        country.code.clone(),
+                        city.code.clone(),
+                        relay.hostname.clone(),
+                    ),
+                    active: relay.active,
+                });
+            }
+        }
+        if country_entries.is_empty() {
+            continue;
+        }
+        entries.push(Entry {
+            label: format!("{} ({})", country.name, country.code),
+            location: LocationConstraint::Country(country.code.clone()),
+            active: country_entries.iter().any(|entry| entry.active),
+        });
+        entries.append(&mut country_entries);
+    }
+    entries
+}
+
+/// Returns a score for how well `query` matches `text`, or `None` if it does not match. All
+/// characters of the query must appear in the text, in order. Consecutive characters and
+/// characters at the start of words score higher.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+    for query_char in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let index = position + text[position..].iter().position(|c| *c == query_char)?;
+        score += 1;
+        if previous_match.map_or(false, |previous| previous + 1 == index) {
+            score += 5;
+        }
+        if index == 0 || !text[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous_match = Some(index);
+        position = index + 1;
+    }
+    // Prefer shorter texts, so that countries come before the cities and relays in them.
+    Some(score * 100 - text.len() as i64)
+}
+
+/// Returns the entries matching `query`, best match first. Without a query, all entries are
+/// returned in their original order.
+fn search<'a>(entries: &'a [Entry], query: &str) -> Vec<&'a Entry> {
+    if query.trim().is_empty() {
+        return entries.iter().collect();
+    }
+    let mut matches: Vec<_> = entries
+        .iter()
+        .filter_map(|entry| Some((fuzzy_score(query, &entry.label)?, entry)))
+        .collect();
+    matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+    matches.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Restores the terminal when dropped, even if drawing fails.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Lets the user search for and select one of `entries`. Returns `None` if the user cancelled.
+pub fn pick(entries: &[Entry]) -> io::Result<Option<Entry>> {
+    if !io::stdin().is_tty() || !io::stdout().is_tty() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "The relay picker must be run in a terminal",
+        ));
+    }
+    let _terminal = RawTerminal::enter()?;
+    let mut stdout = io::stdout();
+    let mut query = String::new();
+    let mut selected = 0;
+    loop {
+        let matches = search(entries, &query);
+        selected = selected.min(matches.len().saturating_sub(1));
+        draw(&mut stdout, &query, &matches, selected)?;
+
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            _ => continue,
+        };
+        match (key.code, key.modifiers) {
+            (KeyCode::Esc, _) => return Ok(None),
+            (KeyCode::Char('c'), KeyModifiers::CONTROL) => return Ok(None),
+            (KeyCode::Enter, _) => {
+                if let Some(entry) = matches.get(selected) {
+                    return Ok(Some((*entry).clone()));
+                }
+            }
+            (KeyCode::Up, _) => selected = selected.saturating_sub(1),
+            (KeyCode::Down, _) => selected += 1,
+            (KeyCode::Backspace, _) => {
+                query.pop();
+                selected = 0;
+            }
+            (KeyCode::Char(c), _) => {
+                query.push(c);
+                selected = 0;
+            }
+            _ => (),
+        }
+    }
+}
+
+fn draw(
+    stdout: &mut io::Stdout,
+    query: &str,
+    matches: &[&Entry],
+    selected: usize,
+) -> io::Result<()> {
+    let (width, height) = terminal::size()?;
+    let width = usize::from(width);
+    let rows = usize::from(height).saturating_sub(3);
+    // Scroll so that the selected entry is visible.
+    let first = (selected + 1).saturating_sub(rows);
+
+    queue!(
+        stdout,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0),
+        style::Print(format!("Search: {}", query).bold()),
+        cursor::MoveTo(0, 1),
+        style::Print(
+            format!(
+                "{} matches. Up/Down to move, Enter to select, Esc to cancel",
+                matches.len()
+            )
+            .dark_grey()
+        ),
+    )?;
+    for (row, (index, entry)) in matches
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(rows)
+        .enumerate()
+    {
+        let mut line = entry.label.clone();
+        if !entry.active {
+            line.push_str(" [inactive]");
+        }
+        let line: String = format!("  {}", line).chars().take(width).collect();
+        let line = if index == selected {
+            line.reverse()
+        } else if !entry.active {
+            line.dark_grey()
+        } else {
+            line.stylize()
+        };
+        queue!(
+            stdout,
+            cursor::MoveTo(0, row as u16 + 2),
+            style::Print(line)
+        )?;
+    }
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mullvad_types::relay_list::{RelayListCity, WireguardRelayEndpointData};
+    use talpid_types::net::wireguard::PrivateKey;
+
+    fn relay(
+        hostname: &str,
+        provider: &str,
+        owned: bool,
+        active: bool,
+        tunnel_type: Option<TunnelType>,
+    ) -> Relay {
+        Relay {
+            hostname: hostname.to_string(),
+            ipv4_addr_in: "10.0.0.1".parse().unwrap(),
+            ipv6_addr_in: None,
+            include_in_country: true,
+            active,
+            owned,
+            provider: provider.to_string(),
+            weight: 1,
+            endpoint_data: match tunnel_type {
+                Some(TunnelType::OpenVpn) => RelayEndpointData::Openvpn,
+                Some(TunnelType::Wireguard) => {
+                    RelayEndpointData::Wireguard(WireguardRelayEndpointData {
+                        public_key: PrivateKey::new_from_random().public_key(),
+                    })
+                }
+                None => RelayEndpointData::Bridge,
+            },
+            location: None,
+        }
+    }
+
+    fn city(name: &str, code: &str, relays: Vec<Relay>) -> RelayListCity {
+        RelayListCity {
+            name: name.to_string(),
+            code: code.to_string(),
+            latitude: 0.0,
+            longitude: 0.0,
+            relays,
+        }
+    }
+
+    fn countries() -> Vec<RelayListCountry> {
+        vec![
+            RelayListCountry {
+                name: "Sweden".to_string(),
+                code: "se".to_string(),
+                cities: vec![
+                    city(
+                        "Gothenburg",
+                        "got",
+                        vec![
+                            relay(
+                                "se-got-wg-001",
+                                "31173",
+                                true,
+                                true,
+                                Some(TunnelType::Wireguard),
+                            ),
+                            relay("se-got-001", "M247", false, true, Some(TunnelType::OpenVpn)),
+                            relay("se-got-br-001", "31173", true, true, None),
+                        ],
+                    ),
+                    city(
+                        "Stockholm",
+                        "sto",
+                        vec![relay(
+                            "se-sto-wg-001",
+                            "31173",
+                            true,
+                            false,
+                            Some(TunnelType::Wireguard),
+                        )],
+                    ),
+                ],
+            },
+            RelayListCountry {
+                name: "Germany".to_string(),
+                code: "de".to_string(),
+                cities: vec![
+                    city(
+                        "Berlin",
+                        "ber",
+                        vec![relay(
+                            "de-ber-wg-001",
+                            "M247",
+                            false,
+                            false,
+                            Some(TunnelType::Wireguard),
+                        )],
+                    ),
+                    city(
+                        "Frankfurt",
+                        "fra",
+                        vec![relay("de-fra-br-001", "M247", false, true, None)],
+                    ),
+                ],
+            },
+        ]
+    }
+
+    fn any_filters() -> Filters {
+        Filters {
+            providers: Constraint::Any,
+            ownership: Constraint::Any,
+            tunnel_type: Constraint::Any,
+        }
+    }
+
+    fn locations(entries: &[Entry]) -> Vec<LocationConstraint> {
+        entries.iter().map(|entry| entry.location.clone()).collect()
+    }
+
+    fn hostnames(entries: &[Entry]) -> Vec<&str> {
+        entries
+            .iter()
+            .filter_map(|entry| match &entry.location {
+                LocationConstraint::Hostname(_, _, hostname) => Some(hostname.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_entries_exclude_bridges() {
+        let entries = entries(&countries(), &any_filters());
+        assert_eq!(
+            locations(&entries),
+            vec![
+                LocationConstraint::Country("se".to_string()),
+                LocationConstraint::City("se".to_string(), "got".to_string()),
+                LocationConstraint::Hostname(
+                    "se".to_string(),
+                    "got".to_string(),
+                    "se-got-wg-001".to_string()
+                ),
+                LocationConstraint::Hostname(
+                    "se".to_string(),
+                    "got".to_string(),
+                    "se-got-001".to_string()
+                ),
+                LocationConstraint::City("se".to_string(), "sto".to_string()),
+                LocationConstraint::Hostname(
+                    "se".to_string(),
+                    "sto".to_string(),
+                    "se-sto-wg-001".to_string()
+                ),
+                LocationConstraint::Country("de".to_string()),
+                LocationConstraint::City("de".to_string(), "ber".to_string()),
+                LocationConstraint::Hostname(
+                    "de".to_string(),
+                    "ber".to_string(),
+                    "de-ber-wg-001".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_entries_filters() {
+        let countries = countries();
+
+        let filters = Filters {
+            providers: Constraint::Only(
+                Providers::new(std::iter::once("M247".to_string())).unwrap(),
+            ),
+            ..any_filters()
+        };
+        assert_eq!(
+            hostnames(&entries(&countries, &filters)),
+            vec!["se-got-001", "de-ber-wg-001"]
+        );
+
+        let filters = Filters {
+            ownership: Constraint::Only(Ownership::MullvadOwned),
+            ..any_filters()
+        };
+        assert_eq!(
+            hostnames(&entries(&countries, &filters)),
+            vec!["se-got-wg-001", "se-sto-wg-001"]
+        );
+
+        let filters = Filters {
+            ownership: Constraint::Only(Ownership::Rented),
+            tunnel_type: Constraint::Only(TunnelType::Wireguard),
+            ..any_filters()
+        };
+        assert_eq!(
+            hostnames(&entries(&countries, &filters)),
+            vec!["de-ber-wg-001"]
+        );
+
+        // Countries and cities without matching relays are left out
+        let filters = Filters {
+            tunnel_type: Constraint::Only(TunnelType::OpenVpn),
+            ..any_filters()
+        };
+        assert_eq!(
+            locations(&entries(&countries, &filters)),
+            vec![
+                LocationConstraint::Country("se".to_string()),
+                LocationConstraint::City("se".to_string(), "got".to_string()),
+                LocationConstraint::Hostname(
+                    "se".to_string(),
+                    "got".to_string(),
+                    "se-got-001".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_entries_inactive() {
+        let entries = entries(&countries(), &any_filters());
+        let active = |location: LocationConstraint| {
+            entries
+                .iter()
+                .find(|entry| entry.location == location)
+                .unwrap()
+                .active
+        };
+
+        assert!(active(LocationConstraint::Country("se".to_string())));
+        assert!(!active(LocationConstraint::City(
+            "se".to_string(),
+            "sto".to_string()
+        )));
+        assert!(!active(LocationConstraint::Hostname(
+            "se".to_string(),
+            "sto".to_string(),
+            "se-sto-wg-001".to_string()
+        )));
+        // Only the bridge in Germany is active, and bridges are not counted
+        assert!(!active(LocationConstraint::Country("de".to_string())));
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("gbg", "Gothenburg").is_some());
+        assert!(fuzzy_score("gbx", "Gothenburg").is_none());
+        assert!(fuzzy_score("se got", "Gothenburg, Sweden (se got)").is_some());
+        assert!(fuzzy_score("xyz", "Sweden (se)").is_none());
+
+        let consecutive = fuzzy_score("swe", "Sweden (se)").unwrap();
+        let scattered = fuzzy_score("swe", "Stockholm, Sweden (se sto)").unwrap();
+        assert!(consecutive > scattered);
+    }
+}
@@ -0,0 +1,275 @@
+//! Keeps track of when the current account expires and notifies the daemon when the remaining
+//! time crosses one of the configured reminder thresholds.
+
+use crate::{device, DaemonEventSender};
+use chrono::{DateTime, Utc};
+use futures::{
+    channel::mpsc,
+    future::{Fuse, FusedFuture},
+    FutureExt, StreamExt,
+};
+use mullvad_types::account::AccountExpiryEvent;
+use std::{future::Future, pin::Pin, time::Duration};
+use talpid_core::mpsc::Sender;
+use talpid_types::ErrorExt;
+
+/// How often to compare the known expiry against the reminder thresholds.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How often to fetch the expiry from the API.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60 * 12);
+/// Wait this long before fetching the expiry again if it failed.
+const REFRESH_INTERVAL_ERROR: Duration = Duration::from_secs(60 * 15);
+
+type BoxedFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Sent to the daemon when the expiry reminder changes.
+pub(crate) struct AccountExpiryUpdate {
+    /// The reminder that currently applies, if any.
+    pub event: Option<AccountExpiryEvent>,
+    /// Whether a new threshold was crossed, and listeners should be notified.
+    pub notify: bool,
+}
+
+pub(crate) struct ExpiryChecker {
+    account_manager: device::AccountManagerHandle,
+    update_sender: DaemonEventSender<AccountExpiryUpdate>,
+    reminder_days: Vec<u32>,
+    expiry: Option<DateTime<Utc>>,
+    /// The expiry and reminder threshold of the last reminder sent to the daemon. The threshold
+    /// is `None` if the account had expired.
+    last_reminder: Option<(DateTime<Utc>, Option<u32>)>,
+    rx: Option<mpsc::UnboundedReceiver<ExpiryCheckerCommand>>,
+}
+
+#[derive(Clone)]
+pub(crate) struct ExpiryCheckerHandle {
+    tx: mpsc::UnboundedSender<ExpiryCheckerCommand>,
+}
+
+enum ExpiryCheckerCommand {
+    SetReminderDays(Vec<u32>),
+    SetExpiry(DateTime<Utc>),
+    /// Forget the current expiry and fetch it again, e.g. after logging in to another account.
+    Reset,
+    /// Forget the current expiry, e.g. after logging out.
+    Clear,
+}
+
+impl ExpiryCheckerHandle {
+    pub fn set_reminder_days(&self, reminder_days: Vec<u32>) {
+        self.send_command(ExpiryCheckerCommand::SetReminderDays(reminder_days));
+    }
+
+    /// Update the expiry after it was fetched elsewhere, such as when submitting a voucher.
+    pub fn set_expiry(&self, expiry: DateTime<Utc>) {
+        self.send_command(ExpiryCheckerCommand::SetExpiry(expiry));
+    }
+
+    pub fn reset(&self) {
+        self.send_command(ExpiryCheckerCommand::Reset);
+    }
+
+    pub fn clear(&self) {
+        self.send_command(ExpiryCheckerCommand::Clear);
+    }
+
+    fn send_command(&self, command: ExpiryCheckerCommand) {
+        if self.tx.unbounded_send(command).is_err() {
+            log::error!("Account expiry checker is down");
+        }
+    }
+}
+
+impl ExpiryChecker {
+    pub fn new(
+        account_manager: device::AccountManagerHandle,
+        update_sender: DaemonEventSender<AccountExpiryUpdate>,
+        reminder_days: Vec<u32>,
+    ) -> (Self, ExpiryCheckerHandle) {
+        let (tx, rx) = mpsc::unbounded();
+        (
+            ExpiryChecker {
+                account_manager,
+                update_sender,
+                reminder_days,
+                expiry: None,
+                last_reminder: None,
+                rx: Some(rx),
+            },
+            ExpiryCheckerHandle { tx },
+        )
+    }
+
+    pub async fn run(mut self) {
+        let mut rx = self.rx.take().unwrap();
+        let mut check_delay = Self::delay(CHECK_INTERVAL);
+        let mut refresh_delay = Fuse::terminated();
+        let mut refresh = self.create_refresh_future();
+
+        loop {
+            futures::select! {
+                command = rx.next() => {
+                    match command {
+                        Some(ExpiryCheckerCommand::SetReminderDays(reminder_days)) => {
+                            self.reminder_days = reminder_days;
+                            self.check();
+                        }
+                        Some(ExpiryCheckerCommand::SetExpiry(expiry)) => {
+                            self.expiry = Some(expiry);
+                            self.check();
+                        }
+                        Some(ExpiryCheckerCommand::Reset) => {
+                            self.clear();
+                            refresh_delay = Fuse::terminated();
+                            refresh = self.create_refresh_future();
+                        }
+                        Some(ExpiryCheckerCommand::Clear) => {
+                            self.clear();
+                            refresh_delay = Fuse::terminated();
+                            refresh = Fuse::terminated();
+                        }
+                        // time to shut down
+                        None => return,
+                    }
+                }
+
+                _ = check_delay => {
+                    if self.update_sender.is_closed() {
+                        return;
+                    }
+                    self.check();
+                    check_delay = Self::delay(CHECK_INTERVAL);
+                }
+
+                _ = refresh_delay => {
+                    refresh = self.create_refresh_future();
+                }
+
+                result = refresh => {
+                    let next_refresh = match result {
+                        Ok(expiry) => {
+                            self.expiry = Some(expiry);
+                            self.check();
+                            REFRESH_INTERVAL
+                        }
+                        Err(device::Error::NoDevice) => {
+                            // Logged out. A reset is sent when logging in again.
+                            self.clear();
+                            continue;
+                        }
+                        Err(error) => {
+                            log::error!(
+                                "{}",
+                                error.display_chain_with_msg("Failed to fetch account expiry")
+                            );
+                            REFRESH_INTERVAL_ERROR
+                        }
+                    };
+                    refresh_delay = Self::delay(next_refresh);
+                }
+            }
+
+            if refresh.is_terminated() && refresh_delay.is_terminated() && self.expiry.is_some() {
+                refresh_delay = Self::delay(REFRESH_INTERVAL);
+            }
+        }
+    }
+
+    fn delay(duration: Duration) -> Fuse<BoxedFuture<()>> {
+        let sleep: BoxedFuture<()> = Box::pin(talpid_time::sleep(duration));
+        sleep.fuse()
+    }
+
+    fn create_refresh_future(&self) -> Fuse<BoxedFuture<Result<DateTime<Utc>, device::Error>>> {
+        let account_manager = self.account_manager.clone();
+        let refresh: BoxedFuture<Result<DateTime<Utc>, device::Error>> =
+            Box::pin(async move { account_manager.check_expiry().await });
+        refresh.fuse()
+    }
+
+    fn clear(&mut self) {
+        self.expiry = None;
+        if self.last_reminder.take().is_some() {
+            self.send_update(None, false);
+        }
+    }
+
+    /// Compares the known expiry against the reminder thresholds and notifies the daemon if a new
+    /// threshold was crossed.
+    fn check(&mut self) {
+        let expiry = match self.expiry {
+            Some(expiry) => expiry,
+            None => return,
+        };
+        let reminder =
+            AccountExpiryEvent::new_with_threshold(expiry, Utc::now(), &self.reminder_days);
+        let last_reminder = self.last_reminder;
+        self.last_reminder = reminder.as_ref().map(|(_, threshold)| (expiry, *threshold));
+
+        match (last_reminder, reminder) {
+            (None, None) => (),
+            (Some(_), None) => {
+                log::debug!("Account expiry is no longer within a reminder threshold");
+                self.send_update(None, false);
+            }
+            (last_reminder, Some((event, threshold))) => {
+                let notify =
+                    last_reminder.map(|(_, last_threshold)| last_threshold) != Some(threshold);
+                if notify || last_reminder.map(|(last_expiry, _)| last_expiry) != Some(expiry) {
+                    if notify {
+                        log::info!("{}", event);
+                    }
+                    self.send_update(Some(event), notify);
+                }
+            }
+        }
+    }
+
+    fn send_update(&self, event: Option<AccountExpiryEvent>, notify: bool) {
+        let _ = self
+            .update_sender
+            .send(AccountExpiryUpdate { event, notify });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reminder_threshold() {
+        let now = Utc::now();
+        let reminder_days = [7, 3, 1];
+        let threshold = |expiry, reminder_days: &[u32]| {
+            AccountExpiryEvent::new_with_threshold(expiry, now, reminder_days)
+                .map(|(_, threshold)| threshold)
+        };
+
+        let expiry = now + chrono::Duration::days(10);
+        assert_eq!(threshold(expiry, &reminder_days), None);
+
+        let expiry = now + chrono::Duration::days(5);
+        assert_eq!(threshold(expiry, &reminder_days), Some(Some(7)));
+
+        let expiry = now + chrono::Duration::hours(12);
+        assert_eq!(threshold(expiry, &reminder_days), Some(Some(1)));
+
+        let expiry = now - chrono::Duration::hours(1);
+        assert_eq!(threshold(expiry, &reminder_days), Some(None));
+        assert_eq!(threshold(expiry, &[]), Some(None));
+    }
+
+    #[test]
+    fn test_expiry_event() {
+        let now = Utc::now();
+        let expiry = now + chrono::Duration::hours(60);
+        assert_eq!(
+            AccountExpiryEvent::new(expiry, now, &[3, 1]),
+            Some(AccountExpiryEvent::ExpiringSoon {
+                expiry,
+                days_left: 2
+            })
+        );
+        assert_eq!(AccountExpiryEvent::new(expiry, now, &[1]), None);
+    }
+}
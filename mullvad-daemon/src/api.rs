@@ -10,6 +10,7 @@ use mullvad_api::{
     ApiEndpointUpdateCallback,
 };
 use mullvad_relay_selector::RelaySelector;
+use mullvad_types::access_method::{AccessMethod, ApiAccessMethod};
 use std::{
     net::SocketAddr,
     path::PathBuf,
@@ -29,17 +30,22 @@ use talpid_types::{
 ///
 /// When `mullvad-api` fails to contact the API, it requests a new connection mode.
 /// The API can be connected to either directly (i.e., [`ApiConnectionMode::Direct`])
-/// or from a bridge ([`ApiConnectionMode::Proxied`]).
+/// or through a proxy (i.e., [`ApiConnectionMode::Proxied`]).
 ///
-/// * Every 3rd attempt returns [`ApiConnectionMode::Direct`].
-/// * Any other attempt returns a configuration for the bridge that is closest to the selected relay
-///   location and matches all bridge constraints.
-/// * When no matching bridge is found, e.g. if the selected hosting providers don't match any
-///   bridge, [`ApiConnectionMode::Direct`] is returned.
+/// * Every 3rd attempt returns [`ApiConnectionMode::Direct`], if the direct access method is
+///   enabled.
+/// * Any other attempt returns the next enabled proxy access method, in the order that they are
+///   listed in the settings. After the last one, it starts over from the first one.
+/// * [`AccessMethod::Bridges`] returns a configuration for the bridge that is closest to the
+///   selected relay location and matches all bridge constraints. When no matching bridge is
+///   found, e.g. if the selected hosting providers don't match any bridge,
+///   [`ApiConnectionMode::Direct`] is returned.
+/// * When no access method is enabled, [`ApiConnectionMode::Direct`] is returned.
 pub struct ApiConnectionModeProvider {
     cache_dir: PathBuf,
 
     relay_selector: RelaySelector,
+    access_methods: AccessMethodsHandle,
     retry_attempt: u32,
 
     current_task: Option<Pin<Box<dyn Future<Output = ApiConnectionMode> + Send>>>,
@@ -64,21 +70,12 @@ impl Stream for ApiConnectionModeProvider {
         }
 
         // Create a new task.
-        let config = if Self::should_use_bridge(self.retry_attempt) {
-            self.relay_selector
-                .get_bridge_forced()
-                .map(|settings| match settings {
-                    ProxySettings::Shadowsocks(ss_settings) => {
-                        ApiConnectionMode::Proxied(ProxyConfig::Shadowsocks(ss_settings))
-                    }
-                    _ => {
-                        log::error!("Received unexpected proxy settings type");
-                        ApiConnectionMode::Direct
-                    }
-                })
-                .unwrap_or(ApiConnectionMode::Direct)
-        } else {
-            ApiConnectionMode::Direct
+        let config = match self.access_methods.for_attempt(self.retry_attempt) {
+            Some(method) => {
+                log::debug!("Using API access method \"{}\"", method.name);
+                resolve_connection_mode(&method.access_method, &self.relay_selector)
+            }
+            None => ApiConnectionMode::Direct,
         };
 
         self.retry_attempt = self.retry_attempt.wrapping_add(1);
@@ -99,33 +96,107 @@ impl Stream for ApiConnectionModeProvider {
 }
 
 impl ApiConnectionModeProvider {
-    pub(crate) fn new(cache_dir: PathBuf, relay_selector: RelaySelector) -> Self {
+    pub(crate) fn new(
+        cache_dir: PathBuf,
+        relay_selector: RelaySelector,
+        access_methods: AccessMethodsHandle,
+    ) -> Self {
         Self {
             cache_dir,
 
             relay_selector,
+            access_methods,
             retry_attempt: 0,
 
             current_task: None,
         }
     }
+}
 
-    fn should_use_bridge(retry_attempt: u32) -> bool {
-        retry_attempt % 3 > 0
+/// Returns the connection mode to use for a given access method.
+pub(crate) fn resolve_connection_mode(
+    access_method: &AccessMethod,
+    relay_selector: &RelaySelector,
+) -> ApiConnectionMode {
+    match access_method {
+        AccessMethod::Direct => ApiConnectionMode::Direct,
+        AccessMethod::Bridges => relay_selector
+            .get_bridge_forced()
+            .map(|settings| match settings {
+                ProxySettings::Shadowsocks(ss_settings) => {
+                    ApiConnectionMode::Proxied(ProxyConfig::Shadowsocks(ss_settings))
+                }
+                _ => {
+                    log::error!("Received unexpected proxy settings type");
+                    ApiConnectionMode::Direct
+                }
+            })
+            .unwrap_or(ApiConnectionMode::Direct),
+        AccessMethod::Shadowsocks(ss_settings) => {
+            ApiConnectionMode::Proxied(ProxyConfig::Shadowsocks(ss_settings.clone()))
+        }
+        AccessMethod::Socks5(socks_settings) => {
+            ApiConnectionMode::Proxied(ProxyConfig::Socks5(socks_settings.clone()))
+        }
+    }
+}
+
+/// Shares the API access methods in the settings with [`ApiConnectionModeProvider`].
+#[derive(Clone)]
+pub(crate) struct AccessMethodsHandle {
+    access_methods: Arc<Mutex<Vec<ApiAccessMethod>>>,
+}
+
+impl AccessMethodsHandle {
+    pub fn new(access_methods: Vec<ApiAccessMethod>) -> Self {
+        Self {
+            access_methods: Arc::new(Mutex::new(access_methods)),
+        }
+    }
+
+    pub fn set(&self, access_methods: Vec<ApiAccessMethod>) {
+        *self.access_methods.lock().unwrap() = access_methods;
+    }
+
+    /// Returns the access method to use for the given attempt. Every 3rd attempt uses the direct
+    /// access method, and the others rotate through the enabled proxy access methods.
+    fn for_attempt(&self, attempt: u32) -> Option<ApiAccessMethod> {
+        let access_methods = self.access_methods.lock().unwrap();
+        let direct = access_methods
+            .iter()
+            .find(|method| method.enabled && method.access_method == AccessMethod::Direct);
+        let proxies: Vec<_> = access_methods
+            .iter()
+            .filter(|method| method.enabled && method.access_method != AccessMethod::Direct)
+            .collect();
+        if proxies.is_empty() {
+            return direct.cloned();
+        }
+        let attempt = attempt as usize;
+        match direct {
+            Some(direct) if attempt % 3 == 0 => Some(direct.clone()),
+            // Skip the attempts that used the direct access method.
+            Some(_) => Some(proxies[(attempt - attempt / 3 - 1) % proxies.len()].clone()),
+            None => Some(proxies[attempt % proxies.len()].clone()),
+        }
     }
 }
 
 /// Notifies the tunnel state machine that the API (real or proxied) endpoint has
 /// changed. [ApiEndpointUpdaterHandle::callback()] creates a callback that may
 /// be passed to the `mullvad-api` runtime.
+#[derive(Clone)]
 pub(super) struct ApiEndpointUpdaterHandle {
     tunnel_cmd_tx: Arc<Mutex<Option<Weak<mpsc::UnboundedSender<TunnelCommand>>>>>,
+    /// The endpoint most recently accepted by the callback.
+    current_endpoint: Arc<Mutex<Option<SocketAddr>>>,
 }
 
 impl ApiEndpointUpdaterHandle {
     pub fn new() -> Self {
         Self {
             tunnel_cmd_tx: Arc::new(Mutex::new(None)),
+            current_endpoint: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -134,30 +205,59 @@ impl ApiEndpointUpdaterHandle {
     }
 
     pub fn callback(&self) -> impl ApiEndpointUpdateCallback {
-        let tunnel_tx = self.tunnel_cmd_tx.clone();
+        let handle = self.clone();
         move |address: SocketAddr| {
-            let inner_tx = tunnel_tx.clone();
+            let handle = handle.clone();
             async move {
-                let tunnel_tx = if let Some(Some(tunnel_tx)) = { inner_tx.lock().unwrap().as_ref() }
-                    .map(|tx: &Weak<mpsc::UnboundedSender<TunnelCommand>>| tx.upgrade())
-                {
-                    tunnel_tx
-                } else {
-                    log::error!("Rejecting allowed endpoint: Tunnel state machine is not running");
+                if !handle.allow_endpoint(address).await {
                     return false;
-                };
-                let (result_tx, result_rx) = oneshot::channel();
-                let _ = tunnel_tx.unbounded_send(TunnelCommand::AllowEndpoint(
-                    get_allowed_endpoint(address),
-                    result_tx,
-                ));
-                // Wait for the firewall policy to be updated.
-                let _ = result_rx.await;
+                }
+                *handle.current_endpoint.lock().unwrap() = Some(address);
                 log::debug!("API endpoint: {}", address);
                 true
             }
         }
     }
+
+    /// Allows `address` through the firewall while `future` runs. Afterwards, the endpoint most
+    /// recently accepted by the callback is allowed again, or `default_endpoint` if there is none.
+    ///
+    /// Only one endpoint is allowed at a time, so the main request service cannot reach the API
+    /// in the meantime.
+    pub async fn with_temporary_endpoint<T>(
+        &self,
+        address: SocketAddr,
+        default_endpoint: SocketAddr,
+        future: impl Future<Output = T>,
+    ) -> T {
+        self.allow_endpoint(address).await;
+        let output = future.await;
+        let current_endpoint = (*self.current_endpoint.lock().unwrap()).unwrap_or(default_endpoint);
+        self.allow_endpoint(current_endpoint).await;
+        output
+    }
+
+    /// Allows traffic to `address` through the firewall. Returns `false` if the tunnel state
+    /// machine is not running.
+    async fn allow_endpoint(&self, address: SocketAddr) -> bool {
+        let tunnel_tx = if let Some(Some(tunnel_tx)) =
+            { self.tunnel_cmd_tx.lock().unwrap().as_ref() }
+                .map(|tx: &Weak<mpsc::UnboundedSender<TunnelCommand>>| tx.upgrade())
+        {
+            tunnel_tx
+        } else {
+            log::error!("Rejecting allowed endpoint: Tunnel state machine is not running");
+            return false;
+        };
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = tunnel_tx.unbounded_send(TunnelCommand::AllowEndpoint(
+            get_allowed_endpoint(address),
+            result_tx,
+        ));
+        // Wait for the firewall policy to be updated.
+        let _ = result_rx.await;
+        true
+    }
 }
 
 pub(super) fn get_allowed_endpoint(api_address: SocketAddr) -> AllowedEndpoint {
@@ -213,3 +313,64 @@ pub(crate) fn create_bypass_tx(
     });
     Some(bypass_tx)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use talpid_types::net::openvpn::ShadowsocksProxySettings;
+
+    #[test]
+    fn test_access_method_rotation() {
+        let mut methods = mullvad_types::access_method::default_access_methods();
+        methods.push(ApiAccessMethod::new(
+            "custom".to_owned(),
+            AccessMethod::Shadowsocks(ShadowsocksProxySettings {
+                peer: "192.0.2.1:443".parse().unwrap(),
+                password: "password".to_owned(),
+                cipher: "aes-256-gcm".to_owned(),
+                #[cfg(target_os = "linux")]
+                fwmark: None,
+            }),
+        ));
+        methods[2].enabled = false;
+        let handle = AccessMethodsHandle::new(methods.clone());
+        let rotation = |handle: &AccessMethodsHandle| -> Vec<String> {
+            (0..6)
+                .map(|attempt| {
+                    handle
+                        .for_attempt(attempt)
+                        .map(|method| method.name)
+                        .unwrap_or_default()
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            rotation(&handle),
+            ["Direct", "Bridges", "Bridges", "Direct", "Bridges", "Bridges"]
+        );
+
+        methods[2].enabled = true;
+        handle.set(methods.clone());
+        assert_eq!(
+            rotation(&handle),
+            ["Direct", "Bridges", "custom", "Direct", "Bridges", "custom"]
+        );
+
+        methods[0].enabled = false;
+        handle.set(methods.clone());
+        assert_eq!(
+            rotation(&handle),
+            ["Bridges", "custom", "Bridges", "custom", "Bridges", "custom"]
+        );
+
+        methods[1].enabled = false;
+        methods[2].enabled = false;
+        handle.set(methods.clone());
+        assert!(handle.for_attempt(0).is_none());
+
+        methods[0].enabled = true;
+        handle.set(methods);
+        assert_eq!(rotation(&handle), ["Direct"; 6]);
+    }
+}
@@ -0,0 +1,256 @@
+//! Audit log of the commands that clients send to the daemon. Each entry records who sent a
+//! command and which settings it changed. The log is written as JSON lines to a file in the log
+//! directory, which is rotated when it grows too large.
+
+use chrono::Utc;
+use mullvad_types::audit_log::{AuditLogEntry, RpcContext, SettingChange};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use talpid_types::ErrorExt;
+use tokio::{
+    fs,
+    io::{self, AsyncWriteExt},
+};
+
+const AUDIT_LOG_FILE: &str = "audit-log.jsonl";
+const OLD_AUDIT_LOG_FILE: &str = "audit-log.old.jsonl";
+
+/// The log is rotated when it grows larger than this.
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// Keys of values that are replaced before they are written to the log.
+const SECRET_KEYS: &[&str] = &["password", "private_key", "psk"];
+const REDACTED: &str = "[redacted]";
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "The audit log is disabled because there is no log directory")]
+    Disabled,
+
+    #[error(display = "Unable to read audit log {}", _0)]
+    Read(String, #[error(source)] io::Error),
+
+    #[error(display = "Unable to write audit log {}", _0)]
+    Write(String, #[error(source)] io::Error),
+
+    #[error(display = "Failed to serialize audit log entry")]
+    Serialize(#[error(source)] serde_json::Error),
+}
+
+/// Writes entries to the audit log. Without a log directory, nothing is written.
+pub struct AuditLog {
+    log_dir: Option<PathBuf>,
+}
+
+impl AuditLog {
+    pub fn new(log_dir: Option<PathBuf>) -> Self {
+        AuditLog { log_dir }
+    }
+
+    pub fn log_dir(&self) -> Option<&Path> {
+        self.log_dir.as_deref()
+    }
+
+    /// Records a command, given the state before and after it was handled. Commands for which
+    /// [is_read_only] returns true should not be recorded.
+    pub async fn record(&self, context: RpcContext, before: &Value, after: &Value) {
+        let log_dir = match &self.log_dir {
+            Some(log_dir) => log_dir,
+            None => return,
+        };
+        let mut changes = vec![];
+        diff(String::new(), before, after, &mut changes);
+        let entry = AuditLogEntry {
+            timestamp: Utc::now(),
+            uid: context.uid,
+            pid: context.pid,
+            command: context.method,
+            changes,
+        };
+        if let Err(error) = append(log_dir, &entry).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to write to the audit log")
+            );
+        }
+    }
+}
+
+/// Returns whether an RPC only reads state. Such RPCs are not recorded.
+pub fn is_read_only(method: &str) -> bool {
+    ["Get", "List", "Is"]
+        .iter()
+        .any(|prefix| method.starts_with(prefix))
+        || method == "EventsListen"
+        || method == "RunDiagnostics"
+        || method == "RunLeakTest"
+}
+
+async fn append(log_dir: &Path, entry: &AuditLogEntry) -> Result<(), Error> {
+    let path = log_dir.join(AUDIT_LOG_FILE);
+    let mut line = serde_json::to_string(entry).map_err(Error::Serialize)?;
+    line.push('\n');
+
+    if let Ok(metadata) = fs::metadata(&path).await {
+        if metadata.len() + line.len() as u64 > MAX_LOG_SIZE {
+            fs::rename(&path, log_dir.join(OLD_AUDIT_LOG_FILE))
+                .await
+                .map_err(|error| Error::Write(path.display().to_string(), error))?;
+        }
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.create(true).append(true);
+    // The log reveals which users changed the settings, so only root may read it
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(&path)
+        .await
+        .map_err(|error| Error::Write(path.display().to_string(), error))?;
+    file.write_all(line.as_bytes())
+        .await
+        .map_err(|error| Error::Write(path.display().to_string(), error))
+}
+
+/// Reads the entries of the audit log, oldest first. If `limit` is non-zero, only that many of
+/// the most recent entries are returned. Lines that cannot be parsed are skipped.
+pub async fn read(log_dir: Option<&Path>, limit: usize) -> Result<Vec<AuditLogEntry>, Error> {
+    let log_dir = log_dir.ok_or(Error::Disabled)?;
+    let mut entries = vec![];
+    for file in [OLD_AUDIT_LOG_FILE, AUDIT_LOG_FILE] {
+        let path = log_dir.join(file);
+        let contents = match fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(Error::Read(path.display().to_string(), error)),
+        };
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(error) => log::warn!("Skipping invalid audit log entry: {}", error),
+            }
+        }
+    }
+    if limit > 0 && entries.len() > limit {
+        entries.drain(..entries.len() - limit);
+    }
+    Ok(entries)
+}
+
+/// Collects the values that differ between `old` and `new`. Objects are compared key by key, and
+/// all other values, including arrays, are compared as a whole.
+fn diff(path: String, old: &Value, new: &Value, changes: &mut Vec<SettingChange>) {
+    if old == new {
+        return;
+    }
+    if let (Value::Object(old_map), Value::Object(new_map)) = (old, new) {
+        let keys = old_map
+            .keys()
+            .chain(new_map.keys().filter(|key| !old_map.contains_key(*key)));
+        for key in keys {
+            let child_path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", path, key)
+            };
+            diff(
+                child_path,
+                old_map.get(key).unwrap_or(&Value::Null),
+                new_map.get(key).unwrap_or(&Value::Null),
+                changes,
+            );
+        }
+        return;
+    }
+    changes.push(SettingChange {
+        setting: path,
+        old_value: redact(old.clone()).to_string(),
+        new_value: redact(new.clone()).to_string(),
+    });
+}
+
+fn redact(mut value: Value) -> Value {
+    match &mut value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.to_owned());
+                } else {
+                    *value = redact(value.take());
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values.iter_mut() {
+                *value = redact(value.take());
+            }
+        }
+        _ => (),
+    }
+    value
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff() {
+        let before = json!({
+            "settings": {
+                "allow_lan": false,
+                "tunnel_options": { "dns_options": { "state": "default" } },
+                "api_access_methods": [],
+            },
+            "target_state": "unsecured",
+        });
+        let after = json!({
+            "settings": {
+                "allow_lan": true,
+                "tunnel_options": { "dns_options": { "state": "custom" } },
+                "api_access_methods": [{ "auth": { "password": "secret" } }],
+            },
+            "target_state": "unsecured",
+        });
+
+        let mut changes = vec![];
+        diff(String::new(), &before, &after, &mut changes);
+        let changes: Vec<_> = changes
+            .iter()
+            .map(|change| {
+                (
+                    change.setting.as_str(),
+                    change.old_value.as_str(),
+                    change.new_value.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("settings.allow_lan", "false", "true"),
+                (
+                    "settings.api_access_methods",
+                    "[]",
+                    "[{\"auth\":{\"password\":\"[redacted]\"}}]"
+                ),
+                (
+                    "settings.tunnel_options.dns_options.state",
+                    "\"default\"",
+                    "\"custom\""
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_read_only() {
+        assert!(is_read_only("GetSettings"));
+        assert!(is_read_only("EventsListen"));
+        assert!(!is_read_only("SetAllowLan"));
+        assert!(!is_read_only("ExportSettings"));
+    }
+}
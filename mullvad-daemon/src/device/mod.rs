@@ -8,8 +8,8 @@ use mullvad_api::rest;
 use mullvad_types::{
     account::{AccountToken, VoucherSubmission},
     device::{
-        AccountAndDevice, Device, DeviceEvent, DeviceEventCause, DeviceId, DeviceName, DevicePort,
-        DeviceState,
+        AccountAndDevice, AccountProfile, Device, DeviceEvent, DeviceEventCause, DeviceId,
+        DeviceName, DevicePort, DeviceState,
     },
     wireguard::{self, RotationInterval, WireguardData},
 };
@@ -30,6 +30,7 @@ use tokio::{
 };
 
 mod api;
+mod profiles;
 mod service;
 pub(crate) use service::{AccountService, DeviceService};
 
@@ -75,6 +76,14 @@ pub enum Error {
     AccountChange,
     #[error(display = "The account manager is down")]
     AccountManagerDown,
+    #[error(display = "No account profile is named \"{}\"", _0)]
+    ProfileNotFound(String),
+    #[error(display = "An account profile named \"{}\" already exists", _0)]
+    ProfileExists(String),
+    #[error(display = "The current device does not belong to an account profile")]
+    UnsavedDevice,
+    #[error(display = "The active account profile cannot be removed")]
+    ActiveProfile,
 }
 
 /// Contains the current device state.
@@ -311,6 +320,11 @@ enum AccountManagerCommand {
     ValidateDevice(ResponseTx<()>),
     SubmitVoucher(String, ResponseTx<VoucherSubmission>),
     CheckExpiry(ResponseTx<DateTime<Utc>>),
+    ListProfiles(ResponseTx<Vec<AccountProfile>>),
+    SaveProfile(String, ResponseTx<()>),
+    SwitchProfile(String, ResponseTx<()>),
+    RemoveProfile(String, ResponseTx<()>),
+    ClearProfiles(ResponseTx<()>),
     Shutdown(oneshot::Sender<()>),
 }
 
@@ -368,6 +382,34 @@ impl AccountManagerHandle {
         self.send_command(AccountManagerCommand::CheckExpiry).await
     }
 
+    pub async fn list_profiles(&self) -> Result<Vec<AccountProfile>, Error> {
+        self.send_command(AccountManagerCommand::ListProfiles).await
+    }
+
+    /// Assign the current device to a new account profile.
+    pub async fn save_profile(&self, name: String) -> Result<(), Error> {
+        self.send_command(|tx| AccountManagerCommand::SaveProfile(name, tx))
+            .await
+    }
+
+    /// Replace the current device with the device of another account profile.
+    pub async fn switch_profile(&self, name: String) -> Result<(), Error> {
+        self.send_command(|tx| AccountManagerCommand::SwitchProfile(name, tx))
+            .await
+    }
+
+    /// Remove an inactive account profile and its device.
+    pub async fn remove_profile(&self, name: String) -> Result<(), Error> {
+        self.send_command(|tx| AccountManagerCommand::RemoveProfile(name, tx))
+            .await
+    }
+
+    /// Remove all account profiles and the devices of the inactive ones.
+    pub async fn clear_profiles(&self) -> Result<(), Error> {
+        self.send_command(AccountManagerCommand::ClearProfiles)
+            .await
+    }
+
     pub async fn shutdown(self) {
         let (tx, rx) = oneshot::channel();
         let _ = self
@@ -390,6 +432,7 @@ impl AccountManagerHandle {
 
 pub(crate) struct AccountManager {
     cacher: DeviceCacher,
+    profiles: profiles::ProfileStore,
     account_service: AccountService,
     device_service: DeviceService,
     data: PrivateDeviceState,
@@ -412,6 +455,7 @@ impl AccountManager {
         listener_tx: impl Sender<AccountEvent> + Send + 'static,
     ) -> Result<(AccountManagerHandle, PrivateDeviceState), Error> {
         let (cacher, data) = DeviceCacher::new(settings_dir).await?;
+        let profiles = profiles::ProfileStore::new(settings_dir, &data).await;
         let token = data.device().map(|state| state.account_token.clone());
         let api_availability = rest_handle.availability.clone();
         let account_service =
@@ -422,6 +466,7 @@ impl AccountManager {
         let device_service = DeviceService::new(rest_handle, api_availability);
         let manager = AccountManager {
             cacher,
+            profiles,
             account_service: account_service.clone(),
             device_service: device_service.clone(),
             data: data.clone(),
@@ -516,6 +561,32 @@ impl AccountManager {
                         Some(AccountManagerCommand::CheckExpiry(tx)) => {
                             self.handle_expiry_request(tx, &mut current_api_call);
                         },
+                        Some(AccountManagerCommand::ListProfiles(tx)) => {
+                            let _ = tx.send(Ok(self.profiles.list(&self.data)));
+                        }
+                        Some(AccountManagerCommand::SaveProfile(name, tx)) => {
+                            let _ = tx.send(self.save_profile(name).await);
+                        }
+                        Some(AccountManagerCommand::SwitchProfile(name, tx)) => {
+                            if current_api_call.is_logging_in() {
+                                let _ = tx.send(Err(Error::AccountChange));
+                                continue
+                            }
+                            let result = self.switch_profile(&name, &mut current_api_call).await;
+                            let _ = tx.send(result);
+                        }
+                        Some(AccountManagerCommand::RemoveProfile(name, tx)) => {
+                            let result = self.profiles.remove(&name).await.map(|data| {
+                                tokio::spawn(self.logout_api_call(data));
+                            });
+                            let _ = tx.send(result);
+                        }
+                        Some(AccountManagerCommand::ClearProfiles(tx)) => {
+                            for data in self.profiles.clear().await {
+                                tokio::spawn(self.logout_api_call(data));
+                            }
+                            let _ = tx.send(Ok(()));
+                        }
 
                         None => {
                             break;
@@ -841,6 +912,8 @@ impl AccountManager {
     async fn revoke_device(&mut self, err_constructor: impl Fn() -> Error) {
         log::debug!("Invalidating the current device");
 
+        self.profiles.clear_active().await;
+
         if let Err(err) = self.cacher.write(&PrivateDeviceState::Revoked).await {
             log::error!(
                 "{}",
@@ -872,6 +945,7 @@ impl AccountManager {
         }
 
         let old_config = self.data.logout();
+        self.profiles.clear_active().await;
 
         self.listeners.retain(|listener| {
             listener
@@ -919,7 +993,10 @@ impl AccountManager {
 
         if let Some(old_config) = self.data.logout() {
             if device_state.device().map(|d| &d.device.id) != Some(&old_config.device.id) {
-                tokio::spawn(self.logout_api_call(old_config));
+                // Keep the device if it belongs to a profile
+                if let Some(old_config) = self.profiles.stash_active(old_config).await {
+                    tokio::spawn(self.logout_api_call(old_config));
+                }
             }
         }
 
@@ -932,6 +1009,42 @@ impl AccountManager {
         Ok(())
     }
 
+    async fn save_profile(&mut self, name: String) -> Result<(), Error> {
+        if !self.data.logged_in() {
+            return Err(Error::NoDevice);
+        }
+        if self.profiles.active() == Some(name.as_str()) {
+            return Ok(());
+        }
+        self.profiles.save_active(name).await
+    }
+
+    async fn switch_profile(
+        &mut self,
+        name: &str,
+        api_call: &mut api::CurrentApiCall,
+    ) -> Result<(), Error> {
+        if self.profiles.active() == Some(name) {
+            return Ok(());
+        }
+        let new_data = self
+            .profiles
+            .switch(name, self.data.device().cloned())
+            .await?;
+
+        // Results of API calls for the previous device must not be applied to the new one
+        api_call.clear();
+        Self::drain_requests(&mut self.validation_requests, || Err(Error::AccountChange));
+        Self::drain_requests(&mut self.rotation_requests, || Err(Error::AccountChange));
+        Self::drain_requests(&mut self.expiry_requests, || Err(Error::AccountChange));
+
+        log::info!("Switching to account profile \"{}\"", name);
+
+        // The previous device now belongs to its profile, so `set` must not remove it
+        self.data.logout();
+        self.set(PrivateDeviceEvent::Login(new_data)).await
+    }
+
     fn initiate_key_rotation(
         &self,
     ) -> Result<impl Future<Output = Result<WireguardData, Error>>, Error> {
@@ -0,0 +1,326 @@
+//! Named account profiles. Each profile keeps its own device, so that switching between accounts
+//! does not require registering a new device.
+
+use super::{DeviceCacher, Error, PrivateAccountAndDevice, PrivateDeviceState};
+use mullvad_types::device::{AccountProfile, Device};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use talpid_types::ErrorExt;
+use tokio::{fs, io::AsyncWriteExt};
+
+/// File that stores the devices of all inactive profiles.
+const PROFILES_FILENAME: &str = "account-profiles.json";
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, Eq)]
+struct Profiles {
+    /// Name of the profile that the current device belongs to, if any.
+    active: Option<String>,
+    /// Devices of all profiles except the active one. The device of the active profile is the
+    /// current device.
+    inactive: BTreeMap<String, PrivateAccountAndDevice>,
+}
+
+pub(super) struct ProfileStore {
+    path: PathBuf,
+    profiles: Profiles,
+}
+
+impl ProfileStore {
+    pub async fn new(settings_dir: &Path, current: &PrivateDeviceState) -> Self {
+        let path = settings_dir.join(PROFILES_FILENAME);
+        let mut profiles = match fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Wiping account profiles due to an error")
+                );
+                Profiles::default()
+            }),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Profiles::default(),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read account profiles")
+                );
+                Profiles::default()
+            }
+        };
+        if !current.logged_in() {
+            profiles.active = None;
+        }
+        ProfileStore { path, profiles }
+    }
+
+    /// Returns the name of the profile that the current device belongs to.
+    pub fn active(&self) -> Option<&str> {
+        self.profiles.active.as_deref()
+    }
+
+    /// Returns all profiles, including the active one, sorted by name.
+    pub fn list(&self, current: &PrivateDeviceState) -> Vec<AccountProfile> {
+        let active = self
+            .profiles
+            .active
+            .as_ref()
+            .zip(current.device())
+            .map(|(name, data)| (name, data, true));
+        let inactive = self
+            .profiles
+            .inactive
+            .iter()
+            .map(|(name, data)| (name, data, false));
+        let mut profiles: Vec<_> = active
+            .into_iter()
+            .chain(inactive)
+            .map(|(name, data, active)| AccountProfile {
+                name: name.clone(),
+                account_token: data.account_token.clone(),
+                device: Device::from(data.device.clone()),
+                active,
+            })
+            .collect();
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        profiles
+    }
+
+    /// Assigns the current device to a new profile.
+    pub async fn save_active(&mut self, name: String) -> Result<(), Error> {
+        if self.profiles.inactive.contains_key(&name) {
+            return Err(Error::ProfileExists(name));
+        }
+        self.profiles.active = Some(name);
+        self.write().await
+    }
+
+    /// Replaces the active profile with the profile `name` and returns its device. The previous
+    /// device, if there is one, is stored in the previously active profile.
+    pub async fn switch(
+        &mut self,
+        name: &str,
+        current: Option<PrivateAccountAndDevice>,
+    ) -> Result<PrivateAccountAndDevice, Error> {
+        if current.is_some() && self.profiles.active.is_none() {
+            return Err(Error::UnsavedDevice);
+        }
+        let mut profiles = self.profiles.clone();
+        let target = profiles
+            .inactive
+            .remove(name)
+            .ok_or_else(|| Error::ProfileNotFound(name.to_owned()))?;
+        if let (Some(previous), Some(current)) = (profiles.active.take(), current) {
+            profiles.inactive.insert(previous, current);
+        }
+        profiles.active = Some(name.to_owned());
+
+        let old_profiles = std::mem::replace(&mut self.profiles, profiles);
+        if let Err(error) = self.write().await {
+            self.profiles = old_profiles;
+            return Err(error);
+        }
+        Ok(target)
+    }
+
+    /// Stores the device of the active profile after the current device was replaced by logging
+    /// in. Returns the device if there is no active profile.
+    pub async fn stash_active(
+        &mut self,
+        current: PrivateAccountAndDevice,
+    ) -> Option<PrivateAccountAndDevice> {
+        let name = match self.profiles.active.take() {
+            Some(name) => name,
+            None => return Some(current),
+        };
+        log::debug!("Keeping the device of account profile \"{}\"", name);
+        self.profiles.inactive.insert(name, current);
+        self.write_or_log().await;
+        None
+    }
+
+    /// Forgets the active profile, since its device has been removed.
+    pub async fn clear_active(&mut self) {
+        if self.profiles.active.take().is_some() {
+            self.write_or_log().await;
+        }
+    }
+
+    /// Removes an inactive profile and returns its device.
+    pub async fn remove(&mut self, name: &str) -> Result<PrivateAccountAndDevice, Error> {
+        if self.profiles.active.as_deref() == Some(name) {
+            return Err(Error::ActiveProfile);
+        }
+        let data = self
+            .profiles
+            .inactive
+            .remove(name)
+            .ok_or_else(|| Error::ProfileNotFound(name.to_owned()))?;
+        self.write().await?;
+        Ok(data)
+    }
+
+    /// Removes all profiles and returns the devices of the inactive ones.
+    pub async fn clear(&mut self) -> Vec<PrivateAccountAndDevice> {
+        let profiles = std::mem::take(&mut self.profiles);
+        self.write_or_log().await;
+        profiles.inactive.into_values().collect()
+    }
+
+    async fn write_or_log(&self) {
+        if let Err(error) = self.write().await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to save account profiles")
+            );
+        }
+    }
+
+    /// Writes the profiles to a temporary file that then replaces the profiles file, so that the
+    /// file is never left partially written.
+    async fn write(&self) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(&self.profiles).unwrap();
+        let temp_path = self.path.with_extension("json.tmp");
+        let mut file = fs::OpenOptions::from(DeviceCacher::file_options())
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_path)
+            .await?;
+        // The mode is only applied when the file is created
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))
+                .await?;
+        }
+        file.write_all(&data).await?;
+        file.sync_all().await?;
+        // The file must be closed before it can be renamed on Windows
+        drop(file);
+        fs::rename(&temp_path, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::device::PrivateDevice;
+    use mullvad_types::wireguard::{AssociatedAddresses, WireguardData};
+    use talpid_types::net::wireguard::PrivateKey;
+
+    fn device(account_token: &str) -> PrivateAccountAndDevice {
+        PrivateAccountAndDevice {
+            account_token: account_token.to_owned(),
+            device: PrivateDevice {
+                id: format!("{account_token}-device"),
+                name: "test device".to_owned(),
+                wg_data: WireguardData {
+                    private_key: PrivateKey::new_from_random(),
+                    addresses: AssociatedAddresses {
+                        ipv4_address: "10.64.0.2/32".parse().unwrap(),
+                        ipv6_address: "fc00:bbbb:bbbb:bb01::2/128".parse().unwrap(),
+                    },
+                    created: chrono::Utc::now(),
+                },
+                ports: vec![],
+                hijack_dns: false,
+                created: chrono::Utc::now(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_switch_profiles() {
+        let dir = crate::test_dir();
+        let first = device("1111");
+        let second = device("2222");
+        let current = PrivateDeviceState::LoggedIn(first.clone());
+
+        let mut store = ProfileStore::new(dir.path(), &current).await;
+        assert!(matches!(
+            store.switch("second", Some(first.clone())).await,
+            Err(Error::UnsavedDevice)
+        ));
+        store.save_active("first".to_owned()).await.unwrap();
+
+        // Logging in to another account keeps the device of the active profile
+        assert!(store.stash_active(first.clone()).await.is_none());
+        assert_eq!(store.active(), None);
+        let current = PrivateDeviceState::LoggedIn(second.clone());
+        assert!(matches!(
+            store.save_active("first".to_owned()).await,
+            Err(Error::ProfileExists(_))
+        ));
+        store.save_active("second".to_owned()).await.unwrap();
+
+        let profiles = store.list(&current);
+        assert_eq!(
+            profiles
+                .iter()
+                .map(|profile| (profile.name.as_str(), profile.active))
+                .collect::<Vec<_>>(),
+            vec![("first", false), ("second", true)]
+        );
+
+        let target = store.switch("first", Some(second.clone())).await.unwrap();
+        assert_eq!(target, first);
+        assert_eq!(store.active(), Some("first"));
+        assert!(matches!(
+            store.switch("first", None).await,
+            Err(Error::ProfileNotFound(_))
+        ));
+        assert!(matches!(
+            store.remove("first").await,
+            Err(Error::ActiveProfile)
+        ));
+
+        // The profiles are persisted
+        let current = PrivateDeviceState::LoggedIn(first.clone());
+        let mut store = ProfileStore::new(dir.path(), &current).await;
+        assert_eq!(store.active(), Some("first"));
+        assert_eq!(store.remove("second").await.unwrap(), second);
+        assert!(store.clear().await.is_empty());
+
+        let store = ProfileStore::new(dir.path(), &current).await;
+        assert_eq!(store.active(), None);
+        assert!(store.list(&current).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_logged_out_has_no_active_profile() {
+        let dir = crate::test_dir();
+        let current = PrivateDeviceState::LoggedIn(device("1111"));
+        let mut store = ProfileStore::new(dir.path(), &current).await;
+        store.save_active("first".to_owned()).await.unwrap();
+
+        let store = ProfileStore::new(dir.path(), &PrivateDeviceState::LoggedOut).await;
+        assert_eq!(store.active(), None);
+    }
+
+    #[tokio::test]
+    async fn test_write_replaces_file() {
+        let dir = crate::test_dir();
+        let path = dir.path().join(PROFILES_FILENAME);
+        std::fs::write(&path, "stale").unwrap();
+
+        let current = PrivateDeviceState::LoggedIn(device("1111"));
+        let mut store = ProfileStore::new(dir.path(), &current).await;
+        store.save_active("first".to_owned()).await.unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::metadata(&path).unwrap().permissions().mode()
+        };
+        let temp_exists = path.with_extension("json.tmp").exists();
+
+        let profiles: Profiles = serde_json::from_str(&content).unwrap();
+        assert_eq!(profiles.active.as_deref(), Some("first"));
+        assert!(!temp_exists);
+        #[cfg(unix)]
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,366 @@
+//! Checks that help diagnose problems with the tunnel. Each check passes or fails, and a failed
+//! check comes with a hint on how to fix the problem. The state of the firewall, routing and DNS
+//! is reported by the tunnel state machine, and the remaining checks are run by the daemon.
+
+use chrono::Utc;
+use futures::future::join_all;
+use mullvad_api::{proxy::ApiConnectionMode, rest::MullvadRestHandle, ApiProxy};
+use mullvad_types::diagnostics::DiagnosticCheck;
+#[cfg(target_os = "linux")]
+use talpid_core::{split_tunnel::PidManager, tunnel_state_machine::TunnelDiagnostics};
+use talpid_types::{net::wireguard::TunnelOptions, ErrorExt};
+
+/// The clock may differ this much from the time reported by the API before the check fails.
+const MAX_CLOCK_SKEW_SECS: i64 = 60;
+
+/// An API access method to test, and the request service to test it with.
+pub struct ApiAccessTest {
+    pub name: String,
+    pub connection_mode: ApiConnectionMode,
+    pub rest_handle: MullvadRestHandle,
+}
+
+/// Returns checks of the firewall, routing and DNS state reported by the tunnel state machine.
+#[cfg(target_os = "linux")]
+pub async fn tunnel_checks(diagnostics: TunnelDiagnostics) -> Vec<DiagnosticCheck> {
+    let will_use_nm = tokio::task::spawn_blocking(talpid_core::dns::will_use_nm)
+        .await
+        .unwrap_or(false);
+    vec![
+        firewall_check(&diagnostics),
+        routing_check(&diagnostics),
+        dns_check(&diagnostics, will_use_nm),
+    ]
+}
+
+#[cfg(target_os = "linux")]
+fn firewall_check(diagnostics: &TunnelDiagnostics) -> DiagnosticCheck {
+    const NAME: &str = "firewall";
+    match &diagnostics.missing_firewall_tables {
+        Ok(missing) if diagnostics.firewall_expected && missing.is_empty() => {
+            DiagnosticCheck::pass(NAME, "The nftables tables are in place")
+        }
+        Ok(missing) if diagnostics.firewall_expected => DiagnosticCheck::fail(
+            NAME,
+            format!("Missing nftables tables: {}", missing.join(", ")),
+            "Make sure that the kernel supports nftables (nf_tables) and that no other program, \
+             such as a firewall manager that flushes the ruleset, removes the tables. \
+             Reconnecting applies the rules again.",
+        ),
+        // All three tables are removed when the policy is reset.
+        Ok(missing) if missing.len() == 3 => {
+            DiagnosticCheck::pass(NAME, "No firewall rules are needed in the current state")
+        }
+        Ok(_) => DiagnosticCheck::fail(
+            NAME,
+            "nftables tables are present although no firewall rules should be applied",
+            "The tables may have been left behind by another instance of the daemon. \
+             Restarting the daemon removes them.",
+        ),
+        Err(error) => DiagnosticCheck::fail(
+            NAME,
+            format!("Failed to list the nftables tables: {}", error),
+            "Make sure that the kernel supports nftables (nf_tables).",
+        ),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn routing_check(diagnostics: &TunnelDiagnostics) -> DiagnosticCheck {
+    const NAME: &str = "routing";
+    if !diagnostics.routing_rules_expected {
+        return DiagnosticCheck::skipped(
+            NAME,
+            "No routing rules are used for the tunnel in the current state",
+        );
+    }
+    match &diagnostics.missing_routing_rules {
+        Ok(missing) if missing.is_empty() => {
+            DiagnosticCheck::pass(NAME, "The routing rules for the tunnel are in place")
+        }
+        Ok(missing) => DiagnosticCheck::fail(
+            NAME,
+            format!("Missing routing rules: {}", missing.join("; ")),
+            "Another program may have removed the rules, e.g. by flushing `ip rule`. \
+             Reconnecting creates them again.",
+        ),
+        Err(error) => DiagnosticCheck::fail(
+            NAME,
+            format!("Failed to list the routing rules: {}", error),
+            "Make sure that the kernel supports policy routing (CONFIG_IP_MULTIPLE_TABLES).",
+        ),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn dns_check(diagnostics: &TunnelDiagnostics, will_use_nm: bool) -> DiagnosticCheck {
+    const NAME: &str = "dns";
+    let nm_note = if will_use_nm {
+        ". DNS is managed via NetworkManager, since systemd-resolved is not available"
+    } else {
+        ""
+    };
+    match &diagnostics.dns_backend {
+        Some(backend) => {
+            DiagnosticCheck::pass(NAME, format!("Managing DNS via {}{}", backend, nm_note))
+        }
+        None => DiagnosticCheck::skipped(
+            NAME,
+            format!("DNS is not managed in the current state{}", nm_note),
+        ),
+    }
+}
+
+/// Checks that processes can be excluded from the tunnel using the `net_cls` cgroup.
+#[cfg(target_os = "linux")]
+pub fn split_tunnel_check(pid_manager: &PidManager) -> DiagnosticCheck {
+    const NAME: &str = "split_tunnel";
+    let mount = match talpid_types::cgroup::find_net_cls_mount() {
+        Ok(Some(mount)) => mount,
+        Ok(None) => {
+            return DiagnosticCheck::fail(
+                NAME,
+                "The net_cls cgroup is not mounted",
+                "Make sure that the kernel supports the cgroup v1 net_cls controller \
+                 (CONFIG_CGROUP_NET_CLASSID), and restart the daemon to mount it. \
+                 TALPID_NET_CLS_MOUNT_DIR sets where it is mounted.",
+            )
+        }
+        Err(error) => {
+            return DiagnosticCheck::fail(
+                NAME,
+                format!("Failed to read the mounted file systems: {}", error),
+                "Make sure that /proc is mounted.",
+            )
+        }
+    };
+    match pid_manager.list() {
+        Ok(pids) => DiagnosticCheck::pass(
+            NAME,
+            format!(
+                "The net_cls cgroup is mounted at {}, and {} processes are excluded",
+                mount.display(),
+                pids.len()
+            ),
+        ),
+        Err(error) => DiagnosticCheck::fail(
+            NAME,
+            format!("Failed to list the excluded processes: {}", error),
+            "The exclusion cgroup may have been removed. Restarting the daemon creates it again.",
+        ),
+    }
+}
+
+/// Reports which WireGuard implementation is used when connecting.
+pub fn wireguard_backend_check(options: &TunnelOptions) -> DiagnosticCheck {
+    const NAME: &str = "wireguard_backend";
+
+    #[cfg(target_os = "linux")]
+    {
+        let kernel_module_loaded = std::path::Path::new("/sys/module/wireguard").exists();
+        if options.use_network_namespace {
+            return if kernel_module_loaded {
+                DiagnosticCheck::pass(NAME, "Kernel WireGuard in a network namespace")
+            } else {
+                DiagnosticCheck::fail(
+                    NAME,
+                    "Kernel WireGuard in a network namespace, but the wireguard kernel module is \
+                     not loaded",
+                    "Load the module with `modprobe wireguard`, or turn off \
+                     `mullvad tunnel wireguard network-namespace`.",
+                )
+            };
+        }
+        if options.use_boringtun {
+            return DiagnosticCheck::pass(NAME, "Userspace WireGuard (boringtun)");
+        }
+        let force_userspace = std::env::var("TALPID_FORCE_USERSPACE_WIREGUARD")
+            .map(|value| value != "0")
+            .unwrap_or(false);
+        if force_userspace {
+            return DiagnosticCheck::pass(
+                NAME,
+                "Userspace WireGuard, forced by TALPID_FORCE_USERSPACE_WIREGUARD",
+            );
+        }
+        if kernel_module_loaded {
+            return DiagnosticCheck::pass(NAME, "Kernel WireGuard");
+        }
+        DiagnosticCheck::pass(
+            NAME,
+            "Kernel WireGuard if the wireguard kernel module can be loaded, otherwise userspace \
+             WireGuard",
+        )
+    }
+
+    #[cfg(windows)]
+    {
+        let details = if options.use_wireguard_nt {
+            "WireGuardNT, or wireguard-go if it fails to start"
+        } else {
+            "wireguard-go"
+        };
+        DiagnosticCheck::pass(NAME, details)
+    }
+
+    #[cfg(not(any(target_os = "linux", windows)))]
+    {
+        let _ = options;
+        DiagnosticCheck::pass(NAME, "wireguard-go")
+    }
+}
+
+/// Tests whether the API can be reached using each access method, and compares the time
+/// reported by the API with the system clock.
+pub async fn api_checks(tests: Vec<ApiAccessTest>) -> Vec<DiagnosticCheck> {
+    let results = join_all(tests.into_iter().map(|test| async move {
+        let result = ApiProxy::new(test.rest_handle).get_server_time().await;
+        (test.name, test.connection_mode, result)
+    }))
+    .await;
+
+    let mut checks = vec![];
+    let mut server_time = None;
+    for (name, connection_mode, result) in results {
+        let check_name = format!("api_access:{}", name);
+        match result {
+            Ok(time) => {
+                server_time = server_time.or(time);
+                checks.push(DiagnosticCheck::pass(
+                    &check_name,
+                    format!("The API is reachable ({})", connection_mode),
+                ));
+            }
+            Err(error) => checks.push(DiagnosticCheck::fail(
+                &check_name,
+                format!(
+                    "The API is unreachable ({}): {}",
+                    connection_mode,
+                    error.display_chain()
+                ),
+                "Check the network connection and the settings of the access method. While all \
+                 traffic is blocked, only the access method in use is allowed by the firewall.",
+            )),
+        }
+    }
+    checks.push(clock_check(server_time));
+    checks
+}
+
+fn clock_check(server_time: Option<chrono::DateTime<Utc>>) -> DiagnosticCheck {
+    const NAME: &str = "clock";
+    let server_time = match server_time {
+        Some(server_time) => server_time,
+        None => {
+            return DiagnosticCheck::skipped(
+                NAME,
+                "The time could not be compared, since the API was not reachable",
+            )
+        }
+    };
+    let skew = (Utc::now() - server_time).num_seconds();
+    if skew.abs() <= MAX_CLOCK_SKEW_SECS {
+        DiagnosticCheck::pass(
+            NAME,
+            format!("The system clock differs by {} seconds from the API", skew),
+        )
+    } else {
+        DiagnosticCheck::fail(
+            NAME,
+            format!(
+                "The system clock is {} seconds {} the API",
+                skew.abs(),
+                if skew > 0 { "ahead of" } else { "behind" }
+            ),
+            "Turn on time synchronization, e.g. with `timedatectl set-ntp true`. A wrong clock \
+             can make WireGuard handshakes and TLS connections fail.",
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mullvad_types::diagnostics::CheckStatus;
+
+    #[cfg(target_os = "linux")]
+    fn diagnostics(
+        firewall_expected: bool,
+        missing_firewall_tables: &[&str],
+        routing_rules_expected: bool,
+        missing_routing_rules: &[&str],
+    ) -> TunnelDiagnostics {
+        let to_strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+        TunnelDiagnostics {
+            firewall_expected,
+            missing_firewall_tables: Ok(to_strings(missing_firewall_tables)),
+            routing_rules_expected,
+            missing_routing_rules: Ok(to_strings(missing_routing_rules)),
+            dns_backend: None,
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_firewall_check() {
+        const ALL_TABLES: [&str; 3] = ["mullvad", "mullvadmangle4", "mullvadmangle6"];
+        let status = |diagnostics| firewall_check(&diagnostics).status;
+
+        assert_eq!(
+            status(diagnostics(true, &[], false, &[])),
+            CheckStatus::Pass
+        );
+        assert_eq!(
+            status(diagnostics(true, &ALL_TABLES[..1], false, &[])),
+            CheckStatus::Fail
+        );
+        assert_eq!(
+            status(diagnostics(false, &ALL_TABLES, false, &[])),
+            CheckStatus::Pass
+        );
+        assert_eq!(
+            status(diagnostics(false, &ALL_TABLES[1..], false, &[])),
+            CheckStatus::Fail
+        );
+
+        let mut failed_listing = diagnostics(true, &[], false, &[]);
+        failed_listing.missing_firewall_tables = Err("nft not found".to_owned());
+        assert_eq!(status(failed_listing), CheckStatus::Fail);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_routing_check() {
+        let status = |diagnostics| routing_check(&diagnostics).status;
+
+        assert_eq!(
+            status(diagnostics(true, &[], false, &[])),
+            CheckStatus::Skipped
+        );
+        assert_eq!(status(diagnostics(true, &[], true, &[])), CheckStatus::Pass);
+        assert_eq!(
+            status(diagnostics(true, &[], true, &["not from all fwmark"])),
+            CheckStatus::Fail
+        );
+    }
+
+    #[test]
+    fn test_clock_check() {
+        let skewed = |secs| Some(Utc::now() + chrono::Duration::seconds(secs));
+
+        assert_eq!(clock_check(None).status, CheckStatus::Skipped);
+        assert_eq!(clock_check(skewed(0)).status, CheckStatus::Pass);
+        assert_eq!(
+            clock_check(skewed(MAX_CLOCK_SKEW_SECS / 2)).status,
+            CheckStatus::Pass
+        );
+
+        let behind = clock_check(skewed(10 * MAX_CLOCK_SKEW_SECS));
+        assert_eq!(behind.status, CheckStatus::Fail);
+        assert!(behind.details.contains("behind"));
+
+        let ahead = clock_check(skewed(-10 * MAX_CLOCK_SKEW_SECS));
+        assert_eq!(ahead.status, CheckStatus::Fail);
+        assert!(ahead.details.contains("ahead of"));
+    }
+}
@@ -33,6 +33,6 @@ pub async fn initialize_firewall() -> Result<(), Error> {
 
 async fn get_allow_lan() -> Result<bool, Error> {
     let path = mullvad_paths::settings_dir()?;
-    let settings = SettingsPersister::load(&path).await;
+    let settings = SettingsPersister::load(&path).await?;
     Ok(settings.allow_lan)
 }
@@ -4,24 +4,31 @@
 #[macro_use]
 extern crate serde;
 
+mod account_expiry;
 pub mod account_history;
 mod api;
+mod audit_log;
 #[cfg(not(target_os = "android"))]
 mod cleanup;
 pub mod device;
+mod diagnostics;
 mod dns;
 pub mod exception_logging;
 mod geoip;
 pub mod logging;
 #[cfg(target_os = "macos")]
 mod macos;
+#[cfg(all(unix, not(target_os = "android")))]
+mod management_access;
 #[cfg(not(target_os = "android"))]
 pub mod management_interface;
 mod migrations;
+mod policy;
 #[cfg(not(target_os = "android"))]
 pub mod rpc_uniqueness_check;
 pub mod runtime;
 pub mod settings;
+mod settings_bundle;
 pub mod shutdown;
 mod target_state;
 mod tunnel;
@@ -40,15 +47,23 @@ use mullvad_relay_selector::{
     RelaySelector, SelectorConfig,
 };
 use mullvad_types::{
-    account::{AccountData, AccountToken, VoucherSubmission},
+    access_method::ApiAccessMethod,
+    account::{AccountData, AccountExpiryEvent, AccountToken, VoucherSubmission},
+    api_diagnostics::ApiDiagnostics,
+    audit_log::{AuditLogEntry, RpcContext},
     auth_failed::AuthFailed,
-    device::{Device, DeviceEvent, DeviceEventCause, DeviceId, DeviceState, RemoveDeviceEvent},
+    device::{
+        AccountProfile, Device, DeviceEvent, DeviceEventCause, DeviceId, DeviceState,
+        RemoveDeviceEvent,
+    },
+    diagnostics::DiagnosticCheck,
     location::GeoIpLocation,
     relay_constraints::{BridgeSettings, BridgeState, ObfuscationSettings, RelaySettingsUpdate},
     relay_list::RelayList,
-    settings::{DnsOptions, Settings},
+    settings::{DnsOptions, Settings, SettingsPatch},
     states::{TargetState, TunnelState},
     version::{AppVersion, AppVersionInfo},
+    wg_quick::WgQuickConfig,
     wireguard::{PublicKey, RotationInterval},
 };
 use settings::SettingsPersister;
@@ -59,11 +74,14 @@ use std::{collections::HashSet, ffi::OsString};
 use std::{
     marker::PhantomData,
     mem,
-    path::PathBuf,
+    net::IpAddr,
+    path::{Path, PathBuf},
     pin::Pin,
     sync::{Arc, Weak},
     time::Duration,
 };
+#[cfg(target_os = "linux")]
+use talpid_core::leak_test;
 #[cfg(any(target_os = "linux", windows))]
 use talpid_core::split_tunnel;
 use talpid_core::{
@@ -72,6 +90,8 @@ use talpid_core::{
 };
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
+#[cfg(target_os = "linux")]
+use talpid_types::leak_test::ProbeResult;
 use talpid_types::{
     net::{TunnelEndpoint, TunnelType},
     tunnel::{ErrorStateCause, TunnelStateTransition},
@@ -131,6 +151,15 @@ pub enum Error {
     #[error(display = "Failed to submit voucher")]
     VoucherSubmission(#[error(source)] device::Error),
 
+    #[error(display = "Failed to update account profiles")]
+    AccountProfileError(#[error(source)] device::Error),
+
+    #[error(display = "Failed to export or import settings")]
+    SettingsBundleError(#[error(source)] settings_bundle::Error),
+
+    #[error(display = "Failed to read the audit log")]
+    AuditLogError(#[error(source)] audit_log::Error),
+
     #[cfg(target_os = "linux")]
     #[error(display = "Unable to initialize split tunneling")]
     InitSplitTunneling(#[error(source)] split_tunnel::Error),
@@ -161,6 +190,21 @@ pub enum Error {
     #[error(display = "Tunnel state machine error")]
     TunnelError(#[error(source)] tunnel_state_machine::Error),
 
+    #[error(display = "Not connected to a WireGuard relay")]
+    NoWireguardConnection,
+
+    #[error(display = "Failed to export WireGuard config")]
+    ExportWireguardConfig(#[error(source)] mullvad_types::wg_quick::Error),
+
+    #[error(display = "An API access method named \"{}\" already exists", _0)]
+    ApiAccessMethodExists(String),
+
+    #[error(display = "No API access method named \"{}\" exists", _0)]
+    NoSuchApiAccessMethod(String),
+
+    #[error(display = "Built-in API access methods cannot be removed")]
+    RemoveBuiltinApiAccessMethod,
+
     #[cfg(target_os = "macos")]
     #[error(display = "Failed to set exclusion group")]
     GroupIdError(#[error(source)] io::Error),
@@ -207,14 +251,51 @@ pub enum DaemonCommand {
     ListDevices(ResponseTx<Vec<Device>, Error>, AccountToken),
     /// Remove device from a given account.
     RemoveDevice(ResponseTx<(), Error>, AccountToken, DeviceId),
+    /// Return all account profiles.
+    ListAccountProfiles(ResponseTx<Vec<AccountProfile>, Error>),
+    /// Assign the current device to a new account profile.
+    SaveAccountProfile(ResponseTx<(), Error>, String),
+    /// Replace the current device with the device of an account profile.
+    SwitchAccountProfile(ResponseTx<(), Error>, String),
+    /// Remove an inactive account profile and its device.
+    RemoveAccountProfile(ResponseTx<(), Error>, String),
     /// Place constraints on the type of tunnel and relay
     UpdateRelaySettings(ResponseTx<(), settings::Error>, RelaySettingsUpdate),
+    /// Append an API access method to the list of methods to try.
+    AddApiAccessMethod(ResponseTx<(), Error>, ApiAccessMethod),
+    /// Remove a custom API access method by name.
+    RemoveApiAccessMethod(ResponseTx<(), Error>, String),
+    /// Enable or disable an API access method by name.
+    SetApiAccessMethodEnabled(ResponseTx<(), Error>, String, bool),
+    /// Check whether the API can be reached using an API access method.
+    TestApiAccessMethod(ResponseTx<bool, Error>, String),
+    /// Get statistics about requests made to the API.
+    GetApiDiagnostics(oneshot::Sender<ApiDiagnostics>),
+    /// Return the account expiry reminder that currently applies, if any.
+    GetAccountExpiryEvent(oneshot::Sender<Option<AccountExpiryEvent>>),
+    /// Set the number of days before expiry at which to remind about the account expiry.
+    SetExpiryReminderDays(ResponseTx<(), settings::Error>, Vec<u32>),
+    /// Serialize the settings as a portable bundle, optionally including secrets.
+    ExportSettings(ResponseTx<String, Error>, bool),
+    /// Replace the settings with the ones in a bundle created by `ExportSettings`.
+    ImportSettings(ResponseTx<(), Error>, String),
+    /// Return the most recent entries of the audit log. Zero returns all entries.
+    GetAuditLog(ResponseTx<Vec<AuditLogEntry>, Error>, usize),
+    /// Check the system configuration, the API and the clock for common problems.
+    RunDiagnostics(oneshot::Sender<Vec<DiagnosticCheck>>),
+    /// Send probes outside the tunnel and report whether the firewall blocked them.
+    #[cfg(target_os = "linux")]
+    RunLeakTest(ResponseTx<Vec<ProbeResult>, leak_test::Error>),
+    /// Change several settings at once, saving and reconnecting at most once.
+    ApplySettingsPatch(ResponseTx<(), settings::Error>, SettingsPatch),
     /// Set the allow LAN setting.
     SetAllowLan(ResponseTx<(), settings::Error>, bool),
     /// Set the beta program setting.
     SetShowBetaReleases(ResponseTx<(), settings::Error>, bool),
     /// Set the block_when_disconnected setting.
     SetBlockWhenDisconnected(ResponseTx<(), settings::Error>, bool),
+    /// Set the persistent lockdown setting.
+    SetPersistentLockdown(ResponseTx<(), settings::Error>, bool),
     /// Set the auto-connect setting.
     SetAutoConnect(ResponseTx<(), settings::Error>, bool),
     /// Set the mssfix argument for OpenVPN
@@ -227,6 +308,8 @@ pub enum DaemonCommand {
     SetEnableIpv6(ResponseTx<(), settings::Error>, bool),
     /// Set whether to enable PQ PSK exchange in the tunnel
     SetQuantumResistantTunnel(ResponseTx<(), settings::Error>, bool),
+    /// Set the interval at which the quantum-resistant PSK is renegotiated
+    SetQuantumResistantPskRotationInterval(ResponseTx<(), settings::Error>, Option<Duration>),
     /// Set DNS options or servers to use
     SetDnsOptions(ResponseTx<(), settings::Error>, DnsOptions),
     /// Toggle macOS network check leak
@@ -240,6 +323,8 @@ pub enum DaemonCommand {
     RotateWireguardKey(ResponseTx<(), Error>),
     /// Return a public key of the currently set wireguard private key, if there is one
     GetWireguardKey(ResponseTx<Option<PublicKey>, Error>),
+    /// Return the current WireGuard connection as a wg-quick config, including the private key
+    ExportWireguardConfig(ResponseTx<String, Error>),
     /// Get information about the currently running and latest app versions
     GetVersionInfo(oneshot::Sender<Option<AppVersionInfo>>),
     /// Return whether the daemon is performing post-upgrade tasks
@@ -279,6 +364,12 @@ pub enum DaemonCommand {
     /// Toggle wireguard-nt on or off
     #[cfg(target_os = "windows")]
     UseWireGuardNt(ResponseTx<(), Error>, bool),
+    /// Toggle the boringtun userspace implementation on or off
+    #[cfg(target_os = "linux")]
+    UseBoringtun(ResponseTx<(), Error>, bool),
+    /// Toggle isolation of the WireGuard tunnel in a network namespace on or off
+    #[cfg(target_os = "linux")]
+    UseNetworkNamespace(ResponseTx<(), Error>, bool),
     /// Notify the split tunnel monitor that a volume was mounted or dismounted
     #[cfg(target_os = "windows")]
     CheckVolumes(ResponseTx<(), Error>),
@@ -297,8 +388,8 @@ pub enum DaemonCommand {
 pub(crate) enum InternalDaemonEvent {
     /// Tunnel has changed state.
     TunnelStateTransition(TunnelStateTransition),
-    /// A command sent to the daemon.
-    Command(DaemonCommand),
+    /// A command sent to the daemon, and the RPC that sent it, if any.
+    Command(DaemonCommand, Option<RpcContext>),
     /// Daemon shutdown triggered by a signal, ctrl-c or similar.
     /// The boolean should indicate whether the shutdown was user-initiated.
     TriggerShutdown(bool),
@@ -308,6 +399,8 @@ pub(crate) enum InternalDaemonEvent {
     DeviceEvent(AccountEvent),
     /// Handles updates from versions without devices.
     DeviceMigrationEvent(Result<PrivateAccountAndDevice, device::Error>),
+    /// The account expiry reminder changed.
+    AccountExpiry(account_expiry::AccountExpiryUpdate),
     /// The split tunnel paths or state were updated.
     #[cfg(target_os = "windows")]
     ExcludedPathsEvent(ExcludedPathsUpdate, oneshot::Sender<Result<(), Error>>),
@@ -327,7 +420,7 @@ impl From<TunnelStateTransition> for InternalDaemonEvent {
 
 impl From<DaemonCommand> for InternalDaemonEvent {
     fn from(command: DaemonCommand) -> Self {
-        InternalDaemonEvent::Command(command)
+        InternalDaemonEvent::Command(command, None)
     }
 }
 
@@ -343,6 +436,12 @@ impl From<AccountEvent> for InternalDaemonEvent {
     }
 }
 
+impl From<account_expiry::AccountExpiryUpdate> for InternalDaemonEvent {
+    fn from(update: account_expiry::AccountExpiryUpdate) -> Self {
+        InternalDaemonEvent::AccountExpiry(update)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum DaemonExecutionState {
     Running,
@@ -425,9 +524,15 @@ impl DaemonCommandChannel {
 pub struct DaemonCommandSender(Arc<mpsc::UnboundedSender<InternalDaemonEvent>>);
 
 impl DaemonCommandSender {
+    /// Sends a command to the daemon. When called from an RPC handler, the command is recorded in
+    /// the audit log together with the client that called the RPC.
     pub fn send(&self, command: DaemonCommand) -> Result<(), Error> {
+        #[cfg(not(target_os = "android"))]
+        let context = mullvad_management_interface::rpc_context();
+        #[cfg(target_os = "android")]
+        let context = None;
         self.0
-            .unbounded_send(InternalDaemonEvent::Command(command))
+            .unbounded_send(InternalDaemonEvent::Command(command, context))
             .map_err(|_| Error::DaemonUnavailable)
     }
 
@@ -525,6 +630,9 @@ pub trait EventListener {
 
     /// Notify that a device was revoked using `RemoveDevice`.
     fn notify_remove_device_event(&self, event: RemoveDeviceEvent);
+
+    /// Notify that the account is about to expire or has expired.
+    fn notify_account_expiry(&self, event: AccountExpiryEvent);
 }
 
 pub struct Daemon<L: EventListener> {
@@ -539,11 +647,16 @@ pub struct Daemon<L: EventListener> {
     event_listener: L,
     migration_complete: migrations::MigrationComplete,
     settings: SettingsPersister,
+    audit_log: audit_log::AuditLog,
     account_history: account_history::AccountHistory,
     device_checker: device::TunnelStateChangeHandler,
     account_manager: device::AccountManagerHandle,
+    expiry_checker_handle: account_expiry::ExpiryCheckerHandle,
+    account_expiry_event: Option<AccountExpiryEvent>,
     api_runtime: mullvad_api::Runtime,
     api_handle: mullvad_api::rest::MullvadRestHandle,
+    api_access_methods: api::AccessMethodsHandle,
+    api_endpoint_updater: api::ApiEndpointUpdaterHandle,
     version_updater_handle: version_check::VersionUpdaterHandle,
     relay_selector: RelaySelector,
     relay_list_updater: RelayListUpdaterHandle,
@@ -601,14 +714,21 @@ where
                 );
                 None
             });
-        let settings = SettingsPersister::load(&settings_dir).await;
+        let settings = SettingsPersister::load(&settings_dir)
+            .await
+            .map_err(Error::SettingsError)?;
+        let audit_log = audit_log::AuditLog::new(log_dir.clone());
         let app_version_info = version_check::load_cache(&cache_dir).await;
 
         let initial_selector_config = new_selector_config(&settings, &app_version_info);
         let relay_selector = RelaySelector::new(initial_selector_config, &resource_dir, &cache_dir);
 
-        let proxy_provider =
-            api::ApiConnectionModeProvider::new(cache_dir.clone(), relay_selector.clone());
+        let api_access_methods = api::AccessMethodsHandle::new(settings.api_access_methods.clone());
+        let proxy_provider = api::ApiConnectionModeProvider::new(
+            cache_dir.clone(),
+            relay_selector.clone(),
+            api_access_methods.clone(),
+        );
         let api_handle = api_runtime
             .mullvad_rest_handle(proxy_provider, endpoint_updater.callback())
             .await;
@@ -675,10 +795,10 @@ where
         let tunnel_state_machine_handle = tunnel_state_machine::spawn(
             tunnel_state_machine::InitialTunnelState {
                 allow_lan: settings.allow_lan,
-                block_when_disconnected: settings.block_when_disconnected,
+                block_when_disconnected: settings.should_block_when_disconnected(),
                 dns_servers: dns::addresses_from_options(&settings.tunnel_options.dns_options),
                 allowed_endpoint: initial_api_endpoint,
-                reset_firewall: *target_state != TargetState::Secured,
+                reset_firewall: should_reset_firewall_on_start(&settings, *target_state),
                 #[cfg(windows)]
                 exclude_paths,
             },
@@ -729,6 +849,13 @@ where
         );
         tokio::spawn(version_updater.run());
 
+        let (expiry_checker, expiry_checker_handle) = account_expiry::ExpiryChecker::new(
+            account_manager.clone(),
+            internal_event_tx.to_specialized_sender(),
+            settings.expiry_reminder_days.clone(),
+        );
+        tokio::spawn(expiry_checker.run());
+
         // Attempt to download a fresh relay list
         relay_list_updater.update().await;
 
@@ -744,11 +871,16 @@ where
             event_listener,
             migration_complete,
             settings,
+            audit_log,
             account_history,
             device_checker: device::TunnelStateChangeHandler::new(account_manager.clone()),
             account_manager,
+            expiry_checker_handle,
+            account_expiry_event: None,
             api_runtime,
             api_handle,
+            api_access_methods,
+            api_endpoint_updater: endpoint_updater,
             version_updater_handle,
             relay_selector,
             relay_list_updater,
@@ -840,13 +972,14 @@ where
             TunnelStateTransition(transition) => {
                 self.handle_tunnel_state_transition(transition).await
             }
-            Command(command) => self.handle_command(command).await,
+            Command(command, context) => self.handle_command(command, context).await,
             TriggerShutdown(user_init_shutdown) => self.trigger_shutdown_event(user_init_shutdown),
             NewAppVersionInfo(app_version_info) => {
                 self.handle_new_app_version_info(app_version_info);
             }
             DeviceEvent(event) => self.handle_device_event(event).await,
             DeviceMigrationEvent(event) => self.handle_device_migration_event(event).await,
+            AccountExpiry(update) => self.handle_account_expiry_update(update),
             #[cfg(windows)]
             ExcludedPathsEvent(update, tx) => self.handle_new_excluded_paths(update, tx).await,
         }
@@ -959,13 +1092,36 @@ where
         }
     }
 
-    async fn handle_command(&mut self, command: DaemonCommand) {
-        use self::DaemonCommand::*;
+    async fn handle_command(&mut self, command: DaemonCommand, context: Option<RpcContext>) {
         if !self.state.is_running() {
             log::trace!("Dropping daemon command because the daemon is shutting down",);
             return;
         }
 
+        // Serializing the settings is only worth it for commands that may change them
+        let context = context.filter(|context| {
+            self.audit_log.log_dir().is_some() && !audit_log::is_read_only(&context.method)
+        });
+        let before = context.as_ref().map(|_| self.audit_snapshot());
+        self.handle_command_inner(command).await;
+        if let (Some(context), Some(before)) = (context, before) {
+            let after = self.audit_snapshot();
+            self.audit_log.record(context, &before, &after).await;
+        }
+    }
+
+    /// Returns the state that is compared before and after a command to find out what the
+    /// command changed.
+    fn audit_snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "settings": &*self.settings,
+            "target_state": *self.target_state,
+        })
+    }
+
+    async fn handle_command_inner(&mut self, command: DaemonCommand) {
+        use self::DaemonCommand::*;
+
         if self.tunnel_state.is_disconnected() {
             self.api_handle.availability.reset_inactivity_timer();
         }
@@ -989,15 +1145,40 @@ where
             RemoveDevice(tx, account_token, device_id) => {
                 self.on_remove_device(tx, account_token, device_id).await
             }
+            ListAccountProfiles(tx) => self.on_list_account_profiles(tx),
+            SaveAccountProfile(tx, name) => self.on_save_account_profile(tx, name),
+            SwitchAccountProfile(tx, name) => self.on_switch_account_profile(tx, name),
+            RemoveAccountProfile(tx, name) => self.on_remove_account_profile(tx, name),
             GetAccountHistory(tx) => self.on_get_account_history(tx),
             ClearAccountHistory(tx) => self.on_clear_account_history(tx).await,
             UpdateRelaySettings(tx, update) => self.on_update_relay_settings(tx, update).await,
+            AddApiAccessMethod(tx, method) => self.on_add_api_access_method(tx, method).await,
+            RemoveApiAccessMethod(tx, name) => self.on_remove_api_access_method(tx, name).await,
+            SetApiAccessMethodEnabled(tx, name, enabled) => {
+                self.on_set_api_access_method_enabled(tx, name, enabled)
+                    .await
+            }
+            TestApiAccessMethod(tx, name) => self.on_test_api_access_method(tx, name).await,
+            GetApiDiagnostics(tx) => self.on_get_api_diagnostics(tx),
+            GetAccountExpiryEvent(tx) => self.on_get_account_expiry_event(tx),
+            SetExpiryReminderDays(tx, days) => self.on_set_expiry_reminder_days(tx, days).await,
+            ExportSettings(tx, include_secrets) => self.on_export_settings(tx, include_secrets),
+            ImportSettings(tx, bundle) => self.on_import_settings(tx, bundle).await,
+            GetAuditLog(tx, limit) => self.on_get_audit_log(tx, limit),
+            RunDiagnostics(tx) => self.on_run_diagnostics(tx).await,
+            #[cfg(target_os = "linux")]
+            RunLeakTest(tx) => self.on_run_leak_test(tx),
+            ApplySettingsPatch(tx, patch) => self.on_apply_settings_patch(tx, patch).await,
             SetAllowLan(tx, allow_lan) => self.on_set_allow_lan(tx, allow_lan).await,
             SetShowBetaReleases(tx, enabled) => self.on_set_show_beta_releases(tx, enabled).await,
             SetBlockWhenDisconnected(tx, block_when_disconnected) => {
                 self.on_set_block_when_disconnected(tx, block_when_disconnected)
                     .await
             }
+            SetPersistentLockdown(tx, persistent_lockdown) => {
+                self.on_set_persistent_lockdown(tx, persistent_lockdown)
+                    .await
+            }
             SetAutoConnect(tx, auto_connect) => self.on_set_auto_connect(tx, auto_connect).await,
             SetOpenVpnMssfix(tx, mssfix_arg) => self.on_set_openvpn_mssfix(tx, mssfix_arg).await,
             SetBridgeSettings(tx, bridge_settings) => {
@@ -1008,6 +1189,10 @@ where
             SetQuantumResistantTunnel(tx, enable_pq) => {
                 self.on_set_quantum_resistant_tunnel(tx, enable_pq).await
             }
+            SetQuantumResistantPskRotationInterval(tx, interval) => {
+                self.on_set_quantum_resistant_psk_rotation_interval(tx, interval)
+                    .await
+            }
             SetDnsOptions(tx, dns_servers) => self.on_set_dns_options(tx, dns_servers).await,
             SetWireguardMtu(tx, mtu) => self.on_set_wireguard_mtu(tx, mtu).await,
             SetWireguardRotationInterval(tx, interval) => {
@@ -1016,6 +1201,7 @@ where
             GetSettings(tx) => self.on_get_settings(tx),
             RotateWireguardKey(tx) => self.on_rotate_wireguard_key(tx).await,
             GetWireguardKey(tx) => self.on_get_wireguard_key(tx).await,
+            ExportWireguardConfig(tx) => self.on_export_wireguard_config(tx).await,
             GetVersionInfo(tx) => self.on_get_version_info(tx).await,
             IsPerformingPostUpgrade(tx) => self.on_is_performing_post_upgrade(tx).await,
             GetCurrentVersion(tx) => self.on_get_current_version(tx),
@@ -1041,6 +1227,10 @@ where
             GetSplitTunnelProcesses(tx) => self.on_get_split_tunnel_processes(tx),
             #[cfg(target_os = "windows")]
             UseWireGuardNt(tx, state) => self.on_use_wireguard_nt(tx, state).await,
+            #[cfg(target_os = "linux")]
+            UseBoringtun(tx, state) => self.on_use_boringtun(tx, state).await,
+            #[cfg(target_os = "linux")]
+            UseNetworkNamespace(tx, state) => self.on_use_network_namespace(tx, state).await,
             #[cfg(target_os = "windows")]
             CheckVolumes(tx) => self.on_check_volumes(tx).await,
             SetObfuscationSettings(tx, settings) => {
@@ -1059,9 +1249,20 @@ where
         self.event_listener.notify_app_version(app_version_info);
     }
 
+    fn handle_account_expiry_update(&mut self, update: account_expiry::AccountExpiryUpdate) {
+        self.account_expiry_event = update.event.clone();
+        if let (true, Some(event)) = (update.notify, update.event) {
+            self.event_listener.notify_account_expiry(event);
+        }
+    }
+
     async fn handle_device_event(&mut self, event: AccountEvent) {
+        if let AccountEvent::Expiry(expiry) = &event {
+            self.expiry_checker_handle.set_expiry(*expiry);
+        }
         match &event {
             AccountEvent::Device(PrivateDeviceEvent::Login(device)) => {
+                self.expiry_checker_handle.reset();
                 if let Err(error) = self.account_history.set(device.account_token.clone()).await {
                     log::error!(
                         "{}",
@@ -1074,10 +1275,12 @@ where
                 }
             }
             AccountEvent::Device(PrivateDeviceEvent::Logout) => {
+                self.expiry_checker_handle.clear();
                 log::info!("Disconnecting because account token was cleared");
                 self.set_target_state(TargetState::Unsecured).await;
             }
             AccountEvent::Device(PrivateDeviceEvent::Revoked) => {
+                self.expiry_checker_handle.clear();
                 // If we're currently in a secured state, reconnect to make sure we immediately
                 // enter the error state.
                 if *self.target_state == TargetState::Secured {
@@ -1455,6 +1658,62 @@ where
         });
     }
 
+    fn on_list_account_profiles(&mut self, tx: ResponseTx<Vec<AccountProfile>, Error>) {
+        let account_manager = self.account_manager.clone();
+        tokio::spawn(async move {
+            Self::oneshot_send(
+                tx,
+                account_manager
+                    .list_profiles()
+                    .await
+                    .map_err(Error::AccountProfileError),
+                "list_account_profiles response",
+            );
+        });
+    }
+
+    fn on_save_account_profile(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        let account_manager = self.account_manager.clone();
+        tokio::spawn(async move {
+            Self::oneshot_send(
+                tx,
+                account_manager
+                    .save_profile(name)
+                    .await
+                    .map_err(Error::AccountProfileError),
+                "save_account_profile response",
+            );
+        });
+    }
+
+    fn on_switch_account_profile(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        let account_manager = self.account_manager.clone();
+        tokio::spawn(async move {
+            let result = account_manager.switch_profile(name).await.map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to switch account profile")
+                );
+                Error::AccountProfileError(error)
+            });
+            Self::oneshot_send(tx, result, "switch_account_profile response");
+        });
+    }
+
+    fn on_remove_account_profile(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        let account_manager = self.account_manager.clone();
+        tokio::spawn(async move {
+            Self::oneshot_send(
+                tx,
+                account_manager
+                    .remove_profile(name)
+                    .await
+                    .map_err(Error::AccountProfileError),
+                "remove_account_profile response",
+            );
+        });
+    }
+
     fn on_get_account_history(&mut self, tx: oneshot::Sender<Option<AccountToken>>) {
         Self::oneshot_send(
             tx,
@@ -1513,6 +1772,13 @@ where
     async fn on_factory_reset(&mut self, tx: ResponseTx<(), Error>) {
         let mut last_error = Ok(());
 
+        if let Err(error) = self.account_manager.clear_profiles().await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to clear account profiles")
+            );
+        }
+
         if let Err(error) = self.account_manager.logout().await {
             log::error!(
                 "{}",
@@ -1760,6 +2026,70 @@ where
         }
     }
 
+    #[cfg(target_os = "linux")]
+    async fn on_use_boringtun(&mut self, tx: ResponseTx<(), Error>, state: bool) {
+        let save_result = self
+            .settings
+            .set_use_boringtun(state)
+            .await
+            .map_err(Error::SettingsError);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "use_boringtun response");
+                if settings_changed {
+                    self.parameters_generator
+                        .set_tunnel_options(&self.settings.tunnel_options)
+                        .await;
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let Some(TunnelType::Wireguard) = self.get_target_tunnel_type() {
+                        log::info!("Initiating tunnel restart");
+                        self.reconnect_tunnel();
+                    }
+                }
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to save settings")
+                );
+                Self::oneshot_send(tx, Err(error), "use_boringtun response");
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn on_use_network_namespace(&mut self, tx: ResponseTx<(), Error>, state: bool) {
+        let save_result = self
+            .settings
+            .set_use_network_namespace(state)
+            .await
+            .map_err(Error::SettingsError);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "use_network_namespace response");
+                if settings_changed {
+                    self.parameters_generator
+                        .set_tunnel_options(&self.settings.tunnel_options)
+                        .await;
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let Some(TunnelType::Wireguard) = self.get_target_tunnel_type() {
+                        log::info!("Initiating tunnel restart");
+                        self.reconnect_tunnel();
+                    }
+                }
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to save settings")
+                );
+                Self::oneshot_send(tx, Err(error), "use_network_namespace response");
+            }
+        }
+    }
+
     #[cfg(windows)]
     async fn on_check_volumes(&mut self, tx: ResponseTx<(), Error>) {
         if self.volume_update_tx.unbounded_send(()).is_ok() {
@@ -1792,6 +2122,396 @@ where
         }
     }
 
+    async fn on_add_api_access_method(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        access_method: ApiAccessMethod,
+    ) {
+        let mut access_methods = self.settings.api_access_methods.clone();
+        let result = if access_methods
+            .iter()
+            .any(|method| method.name == access_method.name)
+        {
+            Err(Error::ApiAccessMethodExists(access_method.name))
+        } else {
+            access_methods.push(access_method);
+            self.set_api_access_methods(access_methods).await
+        };
+        Self::oneshot_send(tx, result, "add_api_access_method response");
+    }
+
+    async fn on_remove_api_access_method(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        let mut access_methods = self.settings.api_access_methods.clone();
+        let result = match access_methods.iter().position(|method| method.name == name) {
+            Some(index) if access_methods[index].is_builtin() => {
+                Err(Error::RemoveBuiltinApiAccessMethod)
+            }
+            Some(index) => {
+                access_methods.remove(index);
+                self.set_api_access_methods(access_methods).await
+            }
+            None => Err(Error::NoSuchApiAccessMethod(name)),
+        };
+        Self::oneshot_send(tx, result, "remove_api_access_method response");
+    }
+
+    async fn on_set_api_access_method_enabled(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        name: String,
+        enabled: bool,
+    ) {
+        let mut access_methods = self.settings.api_access_methods.clone();
+        let result = match access_methods.iter_mut().find(|method| method.name == name) {
+            Some(method) => {
+                method.enabled = enabled;
+                self.set_api_access_methods(access_methods).await
+            }
+            None => Err(Error::NoSuchApiAccessMethod(name)),
+        };
+        Self::oneshot_send(tx, result, "set_api_access_method_enabled response");
+    }
+
+    async fn set_api_access_methods(
+        &mut self,
+        access_methods: Vec<ApiAccessMethod>,
+    ) -> Result<(), Error> {
+        let settings_changed = self
+            .settings
+            .set_api_access_methods(access_methods)
+            .await
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to set API access methods")
+                );
+                Error::SettingsError(error)
+            })?;
+        if settings_changed {
+            self.api_access_methods
+                .set(self.settings.api_access_methods.clone());
+            self.event_listener
+                .notify_settings(self.settings.to_settings());
+            if let Err(error) = self.api_handle.service().next_api_endpoint().await {
+                log::error!("Failed to rotate API endpoint: {}", error);
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_test_api_access_method(&mut self, tx: ResponseTx<bool, Error>, name: String) {
+        let access_method = match self
+            .settings
+            .api_access_methods
+            .iter()
+            .find(|method| method.name == name)
+        {
+            Some(method) => method.access_method.clone(),
+            None => {
+                Self::oneshot_send(
+                    tx,
+                    Err(Error::NoSuchApiAccessMethod(name)),
+                    "test_api_access_method response",
+                );
+                return;
+            }
+        };
+
+        // Use a separate request service so that the connection mode of the main one is
+        // unaffected. The firewall allows the tested endpoint until the test is done.
+        let connection_mode = api::resolve_connection_mode(&access_method, &self.relay_selector);
+        let default_endpoint = self.api_runtime.address_cache.get_address().await;
+        let endpoint = connection_mode.get_endpoint().unwrap_or(default_endpoint);
+        let rest_handle = self
+            .api_runtime
+            .unmetered_mullvad_rest_handle(connection_mode.into_repeat(), |_| async { true })
+            .await;
+        let endpoint_updater = self.api_endpoint_updater.clone();
+        tokio::spawn(async move {
+            let result = endpoint_updater
+                .with_temporary_endpoint(
+                    endpoint,
+                    default_endpoint,
+                    mullvad_api::ApiProxy::new(rest_handle).get_api_addrs(),
+                )
+                .await;
+            if let Err(error) = &result {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Failed to reach the API using access method \"{}\"",
+                        name
+                    ))
+                );
+            }
+            Self::oneshot_send(tx, Ok(result.is_ok()), "test_api_access_method response");
+        });
+    }
+
+    fn on_get_api_diagnostics(&mut self, tx: oneshot::Sender<ApiDiagnostics>) {
+        Self::oneshot_send(
+            tx,
+            self.api_runtime.api_diagnostics(),
+            "get_api_diagnostics response",
+        );
+    }
+
+    fn on_get_account_expiry_event(&mut self, tx: oneshot::Sender<Option<AccountExpiryEvent>>) {
+        // Recompute the days left, since reminders are only sent when a threshold is crossed.
+        let event = self.account_expiry_event.as_ref().and_then(|event| {
+            AccountExpiryEvent::new(
+                event.expiry(),
+                chrono::Utc::now(),
+                &self.settings.expiry_reminder_days,
+            )
+        });
+        Self::oneshot_send(tx, event, "get_account_expiry_event response");
+    }
+
+    async fn on_set_expiry_reminder_days(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        expiry_reminder_days: Vec<u32>,
+    ) {
+        let save_result = self
+            .settings
+            .set_expiry_reminder_days(expiry_reminder_days.clone())
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_expiry_reminder_days response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.expiry_checker_handle
+                        .set_reminder_days(expiry_reminder_days);
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_expiry_reminder_days response");
+            }
+        }
+    }
+
+    fn on_export_settings(&self, tx: ResponseTx<String, Error>, include_secrets: bool) {
+        let result = settings_bundle::export(&self.settings, include_secrets).map_err(|error| {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to export settings")
+            );
+            Error::SettingsBundleError(error)
+        });
+        Self::oneshot_send(tx, result, "export_settings response");
+    }
+
+    fn on_get_audit_log(&self, tx: ResponseTx<Vec<AuditLogEntry>, Error>, limit: usize) {
+        let log_dir = self.audit_log.log_dir().map(Path::to_path_buf);
+        tokio::spawn(async move {
+            let result = audit_log::read(log_dir.as_deref(), limit)
+                .await
+                .map_err(|error| {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to read the audit log")
+                    );
+                    Error::AuditLogError(error)
+                });
+            Self::oneshot_send(tx, result, "get_audit_log response");
+        });
+    }
+
+    async fn on_run_diagnostics(&mut self, tx: oneshot::Sender<Vec<DiagnosticCheck>>) {
+        #[cfg(target_os = "linux")]
+        let tunnel_diagnostics_rx = {
+            let (diagnostics_tx, diagnostics_rx) = oneshot::channel();
+            self.send_tunnel_command(TunnelCommand::Diagnostics(diagnostics_tx));
+            diagnostics_rx
+        };
+        let mut daemon_checks = vec![];
+        #[cfg(target_os = "linux")]
+        daemon_checks.push(diagnostics::split_tunnel_check(&self.exclude_pids));
+        daemon_checks.push(diagnostics::wireguard_backend_check(
+            &self.settings.tunnel_options.wireguard.options,
+        ));
+
+        // As when testing a single access method, separate request services are used so that the
+        // connection mode of the main one is unaffected.
+        let mut api_tests = vec![];
+        for method in self.settings.api_access_methods.iter() {
+            if !method.enabled {
+                continue;
+            }
+            let connection_mode =
+                api::resolve_connection_mode(&method.access_method, &self.relay_selector);
+            let rest_handle = self
+                .api_runtime
+                .unmetered_mullvad_rest_handle(connection_mode.clone().into_repeat(), |_| async {
+                    true
+                })
+                .await;
+            api_tests.push(diagnostics::ApiAccessTest {
+                name: method.name.clone(),
+                connection_mode,
+                rest_handle,
+            });
+        }
+
+        tokio::spawn(async move {
+            let mut checks = vec![];
+            #[cfg(target_os = "linux")]
+            match tunnel_diagnostics_rx.await {
+                Ok(tunnel_diagnostics) => {
+                    checks.extend(diagnostics::tunnel_checks(tunnel_diagnostics).await)
+                }
+                Err(_) => log::error!("The tunnel state machine did not return diagnostics"),
+            }
+            checks.append(&mut daemon_checks);
+            checks.extend(diagnostics::api_checks(api_tests).await);
+            Self::oneshot_send(tx, checks, "run_diagnostics response");
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    fn on_run_leak_test(&mut self, tx: ResponseTx<Vec<ProbeResult>, leak_test::Error>) {
+        self.send_tunnel_command(TunnelCommand::LeakTest(tx));
+    }
+
+    async fn on_import_settings(&mut self, tx: ResponseTx<(), Error>, bundle: String) {
+        let settings = match settings_bundle::import(&bundle, &self.settings).await {
+            Ok(settings) => settings,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to import settings")
+                );
+                Self::oneshot_send(
+                    tx,
+                    Err(Error::SettingsBundleError(error)),
+                    "import_settings response",
+                );
+                return;
+            }
+        };
+        let old_settings = self.settings.to_settings();
+        match self.settings.replace(settings).await {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "import_settings response");
+                if settings_changed {
+                    self.apply_changed_settings(old_settings).await;
+                    log::info!("Initiating tunnel restart because settings were imported");
+                    self.reconnect_tunnel();
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(Error::SettingsError(e)), "import_settings response");
+            }
+        }
+    }
+
+    async fn on_apply_settings_patch(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        patch: SettingsPatch,
+    ) {
+        let old_settings = self.settings.to_settings();
+        match self.settings.apply_patch(patch).await {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "apply_settings_patch response");
+                if settings_changed && self.apply_changed_settings(old_settings).await {
+                    log::info!("Initiating tunnel restart because a settings patch was applied");
+                    self.reconnect_tunnel();
+                }
+            }
+            Err(e) => {
+                log::error!(
+                    "{}",
+                    e.display_chain_with_msg("Unable to apply settings patch")
+                );
+                Self::oneshot_send(tx, Err(e), "apply_settings_patch response");
+            }
+        }
+    }
+
+    /// Performs what the individual setters do for each setting that differs from
+    /// `old_settings`, after several settings were changed at once, except for reconnecting.
+    /// Returns whether the tunnel must be reconnected for the changes to take effect.
+    async fn apply_changed_settings(&mut self, old_settings: Settings) -> bool {
+        let settings = self.settings.to_settings();
+        self.event_listener.notify_settings(settings.clone());
+
+        if settings.allow_lan != old_settings.allow_lan {
+            self.send_tunnel_command(TunnelCommand::AllowLan(settings.allow_lan));
+        }
+        if settings.should_block_when_disconnected()
+            != old_settings.should_block_when_disconnected()
+        {
+            self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
+                settings.should_block_when_disconnected(),
+            ));
+        }
+        if settings.tunnel_options.dns_options != old_settings.tunnel_options.dns_options {
+            let resolvers = dns::addresses_from_options(&settings.tunnel_options.dns_options);
+            self.send_tunnel_command(TunnelCommand::Dns(resolvers));
+        }
+        if settings.show_beta_releases != old_settings.show_beta_releases {
+            let mut handle = self.version_updater_handle.clone();
+            handle
+                .set_show_beta_releases(settings.show_beta_releases)
+                .await;
+        }
+        if settings.expiry_reminder_days != old_settings.expiry_reminder_days {
+            self.expiry_checker_handle
+                .set_reminder_days(settings.expiry_reminder_days.clone());
+        }
+        if settings.tunnel_options.wireguard.rotation_interval
+            != old_settings.tunnel_options.wireguard.rotation_interval
+        {
+            let interval = settings
+                .tunnel_options
+                .wireguard
+                .rotation_interval
+                .unwrap_or_default();
+            if let Err(error) = self.account_manager.set_rotation_interval(interval).await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to update rotation interval")
+                );
+            }
+        }
+        if settings.api_access_methods != old_settings.api_access_methods
+            || settings.bridge_settings != old_settings.bridge_settings
+        {
+            self.api_access_methods
+                .set(settings.api_access_methods.clone());
+            if let Err(error) = self.api_handle.service().next_api_endpoint().await {
+                log::error!("Failed to rotate API endpoint: {}", error);
+            }
+        }
+
+        let relay_selection_changed = settings.get_relay_settings()
+            != old_settings.get_relay_settings()
+            || settings.bridge_settings != old_settings.bridge_settings
+            || settings.get_bridge_state() != old_settings.get_bridge_state()
+            || settings.obfuscation_settings != old_settings.obfuscation_settings;
+        if relay_selection_changed {
+            self.relay_selector
+                .set_config(new_selector_config(&self.settings, &self.app_version_info));
+        }
+
+        let tunnel_options_changed = settings.tunnel_options.generic
+            != old_settings.tunnel_options.generic
+            || settings.tunnel_options.openvpn != old_settings.tunnel_options.openvpn
+            || settings.tunnel_options.wireguard.options
+                != old_settings.tunnel_options.wireguard.options;
+        self.parameters_generator
+            .set_tunnel_options(&settings.tunnel_options)
+            .await;
+
+        relay_selection_changed || tunnel_options_changed
+    }
+
     async fn on_set_allow_lan(&mut self, tx: ResponseTx<(), settings::Error>, allow_lan: bool) {
         let save_result = self.settings.set_allow_lan(allow_lan).await;
         match save_result {
@@ -1849,7 +2569,7 @@ where
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
                     self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
-                        block_when_disconnected,
+                        self.settings.should_block_when_disconnected(),
                     ));
                 }
             }
@@ -1860,6 +2580,33 @@ where
         }
     }
 
+    async fn on_set_persistent_lockdown(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        persistent_lockdown: bool,
+    ) {
+        let save_result = self
+            .settings
+            .set_persistent_lockdown(persistent_lockdown)
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_persistent_lockdown response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
+                        self.settings.should_block_when_disconnected(),
+                    ));
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_persistent_lockdown response");
+            }
+        }
+    }
+
     async fn on_set_auto_connect(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -2048,6 +2795,52 @@ where
         }
     }
 
+    async fn on_set_quantum_resistant_psk_rotation_interval(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        interval: Option<Duration>,
+    ) {
+        let save_result = self
+            .settings
+            .set_quantum_resistant_psk_rotation_interval(interval)
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(
+                    tx,
+                    Ok(()),
+                    "set_quantum_resistant_psk_rotation_interval response",
+                );
+                if settings_changed {
+                    self.parameters_generator
+                        .set_tunnel_options(&self.settings.tunnel_options)
+                        .await;
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if self
+                        .settings
+                        .tunnel_options
+                        .wireguard
+                        .options
+                        .use_pq_safe_psk
+                        && self.get_target_tunnel_type() == Some(TunnelType::Wireguard)
+                    {
+                        log::info!("Reconnecting because the PQ PSK rotation interval changed");
+                        self.reconnect_tunnel();
+                    }
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(
+                    tx,
+                    Err(e),
+                    "set_quantum_resistant_psk_rotation_interval response",
+                );
+            }
+        }
+    }
+
     async fn on_set_dns_options(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -2164,6 +2957,36 @@ where
         Self::oneshot_send(tx, result, "get_wireguard_key response");
     }
 
+    async fn on_export_wireguard_config(&self, tx: ResponseTx<String, Error>) {
+        let result = self.export_wireguard_config().await;
+        Self::oneshot_send(tx, result, "export_wireguard_config response");
+    }
+
+    async fn export_wireguard_config(&self) -> Result<String, Error> {
+        let parameters = match &self.tunnel_state {
+            TunnelState::Connected { endpoint, .. }
+                if endpoint.tunnel_type == TunnelType::Wireguard =>
+            {
+                self.parameters_generator
+                    .get_last_wireguard_parameters()
+                    .await
+            }
+            _ => None,
+        }
+        .ok_or(Error::NoWireguardConnection)?;
+
+        let dns_servers = dns::addresses_from_options(&self.settings.tunnel_options.dns_options)
+            .unwrap_or_else(|| {
+                let mut servers = vec![IpAddr::from(parameters.connection.ipv4_gateway)];
+                servers.extend(parameters.connection.ipv6_gateway.map(IpAddr::from));
+                servers
+            });
+
+        WgQuickConfig::from_tunnel_parameters(&parameters, dns_servers)
+            .map(|config| config.to_string())
+            .map_err(Error::ExportWireguardConfig)
+    }
+
     fn on_get_settings(&self, tx: oneshot::Sender<Settings>) {
         Self::oneshot_send(tx, self.settings.to_settings(), "get_settings response");
     }
@@ -2177,10 +3000,8 @@ where
     fn trigger_shutdown_event(&mut self, user_init_shutdown: bool) {
         // Block all traffic before shutting down to ensure that no traffic can leak on boot or
         // shutdown.
-        if !user_init_shutdown
-            && (*self.target_state == TargetState::Secured || self.settings.auto_connect)
-        {
-            log::debug!("Blocking firewall during shutdown since system is going down");
+        if should_block_on_shutdown(&self.settings, *self.target_state, user_init_shutdown) {
+            log::debug!("Blocking firewall during shutdown");
             self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(true));
         }
 
@@ -2192,7 +3013,7 @@ where
         // TODO: See if this can be made to also shut down the daemon
         //       without causing the service to be restarted.
 
-        if *self.target_state == TargetState::Secured {
+        if *self.target_state == TargetState::Secured || self.settings.persistent_lockdown {
             self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(true));
         }
         self.target_state.lock();
@@ -2330,3 +3151,88 @@ fn new_selector_config(
         default_tunnel_type,
     }
 }
+
+/// Returns whether the firewall rules left behind by a previous daemon instance should be removed
+/// on start. They are kept if the tunnel should be secured or if persistent lockdown is enabled.
+fn should_reset_firewall_on_start(settings: &Settings, target_state: TargetState) -> bool {
+    target_state != TargetState::Secured && !settings.persistent_lockdown
+}
+
+/// Returns whether traffic should remain blocked after the daemon has shut down. This is always
+/// the case with persistent lockdown. Otherwise, it is only done when the system is going down
+/// and the tunnel would be secured again on boot.
+fn should_block_on_shutdown(
+    settings: &Settings,
+    target_state: TargetState,
+    user_init_shutdown: bool,
+) -> bool {
+    settings.persistent_lockdown
+        || (!user_init_shutdown && (target_state == TargetState::Secured || settings.auto_connect))
+}
+
+/// Creates a temporary directory for tests. Every call gets a unique path, and the directory is
+/// removed when the returned value is dropped, also if the test panics.
+#[cfg(test)]
+pub(crate) fn test_dir() -> tempfile::TempDir {
+    tempfile::Builder::new()
+        .prefix("mullvad-daemon-test-")
+        .tempdir()
+        .expect("Failed to create temporary directory")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_persistent_lockdown() {
+        let mut settings = Settings::default();
+        assert!(!settings.should_block_when_disconnected());
+        assert!(should_reset_firewall_on_start(
+            &settings,
+            TargetState::Unsecured
+        ));
+        assert!(!should_block_on_shutdown(
+            &settings,
+            TargetState::Unsecured,
+            true
+        ));
+        assert!(!should_block_on_shutdown(
+            &settings,
+            TargetState::Secured,
+            true
+        ));
+        assert!(should_block_on_shutdown(
+            &settings,
+            TargetState::Secured,
+            false
+        ));
+
+        settings.persistent_lockdown = true;
+        assert!(settings.should_block_when_disconnected());
+        assert!(!should_reset_firewall_on_start(
+            &settings,
+            TargetState::Unsecured
+        ));
+        assert!(should_block_on_shutdown(
+            &settings,
+            TargetState::Unsecured,
+            true
+        ));
+
+        // Turning it off lifts the block unless block_when_disconnected is enabled
+        settings.persistent_lockdown = false;
+        assert!(!settings.should_block_when_disconnected());
+        settings.block_when_disconnected = true;
+        assert!(settings.should_block_when_disconnected());
+        assert!(should_reset_firewall_on_start(
+            &settings,
+            TargetState::Unsecured
+        ));
+        assert!(!should_block_on_shutdown(
+            &settings,
+            TargetState::Unsecured,
+            true
+        ));
+    }
+}
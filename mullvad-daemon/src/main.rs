@@ -7,7 +7,11 @@ use mullvad_daemon::{
     runtime::new_runtime_builder,
     version, Daemon, DaemonCommandChannel, DaemonCommandSender,
 };
-use std::{path::PathBuf, thread, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
 use talpid_types::ErrorExt;
 
 mod cli;
@@ -178,7 +182,8 @@ async fn create_daemon(
         .map_err(|e| e.display_chain_with_msg("Unable to get cache dir"))?;
 
     let command_channel = DaemonCommandChannel::new();
-    let event_listener = spawn_management_interface(command_channel.sender()).await?;
+    let event_listener =
+        spawn_management_interface(command_channel.sender(), &settings_dir).await?;
 
     Daemon::start(
         log_dir,
@@ -194,12 +199,14 @@ async fn create_daemon(
 
 async fn spawn_management_interface(
     command_sender: DaemonCommandSender,
+    settings_dir: &Path,
 ) -> Result<ManagementInterfaceEventBroadcaster, String> {
-    let (socket_path, event_broadcaster) = ManagementInterfaceServer::start(command_sender)
-        .await
-        .map_err(|error| {
-        error.display_chain_with_msg("Unable to start management interface server")
-    })?;
+    let (socket_path, event_broadcaster) =
+        ManagementInterfaceServer::start(command_sender, settings_dir)
+            .await
+            .map_err(|error| {
+                error.display_chain_with_msg("Unable to start management interface server")
+            })?;
 
     log::info!("Management interface listening on {}", socket_path);
 
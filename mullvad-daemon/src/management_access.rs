@@ -0,0 +1,213 @@
+//! Role-based access control for the management interface. Each RPC requires one of three roles,
+//! and the role of a client is determined by the Unix groups of the user that runs it. The
+//! mapping of groups to roles is read from a file in the settings directory. Without that file,
+//! every client may call every RPC.
+
+use mullvad_management_interface::{AccessCheck, Status, UCred};
+use nix::unistd::{Gid, Group, Uid, User};
+use serde::Deserialize;
+use std::{path::Path, sync::Arc};
+use talpid_types::ErrorExt;
+use tokio::{fs, io};
+
+const ACCESS_FILE: &str = "management-access.json";
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Unable to read access file {}", _0)]
+    Read(String, #[error(source)] io::Error),
+
+    #[error(display = "Unable to parse access file")]
+    Parse(#[error(source)] serde_json::Error),
+}
+
+/// Roles, from least to most privileged. Each role may call the RPCs of the roles below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// May read the state and settings, but not change anything.
+    ReadOnly,
+    /// May also connect, disconnect and select relays.
+    Operator,
+    /// May call every RPC.
+    Admin,
+}
+
+/// Maps Unix groups to roles. Root is always an admin.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessConfig {
+    #[serde(default)]
+    admin_groups: Vec<String>,
+    #[serde(default)]
+    operator_groups: Vec<String>,
+    /// Role of users that are not in any of the groups above.
+    #[serde(default = "default_role")]
+    default_role: Role,
+}
+
+fn default_role() -> Role {
+    Role::ReadOnly
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        AccessConfig {
+            admin_groups: vec![],
+            operator_groups: vec![],
+            default_role: default_role(),
+        }
+    }
+}
+
+impl AccessConfig {
+    /// Loads the access configuration from the settings directory. Returns `None` if there is no
+    /// configuration, in which case all RPCs are allowed for every client.
+    pub async fn load(settings_dir: &Path) -> Result<Option<Self>, Error> {
+        let path = settings_dir.join(ACCESS_FILE);
+        let bytes = match fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(Error::Read(path.display().to_string(), error)),
+        };
+        let config = serde_json::from_slice(&bytes).map_err(Error::Parse)?;
+        log::info!(
+            "Loaded management interface access control from {}",
+            path.display()
+        );
+        Ok(Some(config))
+    }
+
+    /// Returns a check that rejects RPCs which the role of the client does not allow.
+    pub fn into_access_check(self) -> AccessCheck {
+        Arc::new(move |method: &str, credentials: Option<&UCred>| {
+            let required_role = required_role(method);
+            let role = match credentials {
+                Some(credentials) => self.role(credentials),
+                None => Role::ReadOnly,
+            };
+            if role >= required_role {
+                return Ok(());
+            }
+            log::warn!(
+                "Denied {} to client with uid {:?}",
+                method,
+                credentials.map(|credentials| credentials.uid())
+            );
+            Err(Status::permission_denied(format!(
+                "{} requires the {:?} role",
+                method, required_role
+            )))
+        })
+    }
+
+    fn role(&self, credentials: &UCred) -> Role {
+        if credentials.uid() == 0 {
+            return Role::Admin;
+        }
+        let user = match User::from_uid(Uid::from_raw(credentials.uid())) {
+            Ok(Some(user)) => user,
+            Ok(None) => return self.default_role,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to look up user of client")
+                );
+                return self.default_role;
+            }
+        };
+        let is_member = |group_name: &String| match Group::from_name(group_name) {
+            Ok(Some(group)) => {
+                group.gid == Gid::from_raw(credentials.gid())
+                    || group.gid == user.gid
+                    || group.mem.contains(&user.name)
+            }
+            Ok(None) => false,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Failed to look up group \"{}\"",
+                        group_name
+                    ))
+                );
+                false
+            }
+        };
+        if self.admin_groups.iter().any(is_member) {
+            Role::Admin
+        } else if self.operator_groups.iter().any(is_member) {
+            Role::Operator
+        } else {
+            self.default_role
+        }
+    }
+}
+
+/// Pseudo RPC that is checked when `UpdateRelaySettings` selects a custom tunnel endpoint. A
+/// custom endpoint can send all traffic to an arbitrary server, which operators may not do.
+pub const CUSTOM_TUNNEL_ENDPOINT: &str = "UpdateRelaySettings/CustomTunnelEndpoint";
+
+/// Returns the role that is required to call an RPC. RPCs that are not listed require the admin
+/// role, so that new RPCs are restricted until they are classified. `GetDevice` and
+/// `EventsListen` expose the account token, so they are not available to read-only clients.
+fn required_role(method: &str) -> Role {
+    match method {
+        "GetTunnelState"
+        | "GetCurrentVersion"
+        | "GetVersionInfo"
+        | "IsPerformingPostUpgrade"
+        | "GetRelayLocations"
+        | "GetCurrentLocation"
+        | "GetSettings"
+        | "GetApiDiagnostics"
+        | "GetAccountExpiryEvent"
+        | "GetWireguardKey"
+        | "GetSplitTunnelProcesses"
+        | "GetExcludedProcesses" => Role::ReadOnly,
+
+        "EventsListen"
+        | "GetDevice"
+        | "ConnectTunnel"
+        | "DisconnectTunnel"
+        | "ReconnectTunnel"
+        | "UpdateRelayLocations"
+        | "UpdateRelaySettings"
+        | "UpdateDevice"
+        | "TestApiAccessMethod"
+        | "RunDiagnostics"
+        | "RunLeakTest"
+        | "CheckVolumes" => Role::Operator,
+
+        CUSTOM_TUNNEL_ENDPOINT => Role::Admin,
+        _ => Role::Admin,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_required_role() {
+        assert_eq!(required_role("GetSettings"), Role::ReadOnly);
+        assert_eq!(required_role("ConnectTunnel"), Role::Operator);
+        assert_eq!(required_role("SetBlockWhenDisconnected"), Role::Admin);
+        assert_eq!(required_role("SomeNewRpc"), Role::Admin);
+        assert_eq!(required_role("UpdateRelaySettings"), Role::Operator);
+        assert_eq!(required_role(CUSTOM_TUNNEL_ENDPOINT), Role::Admin);
+        assert_eq!(required_role("GetDevice"), Role::Operator);
+        assert_eq!(required_role("EventsListen"), Role::Operator);
+        assert!(Role::Admin > Role::Operator && Role::Operator > Role::ReadOnly);
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config: AccessConfig =
+            serde_json::from_str(r#"{ "operator_groups": ["netdev"] }"#).unwrap();
+        assert_eq!(config.default_role, Role::ReadOnly);
+        assert!(config.admin_groups.is_empty());
+        assert!(serde_json::from_str::<AccessConfig>(r#"{ "admins": [] }"#).is_err());
+    }
+}
@@ -1,4 +1,9 @@
-use crate::{account_history, device, settings, DaemonCommand, DaemonCommandSender, EventListener};
+#[cfg(unix)]
+use crate::management_access::{self, AccessConfig};
+use crate::{
+    account_history, audit_log, device, settings, settings_bundle, DaemonCommand,
+    DaemonCommandSender, EventListener,
+};
 use futures::{
     channel::{mpsc, oneshot},
     StreamExt,
@@ -8,23 +13,27 @@ use mullvad_management_interface::{
     types::{self, daemon_event, management_service_server::ManagementService},
     Code, Request, Response, Status,
 };
+#[cfg(unix)]
+use mullvad_management_interface::{AccessCheck, UCred};
 use mullvad_paths;
 #[cfg(not(target_os = "android"))]
 use mullvad_types::settings::DnsOptions;
 use mullvad_types::{
+    access_method::ApiAccessMethod,
     account::AccountToken,
     relay_constraints::{BridgeSettings, BridgeState, ObfuscationSettings, RelaySettingsUpdate},
     relay_list::RelayList,
-    settings::Settings,
+    settings::{Settings, SettingsPatch},
     states::{TargetState, TunnelState},
     version,
-    wireguard::{RotationInterval, RotationIntervalError},
+    wireguard::{RotationInterval, RotationIntervalError, MIN_PQ_PSK_ROTATION_INTERVAL},
 };
 use parking_lot::RwLock;
 #[cfg(windows)]
 use std::path::PathBuf;
 use std::{
     convert::{TryFrom, TryInto},
+    path::Path,
     sync::Arc,
     time::Duration,
 };
@@ -42,6 +51,8 @@ pub enum Error {
 struct ManagementServiceImpl {
     daemon_tx: DaemonCommandSender,
     subscriptions: Arc<RwLock<Vec<EventsListenerSender>>>,
+    #[cfg(unix)]
+    access_check: Option<AccessCheck>,
 }
 
 pub type ServiceResult<T> = std::result::Result<Response<T>, Status>;
@@ -128,6 +139,53 @@ impl ManagementService for ManagementServiceImpl {
         }
     }
 
+    async fn get_audit_log(&self, request: Request<u32>) -> ServiceResult<types::AuditLog> {
+        let limit = request.into_inner();
+        log::debug!("get_audit_log({})", limit);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetAuditLog(tx, limit as usize))?;
+        let entries = self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+        Ok(Response::new(types::AuditLog {
+            entries: entries
+                .into_iter()
+                .map(types::AuditLogEntry::from)
+                .collect(),
+        }))
+    }
+
+    async fn run_diagnostics(&self, _: Request<()>) -> ServiceResult<types::Diagnostics> {
+        log::debug!("run_diagnostics");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::RunDiagnostics(tx))?;
+        let checks = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::Diagnostics {
+            checks: checks
+                .into_iter()
+                .map(types::DiagnosticCheck::from)
+                .collect(),
+        }))
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn run_leak_test(&self, _: Request<()>) -> ServiceResult<types::LeakTestResult> {
+        log::debug!("run_leak_test");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::RunLeakTest(tx))?;
+        let probes = self
+            .wait_for_result(rx)
+            .await?
+            .map_err(|error| Status::failed_precondition(error.to_string()))?;
+        Ok(Response::new(types::LeakTestResult {
+            probes: probes.into_iter().map(types::LeakTestProbe::from).collect(),
+        }))
+    }
+    #[cfg(not(target_os = "linux"))]
+    async fn run_leak_test(&self, _: Request<()>) -> ServiceResult<types::LeakTestResult> {
+        Err(Status::unimplemented(
+            "the leak test is not supported on this platform",
+        ))
+    }
+
     async fn get_current_version(&self, _: Request<()>) -> ServiceResult<String> {
         log::debug!("get_current_version");
         let (tx, rx) = oneshot::channel();
@@ -169,9 +227,18 @@ impl ManagementService for ManagementServiceImpl {
         request: Request<types::RelaySettingsUpdate>,
     ) -> ServiceResult<()> {
         log::debug!("update_relay_settings");
+        #[cfg(unix)]
+        let credentials = mullvad_management_interface::peer_credentials(&request);
         let (tx, rx) = oneshot::channel();
         let constraints_update =
             RelaySettingsUpdate::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        #[cfg(unix)]
+        if matches!(
+            constraints_update,
+            RelaySettingsUpdate::CustomTunnelEndpoint(_)
+        ) {
+            self.check_access(management_access::CUSTOM_TUNNEL_ENDPOINT, credentials)?;
+        }
 
         let message = DaemonCommand::UpdateRelaySettings(tx, constraints_update);
         self.send_command_to_daemon(message)?;
@@ -295,6 +362,20 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn set_persistent_lockdown(&self, request: Request<bool>) -> ServiceResult<()> {
+        let persistent_lockdown = request.into_inner();
+        log::debug!("set_persistent_lockdown({})", persistent_lockdown);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetPersistentLockdown(
+            tx,
+            persistent_lockdown,
+        ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     async fn set_auto_connect(&self, request: Request<bool>) -> ServiceResult<()> {
         let auto_connect = request.into_inner();
         log::debug!("set_auto_connect({})", auto_connect);
@@ -356,6 +437,49 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
+    async fn set_quantum_resistant_psk_rotation_interval(
+        &self,
+        request: Request<types::Duration>,
+    ) -> ServiceResult<()> {
+        let interval = Duration::try_from(request.into_inner())
+            .map_err(|_| Status::invalid_argument("unexpected negative rotation interval"))?;
+        if interval < MIN_PQ_PSK_ROTATION_INTERVAL {
+            return Err(Status::invalid_argument(format!(
+                "Rotation interval must be at least {} minutes",
+                MIN_PQ_PSK_ROTATION_INTERVAL.as_secs() / 60
+            )));
+        }
+
+        log::debug!(
+            "set_quantum_resistant_psk_rotation_interval({:?})",
+            interval
+        );
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetQuantumResistantPskRotationInterval(
+            tx,
+            Some(interval),
+        ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn reset_quantum_resistant_psk_rotation_interval(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<()> {
+        log::debug!("reset_quantum_resistant_psk_rotation_interval");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetQuantumResistantPskRotationInterval(
+            tx, None,
+        ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     #[cfg(not(target_os = "android"))]
     async fn set_dns_options(&self, request: Request<types::DnsOptions>) -> ServiceResult<()> {
         let options = DnsOptions::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
@@ -374,6 +498,112 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(()))
     }
 
+    async fn export_settings(&self, request: Request<bool>) -> ServiceResult<String> {
+        let include_secrets = request.into_inner();
+        log::debug!("export_settings({})", include_secrets);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ExportSettings(tx, include_secrets))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn apply_settings_patch(
+        &self,
+        request: Request<types::SettingsPatch>,
+    ) -> ServiceResult<()> {
+        let patch = SettingsPatch::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("apply_settings_patch({:?})", patch);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ApplySettingsPatch(tx, patch))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn import_settings(&self, request: Request<String>) -> ServiceResult<()> {
+        log::debug!("import_settings");
+        let bundle = request.into_inner();
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ImportSettings(tx, bundle))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    // API access methods
+    //
+
+    async fn add_api_access_method(
+        &self,
+        request: Request<types::ApiAccessMethod>,
+    ) -> ServiceResult<()> {
+        let access_method =
+            ApiAccessMethod::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("add_api_access_method({})", access_method.name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::AddApiAccessMethod(tx, access_method))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn remove_api_access_method(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("remove_api_access_method({})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::RemoveApiAccessMethod(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn set_api_access_method_enabled(
+        &self,
+        request: Request<types::ApiAccessMethodToggle>,
+    ) -> ServiceResult<()> {
+        let toggle = request.into_inner();
+        log::debug!(
+            "set_api_access_method_enabled({}, {})",
+            toggle.name,
+            toggle.enabled
+        );
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetApiAccessMethodEnabled(
+            tx,
+            toggle.name,
+            toggle.enabled,
+        ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn test_api_access_method(&self, request: Request<String>) -> ServiceResult<bool> {
+        let name = request.into_inner();
+        log::debug!("test_api_access_method({})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::TestApiAccessMethod(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn get_api_diagnostics(&self, _: Request<()>) -> ServiceResult<types::ApiDiagnostics> {
+        log::debug!("get_api_diagnostics");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetApiDiagnostics(tx))?;
+        let diagnostics = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::ApiDiagnostics::from(diagnostics)))
+    }
+
     // Account management
     //
 
@@ -490,6 +720,33 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
     }
 
+    async fn get_account_expiry_event(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::AccountExpiryEvent> {
+        log::debug!("get_account_expiry_event");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetAccountExpiryEvent(tx))?;
+        match self.wait_for_result(rx).await? {
+            Some(event) => Ok(Response::new(types::AccountExpiryEvent::from(event))),
+            None => Err(Status::not_found("the account is not about to expire")),
+        }
+    }
+
+    async fn set_expiry_reminder_days(
+        &self,
+        request: Request<types::ExpiryReminderDays>,
+    ) -> ServiceResult<()> {
+        let days = request.into_inner().days;
+        log::debug!("set_expiry_reminder_days({:?})", days);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetExpiryReminderDays(tx, days))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
     // Device management
     async fn get_device(&self, _: Request<()>) -> ServiceResult<types::DeviceState> {
         log::debug!("get_device");
@@ -534,6 +791,55 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(()))
     }
 
+    // Account profiles
+    //
+
+    async fn list_account_profiles(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::AccountProfileList> {
+        log::debug!("list_account_profiles");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ListAccountProfiles(tx))?;
+        let profiles = self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+        Ok(Response::new(types::AccountProfileList {
+            profiles: profiles
+                .into_iter()
+                .map(types::AccountProfile::from)
+                .collect(),
+        }))
+    }
+
+    async fn save_account_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("save_account_profile({})", name);
+        if name.is_empty() {
+            return Err(Status::invalid_argument("profile name must not be empty"));
+        }
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SaveAccountProfile(tx, name))?;
+        self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+        Ok(Response::new(()))
+    }
+
+    async fn switch_account_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("switch_account_profile({})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SwitchAccountProfile(tx, name))?;
+        self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+        Ok(Response::new(()))
+    }
+
+    async fn remove_account_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("remove_account_profile({})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::RemoveAccountProfile(tx, name))?;
+        self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+        Ok(Response::new(()))
+    }
+
     // WireGuard key management
     //
 
@@ -591,6 +897,23 @@ impl ManagementService for ManagementServiceImpl {
         }
     }
 
+    async fn export_wireguard_config(&self, request: Request<()>) -> ServiceResult<String> {
+        log::debug!("export_wireguard_config");
+        // The config contains the private key of the device, so only privileged clients may read
+        // it
+        if !is_privileged_client(&request) {
+            return Err(Status::permission_denied(
+                "exporting the WireGuard config requires root or administrator privileges",
+            ));
+        }
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ExportWireguardConfig(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
     // Split tunneling
     //
 
@@ -788,6 +1111,38 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(()))
     }
 
+    #[cfg(target_os = "linux")]
+    async fn set_use_boringtun(&self, request: Request<bool>) -> ServiceResult<()> {
+        log::debug!("set_use_boringtun");
+        let state = request.into_inner();
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::UseBoringtun(tx, state))?;
+        self.wait_for_result(rx)
+            .await?
+            .map_err(map_daemon_error)
+            .map(Response::new)
+    }
+    #[cfg(not(target_os = "linux"))]
+    async fn set_use_boringtun(&self, _: Request<bool>) -> ServiceResult<()> {
+        Ok(Response::new(()))
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn set_use_network_namespace(&self, request: Request<bool>) -> ServiceResult<()> {
+        log::debug!("set_use_network_namespace");
+        let state = request.into_inner();
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::UseNetworkNamespace(tx, state))?;
+        self.wait_for_result(rx)
+            .await?
+            .map_err(map_daemon_error)
+            .map(Response::new)
+    }
+    #[cfg(not(target_os = "linux"))]
+    async fn set_use_network_namespace(&self, _: Request<bool>) -> ServiceResult<()> {
+        Ok(Response::new(()))
+    }
+
     #[cfg(windows)]
     async fn check_volumes(&self, _: Request<()>) -> ServiceResult<()> {
         log::debug!("check_volumes");
@@ -816,6 +1171,16 @@ impl ManagementServiceImpl {
     async fn wait_for_result<T>(&self, rx: oneshot::Receiver<T>) -> Result<T, Status> {
         rx.await.map_err(|_| Status::internal("sender was dropped"))
     }
+
+    /// Checks whether the client may perform `action`, for requests that need more privileges
+    /// than the RPC itself, depending on their content.
+    #[cfg(unix)]
+    fn check_access(&self, action: &str, credentials: Option<UCred>) -> Result<(), Status> {
+        match &self.access_check {
+            Some(access_check) => access_check(action, credentials.as_ref()),
+            None => Ok(()),
+        }
+    }
 }
 
 pub struct ManagementInterfaceServer(());
@@ -823,6 +1188,7 @@ pub struct ManagementInterfaceServer(());
 impl ManagementInterfaceServer {
     pub async fn start(
         tunnel_tx: DaemonCommandSender,
+        settings_dir: &Path,
     ) -> Result<(String, ManagementInterfaceEventBroadcaster), Error> {
         let subscriptions = Arc::<RwLock<Vec<EventsListenerSender>>>::default();
 
@@ -831,13 +1197,24 @@ impl ManagementInterfaceServer {
             .to_string();
 
         let (server_abort_tx, server_abort_rx) = mpsc::channel(0);
+        #[cfg(unix)]
+        let access_check = Self::load_access_check(settings_dir).await;
+        #[cfg(not(unix))]
+        let _ = settings_dir;
         let server = ManagementServiceImpl {
             daemon_tx: tunnel_tx,
             subscriptions: subscriptions.clone(),
+            #[cfg(unix)]
+            access_check: access_check.clone(),
         };
-        let join_handle = mullvad_management_interface::spawn_rpc_server(server, async move {
-            server_abort_rx.into_future().await;
-        })
+        let join_handle = mullvad_management_interface::spawn_rpc_server(
+            server,
+            async move {
+                server_abort_rx.into_future().await;
+            },
+            #[cfg(unix)]
+            access_check,
+        )
         .await
         .map_err(Error::SetupError)?;
 
@@ -858,6 +1235,29 @@ impl ManagementInterfaceServer {
     }
 }
 
+impl ManagementInterfaceServer {
+    /// Loads the access control configuration. If it is broken, only root is allowed to change
+    /// anything, rather than opening up the interface to everyone.
+    #[cfg(unix)]
+    async fn load_access_check(
+        settings_dir: &Path,
+    ) -> Option<mullvad_management_interface::AccessCheck> {
+        match AccessConfig::load(settings_dir).await {
+            Ok(config) => config.map(AccessConfig::into_access_check),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg(
+                        "Failed to load management interface access control. Only root may \
+                         make changes."
+                    )
+                );
+                Some(AccessConfig::default().into_access_check())
+            }
+        }
+    }
+}
+
 /// A handle that allows broadcasting messages to all subscribers of the management interface.
 #[derive(Clone)]
 pub struct ManagementInterfaceEventBroadcaster {
@@ -921,6 +1321,15 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
             )),
         })
     }
+
+    fn notify_account_expiry(&self, event: mullvad_types::account::AccountExpiryEvent) {
+        log::debug!("Broadcasting account expiry event");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::AccountExpiry(
+                types::AccountExpiryEvent::from(event),
+            )),
+        })
+    }
 }
 
 impl ManagementInterfaceEventBroadcaster {
@@ -946,12 +1355,27 @@ fn map_daemon_error(error: crate::Error) -> Status {
         DaemonError::RemoveDeviceError(error) => map_device_error(&error),
         DaemonError::UpdateDeviceError(error) => map_device_error(&error),
         DaemonError::VoucherSubmission(error) => map_device_error(&error),
+        DaemonError::AccountProfileError(error) => map_device_error(&error),
+        DaemonError::SettingsBundleError(ref bundle_error) => match bundle_error {
+            settings_bundle::Error::Serialize(_) => Status::internal(error.display_chain()),
+            _ => Status::invalid_argument(error.display_chain()),
+        },
+        DaemonError::AuditLogError(audit_log::Error::Disabled) => {
+            Status::failed_precondition(error.display_chain())
+        }
+        DaemonError::AuditLogError(_) => Status::internal(error.display_chain()),
         #[cfg(windows)]
         DaemonError::SplitTunnelError(error) => map_split_tunnel_error(error),
         DaemonError::AccountHistory(error) => map_account_history_error(error),
         DaemonError::NoAccountToken | DaemonError::NoAccountTokenHistory => {
             Status::unauthenticated(error.to_string())
         }
+        DaemonError::NoWireguardConnection | DaemonError::ExportWireguardConfig(_) => {
+            Status::failed_precondition(error.display_chain())
+        }
+        DaemonError::ApiAccessMethodExists(_) => Status::already_exists(error.to_string()),
+        DaemonError::NoSuchApiAccessMethod(_) => Status::not_found(error.to_string()),
+        DaemonError::RemoveBuiltinApiAccessMethod => Status::invalid_argument(error.to_string()),
         error => Status::unknown(error.to_string()),
     }
 }
@@ -973,6 +1397,31 @@ fn map_split_tunnel_error(error: talpid_core::split_tunnel::Error) -> Status {
     }
 }
 
+/// Returns whether the client that sent `request` runs as root.
+#[cfg(unix)]
+fn is_privileged_client<T>(request: &Request<T>) -> bool {
+    matches!(
+        mullvad_management_interface::peer_credentials(request),
+        Some(credentials) if credentials.uid() == 0
+    )
+}
+
+/// Returns whether the client that sent `request` runs elevated, or as `SYSTEM`.
+#[cfg(windows)]
+fn is_privileged_client<T>(request: &Request<T>) -> bool {
+    let connect_info = match mullvad_management_interface::peer_connect_info(request) {
+        Some(connect_info) => connect_info,
+        None => return false,
+    };
+    connect_info.is_elevated_client().unwrap_or_else(|error| {
+        log::error!(
+            "{}",
+            error.display_chain_with_msg("Failed to check whether the client is elevated")
+        );
+        false
+    })
+}
+
 /// Converts a REST API error into a tonic status.
 fn map_rest_error(error: &RestError) -> Status {
     match error {
@@ -996,8 +1445,12 @@ fn map_settings_error(error: settings::Error) -> Status {
         | settings::Error::SetPermissions(..) => {
             Status::new(Code::FailedPrecondition, error.to_string())
         }
-        settings::Error::SerializeError(..) | settings::Error::ParseError(..) => {
-            Status::new(Code::Internal, error.to_string())
+        settings::Error::SerializeError(..)
+        | settings::Error::ParseError(..)
+        | settings::Error::Policy(..) => Status::new(Code::Internal, error.to_string()),
+        settings::Error::Locked(..) => Status::new(Code::PermissionDenied, error.to_string()),
+        settings::Error::InvalidCombination(..) => {
+            Status::new(Code::InvalidArgument, error.to_string())
         }
     }
 }
@@ -1007,8 +1460,12 @@ fn map_device_error(error: &device::Error) -> Status {
     match error {
         device::Error::MaxDevicesReached => Status::new(Code::ResourceExhausted, error.to_string()),
         device::Error::InvalidAccount => Status::new(Code::Unauthenticated, error.to_string()),
-        device::Error::InvalidDevice | device::Error::NoDevice => {
-            Status::new(Code::NotFound, error.to_string())
+        device::Error::InvalidDevice
+        | device::Error::NoDevice
+        | device::Error::ProfileNotFound(_) => Status::new(Code::NotFound, error.to_string()),
+        device::Error::ProfileExists(_) => Status::new(Code::AlreadyExists, error.to_string()),
+        device::Error::UnsavedDevice | device::Error::ActiveProfile => {
+            Status::new(Code::FailedPrecondition, error.to_string())
         }
         device::Error::InvalidVoucher => Status::new(Code::NotFound, INVALID_VOUCHER_MESSAGE),
         device::Error::UsedVoucher => Status::new(Code::ResourceExhausted, USED_VOUCHER_MESSAGE),
@@ -179,6 +179,23 @@ pub(crate) async fn migrate_all(
     Ok(migration_data)
 }
 
+/// Migrates settings that did not come from the settings file, such as an imported settings
+/// bundle, to the current format. Unlike [`migrate_all`], this neither touches any files nor
+/// returns the discarded account data.
+pub(crate) async fn migrate_settings(settings: &mut serde_json::Value) -> Result<()> {
+    if !settings.is_object() {
+        return Err(Error::NoMatchingVersion);
+    }
+
+    v1::migrate(settings)?;
+    v2::migrate(settings)?;
+    v3::migrate(settings)?;
+    v4::migrate(settings)?;
+    let _ = v5::migrate(settings).await?;
+
+    Ok(())
+}
+
 pub(crate) fn migrate_device(
     migration_data: MigrationData,
     rest_handle: mullvad_api::rest::MullvadRestHandle,
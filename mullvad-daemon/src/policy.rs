@@ -0,0 +1,187 @@
+//! Admin policies that enforce the values of some settings. The policy is read from a file in the
+//! settings directory when the daemon starts. Settings that are set by the policy are locked, and
+//! attempts to change them are rejected.
+
+use crate::settings::SettingsPersister;
+use mullvad_types::{
+    relay_constraints::RelaySettings,
+    settings::{DnsOptions, Settings},
+};
+use serde::Deserialize;
+use std::path::Path;
+use tokio::{fs, io};
+
+const POLICY_FILE: &str = "policy.json";
+
+pub const ALLOW_LAN: &str = "allow_lan";
+pub const BLOCK_WHEN_DISCONNECTED: &str = "block_when_disconnected";
+pub const PERSISTENT_LOCKDOWN: &str = "persistent_lockdown";
+pub const AUTO_CONNECT: &str = "auto_connect";
+pub const DNS_OPTIONS: &str = "dns_options";
+pub const RELAY_SETTINGS: &str = "relay_settings";
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Unable to read policy file {}", _0)]
+    Read(String, #[error(source)] io::Error),
+
+    #[error(display = "Unable to parse policy file")]
+    Parse(#[error(source)] serde_json::Error),
+}
+
+/// Values of settings that are enforced by an admin. The fields use the same format as the
+/// settings file.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    allow_lan: Option<bool>,
+    block_when_disconnected: Option<bool>,
+    persistent_lockdown: Option<bool>,
+    auto_connect: Option<bool>,
+    dns_options: Option<DnsOptions>,
+    relay_settings: Option<RelaySettings>,
+}
+
+impl Policy {
+    /// Loads the policy from the settings directory. If there is no policy file, nothing is
+    /// locked. A policy file that cannot be read or parsed is an error, since ignoring it would
+    /// unlock every setting that it is meant to enforce.
+    pub async fn load(settings_dir: &Path) -> Result<Self, Error> {
+        Self::load_from_file(&settings_dir.join(POLICY_FILE)).await
+    }
+
+    async fn load_from_file(path: &Path) -> Result<Self, Error> {
+        let bytes = match fs::read(path).await {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Policy::default()),
+            Err(error) => return Err(Error::Read(path.display().to_string(), error)),
+        };
+        let policy: Policy = serde_json::from_slice(&bytes).map_err(Error::Parse)?;
+        log::info!(
+            "Loaded policy from {}. Locked settings: {}",
+            path.display(),
+            policy.locked_settings().join(", ")
+        );
+        Ok(policy)
+    }
+
+    /// Returns the names of all settings that are set by the policy.
+    pub fn locked_settings(&self) -> Vec<&'static str> {
+        let mut locked = vec![];
+        if self.allow_lan.is_some() {
+            locked.push(ALLOW_LAN);
+        }
+        if self.block_when_disconnected.is_some() {
+            locked.push(BLOCK_WHEN_DISCONNECTED);
+        }
+        if self.persistent_lockdown.is_some() {
+            locked.push(PERSISTENT_LOCKDOWN);
+        }
+        if self.auto_connect.is_some() {
+            locked.push(AUTO_CONNECT);
+        }
+        if self.dns_options.is_some() {
+            locked.push(DNS_OPTIONS);
+        }
+        if self.relay_settings.is_some() {
+            locked.push(RELAY_SETTINGS);
+        }
+        locked
+    }
+
+    pub fn is_locked(&self, setting: &str) -> bool {
+        self.locked_settings().contains(&setting)
+    }
+
+    /// Overwrites the locked settings with the values from the policy. Returns whether any
+    /// persisted setting changed.
+    pub fn apply(&self, settings: &mut Settings) -> bool {
+        let mut changed = false;
+        if let Some(allow_lan) = self.allow_lan {
+            changed |= SettingsPersister::update_field(&mut settings.allow_lan, allow_lan);
+        }
+        if let Some(block_when_disconnected) = self.block_when_disconnected {
+            changed |= SettingsPersister::update_field(
+                &mut settings.block_when_disconnected,
+                block_when_disconnected,
+            );
+        }
+        if let Some(persistent_lockdown) = self.persistent_lockdown {
+            changed |= SettingsPersister::update_field(
+                &mut settings.persistent_lockdown,
+                persistent_lockdown,
+            );
+        }
+        if let Some(auto_connect) = self.auto_connect {
+            changed |= SettingsPersister::update_field(&mut settings.auto_connect, auto_connect);
+        }
+        if let Some(ref dns_options) = self.dns_options {
+            changed |= SettingsPersister::update_field(
+                &mut settings.tunnel_options.dns_options,
+                dns_options.clone(),
+            );
+        }
+        if let Some(ref relay_settings) = self.relay_settings {
+            changed |= settings.set_relay_settings(relay_settings.clone());
+        }
+        settings.locked_settings = self
+            .locked_settings()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply_policy() {
+        let policy: Policy = serde_json::from_str(
+            r#"{
+                "allow_lan": false,
+                "block_when_disconnected": true
+            }"#,
+        )
+        .unwrap();
+
+        let mut settings = Settings::default();
+        settings.allow_lan = true;
+        settings.block_when_disconnected = false;
+
+        assert!(policy.apply(&mut settings));
+        assert!(!settings.allow_lan);
+        assert!(settings.block_when_disconnected);
+        assert_eq!(
+            settings.locked_settings,
+            [ALLOW_LAN, BLOCK_WHEN_DISCONNECTED]
+        );
+        assert!(policy.is_locked(ALLOW_LAN));
+        assert!(!policy.is_locked(AUTO_CONNECT));
+
+        assert!(!policy.apply(&mut settings));
+    }
+
+    #[test]
+    fn test_unknown_policy_field() {
+        assert!(serde_json::from_str::<Policy>(r#"{ "allow_lna": true }"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_policy_file_is_an_error() {
+        let dir = crate::test_dir();
+
+        assert!(Policy::load(dir.path())
+            .await
+            .unwrap()
+            .locked_settings()
+            .is_empty());
+
+        std::fs::write(dir.path().join(POLICY_FILE), r#"{ "allow_lan": "#).unwrap();
+        let result = Policy::load(dir.path()).await;
+        assert!(matches!(result, Err(Error::Parse(_))));
+    }
+}
@@ -1,8 +1,13 @@
+use crate::policy::{self, Policy};
 #[cfg(not(target_os = "android"))]
 use futures::TryFutureExt;
 use mullvad_types::{
-    relay_constraints::{BridgeSettings, BridgeState, ObfuscationSettings, RelaySettingsUpdate},
-    settings::{DnsOptions, Settings},
+    access_method::ApiAccessMethod,
+    relay_constraints::{
+        BridgeSettings, BridgeState, Constraint, ObfuscationSettings, RelaySettings,
+        RelaySettingsUpdate, SelectedObfuscation,
+    },
+    settings::{DnsOptions, DnsState, Settings, SettingsPatch},
     wireguard::RotationInterval,
 };
 use rand::Rng;
@@ -11,8 +16,11 @@ use std::collections::HashSet;
 use std::{
     ops::Deref,
     path::{Path, PathBuf},
+    time::Duration,
 };
-use talpid_types::ErrorExt;
+#[cfg(target_os = "linux")]
+use talpid_types::net::wireguard;
+use talpid_types::{net::TunnelType, ErrorExt};
 use tokio::{
     fs,
     io::{self, AsyncWriteExt},
@@ -41,17 +49,28 @@ pub enum Error {
 
     #[error(display = "Unable to set settings file permissions")]
     SetPermissions(#[error(source)] io::Error),
+
+    #[error(display = "The setting \"{}\" is locked by an admin policy", _0)]
+    Locked(&'static str),
+
+    #[error(display = "Invalid combination of settings: {}", _0)]
+    InvalidCombination(&'static str),
+
+    #[error(display = "Unable to load the admin policy")]
+    Policy(#[error(source)] policy::Error),
 }
 
 #[derive(Debug)]
 pub struct SettingsPersister {
     settings: Settings,
     path: PathBuf,
+    policy: Policy,
 }
 
 impl SettingsPersister {
-    /// Loads user settings from file. If it fails, it returns the defaults.
-    pub async fn load(settings_dir: &Path) -> Self {
+    /// Loads user settings from file. If it fails, it returns the defaults. Fails if there is an
+    /// admin policy that cannot be loaded.
+    pub async fn load(settings_dir: &Path) -> Result<Self, Error> {
         let path = settings_dir.join(SETTINGS_FILE);
         let (mut settings, mut should_save) = match Self::load_from_file(&path).await {
             Ok(value) => value,
@@ -88,7 +107,14 @@ impl SettingsPersister {
             should_save |= Self::update_field(&mut settings.show_beta_releases, true);
         }
 
-        let mut persister = SettingsPersister { settings, path };
+        let policy = Policy::load(settings_dir).await.map_err(Error::Policy)?;
+        should_save |= policy.apply(&mut settings);
+
+        let mut persister = SettingsPersister {
+            settings,
+            path,
+            policy,
+        };
 
         if should_save {
             if let Err(error) = persister.save().await {
@@ -99,7 +125,7 @@ impl SettingsPersister {
             }
         }
 
-        persister
+        Ok(persister)
     }
 
     async fn load_from_file(path: &Path) -> Result<(Settings, bool), Error> {
@@ -172,6 +198,7 @@ impl SettingsPersister {
     #[cfg(not(target_os = "android"))]
     pub async fn reset(&mut self) -> Result<(), Error> {
         self.settings = Self::default_settings();
+        self.policy.apply(&mut self.settings);
         let path = self.path.clone();
         self.save()
             .or_else(|e| async move {
@@ -206,11 +233,13 @@ impl SettingsPersister {
         &mut self,
         update: RelaySettingsUpdate,
     ) -> Result<bool, Error> {
+        self.ensure_unlocked(policy::RELAY_SETTINGS)?;
         let should_save = self.settings.update_relay_settings(update);
         self.update(should_save).await
     }
 
     pub async fn set_allow_lan(&mut self, allow_lan: bool) -> Result<bool, Error> {
+        self.ensure_unlocked(policy::ALLOW_LAN)?;
         let should_save = Self::update_field(&mut self.settings.allow_lan, allow_lan);
         self.update(should_save).await
     }
@@ -219,6 +248,7 @@ impl SettingsPersister {
         &mut self,
         block_when_disconnected: bool,
     ) -> Result<bool, Error> {
+        self.ensure_unlocked(policy::BLOCK_WHEN_DISCONNECTED)?;
         let should_save = Self::update_field(
             &mut self.settings.block_when_disconnected,
             block_when_disconnected,
@@ -226,7 +256,38 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
+    pub async fn set_persistent_lockdown(
+        &mut self,
+        persistent_lockdown: bool,
+    ) -> Result<bool, Error> {
+        self.ensure_unlocked(policy::PERSISTENT_LOCKDOWN)?;
+        let should_save =
+            Self::update_field(&mut self.settings.persistent_lockdown, persistent_lockdown);
+        self.update(should_save).await
+    }
+
+    pub async fn set_api_access_methods(
+        &mut self,
+        api_access_methods: Vec<ApiAccessMethod>,
+    ) -> Result<bool, Error> {
+        let should_save =
+            Self::update_field(&mut self.settings.api_access_methods, api_access_methods);
+        self.update(should_save).await
+    }
+
+    pub async fn set_expiry_reminder_days(
+        &mut self,
+        expiry_reminder_days: Vec<u32>,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.expiry_reminder_days,
+            expiry_reminder_days,
+        );
+        self.update(should_save).await
+    }
+
     pub async fn set_auto_connect(&mut self, auto_connect: bool) -> Result<bool, Error> {
+        self.ensure_unlocked(policy::AUTO_CONNECT)?;
         let should_save = Self::update_field(&mut self.settings.auto_connect, auto_connect);
         self.update(should_save).await
     }
@@ -251,6 +312,12 @@ impl SettingsPersister {
         &mut self,
         use_pq_safe_psk: bool,
     ) -> Result<bool, Error> {
+        #[cfg(target_os = "linux")]
+        if use_pq_safe_psk {
+            let mut options = self.settings.tunnel_options.wireguard.options.clone();
+            options.use_pq_safe_psk = true;
+            check_network_namespace(&options)?;
+        }
         let should_save = Self::update_field(
             &mut self
                 .settings
@@ -263,7 +330,24 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
+    pub async fn set_quantum_resistant_psk_rotation_interval(
+        &mut self,
+        interval: Option<Duration>,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self
+                .settings
+                .tunnel_options
+                .wireguard
+                .options
+                .pq_psk_rotation_interval,
+            interval,
+        );
+        self.update(should_save).await
+    }
+
     pub async fn set_dns_options(&mut self, options: DnsOptions) -> Result<bool, Error> {
+        self.ensure_unlocked(policy::DNS_OPTIONS)?;
         let should_save =
             Self::update_field(&mut self.settings.tunnel_options.dns_options, options);
         self.update(should_save).await
@@ -338,7 +422,146 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
-    fn update_field<T: Eq>(field: &mut T, new_value: T) -> bool {
+    #[cfg(target_os = "linux")]
+    pub async fn set_use_boringtun(&mut self, state: bool) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.tunnel_options.wireguard.options.use_boringtun,
+            state,
+        );
+        self.update(should_save).await
+    }
+
+    #[cfg(target_os = "linux")]
+    pub async fn set_use_network_namespace(&mut self, state: bool) -> Result<bool, Error> {
+        if state {
+            let mut options = self.settings.tunnel_options.wireguard.options.clone();
+            options.use_network_namespace = true;
+            check_network_namespace(&options)?;
+        }
+        let should_save = Self::update_field(
+            &mut self
+                .settings
+                .tunnel_options
+                .wireguard
+                .options
+                .use_network_namespace,
+            state,
+        );
+        self.update(should_save).await
+    }
+
+    /// Fails if `setting` is enforced by the admin policy.
+    fn ensure_unlocked(&self, setting: &'static str) -> Result<(), Error> {
+        if self.policy.is_locked(setting) {
+            log::warn!("Refusing to change locked setting \"{}\"", setting);
+            return Err(Error::Locked(setting));
+        }
+        Ok(())
+    }
+
+    /// Applies several changes at once and saves the settings only once. Nothing is changed if
+    /// the patch touches a locked setting or if the settings cannot be saved.
+    pub async fn apply_patch(&mut self, patch: SettingsPatch) -> Result<bool, Error> {
+        let locked_fields = [
+            (patch.relay_settings.is_some(), policy::RELAY_SETTINGS),
+            (patch.allow_lan.is_some(), policy::ALLOW_LAN),
+            (
+                patch.block_when_disconnected.is_some(),
+                policy::BLOCK_WHEN_DISCONNECTED,
+            ),
+            (
+                patch.persistent_lockdown.is_some(),
+                policy::PERSISTENT_LOCKDOWN,
+            ),
+            (patch.auto_connect.is_some(), policy::AUTO_CONNECT),
+            (patch.dns_options.is_some(), policy::DNS_OPTIONS),
+        ];
+        for (_, setting) in locked_fields.iter().filter(|(touched, _)| *touched) {
+            self.ensure_unlocked(setting)?;
+        }
+        let checks = PatchChecks::new(&patch);
+
+        let mut settings = self.settings.clone();
+        let mut should_save = false;
+        if let Some(update) = patch.relay_settings {
+            should_save |= settings.update_relay_settings(update);
+        }
+        if let Some(bridge_settings) = patch.bridge_settings {
+            should_save |= Self::update_field(&mut settings.bridge_settings, bridge_settings);
+        }
+        if let Some(bridge_state) = patch.bridge_state {
+            should_save |= settings.set_bridge_state(bridge_state);
+        }
+        if let Some(obfuscation_settings) = patch.obfuscation_settings {
+            should_save |=
+                Self::update_field(&mut settings.obfuscation_settings, obfuscation_settings);
+        }
+        if let Some(allow_lan) = patch.allow_lan {
+            should_save |= Self::update_field(&mut settings.allow_lan, allow_lan);
+        }
+        if let Some(block_when_disconnected) = patch.block_when_disconnected {
+            should_save |= Self::update_field(
+                &mut settings.block_when_disconnected,
+                block_when_disconnected,
+            );
+        }
+        if let Some(persistent_lockdown) = patch.persistent_lockdown {
+            should_save |=
+                Self::update_field(&mut settings.persistent_lockdown, persistent_lockdown);
+        }
+        if let Some(auto_connect) = patch.auto_connect {
+            should_save |= Self::update_field(&mut settings.auto_connect, auto_connect);
+        }
+        if let Some(dns_options) = patch.dns_options {
+            should_save |=
+                Self::update_field(&mut settings.tunnel_options.dns_options, dns_options);
+        }
+        if let Some(enable_ipv6) = patch.enable_ipv6 {
+            should_save |= Self::update_field(
+                &mut settings.tunnel_options.generic.enable_ipv6,
+                enable_ipv6,
+            );
+        }
+        if let Some(mssfix) = patch.openvpn_mssfix {
+            should_save |= Self::update_field(&mut settings.tunnel_options.openvpn.mssfix, mssfix);
+        }
+        if let Some(mtu) = patch.wireguard_mtu {
+            should_save |=
+                Self::update_field(&mut settings.tunnel_options.wireguard.options.mtu, mtu);
+        }
+        if let Some(use_pq_safe_psk) = patch.quantum_resistant_tunnel {
+            should_save |= Self::update_field(
+                &mut settings.tunnel_options.wireguard.options.use_pq_safe_psk,
+                use_pq_safe_psk,
+            );
+        }
+
+        if !should_save {
+            return Ok(false);
+        }
+        checks.validate(&settings)?;
+        let old_settings = std::mem::replace(&mut self.settings, settings);
+        if let Err(error) = self.save().await {
+            self.settings = old_settings;
+            return Err(error);
+        }
+        Ok(true)
+    }
+
+    /// Replaces all settings, e.g. with imported ones. Locked settings keep the values from the
+    /// admin policy.
+    pub async fn replace(&mut self, mut settings: Settings) -> Result<bool, Error> {
+        self.policy.apply(&mut settings);
+        let old_value = serde_json::to_value(&self.settings).map_err(Error::SerializeError)?;
+        let new_value = serde_json::to_value(&settings).map_err(Error::SerializeError)?;
+        let should_save = old_value != new_value;
+        if should_save {
+            self.settings = settings;
+        }
+        self.update(should_save).await
+    }
+
+    pub(crate) fn update_field<T: Eq>(field: &mut T, new_value: T) -> bool {
         if *field != new_value {
             *field = new_value;
             true
@@ -376,11 +599,92 @@ impl Deref for SettingsPersister {
     }
 }
 
+/// Which combinations of settings to check after applying a patch. Only the combinations that
+/// the patch touches are checked, so that a patch is not rejected because of settings that it
+/// does not change.
+struct PatchChecks {
+    dns: bool,
+    obfuscation: bool,
+    bridges: bool,
+    #[cfg(target_os = "linux")]
+    quantum_resistant: bool,
+}
+
+impl PatchChecks {
+    fn new(patch: &SettingsPatch) -> Self {
+        let relay_settings = patch.relay_settings.is_some();
+        PatchChecks {
+            dns: patch.dns_options.is_some(),
+            obfuscation: relay_settings || patch.obfuscation_settings.is_some(),
+            bridges: relay_settings || patch.bridge_state.is_some(),
+            #[cfg(target_os = "linux")]
+            quantum_resistant: patch.quantum_resistant_tunnel.is_some(),
+        }
+    }
+
+    fn validate(&self, settings: &Settings) -> Result<(), Error> {
+        let dns_options = &settings.tunnel_options.dns_options;
+        if self.dns
+            && dns_options.state == DnsState::Custom
+            && dns_options.custom_options.addresses.is_empty()
+        {
+            return Err(Error::InvalidCombination(
+                "custom DNS is enabled without any DNS servers",
+            ));
+        }
+
+        #[cfg(target_os = "linux")]
+        if self.quantum_resistant {
+            check_network_namespace(&settings.tunnel_options.wireguard.options)?;
+        }
+
+        let tunnel_protocol = match settings.get_relay_settings() {
+            RelaySettings::Normal(constraints) => constraints.tunnel_protocol,
+            RelaySettings::CustomTunnelEndpoint(_) => return Ok(()),
+        };
+        if self.obfuscation
+            && tunnel_protocol == Constraint::Only(TunnelType::OpenVpn)
+            && settings.obfuscation_settings.selected_obfuscation == SelectedObfuscation::Udp2Tcp
+        {
+            return Err(Error::InvalidCombination(
+                "UDP-over-TCP obfuscation is only supported with WireGuard",
+            ));
+        }
+        if self.bridges
+            && tunnel_protocol == Constraint::Only(TunnelType::Wireguard)
+            && settings.get_bridge_state() == BridgeState::On
+        {
+            return Err(Error::InvalidCombination(
+                "bridges are only supported with OpenVPN",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The PQ-safe PSK is negotiated from the network namespace of the daemon, so it cannot be used
+/// when the tunnel interface is moved into a separate namespace.
+#[cfg(target_os = "linux")]
+fn check_network_namespace(options: &wireguard::TunnelOptions) -> Result<(), Error> {
+    if options.use_pq_safe_psk && options.use_network_namespace {
+        return Err(Error::InvalidCombination(
+            "quantum-resistant tunnels cannot be used with network namespace isolation",
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
-    use super::SettingsPersister;
-    use mullvad_types::settings::SettingsVersion;
+    use super::{Error, PatchChecks, SettingsPersister};
+    use mullvad_types::{
+        relay_constraints::{
+            BridgeState, Constraint, RelayConstraints, RelaySettings, SelectedObfuscation,
+        },
+        settings::{DnsState, Settings, SettingsPatch, SettingsVersion},
+    };
     use serde_json;
+    use talpid_types::net::TunnelType;
 
     #[test]
     #[should_panic]
@@ -457,4 +761,128 @@ mod test {
 
         let _ = SettingsPersister::load_from_bytes(settings).unwrap();
     }
+
+    fn settings_with_protocol(tunnel_protocol: TunnelType) -> Settings {
+        let mut settings = Settings::default();
+        settings.set_relay_settings(RelaySettings::Normal(RelayConstraints {
+            tunnel_protocol: Constraint::Only(tunnel_protocol),
+            ..Default::default()
+        }));
+        settings
+    }
+
+    #[test]
+    fn test_patch_checks() {
+        let dns_patch = SettingsPatch {
+            dns_options: Some(Default::default()),
+            ..Default::default()
+        };
+        let obfuscation_patch = SettingsPatch {
+            obfuscation_settings: Some(Default::default()),
+            ..Default::default()
+        };
+        let bridge_patch = SettingsPatch {
+            bridge_state: Some(BridgeState::On),
+            ..Default::default()
+        };
+
+        let mut settings = Settings::default();
+        settings.tunnel_options.dns_options.state = DnsState::Custom;
+        assert!(matches!(
+            PatchChecks::new(&dns_patch).validate(&settings),
+            Err(Error::InvalidCombination(_))
+        ));
+        settings
+            .tunnel_options
+            .dns_options
+            .custom_options
+            .addresses
+            .push("10.64.0.1".parse().unwrap());
+        assert!(PatchChecks::new(&dns_patch).validate(&settings).is_ok());
+
+        let mut settings = settings_with_protocol(TunnelType::OpenVpn);
+        settings.obfuscation_settings.selected_obfuscation = SelectedObfuscation::Udp2Tcp;
+        assert!(matches!(
+            PatchChecks::new(&obfuscation_patch).validate(&settings),
+            Err(Error::InvalidCombination(_))
+        ));
+        // Only the combinations touched by the patch are checked
+        assert!(PatchChecks::new(&dns_patch).validate(&settings).is_ok());
+
+        let mut settings = settings_with_protocol(TunnelType::Wireguard);
+        settings.obfuscation_settings.selected_obfuscation = SelectedObfuscation::Udp2Tcp;
+        assert!(PatchChecks::new(&obfuscation_patch)
+            .validate(&settings)
+            .is_ok());
+        settings.set_bridge_state(BridgeState::On);
+        assert!(matches!(
+            PatchChecks::new(&bridge_patch).validate(&settings),
+            Err(Error::InvalidCombination(_))
+        ));
+
+        let mut settings = settings_with_protocol(TunnelType::OpenVpn);
+        settings.set_bridge_state(BridgeState::On);
+        assert!(PatchChecks::new(&bridge_patch).validate(&settings).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_set_persistent_lockdown() {
+        let dir = crate::test_dir();
+
+        let mut settings = SettingsPersister::load(dir.path()).await.unwrap();
+        assert!(settings.set_block_when_disconnected(false).await.unwrap());
+        assert!(settings.set_persistent_lockdown(true).await.unwrap());
+        assert!(!settings.set_persistent_lockdown(true).await.unwrap());
+        let enabled = SettingsPersister::load(dir.path()).await.unwrap();
+
+        assert!(settings.set_persistent_lockdown(false).await.unwrap());
+        let disabled = SettingsPersister::load(dir.path()).await.unwrap();
+
+        assert!(enabled.persistent_lockdown);
+        assert!(enabled.should_block_when_disconnected());
+        assert!(!disabled.persistent_lockdown);
+        assert!(!disabled.should_block_when_disconnected());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_patch_rejects_quantum_resistant_network_namespace() {
+        let pq_patch = SettingsPatch {
+            quantum_resistant_tunnel: Some(true),
+            ..Default::default()
+        };
+
+        let mut settings = Settings::default();
+        settings.tunnel_options.wireguard.options.use_pq_safe_psk = true;
+        assert!(PatchChecks::new(&pq_patch).validate(&settings).is_ok());
+
+        settings
+            .tunnel_options
+            .wireguard
+            .options
+            .use_network_namespace = true;
+        assert!(matches!(
+            PatchChecks::new(&pq_patch).validate(&settings),
+            Err(Error::InvalidCombination(_))
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_quantum_resistant_network_namespace_setters() {
+        let dir = crate::test_dir();
+        let mut settings = SettingsPersister::load(dir.path()).await.unwrap();
+
+        assert!(settings.set_use_network_namespace(true).await.unwrap());
+        let pq_result = settings.set_quantum_resistant_tunnel(true).await;
+        assert!(settings.set_use_network_namespace(false).await.unwrap());
+        assert!(settings.set_quantum_resistant_tunnel(true).await.unwrap());
+        let netns_result = settings.set_use_network_namespace(true).await;
+
+        assert!(matches!(pq_result, Err(Error::InvalidCombination(_))));
+        assert!(matches!(netns_result, Err(Error::InvalidCombination(_))));
+        let options = &settings.tunnel_options.wireguard.options;
+        assert!(options.use_pq_safe_psk);
+        assert!(!options.use_network_namespace);
+    }
 }
@@ -0,0 +1,282 @@
+//! Portable, versioned settings bundles that can be used to move a configuration between
+//! machines. Secrets, such as proxy passwords and custom tunnel keys, are left out of a bundle
+//! unless explicitly asked for. Settings that were left out keep their local values on import.
+
+use crate::migrations;
+use mullvad_types::{
+    access_method::{AccessMethod, ApiAccessMethod},
+    relay_constraints::{BridgeConstraints, BridgeSettings, RelaySettings},
+    settings::{Settings, CURRENT_SETTINGS_VERSION},
+};
+use serde::{Deserialize, Serialize};
+use talpid_types::net::openvpn::ProxySettings;
+
+/// The version of the bundle format written by this version of the daemon. This is separate from
+/// the settings version, which is handled by the settings migrations.
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Failed to parse the settings bundle")]
+    Parse(#[error(source)] serde_json::Error),
+
+    #[error(display = "Unsupported settings bundle format version: {}", _0)]
+    UnsupportedFormatVersion(u32),
+
+    #[error(display = "Failed to migrate the settings in the bundle")]
+    Migrate(#[error(source)] migrations::Error),
+
+    #[error(display = "The settings in the bundle are invalid")]
+    InvalidSettings(#[error(source)] serde_json::Error),
+
+    #[error(display = "The settings in the bundle have an unsupported version")]
+    UnsupportedSettingsVersion,
+
+    #[error(display = "Multiple API access methods are named \"{}\"", _0)]
+    DuplicateApiAccessMethod(String),
+
+    #[error(display = "A built-in API access method is missing")]
+    MissingBuiltinApiAccessMethod,
+
+    #[error(display = "Failed to serialize the settings bundle")]
+    Serialize(#[error(source)] serde_json::Error),
+}
+
+/// Settings that were left out of a bundle because they contain secrets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ExcludedSetting {
+    RelaySettings,
+    BridgeSettings,
+    ApiAccessMethods,
+}
+
+impl ExcludedSetting {
+    /// Returns the key of the setting in the serialized settings.
+    fn key(&self) -> &'static str {
+        match self {
+            ExcludedSetting::RelaySettings => "relay_settings",
+            ExcludedSetting::BridgeSettings => "bridge_settings",
+            ExcludedSetting::ApiAccessMethods => "api_access_methods",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct SettingsBundle {
+    format_version: u32,
+    /// Version of the app that created the bundle. Only informational.
+    #[serde(default)]
+    app_version: String,
+    #[serde(default)]
+    excluded: Vec<ExcludedSetting>,
+    /// The settings, in the format of the settings file.
+    settings: serde_json::Value,
+}
+
+/// Serializes `settings` as a bundle. Unless `include_secrets` is set, settings that contain
+/// secrets are replaced by their default values and marked as excluded.
+pub fn export(settings: &Settings, include_secrets: bool) -> Result<String, Error> {
+    let mut value = serde_json::to_value(settings).map_err(Error::Serialize)?;
+    let mut excluded = vec![];
+
+    if !include_secrets {
+        if let RelaySettings::CustomTunnelEndpoint(_) = settings.get_relay_settings() {
+            let default_settings = Settings::default().get_relay_settings();
+            value[ExcludedSetting::RelaySettings.key()] =
+                serde_json::to_value(default_settings).map_err(Error::Serialize)?;
+            excluded.push(ExcludedSetting::RelaySettings);
+        }
+
+        if let BridgeSettings::Custom(ref proxy) = settings.bridge_settings {
+            if proxy_has_secrets(proxy) {
+                let default_settings = BridgeSettings::Normal(BridgeConstraints::default());
+                value[ExcludedSetting::BridgeSettings.key()] =
+                    serde_json::to_value(default_settings).map_err(Error::Serialize)?;
+                excluded.push(ExcludedSetting::BridgeSettings);
+            }
+        }
+
+        if settings.api_access_methods.iter().any(has_secrets) {
+            let access_methods: Vec<_> = settings
+                .api_access_methods
+                .iter()
+                .filter(|method| !has_secrets(method))
+                .collect();
+            value[ExcludedSetting::ApiAccessMethods.key()] =
+                serde_json::to_value(access_methods).map_err(Error::Serialize)?;
+            excluded.push(ExcludedSetting::ApiAccessMethods);
+        }
+    }
+
+    let bundle = SettingsBundle {
+        format_version: BUNDLE_FORMAT_VERSION,
+        app_version: mullvad_version::VERSION.to_owned(),
+        excluded,
+        settings: value,
+    };
+    serde_json::to_string_pretty(&bundle).map_err(Error::Serialize)
+}
+
+/// Parses a bundle, migrates the settings in it to the current format and validates them.
+/// Settings that were excluded from the bundle are taken from `current`.
+pub async fn import(bundle: &str, current: &Settings) -> Result<Settings, Error> {
+    let bundle: SettingsBundle = serde_json::from_str(bundle).map_err(Error::Parse)?;
+    if bundle.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(Error::UnsupportedFormatVersion(bundle.format_version));
+    }
+    log::debug!(
+        "Importing settings bundle created by version {}",
+        bundle.app_version
+    );
+
+    let mut value = bundle.settings;
+    migrations::migrate_settings(&mut value)
+        .await
+        .map_err(Error::Migrate)?;
+
+    let current_value = serde_json::to_value(current).map_err(Error::Serialize)?;
+    for excluded in &bundle.excluded {
+        if *excluded != ExcludedSetting::ApiAccessMethods {
+            value[excluded.key()] = current_value[excluded.key()].clone();
+        }
+    }
+
+    let mut settings: Settings = serde_json::from_value(value).map_err(Error::InvalidSettings)?;
+    if settings.get_settings_version() != CURRENT_SETTINGS_VERSION {
+        return Err(Error::UnsupportedSettingsVersion);
+    }
+
+    if bundle.excluded.contains(&ExcludedSetting::ApiAccessMethods) {
+        for method in current.api_access_methods.iter().filter(|m| has_secrets(m)) {
+            if !settings
+                .api_access_methods
+                .iter()
+                .any(|imported| imported.name == method.name)
+            {
+                settings.api_access_methods.push(method.clone());
+            }
+        }
+    }
+
+    // These are specific to each machine.
+    settings.wg_migration_rand_num = current.wg_migration_rand_num;
+    #[cfg(windows)]
+    {
+        settings.split_tunnel = current.split_tunnel.clone();
+    }
+
+    validate(&settings)?;
+    Ok(settings)
+}
+
+fn validate(settings: &Settings) -> Result<(), Error> {
+    let methods = &settings.api_access_methods;
+    for (index, method) in methods.iter().enumerate() {
+        if methods[..index]
+            .iter()
+            .any(|other| other.name == method.name)
+        {
+            return Err(Error::DuplicateApiAccessMethod(method.name.clone()));
+        }
+    }
+    let has_builtin = |access_method: AccessMethod| {
+        methods
+            .iter()
+            .any(|method| method.access_method == access_method)
+    };
+    if !has_builtin(AccessMethod::Direct) || !has_builtin(AccessMethod::Bridges) {
+        return Err(Error::MissingBuiltinApiAccessMethod);
+    }
+    Ok(())
+}
+
+fn has_secrets(method: &ApiAccessMethod) -> bool {
+    match method.access_method {
+        AccessMethod::Direct | AccessMethod::Bridges => false,
+        AccessMethod::Shadowsocks(_) => true,
+        AccessMethod::Socks5(ref settings) => settings.auth.is_some(),
+    }
+}
+
+fn proxy_has_secrets(proxy: &ProxySettings) -> bool {
+    match proxy {
+        ProxySettings::Local(_) => false,
+        ProxySettings::Remote(settings) => settings.auth.is_some(),
+        ProxySettings::Shadowsocks(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use talpid_types::net::openvpn::{ProxyAuth, RemoteProxySettings};
+
+    fn socks5_method(name: &str, auth: Option<ProxyAuth>) -> ApiAccessMethod {
+        ApiAccessMethod::new(
+            name.to_owned(),
+            AccessMethod::Socks5(RemoteProxySettings {
+                address: "1.2.3.4:1080".parse().unwrap(),
+                auth,
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_export_import_without_secrets() {
+        let auth = ProxyAuth {
+            username: "user".to_owned(),
+            password: "secret".to_owned(),
+        };
+
+        let mut exported = Settings::default();
+        exported.allow_lan = true;
+        exported
+            .api_access_methods
+            .push(socks5_method("public", None));
+        exported
+            .api_access_methods
+            .push(socks5_method("private", Some(auth.clone())));
+
+        let bundle = export(&exported, false).unwrap();
+        assert!(!bundle.contains("secret"));
+
+        let mut local = Settings::default();
+        local
+            .api_access_methods
+            .push(socks5_method("local", Some(auth)));
+
+        let imported = import(&bundle, &local).await.unwrap();
+        assert!(imported.allow_lan);
+        let names: Vec<_> = imported
+            .api_access_methods
+            .iter()
+            .map(|method| method.name.as_str())
+            .collect();
+        assert_eq!(names, ["Direct", "Bridges", "public", "local"]);
+        assert_eq!(imported.wg_migration_rand_num, local.wg_migration_rand_num);
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_invalid_bundles() {
+        let local = Settings::default();
+
+        let mut bundle: serde_json::Value =
+            serde_json::from_str(&export(&local, true).unwrap()).unwrap();
+        bundle["format_version"] = serde_json::json!(BUNDLE_FORMAT_VERSION + 1);
+        assert!(matches!(
+            import(&bundle.to_string(), &local).await,
+            Err(Error::UnsupportedFormatVersion(_))
+        ));
+
+        let mut settings = local.clone();
+        settings
+            .api_access_methods
+            .push(socks5_method("Direct", None));
+        assert!(matches!(
+            import(&export(&settings, true).unwrap(), &local).await,
+            Err(Error::DuplicateApiAccessMethod(_))
+        ));
+    }
+}
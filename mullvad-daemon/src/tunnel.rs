@@ -42,6 +42,7 @@ struct InnerParametersGenerator {
     account_manager: AccountManagerHandle,
 
     last_generated_relays: Option<LastSelectedRelays>,
+    last_wireguard_parameters: Option<wireguard::TunnelParameters>,
 }
 
 impl ParametersGenerator {
@@ -58,6 +59,7 @@ impl ParametersGenerator {
             account_manager,
 
             last_generated_relays: None,
+            last_wireguard_parameters: None,
         })))
     }
 
@@ -66,6 +68,11 @@ impl ParametersGenerator {
         self.0.lock().await.tunnel_options = tunnel_options.clone();
     }
 
+    /// Gets the last generated tunnel parameters, if they were for a WireGuard tunnel.
+    pub async fn get_last_wireguard_parameters(&self) -> Option<wireguard::TunnelParameters> {
+        self.0.lock().await.last_wireguard_parameters.clone()
+    }
+
     /// Gets the location associated with the last generated tunnel parameters.
     pub async fn get_last_location(&self) -> Option<GeoIpLocation> {
         let inner = self.0.lock().await;
@@ -120,6 +127,15 @@ impl ParametersGenerator {
 
 impl InnerParametersGenerator {
     async fn generate(&mut self, retry_attempt: u32) -> Result<TunnelParameters, Error> {
+        let result = self.generate_inner(retry_attempt).await;
+        self.last_wireguard_parameters = match &result {
+            Ok(TunnelParameters::Wireguard(parameters)) => Some(parameters.clone()),
+            _ => None,
+        };
+        result
+    }
+
+    async fn generate_inner(&mut self, retry_attempt: u32) -> Result<TunnelParameters, Error> {
         let _data = self.device().await?;
         match self.relay_selector.get_relay(retry_attempt) {
             Ok((SelectedRelay::Custom(custom_relay), _bridge, _obfsucator)) => {
@@ -7,6 +7,7 @@ use jnix::{
 };
 use mullvad_daemon::EventListener;
 use mullvad_types::{
+    account::AccountExpiryEvent,
     device::{DeviceEvent, RemoveDeviceEvent},
     relay_list::RelayList,
     settings::Settings,
@@ -71,6 +72,10 @@ impl EventListener for JniEventListener {
     fn notify_remove_device_event(&self, event: RemoveDeviceEvent) {
         let _ = self.0.send(Event::RemoveDeviceEvent(event));
     }
+
+    fn notify_account_expiry(&self, _event: AccountExpiryEvent) {
+        // The Android app keeps track of the account expiry on its own.
+    }
 }
 
 struct JniEventHandler<'env> {
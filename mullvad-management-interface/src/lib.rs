@@ -1,30 +1,40 @@
 pub mod types;
 
+#[cfg(windows)]
+mod named_pipe;
+#[cfg(unix)]
+mod unix_socket;
+
 use parity_tokio_ipc::Endpoint as IpcEndpoint;
 #[cfg(unix)]
-use std::{env, fs, os::unix::fs::PermissionsExt};
+use std::sync::Arc;
 use std::{
+    convert::Infallible,
     future::Future,
     io,
     pin::Pin,
     task::{Context, Poll},
 };
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
-use tonic::transport::{server::Connected, Endpoint, Server, Uri};
+#[cfg(unix)]
+use tonic::transport::server::UdsConnectInfo;
+use tonic::{
+    body::BoxBody,
+    codegen::http,
+    transport::{Body, Endpoint, NamedService, Server, Uri},
+};
 use tower::service_fn;
 
+pub use mullvad_types::audit_log::RpcContext;
+#[cfg(windows)]
+pub use named_pipe::PipeConnectInfo;
+#[cfg(unix)]
+pub use tokio::net::unix::UCred;
 pub use tonic::{async_trait, transport::Channel, Code, Request, Response, Status};
 
 pub type ManagementServiceClient =
     types::management_service_client::ManagementServiceClient<Channel>;
 pub use types::management_service_server::{ManagementService, ManagementServiceServer};
 
-#[cfg(unix)]
-lazy_static::lazy_static! {
-    static ref MULLVAD_MANAGEMENT_SOCKET_GROUP: Option<String> = env::var("MULLVAD_MANAGEMENT_SOCKET_GROUP")
-        .ok();
-}
-
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
 pub enum Error {
@@ -69,75 +79,143 @@ pub async fn new_rpc_client() -> Result<ManagementServiceClient, Error> {
 
 pub type ServerJoinHandle = tokio::task::JoinHandle<Result<(), Error>>;
 
+tokio::task_local! {
+    static RPC_CONTEXT: RpcContext;
+}
+
+/// Returns the context of the RPC that the current task is handling. This is `None` outside of
+/// RPC handlers.
+pub fn rpc_context() -> Option<RpcContext> {
+    RPC_CONTEXT.try_with(RpcContext::clone).ok()
+}
+
+/// Returns the credentials of the client process that sent `request`, if they are known.
+#[cfg(unix)]
+pub fn peer_credentials<T>(request: &Request<T>) -> Option<UCred> {
+    request
+        .extensions()
+        .get::<UdsConnectInfo>()
+        .and_then(|info| info.peer_cred)
+}
+
+/// Returns information about the client of the pipe that `request` was received on, if it is
+/// known.
+#[cfg(windows)]
+pub fn peer_connect_info<T>(request: &Request<T>) -> Option<&PipeConnectInfo> {
+    request.extensions().get::<PipeConnectInfo>()
+}
+
+/// Decides whether a client may call an RPC, given the name of the RPC, such as `SetAllowLan`,
+/// and the credentials of the client, if they are known.
+#[cfg(unix)]
+pub type AccessCheck = Arc<dyn Fn(&str, Option<&UCred>) -> Result<(), Status> + Send + Sync>;
+
+/// Starts the management interface server. If `access_check` is given, it is consulted before
+/// every RPC.
+#[cfg(unix)]
 pub async fn spawn_rpc_server<T: ManagementService, F: Future<Output = ()> + Send + 'static>(
     service: T,
     abort_rx: F,
+    access_check: Option<AccessCheck>,
 ) -> std::result::Result<ServerJoinHandle, Error> {
-    use futures::stream::TryStreamExt;
-    use parity_tokio_ipc::SecurityAttributes;
-
-    let socket_path = mullvad_paths::get_rpc_socket_path();
+    let incoming = unix_socket::incoming(&mullvad_paths::get_rpc_socket_path())?;
 
-    let mut endpoint = IpcEndpoint::new(socket_path.to_string_lossy().to_string());
-    endpoint.set_security_attributes(
-        SecurityAttributes::allow_everyone_create()
-            .map_err(Error::SecurityAttributes)?
-            .set_mode(0o766)
-            .map_err(Error::SecurityAttributes)?,
-    );
-    let incoming = endpoint.incoming().map_err(Error::StartServerError)?;
-
-    #[cfg(unix)]
-    if let Some(group_name) = &*MULLVAD_MANAGEMENT_SOCKET_GROUP {
-        let group = nix::unistd::Group::from_name(group_name)
-            .map_err(Error::ObtainGidError)?
-            .ok_or(Error::NoGidError)?;
-        nix::unistd::chown(&socket_path, None, Some(group.gid)).map_err(Error::SetGidError)?;
-        fs::set_permissions(&socket_path, PermissionsExt::from_mode(0o760))
-            .map_err(Error::PermissionsError)?;
-    }
+    let service = AccessControl {
+        inner: ManagementServiceServer::new(service),
+        access_check,
+    };
 
     Ok(tokio::spawn(async move {
         Server::builder()
-            .add_service(ManagementServiceServer::new(service))
-            .serve_with_incoming_shutdown(incoming.map_ok(StreamBox), abort_rx)
+            .add_service(service)
+            .serve_with_incoming_shutdown(incoming, abort_rx)
             .await
             .map_err(Error::GrpcTransportError)
     }))
 }
 
-#[derive(Debug)]
-struct StreamBox<T: AsyncRead + AsyncWrite>(pub T);
-impl<T: AsyncRead + AsyncWrite> Connected for StreamBox<T> {
-    type ConnectInfo = Option<()>;
-
-    fn connect_info(&self) -> Self::ConnectInfo {
-        None
-    }
+/// Wraps the management service, rejects RPCs that the client is not allowed to call, and makes
+/// the [`RpcContext`] available to the RPC handlers. On Windows, every RPC is allowed and only
+/// the process ID of the client is known.
+#[derive(Clone)]
+struct AccessControl<S> {
+    inner: S,
+    #[cfg(unix)]
+    access_check: Option<AccessCheck>,
 }
-impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for StreamBox<T> {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &mut ReadBuf<'_>,
-    ) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.0).poll_read(cx, buf)
-    }
+
+impl<S: NamedService> NamedService for AccessControl<S> {
+    const NAME: &'static str = S::NAME;
 }
-impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for StreamBox<T> {
-    fn poll_write(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.0).poll_write(cx, buf)
-    }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.0).poll_flush(cx)
+impl<S> tower::Service<http::Request<Body>> for AccessControl<S>
+where
+    S: tower::Service<http::Request<Body>, Response = http::Response<BoxBody>, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
     }
 
-    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.0).poll_shutdown(cx)
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        // The path has the form `/<package>.<service>/<method>`
+        let method = request
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        #[cfg(unix)]
+        let context = {
+            let credentials = request
+                .extensions()
+                .get::<UdsConnectInfo>()
+                .and_then(|info| info.peer_cred);
+            if let Some(access_check) = &self.access_check {
+                if let Err(status) = access_check(&method, credentials.as_ref()) {
+                    return Box::pin(async move { Ok(status.to_http()) });
+                }
+            }
+            RpcContext {
+                method,
+                uid: credentials.map(|credentials| credentials.uid()),
+                pid: credentials.and_then(|credentials| credentials.pid()),
+            }
+        };
+        #[cfg(windows)]
+        let context = RpcContext {
+            method,
+            uid: None,
+            pid: request
+                .extensions()
+                .get::<PipeConnectInfo>()
+                .and_then(|info| info.process_id)
+                .and_then(|pid| i32::try_from(pid).ok()),
+        };
+        Box::pin(RPC_CONTEXT.scope(context, self.inner.call(request)))
     }
 }
+
+/// Starts the management interface server.
+#[cfg(windows)]
+pub async fn spawn_rpc_server<T: ManagementService, F: Future<Output = ()> + Send + 'static>(
+    service: T,
+    abort_rx: F,
+) -> std::result::Result<ServerJoinHandle, Error> {
+    let incoming = named_pipe::incoming(&mullvad_paths::get_rpc_socket_path())?;
+
+    Ok(tokio::spawn(async move {
+        Server::builder()
+            .add_service(AccessControl {
+                inner: ManagementServiceServer::new(service),
+            })
+            .serve_with_incoming_shutdown(incoming, abort_rx)
+            .await
+            .map_err(Error::GrpcTransportError)
+    }))
+}
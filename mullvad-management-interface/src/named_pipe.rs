@@ -0,0 +1,303 @@
+//! Named pipe transport for the management interface server on Windows. The pipe is created
+//! directly, rather than through `parity_tokio_ipc`, so that the process ID of connecting clients
+//! is available to the RPC handlers.
+
+use crate::Error;
+use futures::{stream, Stream};
+use std::{
+    ffi::{c_void, OsStr},
+    io, mem,
+    os::windows::{ffi::OsStrExt, io::AsRawHandle},
+    path::Path,
+    pin::Pin,
+    ptr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::windows::named_pipe::{NamedPipeServer, ServerOptions},
+};
+use tonic::transport::server::Connected;
+use windows_sys::Win32::{
+    Foundation::{CloseHandle, DuplicateHandle, DUPLICATE_SAME_ACCESS, HANDLE},
+    Security::{
+        Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+        GetTokenInformation, RevertToSelf, TokenElevation, PSECURITY_DESCRIPTOR,
+        SECURITY_ATTRIBUTES, TOKEN_ELEVATION, TOKEN_QUERY,
+    },
+    System::{
+        Memory::LocalFree,
+        Pipes::{GetNamedPipeClientProcessId, ImpersonateNamedPipeClient},
+        Threading::{
+            GetCurrentProcess, GetCurrentThread, GetProcessId, OpenProcess, OpenThreadToken,
+            PROCESS_QUERY_LIMITED_INFORMATION,
+        },
+    },
+};
+
+/// Gives SYSTEM and administrators full access to the pipe, and lets everyone else read from and
+/// write to it.
+///
+/// Every local user must be able to reach the management interface, since the GUI and CLI run
+/// as the logged in user and open the pipe with `GENERIC_READ | GENERIC_WRITE`. This is the same
+/// access that `parity_tokio_ipc::SecurityAttributes::allow_everyone_create` granted before.
+/// Connecting does not grant any privileges by itself: RPCs that change the system are only
+/// accepted from elevated clients, which is decided from the token of the client for each call.
+///
+/// For pipes, `GENERIC_WRITE` includes the right to create new instances. A process that does
+/// so can only intercept clients if it wins the race for a free instance, and it can never take
+/// over the pipe name, since the daemon creates the first instance with
+/// `FILE_FLAG_FIRST_PIPE_INSTANCE` and keeps an instance listening while it runs.
+const PIPE_SECURITY_DESCRIPTOR: &str = "D:(A;;GA;;;SY)(A;;GA;;;BA)(A;;GRGW;;;WD)";
+
+/// Creates the first instance of the pipe at `pipe_path` and returns the stream of incoming
+/// connections. A new instance is created every time a client connects.
+pub fn incoming(
+    pipe_path: &Path,
+) -> Result<impl Stream<Item = io::Result<PipeConnection>> + Send + 'static, Error> {
+    let security = Arc::new(PipeSecurity::new().map_err(Error::SecurityAttributes)?);
+    let pipe_path = pipe_path.as_os_str().to_owned();
+    // Failing if the pipe already exists prevents other processes from impersonating the daemon
+    let first_instance = security
+        .create_pipe(&pipe_path, true)
+        .map_err(Error::StartServerError)?;
+
+    Ok(stream::try_unfold(first_instance, move |pipe| {
+        let security = security.clone();
+        let pipe_path = pipe_path.clone();
+        async move {
+            pipe.connect().await?;
+            let next_instance = security.create_pipe(&pipe_path, false)?;
+            Ok(Some((PipeConnection::new(pipe)?, next_instance)))
+        }
+    }))
+}
+
+/// Security descriptor that every instance of the pipe is created with.
+struct PipeSecurity(PSECURITY_DESCRIPTOR);
+
+// SAFETY: The descriptor is never modified after it has been created.
+unsafe impl Send for PipeSecurity {}
+unsafe impl Sync for PipeSecurity {}
+
+impl PipeSecurity {
+    fn new() -> io::Result<Self> {
+        let sddl: Vec<u16> = OsStr::new(PIPE_SECURITY_DESCRIPTOR)
+            .encode_wide()
+            .chain(Some(0))
+            .collect();
+        let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+        let status = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                ptr::null_mut(),
+            )
+        };
+        if status == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self(descriptor))
+    }
+
+    fn create_pipe(&self, pipe_path: &OsStr, first_instance: bool) -> io::Result<NamedPipeServer> {
+        let mut attributes = SECURITY_ATTRIBUTES {
+            nLength: mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: self.0,
+            bInheritHandle: 0,
+        };
+        // SAFETY: `attributes` and the descriptor it points to outlive the call.
+        unsafe {
+            ServerOptions::new()
+                .first_pipe_instance(first_instance)
+                .create_with_security_attributes_raw(
+                    pipe_path,
+                    &mut attributes as *mut SECURITY_ATTRIBUTES as *mut c_void,
+                )
+        }
+    }
+}
+
+impl Drop for PipeSecurity {
+    fn drop(&mut self) {
+        unsafe { LocalFree(self.0 as isize) };
+    }
+}
+
+/// Information about the client of a [`PipeConnection`].
+#[derive(Debug, Clone)]
+pub struct PipeConnectInfo {
+    /// Process ID of the client, if it could be determined. The client process is kept open for
+    /// as long as the connection or any request on it is alive, so the ID cannot be reused by
+    /// another process in the meantime.
+    pub process_id: Option<u32>,
+    client: Arc<PipeClient>,
+}
+
+impl PipeConnectInfo {
+    /// Returns whether the client runs elevated, or as `SYSTEM`. This checks the token that the
+    /// client connected with, rather than looking up the client process by its ID.
+    pub fn is_elevated_client(&self) -> io::Result<bool> {
+        self.client.is_elevated()
+    }
+}
+
+/// Handles that identify the client of a pipe instance. They are closed on drop.
+#[derive(Debug)]
+struct PipeClient {
+    /// Duplicate of the server end of the pipe, used to impersonate the client.
+    pipe: HANDLE,
+    process: Option<HANDLE>,
+}
+
+impl PipeClient {
+    fn new(pipe: &NamedPipeServer) -> io::Result<Self> {
+        let mut pipe_handle: HANDLE = 0;
+        let status = unsafe {
+            DuplicateHandle(
+                GetCurrentProcess(),
+                pipe.as_raw_handle() as HANDLE,
+                GetCurrentProcess(),
+                &mut pipe_handle,
+                0,
+                0,
+                DUPLICATE_SAME_ACCESS,
+            )
+        };
+        if status == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut client = Self {
+            pipe: pipe_handle,
+            process: None,
+        };
+        client.process = client
+            .open_process()
+            .map_err(|error| log::warn!("Failed to open the process of a client: {}", error))
+            .ok();
+        Ok(client)
+    }
+
+    fn open_process(&self) -> io::Result<HANDLE> {
+        let mut process_id = 0;
+        if unsafe { GetNamedPipeClientProcessId(self.pipe, &mut process_id) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id) };
+        if process == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(process)
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        self.process
+            .map(|process| unsafe { GetProcessId(process) })
+            .filter(|process_id| *process_id != 0)
+    }
+
+    /// Checks the elevation of the client's token, which is obtained by impersonating the client
+    /// on the current thread. This only works once data has been read from the pipe.
+    fn is_elevated(&self) -> io::Result<bool> {
+        if unsafe { ImpersonateNamedPipeClient(self.pipe) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut token: HANDLE = 0;
+        let status = unsafe { OpenThreadToken(GetCurrentThread(), TOKEN_QUERY, 1, &mut token) };
+        let open_result = if status == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(token)
+        };
+        if unsafe { RevertToSelf() } == 0 {
+            // Carrying on with the identity of the client is not an option
+            panic!(
+                "Failed to stop impersonating a client: {}",
+                io::Error::last_os_error()
+            );
+        }
+        let token = open_result?;
+
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut returned_size = 0;
+        let status = unsafe {
+            GetTokenInformation(
+                token,
+                TokenElevation,
+                &mut elevation as *mut TOKEN_ELEVATION as *mut c_void,
+                mem::size_of::<TOKEN_ELEVATION>() as u32,
+                &mut returned_size,
+            )
+        };
+        let result = if status == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(elevation.TokenIsElevated != 0)
+        };
+        unsafe { CloseHandle(token) };
+        result
+    }
+}
+
+impl Drop for PipeClient {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.pipe) };
+        if let Some(process) = self.process {
+            unsafe { CloseHandle(process) };
+        }
+    }
+}
+
+/// Server end of a connected pipe instance.
+pub struct PipeConnection {
+    pipe: NamedPipeServer,
+    client: Arc<PipeClient>,
+}
+
+impl PipeConnection {
+    fn new(pipe: NamedPipeServer) -> io::Result<Self> {
+        let client = Arc::new(PipeClient::new(&pipe)?);
+        Ok(Self { pipe, client })
+    }
+}
+
+impl Connected for PipeConnection {
+    type ConnectInfo = PipeConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        PipeConnectInfo {
+            process_id: self.client.process_id(),
+            client: self.client.clone(),
+        }
+    }
+}
+
+impl AsyncRead for PipeConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PipeConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.pipe).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.pipe).poll_shutdown(cx)
+    }
+}
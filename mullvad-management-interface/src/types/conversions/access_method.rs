@@ -0,0 +1,94 @@
+use crate::types::{proto, FromProtobufTypeError};
+use mullvad_types::access_method::{AccessMethod, ApiAccessMethod};
+use talpid_types::net::openvpn::{ProxyAuth, RemoteProxySettings, ShadowsocksProxySettings};
+
+impl From<&ApiAccessMethod> for proto::ApiAccessMethod {
+    fn from(method: &ApiAccessMethod) -> Self {
+        use proto::api_access_method;
+
+        let access_method = match &method.access_method {
+            AccessMethod::Direct => {
+                api_access_method::AccessMethod::Direct(api_access_method::Direct {})
+            }
+            AccessMethod::Bridges => {
+                api_access_method::AccessMethod::Bridges(api_access_method::Bridges {})
+            }
+            AccessMethod::Shadowsocks(settings) => api_access_method::AccessMethod::Shadowsocks(
+                proto::bridge_settings::ShadowsocksProxySettings {
+                    peer: settings.peer.to_string(),
+                    password: settings.password.clone(),
+                    cipher: settings.cipher.clone(),
+                },
+            ),
+            AccessMethod::Socks5(settings) => api_access_method::AccessMethod::Socks5(
+                proto::bridge_settings::RemoteProxySettings {
+                    address: settings.address.to_string(),
+                    auth: settings.auth.as_ref().map(|auth| {
+                        proto::bridge_settings::RemoteProxyAuth {
+                            username: auth.username.clone(),
+                            password: auth.password.clone(),
+                        }
+                    }),
+                },
+            ),
+        };
+
+        proto::ApiAccessMethod {
+            name: method.name.clone(),
+            enabled: method.enabled,
+            access_method: Some(access_method),
+        }
+    }
+}
+
+impl TryFrom<proto::ApiAccessMethod> for ApiAccessMethod {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(method: proto::ApiAccessMethod) -> Result<Self, Self::Error> {
+        use proto::api_access_method;
+
+        if method.name.is_empty() {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "missing access method name",
+            ));
+        }
+
+        let access_method = method
+            .access_method
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing access method",
+            ))?;
+        let access_method = match access_method {
+            api_access_method::AccessMethod::Direct(_) => AccessMethod::Direct,
+            api_access_method::AccessMethod::Bridges(_) => AccessMethod::Bridges,
+            api_access_method::AccessMethod::Shadowsocks(settings) => {
+                let peer = settings.peer.parse().map_err(|_| {
+                    FromProtobufTypeError::InvalidArgument("failed to parse peer address")
+                })?;
+                AccessMethod::Shadowsocks(ShadowsocksProxySettings {
+                    peer,
+                    password: settings.password,
+                    cipher: settings.cipher,
+                    #[cfg(target_os = "linux")]
+                    fwmark: Some(mullvad_types::TUNNEL_FWMARK),
+                })
+            }
+            api_access_method::AccessMethod::Socks5(settings) => {
+                let address = settings.address.parse().map_err(|_| {
+                    FromProtobufTypeError::InvalidArgument("failed to parse proxy address")
+                })?;
+                let auth = settings.auth.map(|auth| ProxyAuth {
+                    username: auth.username,
+                    password: auth.password,
+                });
+                AccessMethod::Socks5(RemoteProxySettings { address, auth })
+            }
+        };
+
+        Ok(ApiAccessMethod {
+            name: method.name,
+            enabled: method.enabled,
+            access_method,
+        })
+    }
+}
@@ -0,0 +1,48 @@
+use crate::types::{proto, FromProtobufTypeError};
+use mullvad_types::account::AccountExpiryEvent;
+use prost_types::Timestamp;
+
+impl From<AccountExpiryEvent> for proto::AccountExpiryEvent {
+    fn from(event: AccountExpiryEvent) -> Self {
+        use proto::account_expiry_event::Kind;
+
+        let (kind, days_left) = match event {
+            AccountExpiryEvent::ExpiringSoon { days_left, .. } => (Kind::ExpiringSoon, days_left),
+            AccountExpiryEvent::Expired { .. } => (Kind::Expired, 0),
+        };
+        proto::AccountExpiryEvent {
+            kind: kind as i32,
+            expiry: Some(Timestamp {
+                seconds: event.expiry().timestamp(),
+                nanos: 0,
+            }),
+            days_left,
+        }
+    }
+}
+
+impl TryFrom<proto::AccountExpiryEvent> for AccountExpiryEvent {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(event: proto::AccountExpiryEvent) -> Result<Self, Self::Error> {
+        use proto::account_expiry_event::Kind;
+
+        let expiry = event.expiry.ok_or(FromProtobufTypeError::InvalidArgument(
+            "missing 'expiry' field",
+        ))?;
+        let expiry = chrono::DateTime::from_utc(
+            chrono::NaiveDateTime::from_timestamp(expiry.seconds, 0),
+            chrono::Utc,
+        );
+        match Kind::from_i32(event.kind) {
+            Some(Kind::ExpiringSoon) => Ok(AccountExpiryEvent::ExpiringSoon {
+                expiry,
+                days_left: event.days_left,
+            }),
+            Some(Kind::Expired) => Ok(AccountExpiryEvent::Expired { expiry }),
+            None => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid account expiry event kind",
+            )),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::types::proto;
+use mullvad_types::api_diagnostics::{ApiDiagnostics, EndpointMetrics, LatencyBucket};
+
+impl From<ApiDiagnostics> for proto::ApiDiagnostics {
+    fn from(diagnostics: ApiDiagnostics) -> Self {
+        Self {
+            connection_mode: diagnostics.connection_mode,
+            connection_mode_changes: diagnostics.connection_mode_changes,
+            endpoints: diagnostics
+                .endpoints
+                .into_iter()
+                .map(proto::ApiEndpointMetrics::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<EndpointMetrics> for proto::ApiEndpointMetrics {
+    fn from(metrics: EndpointMetrics) -> Self {
+        Self {
+            endpoint: metrics.endpoint,
+            request_count: metrics.request_count,
+            latency_histogram: metrics
+                .latency_histogram
+                .into_iter()
+                .map(|bucket| proto::api_endpoint_metrics::LatencyBucket {
+                    upper_bound_ms: bucket.upper_bound_ms.unwrap_or(0),
+                    count: bucket.count,
+                })
+                .collect(),
+            status_codes: metrics
+                .status_codes
+                .into_iter()
+                .map(|(status, count)| (u32::from(status), count))
+                .collect(),
+            errors: metrics.errors.into_iter().collect(),
+            connection_modes: metrics.connection_modes.into_iter().collect(),
+        }
+    }
+}
+
+impl From<proto::ApiDiagnostics> for ApiDiagnostics {
+    fn from(diagnostics: proto::ApiDiagnostics) -> Self {
+        Self {
+            connection_mode: diagnostics.connection_mode,
+            connection_mode_changes: diagnostics.connection_mode_changes,
+            endpoints: diagnostics
+                .endpoints
+                .into_iter()
+                .map(EndpointMetrics::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<proto::ApiEndpointMetrics> for EndpointMetrics {
+    fn from(metrics: proto::ApiEndpointMetrics) -> Self {
+        Self {
+            endpoint: metrics.endpoint,
+            request_count: metrics.request_count,
+            latency_histogram: metrics
+                .latency_histogram
+                .into_iter()
+                .map(|bucket| LatencyBucket {
+                    upper_bound_ms: Some(bucket.upper_bound_ms).filter(|bound| *bound != 0),
+                    count: bucket.count,
+                })
+                .collect(),
+            status_codes: metrics
+                .status_codes
+                .into_iter()
+                .filter_map(|(status, count)| Some((u16::try_from(status).ok()?, count)))
+                .collect(),
+            errors: metrics.errors.into_iter().collect(),
+            connection_modes: metrics.connection_modes.into_iter().collect(),
+        }
+    }
+}
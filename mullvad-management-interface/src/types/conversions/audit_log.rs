@@ -0,0 +1,56 @@
+use crate::types::{proto, FromProtobufTypeError};
+use mullvad_types::audit_log::{AuditLogEntry, SettingChange};
+use prost_types::Timestamp;
+
+impl From<AuditLogEntry> for proto::AuditLogEntry {
+    fn from(entry: AuditLogEntry) -> Self {
+        Self {
+            timestamp: Some(Timestamp {
+                seconds: entry.timestamp.timestamp(),
+                nanos: 0,
+            }),
+            uid: entry.uid,
+            pid: entry.pid,
+            command: entry.command,
+            changes: entry
+                .changes
+                .into_iter()
+                .map(|change| proto::audit_log_entry::SettingChange {
+                    setting: change.setting,
+                    old_value: change.old_value,
+                    new_value: change.new_value,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::AuditLogEntry> for AuditLogEntry {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(entry: proto::AuditLogEntry) -> Result<Self, Self::Error> {
+        let timestamp = entry
+            .timestamp
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing 'timestamp' field",
+            ))?;
+        Ok(Self {
+            timestamp: chrono::DateTime::from_utc(
+                chrono::NaiveDateTime::from_timestamp(timestamp.seconds, 0),
+                chrono::Utc,
+            ),
+            uid: entry.uid,
+            pid: entry.pid,
+            command: entry.command,
+            changes: entry
+                .changes
+                .into_iter()
+                .map(|change| SettingChange {
+                    setting: change.setting,
+                    old_value: change.old_value,
+                    new_value: change.new_value,
+                })
+                .collect(),
+        })
+    }
+}
@@ -1,5 +1,5 @@
 use crate::types::{
-    conversions::{bytes_to_privkey, bytes_to_pubkey, option_from_proto_string},
+    conversions::{bytes_to_privkey, bytes_to_psk, bytes_to_pubkey, option_from_proto_string},
     proto, FromProtobufTypeError,
 };
 use talpid_types::net::wireguard;
@@ -47,6 +47,11 @@ impl TryFrom<proto::ConnectionConfig> for mullvad_types::ConnectionConfig {
                 ))?;
 
                 let public_key = bytes_to_pubkey(&peer.public_key)?;
+                let psk = if peer.psk.is_empty() {
+                    None
+                } else {
+                    Some(bytes_to_psk(&peer.psk)?)
+                };
 
                 let ipv4_gateway = config.ipv4_gateway.parse().map_err(|_err| {
                     FromProtobufTypeError::InvalidArgument("invalid IPv4 gateway")
@@ -89,7 +94,7 @@ impl TryFrom<proto::ConnectionConfig> for mullvad_types::ConnectionConfig {
                             public_key,
                             allowed_ips,
                             endpoint,
-                            psk: None,
+                            psk,
                         },
                         exit_peer: None,
                         ipv4_gateway,
@@ -139,6 +144,12 @@ impl From<mullvad_types::ConnectionConfig> for proto::ConnectionConfig {
                                 .map(|address| address.to_string())
                                 .collect(),
                             endpoint: config.peer.endpoint.to_string(),
+                            psk: config
+                                .peer
+                                .psk
+                                .as_ref()
+                                .map(|psk| psk.as_bytes().to_vec())
+                                .unwrap_or_default(),
                         }),
                         ipv4_gateway: config.ipv4_gateway.to_string(),
                         ipv6_gateway: config
@@ -80,6 +80,83 @@ impl From<&mullvad_types::device::DeviceState> for proto::device_state::State {
     }
 }
 
+impl TryFrom<proto::DeviceState> for mullvad_types::device::DeviceState {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(state: proto::DeviceState) -> Result<Self, Self::Error> {
+        use mullvad_types::device::DeviceState as MullvadState;
+        match proto::device_state::State::from_i32(state.state) {
+            Some(proto::device_state::State::LoggedIn) => {
+                let device = state.device.ok_or(FromProtobufTypeError::InvalidArgument(
+                    "missing 'device' field",
+                ))?;
+                Ok(MullvadState::LoggedIn(
+                    mullvad_types::device::AccountAndDevice::try_from(device)?,
+                ))
+            }
+            Some(proto::device_state::State::LoggedOut) => Ok(MullvadState::LoggedOut),
+            Some(proto::device_state::State::Revoked) => Ok(MullvadState::Revoked),
+            None => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid device state",
+            )),
+        }
+    }
+}
+
+impl TryFrom<proto::AccountAndDevice> for mullvad_types::device::AccountAndDevice {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(device: proto::AccountAndDevice) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::device::AccountAndDevice {
+            account_token: device.account_token,
+            device: mullvad_types::device::Device::try_from(device.device.ok_or(
+                FromProtobufTypeError::InvalidArgument("missing 'device' field"),
+            )?)?,
+        })
+    }
+}
+
+impl TryFrom<proto::DeviceEvent> for mullvad_types::device::DeviceEvent {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(event: proto::DeviceEvent) -> Result<Self, Self::Error> {
+        use mullvad_types::device::DeviceEventCause as MullvadCause;
+        let cause = match proto::device_event::Cause::from_i32(event.cause) {
+            Some(proto::device_event::Cause::LoggedIn) => MullvadCause::LoggedIn,
+            Some(proto::device_event::Cause::LoggedOut) => MullvadCause::LoggedOut,
+            Some(proto::device_event::Cause::Revoked) => MullvadCause::Revoked,
+            Some(proto::device_event::Cause::Updated) => MullvadCause::Updated,
+            Some(proto::device_event::Cause::RotatedKey) => MullvadCause::RotatedKey,
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "invalid device event cause",
+                ))
+            }
+        };
+        Ok(mullvad_types::device::DeviceEvent {
+            cause,
+            new_state: mullvad_types::device::DeviceState::try_from(event.new_state.ok_or(
+                FromProtobufTypeError::InvalidArgument("missing 'new_state' field"),
+            )?)?,
+        })
+    }
+}
+
+impl TryFrom<proto::RemoveDeviceEvent> for mullvad_types::device::RemoveDeviceEvent {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(event: proto::RemoveDeviceEvent) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::device::RemoveDeviceEvent {
+            account_token: event.account_token,
+            new_devices: event
+                .new_device_list
+                .into_iter()
+                .map(mullvad_types::device::Device::try_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
 impl From<mullvad_types::device::DeviceEvent> for proto::DeviceEvent {
     fn from(event: mullvad_types::device::DeviceEvent) -> Self {
         proto::DeviceEvent {
@@ -137,3 +214,29 @@ impl From<proto::DevicePort> for mullvad_types::device::DevicePort {
         mullvad_types::device::DevicePort { id: port.id }
     }
 }
+
+impl From<mullvad_types::device::AccountProfile> for proto::AccountProfile {
+    fn from(profile: mullvad_types::device::AccountProfile) -> Self {
+        proto::AccountProfile {
+            name: profile.name,
+            account_token: profile.account_token,
+            device: Some(proto::Device::from(profile.device)),
+            active: profile.active,
+        }
+    }
+}
+
+impl TryFrom<proto::AccountProfile> for mullvad_types::device::AccountProfile {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(profile: proto::AccountProfile) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::device::AccountProfile {
+            name: profile.name,
+            account_token: profile.account_token,
+            device: mullvad_types::device::Device::try_from(profile.device.ok_or(
+                FromProtobufTypeError::InvalidArgument("missing 'device' field"),
+            )?)?,
+            active: profile.active,
+        })
+    }
+}
@@ -0,0 +1,43 @@
+use crate::types::{proto, FromProtobufTypeError};
+use mullvad_types::diagnostics::{CheckStatus, DiagnosticCheck};
+
+impl From<DiagnosticCheck> for proto::DiagnosticCheck {
+    fn from(check: DiagnosticCheck) -> Self {
+        use proto::diagnostic_check::Status;
+        let status = match check.status {
+            CheckStatus::Pass => Status::Pass,
+            CheckStatus::Fail => Status::Fail,
+            CheckStatus::Skipped => Status::Skipped,
+        };
+        Self {
+            name: check.name,
+            status: status as i32,
+            details: check.details,
+            remediation: check.remediation,
+        }
+    }
+}
+
+impl TryFrom<proto::DiagnosticCheck> for DiagnosticCheck {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(check: proto::DiagnosticCheck) -> Result<Self, Self::Error> {
+        use proto::diagnostic_check::Status;
+        let status = match Status::from_i32(check.status) {
+            Some(Status::Pass) => CheckStatus::Pass,
+            Some(Status::Fail) => CheckStatus::Fail,
+            Some(Status::Skipped) => CheckStatus::Skipped,
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "invalid diagnostic check status",
+                ))
+            }
+        };
+        Ok(Self {
+            name: check.name,
+            status,
+            details: check.details,
+            remediation: check.remediation,
+        })
+    }
+}
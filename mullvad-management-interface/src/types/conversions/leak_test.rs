@@ -0,0 +1,64 @@
+use crate::types::{proto, FromProtobufTypeError};
+use talpid_types::leak_test::{ProbeKind, ProbeOutcome, ProbeResult};
+
+impl From<ProbeResult> for proto::LeakTestProbe {
+    fn from(probe: ProbeResult) -> Self {
+        use proto::leak_test_probe::{Kind, Outcome};
+        let kind = match probe.kind {
+            ProbeKind::Dns => Kind::Dns,
+            ProbeKind::Ipv4 => Kind::Ipv4,
+            ProbeKind::Ipv6 => Kind::Ipv6,
+            ProbeKind::Lan => Kind::Lan,
+        };
+        let (outcome, error) = match probe.outcome {
+            ProbeOutcome::Blocked => (Outcome::Blocked, String::new()),
+            ProbeOutcome::Sent => (Outcome::Sent, String::new()),
+            ProbeOutcome::Error(error) => (Outcome::Error, error),
+        };
+        Self {
+            kind: kind as i32,
+            target: probe.target.to_string(),
+            should_block: probe.should_block,
+            outcome: outcome as i32,
+            error,
+        }
+    }
+}
+
+impl TryFrom<proto::LeakTestProbe> for ProbeResult {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(probe: proto::LeakTestProbe) -> Result<Self, Self::Error> {
+        use proto::leak_test_probe::{Kind, Outcome};
+        let kind = match Kind::from_i32(probe.kind) {
+            Some(Kind::Dns) => ProbeKind::Dns,
+            Some(Kind::Ipv4) => ProbeKind::Ipv4,
+            Some(Kind::Ipv6) => ProbeKind::Ipv6,
+            Some(Kind::Lan) => ProbeKind::Lan,
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "invalid leak test probe kind",
+                ))
+            }
+        };
+        let outcome = match Outcome::from_i32(probe.outcome) {
+            Some(Outcome::Blocked) => ProbeOutcome::Blocked,
+            Some(Outcome::Sent) => ProbeOutcome::Sent,
+            Some(Outcome::Error) => ProbeOutcome::Error(probe.error),
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "invalid leak test probe outcome",
+                ))
+            }
+        };
+        let target = probe.target.parse().map_err(|_| {
+            FromProtobufTypeError::InvalidArgument("invalid leak test probe target")
+        })?;
+        Ok(Self {
+            kind,
+            target,
+            should_block: probe.should_block,
+            outcome,
+        })
+    }
+}
@@ -1,7 +1,13 @@
 use std::str::FromStr;
 
+mod access_method;
+mod account;
+mod api_diagnostics;
+mod audit_log;
 mod custom_tunnel;
 mod device;
+mod diagnostics;
+mod leak_test;
 mod location;
 mod net;
 pub mod relay_constraints;
@@ -32,6 +38,14 @@ fn bytes_to_privkey(
     ))
 }
 
+fn bytes_to_psk(
+    bytes: &[u8],
+) -> Result<talpid_types::net::wireguard::PresharedKey, FromProtobufTypeError> {
+    Ok(talpid_types::net::wireguard::PresharedKey::from(Box::new(
+        *bytes_to_wg_key(bytes, "invalid preshared key")?,
+    )))
+}
+
 fn bytes_to_wg_key<'a>(
     bytes: &'a [u8],
     error_msg: &'static str,
@@ -1,5 +1,8 @@
 use crate::types::{
-    conversions::{bytes_to_pubkey, option_from_proto_string, to_proto_any, try_from_proto_any},
+    conversions::{
+        arg_from_str, bytes_to_pubkey, net::try_transport_protocol_from_i32,
+        option_from_proto_string, to_proto_any, try_from_proto_any,
+    },
     proto, FromProtobufTypeError,
 };
 
@@ -203,3 +206,114 @@ impl TryFrom<proto::Relay> for mullvad_types::relay_list::Relay {
         })
     }
 }
+
+impl TryFrom<proto::RelayList> for mullvad_types::relay_list::RelayList {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(relay_list: proto::RelayList) -> Result<Self, Self::Error> {
+        use mullvad_types::relay_list::{
+            BridgeEndpointData, OpenVpnEndpoint, OpenVpnEndpointData, ShadowsocksEndpointData,
+            WireguardEndpointData,
+        };
+
+        let openvpn = relay_list
+            .openvpn
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing OpenVPN endpoint data",
+            ))?;
+        let bridge = relay_list
+            .bridge
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing bridge endpoint data",
+            ))?;
+        let wireguard = relay_list
+            .wireguard
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing WireGuard endpoint data",
+            ))?;
+
+        let countries = relay_list
+            .countries
+            .into_iter()
+            .map(mullvad_types::relay_list::RelayListCountry::try_from)
+            .collect::<Result<_, _>>()?;
+
+        let port = |port: u32| {
+            u16::try_from(port).map_err(|_| FromProtobufTypeError::InvalidArgument("invalid port"))
+        };
+
+        Ok(mullvad_types::relay_list::RelayList {
+            etag: None,
+            countries,
+            openvpn: OpenVpnEndpointData {
+                ports: openvpn
+                    .endpoints
+                    .into_iter()
+                    .map(|endpoint| {
+                        Ok(OpenVpnEndpoint {
+                            port: port(endpoint.port)?,
+                            protocol: try_transport_protocol_from_i32(endpoint.protocol)?,
+                        })
+                    })
+                    .collect::<Result<_, FromProtobufTypeError>>()?,
+            },
+            bridge: BridgeEndpointData {
+                shadowsocks: bridge
+                    .shadowsocks
+                    .into_iter()
+                    .map(|endpoint| {
+                        Ok(ShadowsocksEndpointData {
+                            port: port(endpoint.port)?,
+                            cipher: endpoint.cipher,
+                            password: endpoint.password,
+                            protocol: try_transport_protocol_from_i32(endpoint.protocol)?,
+                        })
+                    })
+                    .collect::<Result<_, FromProtobufTypeError>>()?,
+            },
+            wireguard: WireguardEndpointData {
+                port_ranges: wireguard
+                    .port_ranges
+                    .into_iter()
+                    .map(|range| Ok((port(range.first)?, port(range.last)?)))
+                    .collect::<Result<_, FromProtobufTypeError>>()?,
+                ipv4_gateway: arg_from_str(&wireguard.ipv4_gateway, "invalid IPv4 gateway")?,
+                ipv6_gateway: arg_from_str(&wireguard.ipv6_gateway, "invalid IPv6 gateway")?,
+                udp2tcp_ports: wireguard
+                    .udp2tcp_ports
+                    .into_iter()
+                    .map(port)
+                    .collect::<Result<_, _>>()?,
+            },
+        })
+    }
+}
+
+impl TryFrom<proto::RelayListCountry> for mullvad_types::relay_list::RelayListCountry {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(country: proto::RelayListCountry) -> Result<Self, Self::Error> {
+        let cities = country
+            .cities
+            .into_iter()
+            .map(|city| {
+                Ok(mullvad_types::relay_list::RelayListCity {
+                    name: city.name,
+                    code: city.code,
+                    latitude: city.latitude,
+                    longitude: city.longitude,
+                    relays: city
+                        .relays
+                        .into_iter()
+                        .map(mullvad_types::relay_list::Relay::try_from)
+                        .collect::<Result<_, _>>()?,
+                })
+            })
+            .collect::<Result<_, FromProtobufTypeError>>()?;
+        Ok(mullvad_types::relay_list::RelayListCountry {
+            name: country.name,
+            code: country.code,
+            cities,
+        })
+    }
+}
@@ -31,6 +31,7 @@ impl From<&mullvad_types::settings::Settings> for proto::Settings {
             bridge_state: Some(proto::BridgeState::from(settings.get_bridge_state())),
             allow_lan: settings.allow_lan,
             block_when_disconnected: settings.block_when_disconnected,
+            persistent_lockdown: settings.persistent_lockdown,
             auto_connect: settings.auto_connect,
             tunnel_options: Some(proto::TunnelOptions::from(&settings.tunnel_options)),
             show_beta_releases: settings.show_beta_releases,
@@ -38,10 +39,66 @@ impl From<&mullvad_types::settings::Settings> for proto::Settings {
                 &settings.obfuscation_settings,
             )),
             split_tunnel,
+            api_access_methods: settings
+                .api_access_methods
+                .iter()
+                .map(proto::ApiAccessMethod::from)
+                .collect(),
+            expiry_reminder_days: settings.expiry_reminder_days.clone(),
+            locked_settings: settings.locked_settings.clone(),
         }
     }
 }
 
+impl TryFrom<proto::Settings> for mullvad_types::settings::Settings {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(settings: proto::Settings) -> Result<Self, Self::Error> {
+        use mullvad_types::relay_constraints::{
+            BridgeSettings, BridgeState, ObfuscationSettings, RelaySettings,
+        };
+
+        let mut converted = Self::default();
+        converted.set_relay_settings(RelaySettings::try_from(settings.relay_settings.ok_or(
+            FromProtobufTypeError::InvalidArgument("missing relay settings"),
+        )?)?);
+        converted.bridge_settings = BridgeSettings::try_from(settings.bridge_settings.ok_or(
+            FromProtobufTypeError::InvalidArgument("missing bridge settings"),
+        )?)?;
+        converted.set_bridge_state(BridgeState::try_from(settings.bridge_state.ok_or(
+            FromProtobufTypeError::InvalidArgument("missing bridge state"),
+        )?)?);
+        converted.obfuscation_settings =
+            ObfuscationSettings::try_from(settings.obfuscation_settings.ok_or(
+                FromProtobufTypeError::InvalidArgument("missing obfuscation settings"),
+            )?)?;
+        converted.allow_lan = settings.allow_lan;
+        converted.block_when_disconnected = settings.block_when_disconnected;
+        converted.persistent_lockdown = settings.persistent_lockdown;
+        converted.auto_connect = settings.auto_connect;
+        converted.tunnel_options =
+            mullvad_types::settings::TunnelOptions::try_from(settings.tunnel_options.ok_or(
+                FromProtobufTypeError::InvalidArgument("missing tunnel options"),
+            )?)?;
+        converted.show_beta_releases = settings.show_beta_releases;
+        converted.api_access_methods = settings
+            .api_access_methods
+            .into_iter()
+            .map(mullvad_types::access_method::ApiAccessMethod::try_from)
+            .collect::<Result<_, _>>()?;
+        converted.expiry_reminder_days = settings.expiry_reminder_days;
+        converted.locked_settings = settings.locked_settings;
+        #[cfg(windows)]
+        if let Some(split_tunnel) = settings.split_tunnel {
+            converted.split_tunnel = mullvad_types::settings::SplitTunnelSettings {
+                enable_exclusions: split_tunnel.enable_exclusions,
+                apps: split_tunnel.apps.into_iter().map(Into::into).collect(),
+            };
+        }
+        Ok(converted)
+    }
+}
+
 impl From<&mullvad_types::settings::DnsOptions> for proto::DnsOptions {
     fn from(options: &mullvad_types::settings::DnsOptions) -> Self {
         use proto::dns_options;
@@ -87,6 +144,18 @@ impl From<&mullvad_types::settings::TunnelOptions> for proto::TunnelOptions {
                 #[cfg(not(windows))]
                 use_wireguard_nt: false,
                 use_pq_safe_psk: options.wireguard.options.use_pq_safe_psk,
+                pq_psk_rotation_interval: options.wireguard.options.pq_psk_rotation_interval.map(|ivl| {
+                    prost_types::Duration::try_from(ivl)
+                        .expect("Failed to convert std::time::Duration to prost_types::Duration for tunnel_options.wireguard.pq_psk_rotation_interval")
+                }),
+                #[cfg(target_os = "linux")]
+                use_boringtun: options.wireguard.options.use_boringtun,
+                #[cfg(not(target_os = "linux"))]
+                use_boringtun: false,
+                #[cfg(target_os = "linux")]
+                use_network_namespace: options.wireguard.options.use_network_namespace,
+                #[cfg(not(target_os = "linux"))]
+                use_network_namespace: false,
             }),
             generic: Some(proto::tunnel_options::GenericOptions {
                 enable_ipv6: options.generic.enable_ipv6,
@@ -142,8 +211,17 @@ impl TryFrom<proto::TunnelOptions> for mullvad_types::settings::TunnelOptions {
                         None
                     },
                     use_pq_safe_psk: wireguard_options.use_pq_safe_psk,
+                    pq_psk_rotation_interval: wireguard_options
+                        .pq_psk_rotation_interval
+                        .map(std::time::Duration::try_from)
+                        .transpose()
+                        .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid duration"))?,
                     #[cfg(windows)]
                     use_wireguard_nt: wireguard_options.use_wireguard_nt,
+                    #[cfg(target_os = "linux")]
+                    use_boringtun: wireguard_options.use_boringtun,
+                    #[cfg(target_os = "linux")]
+                    use_network_namespace: wireguard_options.use_network_namespace,
                 },
                 rotation_interval: wireguard_options
                     .rotation_interval
@@ -169,6 +247,60 @@ impl TryFrom<proto::TunnelOptions> for mullvad_types::settings::TunnelOptions {
     }
 }
 
+impl TryFrom<proto::SettingsPatch> for mullvad_types::settings::SettingsPatch {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(patch: proto::SettingsPatch) -> Result<Self, Self::Error> {
+        use mullvad_types::relay_constraints::{
+            BridgeSettings, BridgeState, ObfuscationSettings, RelaySettingsUpdate,
+        };
+
+        Ok(mullvad_types::settings::SettingsPatch {
+            relay_settings: patch
+                .relay_settings
+                .map(RelaySettingsUpdate::try_from)
+                .transpose()?,
+            bridge_settings: patch
+                .bridge_settings
+                .map(BridgeSettings::try_from)
+                .transpose()?,
+            bridge_state: patch.bridge_state.map(BridgeState::try_from).transpose()?,
+            obfuscation_settings: patch
+                .obfuscation_settings
+                .map(ObfuscationSettings::try_from)
+                .transpose()?,
+            allow_lan: patch.allow_lan,
+            block_when_disconnected: patch.block_when_disconnected,
+            persistent_lockdown: patch.persistent_lockdown,
+            auto_connect: patch.auto_connect,
+            dns_options: patch
+                .dns_options
+                .map(mullvad_types::settings::DnsOptions::try_from)
+                .transpose()?,
+            enable_ipv6: patch.enable_ipv6,
+            openvpn_mssfix: patch
+                .openvpn_mssfix
+                .map(|mssfix| optional_u16(mssfix, "invalid mssfix"))
+                .transpose()?,
+            wireguard_mtu: patch
+                .wireguard_mtu
+                .map(|mtu| optional_u16(mtu, "invalid MTU"))
+                .transpose()?,
+            quantum_resistant_tunnel: patch.quantum_resistant_tunnel,
+        })
+    }
+}
+
+/// Converts a value where zero means "unset" to an optional `u16`.
+fn optional_u16(value: u32, error: &'static str) -> Result<Option<u16>, FromProtobufTypeError> {
+    match value {
+        0 => Ok(None),
+        value => u16::try_from(value)
+            .map(Some)
+            .map_err(|_| FromProtobufTypeError::InvalidArgument(error)),
+    }
+}
+
 impl TryFrom<proto::DnsOptions> for mullvad_types::settings::DnsOptions {
     type Error = FromProtobufTypeError;
 
@@ -0,0 +1,65 @@
+//! Unix domain socket transport for the management interface server. The socket is bound
+//! directly, rather than through `parity_tokio_ipc`, so that the credentials of connecting
+//! clients are available to the RPC handlers.
+
+use crate::Error;
+use futures::Stream;
+use std::{
+    env, fs, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::net::{UnixListener, UnixStream};
+use tokio_stream::wrappers::UnixListenerStream;
+
+lazy_static::lazy_static! {
+    static ref MULLVAD_MANAGEMENT_SOCKET_GROUP: Option<String> = env::var("MULLVAD_MANAGEMENT_SOCKET_GROUP")
+        .ok();
+}
+
+/// Binds the socket at `socket_path` and returns the stream of incoming connections.
+pub fn incoming(socket_path: &Path) -> Result<UnixIncoming, Error> {
+    let listener = UnixListener::bind(socket_path).map_err(Error::StartServerError)?;
+    let incoming = UnixIncoming {
+        listener: UnixListenerStream::new(listener),
+        socket_path: socket_path.to_owned(),
+    };
+
+    if let Some(group_name) = &*MULLVAD_MANAGEMENT_SOCKET_GROUP {
+        let group = nix::unistd::Group::from_name(group_name)
+            .map_err(Error::ObtainGidError)?
+            .ok_or(Error::NoGidError)?;
+        nix::unistd::chown(socket_path, None, Some(group.gid)).map_err(Error::SetGidError)?;
+        fs::set_permissions(socket_path, PermissionsExt::from_mode(0o760))
+            .map_err(Error::PermissionsError)?;
+    } else {
+        fs::set_permissions(socket_path, PermissionsExt::from_mode(0o766))
+            .map_err(Error::PermissionsError)?;
+    }
+
+    Ok(incoming)
+}
+
+/// Stream of incoming connections. Removes the socket file when dropped.
+pub struct UnixIncoming {
+    listener: UnixListenerStream,
+    socket_path: PathBuf,
+}
+
+impl Stream for UnixIncoming {
+    type Item = io::Result<UnixStream>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.listener).poll_next(cx)
+    }
+}
+
+impl Drop for UnixIncoming {
+    fn drop(&mut self) {
+        if let Ok(()) = fs::remove_file(&self.socket_path) {
+            log::trace!("Removed socket file at: {}", self.socket_path.display());
+        }
+    }
+}
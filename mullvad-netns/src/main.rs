@@ -0,0 +1,131 @@
+#[cfg(target_os = "linux")]
+use nix::{
+    mount::{mount, MsFlags},
+    sched::{setns, unshare, CloneFlags},
+    unistd::{execvp, getgid, getuid, setgid, setuid},
+};
+#[cfg(target_os = "linux")]
+use std::fmt::Write as _;
+#[cfg(target_os = "linux")]
+use std::{
+    convert::Infallible,
+    env,
+    error::Error as StdError,
+    ffi::{CString, NulError},
+    fs::File,
+    io,
+    os::unix::{ffi::OsStrExt, io::AsRawFd},
+};
+
+#[cfg(target_os = "linux")]
+use talpid_types::netns::{namespace_path, resolv_conf_path, NETWORK_NAMESPACE_NAME};
+
+#[cfg(target_os = "linux")]
+const PROGRAM_NAME: &str = "mullvad-netns";
+
+#[cfg(target_os = "linux")]
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+enum Error {
+    #[error(display = "Invalid arguments")]
+    InvalidArguments,
+
+    #[error(display = "Failed to open the network namespace. Is namespace isolation enabled?")]
+    OpenNamespace(#[error(source)] io::Error),
+
+    #[error(display = "Failed to enter the network namespace")]
+    EnterNamespace(#[error(source)] nix::Error),
+
+    #[error(display = "Failed to create a mount namespace")]
+    UnshareMountNamespace(#[error(source)] nix::Error),
+
+    #[error(display = "Failed to mount resolv.conf for the network namespace")]
+    MountResolvConf(#[error(source)] nix::Error),
+
+    #[error(display = "Failed to drop root user privileges for the process")]
+    DropRootUid(#[error(source)] nix::Error),
+
+    #[error(display = "Failed to drop root group privileges for the process")]
+    DropRootGid(#[error(source)] nix::Error),
+
+    #[error(display = "Failed to launch the process")]
+    Exec(#[error(source)] nix::Error),
+
+    #[error(display = "An argument contains interior nul bytes")]
+    ArgumentNulError(#[error(source)] NulError),
+}
+
+fn main() {
+    #[cfg(target_os = "linux")]
+    match run() {
+        Err(Error::InvalidArguments) => {
+            let mut args = env::args();
+            let program = args.next().unwrap_or_else(|| PROGRAM_NAME.to_string());
+            eprintln!("Usage: {} run COMMAND [ARGS]", program);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            let mut s = format!("{}", e);
+            let mut source = e.source();
+            while let Some(error) = source {
+                write!(&mut s, "\nCaused by: {}", error).expect("formatting failed");
+                source = error.source();
+            }
+            eprintln!("{}", s);
+
+            std::process::exit(1);
+        }
+        _ => unreachable!("execv returned unexpectedly"),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run() -> Result<Infallible, Error> {
+    let mut args_iter = env::args_os().skip(1);
+    if args_iter.next().as_deref() != Some("run".as_ref()) {
+        return Err(Error::InvalidArguments);
+    }
+    let program = args_iter.next().ok_or(Error::InvalidArguments)?;
+    let program = CString::new(program.as_bytes()).map_err(Error::ArgumentNulError)?;
+
+    let args: Vec<CString> = env::args_os()
+        .skip(2)
+        .map(|arg| CString::new(arg.as_bytes()))
+        .collect::<Result<Vec<CString>, NulError>>()
+        .map_err(Error::ArgumentNulError)?;
+
+    let netns = File::open(namespace_path(NETWORK_NAMESPACE_NAME)).map_err(Error::OpenNamespace)?;
+    setns(netns.as_raw_fd(), CloneFlags::CLONE_NEWNET).map_err(Error::EnterNamespace)?;
+
+    // Use the DNS config of the namespace, the same way as `ip netns exec` does. The mount is only
+    // visible to this process and its children.
+    let resolv_conf = resolv_conf_path(NETWORK_NAMESPACE_NAME);
+    if resolv_conf.exists() {
+        unshare(CloneFlags::CLONE_NEWNS).map_err(Error::UnshareMountNamespace)?;
+        mount(
+            None::<&str>,
+            "/",
+            None::<&str>,
+            MsFlags::MS_SLAVE | MsFlags::MS_REC,
+            None::<&str>,
+        )
+        .map_err(Error::MountResolvConf)?;
+        mount(
+            Some(&resolv_conf),
+            "/etc/resolv.conf",
+            None::<&str>,
+            MsFlags::MS_BIND,
+            None::<&str>,
+        )
+        .map_err(Error::MountResolvConf)?;
+    }
+
+    // Drop root privileges
+    let real_gid = getgid();
+    setgid(real_gid).map_err(Error::DropRootGid)?;
+    let real_uid = getuid();
+    setuid(real_uid).map_err(Error::DropRootUid)?;
+
+    // Launch the process
+    execvp(&program, &args).map_err(Error::Exec)
+}
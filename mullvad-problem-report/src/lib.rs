@@ -25,6 +25,11 @@ const REPORT_MAX_SIZE: usize = (5 * LOG_MAX_READ_BYTES) + EXTRA_BYTES;
 /// Field delimeter in generated problem report
 const LOG_DELIMITER: &str = "====================";
 
+/// How long to wait for the daemon to return API diagnostics. The report is still collected if
+/// the daemon is not responding.
+#[cfg(not(target_os = "android"))]
+const API_DIAGNOSTICS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 /// Line separator character sequence
 #[cfg(not(windows))]
 const LINE_SEPARATOR: &str = "\n";
@@ -87,6 +92,22 @@ pub enum LogError {
     #[error(display = "Unable to get log directory")]
     GetLogDir(#[error(source)] mullvad_paths::Error),
 
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "Unable to spawn Tokio runtime")]
+    CreateRuntime(#[error(source, no_from)] io::Error),
+
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "Failed to connect to the daemon")]
+    RpcConnection(#[error(source)] mullvad_management_interface::Error),
+
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "Failed to get API diagnostics from the daemon")]
+    ApiDiagnostics(#[error(source)] mullvad_management_interface::Status),
+
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "Timed out while getting API diagnostics from the daemon")]
+    ApiDiagnosticsTimeout,
+
     #[error(display = "Failed to list the files in the log directory: {}", path)]
     ListLogDir {
         path: String,
@@ -171,12 +192,43 @@ pub fn collect_report(
 
     problem_report.add_logs(extra_logs);
 
+    #[cfg(not(target_os = "android"))]
+    match get_api_diagnostics() {
+        Ok(diagnostics) => problem_report.add_section("API diagnostics", &diagnostics.to_string()),
+        Err(error) => problem_report.add_error("Failed to collect API diagnostics", &error),
+    }
+
     write_problem_report(output_path, &problem_report).map_err(|source| Error::WriteReportError {
         path: output_path.display().to_string(),
         source,
     })
 }
 
+/// Fetches statistics about API requests from the daemon.
+#[cfg(not(target_os = "android"))]
+fn get_api_diagnostics() -> Result<mullvad_types::api_diagnostics::ApiDiagnostics, LogError> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(LogError::CreateRuntime)?;
+    runtime.block_on(async {
+        let get_diagnostics = async {
+            let mut rpc = mullvad_management_interface::new_rpc_client()
+                .await
+                .map_err(LogError::RpcConnection)?;
+            let diagnostics = rpc
+                .get_api_diagnostics(())
+                .await
+                .map_err(LogError::ApiDiagnostics)?
+                .into_inner();
+            Ok(diagnostics.into())
+        };
+        tokio::time::timeout(API_DIAGNOSTICS_TIMEOUT, get_diagnostics)
+            .await
+            .map_err(|_| LogError::ApiDiagnosticsTimeout)?
+    })
+}
+
 /// Returns an iterator over all files in the given directory that has the `.log` extension.
 fn list_logs(
     log_dir: PathBuf,
@@ -398,6 +450,13 @@ impl ProblemReport {
         }
     }
 
+    /// Attach arbitrary text to the report, under the given title.
+    #[cfg(not(target_os = "android"))]
+    pub fn add_section(&mut self, title: &str, content: &str) {
+        let redacted_content = self.redact(content);
+        self.logs.push((title.to_owned(), redacted_content));
+    }
+
     /// Attach an error to the report.
     pub fn add_error(&mut self, message: &'static str, error: &impl ErrorExt) {
         let redacted_error = self.redact(&error.display_chain());
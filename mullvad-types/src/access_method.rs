@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use talpid_types::net::openvpn::{RemoteProxySettings, ShadowsocksProxySettings};
+
+/// Name of the built-in access method that connects to the API directly.
+pub const DIRECT_NAME: &str = "Direct";
+/// Name of the built-in access method that connects to the API through a Mullvad bridge.
+pub const BRIDGES_NAME: &str = "Bridges";
+
+/// A named way of reaching the API, which may be turned on or off by the user.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ApiAccessMethod {
+    pub name: String,
+    pub enabled: bool,
+    pub access_method: AccessMethod,
+}
+
+/// How to connect to the API.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessMethod {
+    /// Connect to the API without a proxy.
+    Direct,
+    /// Connect to the API through a bridge matching the current bridge constraints.
+    Bridges,
+    /// Connect to the API through a user-provided Shadowsocks server.
+    Shadowsocks(ShadowsocksProxySettings),
+    /// Connect to the API through a user-provided SOCKS5 proxy.
+    Socks5(RemoteProxySettings),
+}
+
+impl ApiAccessMethod {
+    pub fn new(name: String, access_method: AccessMethod) -> Self {
+        ApiAccessMethod {
+            name,
+            enabled: true,
+            access_method,
+        }
+    }
+
+    /// Returns whether this is one of the access methods that are always present.
+    pub fn is_builtin(&self) -> bool {
+        matches!(
+            self.access_method,
+            AccessMethod::Direct | AccessMethod::Bridges
+        )
+    }
+}
+
+/// Returns the default list of access methods. These are tried in order.
+pub fn default_access_methods() -> Vec<ApiAccessMethod> {
+    vec![
+        ApiAccessMethod::new(DIRECT_NAME.to_owned(), AccessMethod::Direct),
+        ApiAccessMethod::new(BRIDGES_NAME.to_owned(), AccessMethod::Bridges),
+    ]
+}
@@ -2,6 +2,7 @@ use chrono::{offset::Utc, DateTime};
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// Identifier used to identify a Mullvad account.
 pub type AccountToken = String;
@@ -52,3 +53,72 @@ impl AccessTokenData {
         Utc::now() >= self.expiry
     }
 }
+
+/// Reminder about the expiry of the current account. Emitted when the remaining time crosses
+/// one of the reminder thresholds in the settings, and when the account runs out of time.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AccountExpiryEvent {
+    /// The account has time left, but will run out within a reminder threshold.
+    ExpiringSoon {
+        expiry: DateTime<Utc>,
+        /// Number of whole days left. Zero if less than a day remains.
+        days_left: u32,
+    },
+    /// The account has run out of time.
+    Expired { expiry: DateTime<Utc> },
+}
+
+impl AccountExpiryEvent {
+    /// Returns the reminder for an account that expires at `expiry`, or `None` if the expiry is
+    /// further away than all reminder thresholds, given in days.
+    pub fn new(expiry: DateTime<Utc>, now: DateTime<Utc>, reminder_days: &[u32]) -> Option<Self> {
+        Self::new_with_threshold(expiry, now, reminder_days).map(|(event, _)| event)
+    }
+
+    /// Like [`AccountExpiryEvent::new`], but also returns the smallest reminder threshold that the
+    /// expiry is within. The threshold is `None` if the account has expired.
+    pub fn new_with_threshold(
+        expiry: DateTime<Utc>,
+        now: DateTime<Utc>,
+        reminder_days: &[u32],
+    ) -> Option<(Self, Option<u32>)> {
+        if expiry <= now {
+            return Some((AccountExpiryEvent::Expired { expiry }, None));
+        }
+        let remaining = expiry - now;
+        let threshold = reminder_days
+            .iter()
+            .copied()
+            .filter(|days| remaining <= chrono::Duration::days(i64::from(*days)))
+            .min()?;
+        let event = AccountExpiryEvent::ExpiringSoon {
+            expiry,
+            days_left: u32::try_from(remaining.num_days()).unwrap_or(u32::MAX),
+        };
+        Some((event, Some(threshold)))
+    }
+
+    pub fn expiry(&self) -> DateTime<Utc> {
+        match self {
+            AccountExpiryEvent::ExpiringSoon { expiry, .. } => *expiry,
+            AccountExpiryEvent::Expired { expiry } => *expiry,
+        }
+    }
+}
+
+impl fmt::Display for AccountExpiryEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountExpiryEvent::ExpiringSoon { days_left: 0, .. } => {
+                write!(f, "Account expires in less than a day")
+            }
+            AccountExpiryEvent::ExpiringSoon { days_left: 1, .. } => {
+                write!(f, "Account expires in 1 day")
+            }
+            AccountExpiryEvent::ExpiringSoon { days_left, .. } => {
+                write!(f, "Account expires in {} days", days_left)
+            }
+            AccountExpiryEvent::Expired { .. } => write!(f, "Account has expired"),
+        }
+    }
+}
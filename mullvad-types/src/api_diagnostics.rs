@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+
+/// Statistics about requests made to the API since the daemon started.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ApiDiagnostics {
+    /// Description of the connection mode used by the most recent request.
+    pub connection_mode: String,
+    /// Number of times the connection mode has been changed.
+    pub connection_mode_changes: u64,
+    pub endpoints: Vec<EndpointMetrics>,
+}
+
+/// Statistics about requests made to a single API endpoint.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EndpointMetrics {
+    /// Method and path of the endpoint, e.g. `GET /app/v1/relays`. Identifiers in the path are
+    /// replaced by `{id}`.
+    pub endpoint: String,
+    pub request_count: u64,
+    pub latency_histogram: Vec<LatencyBucket>,
+    /// Number of responses per HTTP status code.
+    pub status_codes: BTreeMap<u16, u64>,
+    /// Number of requests that failed without a response, per kind of error.
+    pub errors: BTreeMap<String, u64>,
+    /// Number of requests per connection mode.
+    pub connection_modes: BTreeMap<String, u64>,
+}
+
+/// Number of requests that completed within `upper_bound_ms`, but not within the bound of the
+/// previous bucket. The last bucket has no upper bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LatencyBucket {
+    pub upper_bound_ms: Option<u64>,
+    pub count: u64,
+}
+
+impl fmt::Display for ApiDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Connection mode: {}", self.connection_mode)?;
+        writeln!(
+            f,
+            "Connection mode changes: {}",
+            self.connection_mode_changes
+        )?;
+        for endpoint in &self.endpoints {
+            writeln!(f, "{}", endpoint.endpoint)?;
+            writeln!(f, "    requests: {}", endpoint.request_count)?;
+            write!(f, "    latency:")?;
+            let mut lower_bound_ms = 0;
+            for bucket in &endpoint.latency_histogram {
+                match bucket.upper_bound_ms {
+                    Some(upper_bound_ms) => {
+                        if bucket.count > 0 {
+                            write!(f, " <{}ms: {}", upper_bound_ms, bucket.count)?;
+                        }
+                        lower_bound_ms = upper_bound_ms;
+                    }
+                    None if bucket.count > 0 => {
+                        write!(f, " >={}ms: {}", lower_bound_ms, bucket.count)?
+                    }
+                    None => (),
+                }
+            }
+            writeln!(f)?;
+            write_counts(f, "status codes", &endpoint.status_codes)?;
+            write_counts(f, "errors", &endpoint.errors)?;
+            write_counts(f, "connection modes", &endpoint.connection_modes)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_counts<K: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    label: &str,
+    counts: &BTreeMap<K, u64>,
+) -> fmt::Result {
+    if counts.is_empty() {
+        return Ok(());
+    }
+    let counts: Vec<String> = counts
+        .iter()
+        .map(|(key, count)| format!("{}: {}", key, count))
+        .collect();
+    writeln!(f, "    {}: {}", label, counts.join(", "))
+}
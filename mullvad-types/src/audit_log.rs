@@ -0,0 +1,37 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A record of a command that was sent to the daemon by a client.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuditLogEntry {
+    pub timestamp: DateTime<Utc>,
+    /// User ID of the client process, if it is known. Always `None` on Windows.
+    pub uid: Option<u32>,
+    /// Process ID of the client process, if it is known.
+    pub pid: Option<i32>,
+    /// Name of the command, such as `SetAllowLan`.
+    pub command: String,
+    /// Changes made by the command. Empty for commands that do not change any settings.
+    pub changes: Vec<SettingChange>,
+}
+
+/// A setting that was changed by a command. The values are JSON, in the format of the settings
+/// file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SettingChange {
+    /// Path of the setting, such as `tunnel_options.dns_options.state`.
+    pub setting: String,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+/// Describes an RPC that is being handled and the client that called it.
+#[derive(Debug, Clone)]
+pub struct RpcContext {
+    /// Name of the RPC, such as `SetAllowLan`.
+    pub method: String,
+    /// User ID of the client process, if it is known. Always `None` on Windows.
+    pub uid: Option<u32>,
+    /// Process ID of the client process, if it is known.
+    pub pid: Option<i32>,
+}
@@ -102,8 +102,19 @@ impl AccountAndDevice {
     }
 }
 
+/// A named account profile and the device that belongs to it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AccountProfile {
+    pub name: String,
+    pub account_token: AccountToken,
+    pub device: Device,
+    /// Whether the device of this profile is the current device.
+    pub active: bool,
+}
+
 /// Reason why a [DeviceEvent] was emitted.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
 #[cfg_attr(target_os = "android", derive(IntoJava))]
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.mullvadvpn.model"))]
 pub enum DeviceEventCause {
@@ -120,7 +131,7 @@ pub enum DeviceEventCause {
 }
 
 /// Emitted when logging in or out of an account, or when the device changes.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 #[cfg_attr(target_os = "android", derive(IntoJava))]
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.mullvadvpn.model"))]
 pub struct DeviceEvent {
@@ -130,7 +141,7 @@ pub struct DeviceEvent {
 
 /// Emitted when a device is removed using the `RemoveDevice` RPC.
 /// This is not sent by a normal logout or when it is revoked remotely.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 #[cfg_attr(target_os = "android", derive(IntoJava))]
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.mullvadvpn.model"))]
 pub struct RemoveDeviceEvent {
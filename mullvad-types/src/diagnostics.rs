@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+/// Result of one of the checks run by the daemon to diagnose problems with the tunnel.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DiagnosticCheck {
+    /// Short identifier of the check, e.g. `firewall`.
+    pub name: String,
+    pub status: CheckStatus,
+    /// What was found.
+    pub details: String,
+    /// How to fix the problem. Only set if the check failed.
+    pub remediation: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// The check does not apply in the current state.
+    Skipped,
+}
+
+impl DiagnosticCheck {
+    pub fn pass(name: &str, details: impl Into<String>) -> Self {
+        DiagnosticCheck {
+            name: name.to_owned(),
+            status: CheckStatus::Pass,
+            details: details.into(),
+            remediation: None,
+        }
+    }
+
+    pub fn fail(name: &str, details: impl Into<String>, remediation: impl Into<String>) -> Self {
+        DiagnosticCheck {
+            name: name.to_owned(),
+            status: CheckStatus::Fail,
+            details: details.into(),
+            remediation: Some(remediation.into()),
+        }
+    }
+
+    pub fn skipped(name: &str, details: impl Into<String>) -> Self {
+        DiagnosticCheck {
+            name: name.to_owned(),
+            status: CheckStatus::Skipped,
+            details: details.into(),
+            remediation: None,
+        }
+    }
+}
@@ -1,8 +1,12 @@
 #![deny(rust_2018_idioms)]
 
+pub mod access_method;
 pub mod account;
+pub mod api_diagnostics;
+pub mod audit_log;
 pub mod auth_failed;
 pub mod device;
+pub mod diagnostics;
 pub mod endpoint;
 pub mod location;
 pub mod relay_constraints;
@@ -10,6 +14,7 @@ pub mod relay_list;
 pub mod settings;
 pub mod states;
 pub mod version;
+pub mod wg_quick;
 pub mod wireguard;
 
 mod custom_tunnel;
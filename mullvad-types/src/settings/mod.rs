@@ -1,4 +1,5 @@
 use crate::{
+    access_method::{self, ApiAccessMethod},
     relay_constraints::{
         BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
         ObfuscationSettings, RelayConstraints, RelaySettings, RelaySettingsUpdate,
@@ -79,6 +80,10 @@ pub struct Settings {
     /// the firewall to not allow any traffic in or out.
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub block_when_disconnected: bool,
+    /// Keep blocking all traffic after the daemon has stopped, e.g. during upgrades or after a
+    /// crash. Only turning this setting off lifts the block. Implies `block_when_disconnected`.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub persistent_lockdown: bool,
     /// If the daemon should connect the VPN tunnel directly on start or not.
     pub auto_connect: bool,
     /// Options that should be applied to tunnels of a specific type regardless of where the relays
@@ -86,6 +91,12 @@ pub struct Settings {
     pub tunnel_options: TunnelOptions,
     /// Whether to notify users of beta updates.
     pub show_beta_releases: bool,
+    /// Ways of reaching the API. Enabled methods are tried in order when the API is unreachable.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub api_access_methods: Vec<ApiAccessMethod>,
+    /// Number of days before the account expires at which to emit expiry reminders.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub expiry_reminder_days: Vec<u32>,
     /// Split tunneling settings
     #[cfg(windows)]
     pub split_tunnel: SplitTunnelSettings,
@@ -100,6 +111,11 @@ pub struct Settings {
     /// Specifies settings schema version
     #[cfg_attr(target_os = "android", jnix(skip))]
     settings_version: SettingsVersion,
+    /// Names of the settings whose values are enforced by an admin policy. These are never
+    /// persisted, since the policy is read when the daemon starts.
+    #[serde(skip)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub locked_settings: Vec<String>,
 }
 
 fn out_of_range_wg_migration_rand_num() -> f32 {
@@ -134,13 +150,17 @@ impl Default for Settings {
             bridge_state: BridgeState::Auto,
             allow_lan: false,
             block_when_disconnected: false,
+            persistent_lockdown: false,
             auto_connect: false,
             tunnel_options: TunnelOptions::default(),
             show_beta_releases: false,
+            api_access_methods: access_method::default_access_methods(),
+            expiry_reminder_days: vec![3, 1],
             wg_migration_rand_num: rand::thread_rng().gen_range(0.0..=1.0),
             #[cfg(windows)]
             split_tunnel: SplitTunnelSettings::default(),
             settings_version: CURRENT_SETTINGS_VERSION,
+            locked_settings: vec![],
         }
     }
 }
@@ -170,6 +190,21 @@ impl Settings {
         }
     }
 
+    /// Replaces the relay settings. Returns whether they changed.
+    pub fn set_relay_settings(&mut self, relay_settings: RelaySettings) -> bool {
+        if self.relay_settings != relay_settings {
+            self.relay_settings = relay_settings;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns whether the firewall should block traffic in the disconnected state.
+    pub fn should_block_when_disconnected(&self) -> bool {
+        self.block_when_disconnected || self.persistent_lockdown
+    }
+
     pub fn get_bridge_state(&self) -> BridgeState {
         self.bridge_state
     }
@@ -188,6 +223,27 @@ impl Settings {
     }
 }
 
+/// Changes to several settings that are applied together. Fields that are `None` are left
+/// unchanged.
+#[derive(Debug, Default, Clone)]
+pub struct SettingsPatch {
+    pub relay_settings: Option<RelaySettingsUpdate>,
+    pub bridge_settings: Option<BridgeSettings>,
+    pub bridge_state: Option<BridgeState>,
+    pub obfuscation_settings: Option<ObfuscationSettings>,
+    pub allow_lan: Option<bool>,
+    pub block_when_disconnected: Option<bool>,
+    pub persistent_lockdown: Option<bool>,
+    pub auto_connect: Option<bool>,
+    pub dns_options: Option<DnsOptions>,
+    pub enable_ipv6: Option<bool>,
+    /// `Some(None)` resets the mssfix to the default.
+    pub openvpn_mssfix: Option<Option<u16>>,
+    /// `Some(None)` resets the MTU to the default.
+    pub wireguard_mtu: Option<Option<u16>>,
+    pub quantum_resistant_tunnel: Option<bool>,
+}
+
 /// TunnelOptions holds configuration data that applies to all kinds of tunnels.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
@@ -0,0 +1,631 @@
+//! Parser for the configuration files used by `wg-quick`. Only the subset of keys that can be
+//! represented by a [`CustomTunnelEndpoint`] is supported.
+
+use crate::{ConnectionConfig, CustomTunnelEndpoint};
+use ipnetwork::IpNetwork;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+use talpid_types::net::wireguard;
+
+#[derive(err_derive::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error(
+        display = "Line {}: Expected a section header or a 'Key = Value' pair",
+        _0
+    )]
+    InvalidLine(usize),
+
+    #[error(display = "Line {}: Unknown section '[{}]'", _0, _1)]
+    UnknownSection(usize, String),
+
+    #[error(display = "Line {}: Key '{}' is outside of any section", _0, _1)]
+    KeyOutsideSection(usize, String),
+
+    #[error(display = "Line {}: Unsupported key '{}' in section [{}]", _0, _2, _1)]
+    UnsupportedKey(usize, Section, String),
+
+    #[error(
+        display = "Line {}: Key '{}' is already set in section [{}]",
+        _0,
+        _2,
+        _1
+    )]
+    DuplicateKey(usize, Section, String),
+
+    #[error(display = "Line {}: Invalid value for '{}': {}", _0, _1, _2)]
+    InvalidValue(usize, &'static str, String),
+
+    #[error(display = "Line {}: Only one [Interface] section is supported", _0)]
+    MultipleInterfaces(usize),
+
+    #[error(display = "Line {}: Only one [Peer] section is supported", _0)]
+    MultiplePeers(usize),
+
+    #[error(display = "Missing [{}] section", _0)]
+    MissingSection(Section),
+
+    #[error(display = "Missing required key '{}' in section [{}]", _1, _0)]
+    MissingKey(Section, &'static str),
+
+    #[error(display = "Cannot determine the IPv4 gateway since no IPv4 DNS server is specified")]
+    MissingIpv4Gateway,
+
+    #[error(display = "Multihop connections cannot be represented as a wg-quick config")]
+    MultihopUnsupported,
+}
+
+/// A section of a `wg-quick` configuration file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Interface,
+    Peer,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Section::Interface => f.write_str("Interface"),
+            Section::Peer => f.write_str("Peer"),
+        }
+    }
+}
+
+/// The contents of a `wg-quick` configuration file, mapped onto the types used by the daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WgQuickConfig {
+    /// Hostname or IP of the peer endpoint.
+    pub host: String,
+    /// Tunnel and peer described by the `[Interface]` and `[Peer]` sections. The IP of the peer
+    /// endpoint is unspecified, since it is resolved from `host`.
+    pub connection: wireguard::ConnectionConfig,
+    /// Servers listed under `DNS`, if any.
+    pub dns_servers: Vec<IpAddr>,
+    /// Tunnel MTU, if specified.
+    pub mtu: Option<u16>,
+}
+
+impl WgQuickConfig {
+    /// Returns a custom relay that connects to the peer described by this config.
+    pub fn custom_tunnel_endpoint(&self) -> CustomTunnelEndpoint {
+        CustomTunnelEndpoint::new(
+            self.host.clone(),
+            ConnectionConfig::Wireguard(self.connection.clone()),
+        )
+    }
+
+    /// Creates a config from the parameters of a WireGuard tunnel. IPv6 addresses are omitted
+    /// unless IPv6 is enabled, like they are when the tunnel itself is configured.
+    pub fn from_tunnel_parameters(
+        params: &wireguard::TunnelParameters,
+        dns_servers: Vec<IpAddr>,
+    ) -> Result<Self, Error> {
+        if params.connection.exit_peer.is_some() {
+            return Err(Error::MultihopUnsupported);
+        }
+
+        let enable_ipv6 = params.generic_options.enable_ipv6;
+        let mut connection = params.connection.clone();
+        connection
+            .tunnel
+            .addresses
+            .retain(|ip| ip.is_ipv4() || enable_ipv6);
+        connection
+            .peer
+            .allowed_ips
+            .retain(|ip| ip.is_ipv4() || enable_ipv6);
+        if !enable_ipv6 {
+            connection.ipv6_gateway = None;
+        }
+
+        Ok(WgQuickConfig {
+            host: connection.peer.endpoint.ip().to_string(),
+            connection,
+            dns_servers: dns_servers
+                .into_iter()
+                .filter(|ip| ip.is_ipv4() || enable_ipv6)
+                .collect(),
+            mtu: params.options.mtu,
+        })
+    }
+}
+
+impl FromStr for WgQuickConfig {
+    type Err = Error;
+
+    fn from_str(config: &str) -> Result<Self, Self::Err> {
+        parse(config, None, None)
+    }
+}
+
+impl fmt::Display for WgQuickConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tunnel = &self.connection.tunnel;
+        let peer = &self.connection.peer;
+
+        writeln!(f, "[Interface]")?;
+        writeln!(f, "PrivateKey = {}", tunnel.private_key.to_base64())?;
+        let addresses = tunnel.addresses.iter().map(|address| match address {
+            IpAddr::V4(address) => format!("{}/32", address),
+            IpAddr::V6(address) => format!("{}/128", address),
+        });
+        writeln!(f, "Address = {}", join(addresses))?;
+        if !self.dns_servers.is_empty() {
+            writeln!(f, "DNS = {}", join(self.dns_servers.iter()))?;
+        }
+        if let Some(mtu) = self.mtu {
+            writeln!(f, "MTU = {}", mtu)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "[Peer]")?;
+        writeln!(f, "PublicKey = {}", peer.public_key.to_base64())?;
+        if let Some(psk) = &peer.psk {
+            writeln!(f, "PresharedKey = {}", psk.to_base64())?;
+        }
+        writeln!(f, "AllowedIPs = {}", join(peer.allowed_ips.iter()))?;
+        match IpAddr::from_str(&self.host) {
+            Ok(IpAddr::V6(host)) => writeln!(f, "Endpoint = [{}]:{}", host, peer.endpoint.port()),
+            _ => writeln!(f, "Endpoint = {}:{}", self.host, peer.endpoint.port()),
+        }
+    }
+}
+
+fn join<T: ToString>(items: impl Iterator<Item = T>) -> String {
+    items
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Default)]
+struct InterfaceSection {
+    private_key: Option<wireguard::PrivateKey>,
+    addresses: Vec<IpAddr>,
+    dns_servers: Vec<IpAddr>,
+    mtu: Option<u16>,
+}
+
+#[derive(Default)]
+struct PeerSection {
+    public_key: Option<wireguard::PublicKey>,
+    endpoint: Option<(String, u16)>,
+    allowed_ips: Vec<IpNetwork>,
+    psk: Option<wireguard::PresharedKey>,
+}
+
+/// Parses the contents of a `wg-quick` configuration file.
+///
+/// `wg-quick` has no notion of a tunnel gateway. Unless `ipv4_gateway` is given, the first IPv4
+/// DNS server is assumed to be the gateway, which is the case for Mullvad relays. `ipv6_gateway`
+/// is similarly inferred from the first IPv6 DNS server.
+pub fn parse(
+    config: &str,
+    ipv4_gateway: Option<Ipv4Addr>,
+    ipv6_gateway: Option<Ipv6Addr>,
+) -> Result<WgQuickConfig, Error> {
+    let mut interface: Option<InterfaceSection> = None;
+    let mut peer: Option<PeerSection> = None;
+    let mut current_section = None;
+
+    for (index, line) in config.lines().enumerate() {
+        let line_nr = index + 1;
+        let line = match line.split_once('#') {
+            Some((content, _comment)) => content,
+            None => line,
+        }
+        .trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(section) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            current_section = Some(match section.trim().to_lowercase().as_str() {
+                "interface" => {
+                    if interface.is_some() {
+                        return Err(Error::MultipleInterfaces(line_nr));
+                    }
+                    interface = Some(InterfaceSection::default());
+                    Section::Interface
+                }
+                "peer" => {
+                    if peer.is_some() {
+                        return Err(Error::MultiplePeers(line_nr));
+                    }
+                    peer = Some(PeerSection::default());
+                    Section::Peer
+                }
+                _ => return Err(Error::UnknownSection(line_nr, section.to_owned())),
+            });
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or(Error::InvalidLine(line_nr))?;
+
+        match current_section {
+            Some(Section::Interface) => parse_interface_key(
+                interface.as_mut().expect("section was just created"),
+                line_nr,
+                key,
+                value,
+            )?,
+            Some(Section::Peer) => parse_peer_key(
+                peer.as_mut().expect("section was just created"),
+                line_nr,
+                key,
+                value,
+            )?,
+            None => return Err(Error::KeyOutsideSection(line_nr, key.to_owned())),
+        }
+    }
+
+    let interface = interface.ok_or(Error::MissingSection(Section::Interface))?;
+    let peer = peer.ok_or(Error::MissingSection(Section::Peer))?;
+
+    let private_key = interface
+        .private_key
+        .ok_or(Error::MissingKey(Section::Interface, "PrivateKey"))?;
+    if interface.addresses.is_empty() {
+        return Err(Error::MissingKey(Section::Interface, "Address"));
+    }
+    let public_key = peer
+        .public_key
+        .ok_or(Error::MissingKey(Section::Peer, "PublicKey"))?;
+    let (host, port) = peer
+        .endpoint
+        .ok_or(Error::MissingKey(Section::Peer, "Endpoint"))?;
+    if peer.allowed_ips.is_empty() {
+        return Err(Error::MissingKey(Section::Peer, "AllowedIPs"));
+    }
+
+    let ipv4_gateway = ipv4_gateway
+        .or_else(|| {
+            interface.dns_servers.iter().find_map(|addr| match addr {
+                IpAddr::V4(addr) => Some(*addr),
+                IpAddr::V6(_) => None,
+            })
+        })
+        .ok_or(Error::MissingIpv4Gateway)?;
+    let ipv6_gateway = ipv6_gateway.or_else(|| {
+        interface.dns_servers.iter().find_map(|addr| match addr {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(addr) => Some(*addr),
+        })
+    });
+
+    let connection = wireguard::ConnectionConfig {
+        tunnel: wireguard::TunnelConfig {
+            private_key,
+            addresses: interface.addresses,
+        },
+        peer: wireguard::PeerConfig {
+            public_key,
+            allowed_ips: peer.allowed_ips,
+            endpoint: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port),
+            psk: peer.psk,
+        },
+        exit_peer: None,
+        ipv4_gateway,
+        ipv6_gateway,
+        #[cfg(target_os = "linux")]
+        fwmark: Some(crate::TUNNEL_FWMARK),
+    };
+
+    Ok(WgQuickConfig {
+        host,
+        connection,
+        dns_servers: interface.dns_servers,
+        mtu: interface.mtu,
+    })
+}
+
+fn parse_interface_key(
+    interface: &mut InterfaceSection,
+    line_nr: usize,
+    key: &str,
+    value: &str,
+) -> Result<(), Error> {
+    match key.to_lowercase().as_str() {
+        "privatekey" => {
+            let private_key = wireguard::PrivateKey::from_base64(value).map_err(|_| {
+                Error::InvalidValue(line_nr, "PrivateKey", "invalid key".to_owned())
+            })?;
+            set_once(
+                &mut interface.private_key,
+                private_key,
+                line_nr,
+                Section::Interface,
+                key,
+            )?;
+        }
+        "address" => {
+            for address in split_list(value) {
+                // The prefix length is not needed, since routes are set up by the daemon
+                let address = IpNetwork::from_str(address)
+                    .map_err(|_| Error::InvalidValue(line_nr, "Address", address.to_owned()))?;
+                interface.addresses.push(address.ip());
+            }
+        }
+        "dns" => {
+            for server in split_list(value) {
+                let server = IpAddr::from_str(server)
+                    .map_err(|_| Error::InvalidValue(line_nr, "DNS", server.to_owned()))?;
+                interface.dns_servers.push(server);
+            }
+        }
+        "mtu" => {
+            let mtu = u16::from_str(value)
+                .map_err(|_| Error::InvalidValue(line_nr, "MTU", value.to_owned()))?;
+            set_once(&mut interface.mtu, mtu, line_nr, Section::Interface, key)?;
+        }
+        _ => {
+            return Err(Error::UnsupportedKey(
+                line_nr,
+                Section::Interface,
+                key.to_owned(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn parse_peer_key(
+    peer: &mut PeerSection,
+    line_nr: usize,
+    key: &str,
+    value: &str,
+) -> Result<(), Error> {
+    match key.to_lowercase().as_str() {
+        "publickey" => {
+            let public_key = wireguard::PublicKey::from_base64(value)
+                .map_err(|_| Error::InvalidValue(line_nr, "PublicKey", "invalid key".to_owned()))?;
+            set_once(
+                &mut peer.public_key,
+                public_key,
+                line_nr,
+                Section::Peer,
+                key,
+            )?;
+        }
+        "presharedkey" => {
+            let psk = wireguard::PresharedKey::from_base64(value).map_err(|_| {
+                Error::InvalidValue(line_nr, "PresharedKey", "invalid key".to_owned())
+            })?;
+            set_once(&mut peer.psk, psk, line_nr, Section::Peer, key)?;
+        }
+        "endpoint" => {
+            let endpoint = parse_endpoint(value)
+                .ok_or_else(|| Error::InvalidValue(line_nr, "Endpoint", value.to_owned()))?;
+            set_once(&mut peer.endpoint, endpoint, line_nr, Section::Peer, key)?;
+        }
+        "allowedips" => {
+            for network in split_list(value) {
+                let network = IpNetwork::from_str(network)
+                    .map_err(|_| Error::InvalidValue(line_nr, "AllowedIPs", network.to_owned()))?;
+                peer.allowed_ips.push(network);
+            }
+        }
+        _ => {
+            return Err(Error::UnsupportedKey(
+                line_nr,
+                Section::Peer,
+                key.to_owned(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Sets the value of a key that may only appear once in a section. Keys that take a list, such as
+/// `Address`, may be repeated instead.
+fn set_once<T>(
+    field: &mut Option<T>,
+    value: T,
+    line_nr: usize,
+    section: Section,
+    key: &str,
+) -> Result<(), Error> {
+    if field.is_some() {
+        return Err(Error::DuplicateKey(line_nr, section, key.to_owned()));
+    }
+    *field = Some(value);
+    Ok(())
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// Splits `host:port`, where `host` is a hostname, an IPv4 address or a bracketed IPv6 address.
+fn parse_endpoint(endpoint: &str) -> Option<(String, u16)> {
+    let (host, port) = endpoint.rsplit_once(':')?;
+    let port = u16::from_str(port).ok()?;
+    match host.strip_prefix('[') {
+        Some(host) => {
+            let host = host.strip_suffix(']')?;
+            Ipv6Addr::from_str(host).ok()?;
+            Some((host.to_owned(), port))
+        }
+        None if host.is_empty() || host.contains(':') => None,
+        None => Some((host.to_owned(), port)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "mPue6Xt0pdz4NRAhfQSp/SLKo7kV7DW+2zvBq0N9iUI=";
+    const PUBLIC_KEY: &str = "7XtSp/M8aLjfm6MHEXe4gqfOzV/SI1GILVN2JZbEnEk=";
+
+    #[test]
+    fn test_parse_config() {
+        let input = format!(
+            "# Mullvad relay
+[Interface]
+PrivateKey = {PRIVATE_KEY}
+Address = 10.64.10.20/32, fc00:bbbb:bbbb:bb01::1:a13/128
+DNS = 10.64.0.1
+MTU = 1380
+
+[Peer]
+PublicKey = {PUBLIC_KEY}
+AllowedIPs = 0.0.0.0/0, ::0/0
+Endpoint = se-got-wg-001.relays.mullvad.net:51820
+"
+        );
+        let config = WgQuickConfig::from_str(&input).unwrap();
+
+        assert_eq!(config.host, "se-got-wg-001.relays.mullvad.net");
+        assert_eq!(
+            config.dns_servers,
+            vec!["10.64.0.1".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(config.mtu, Some(1380));
+
+        let connection = &config.connection;
+        assert_eq!(connection.tunnel.private_key.to_base64(), PRIVATE_KEY);
+        assert_eq!(connection.tunnel.addresses.len(), 2);
+        assert_eq!(connection.peer.public_key.to_base64(), PUBLIC_KEY);
+        assert_eq!(connection.peer.endpoint.port(), 51820);
+        assert_eq!(connection.peer.allowed_ips.len(), 2);
+        assert_eq!(connection.ipv4_gateway, Ipv4Addr::new(10, 64, 0, 1));
+        assert_eq!(connection.ipv6_gateway, None);
+        assert!(connection.peer.psk.is_none());
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        let input = format!(
+            "[Interface]
+PrivateKey = {PRIVATE_KEY}
+Address = 10.64.10.20/32, fc00:bbbb:bbbb:bb01::1:a13/128
+DNS = 10.64.0.1, fc00:bbbb:bbbb:bb01::1
+MTU = 1280
+
+[Peer]
+PublicKey = {PUBLIC_KEY}
+AllowedIPs = 0.0.0.0/0, ::/0
+Endpoint = [2001:db8::1]:51820
+"
+        );
+        let config = WgQuickConfig::from_str(&input).unwrap();
+        assert_eq!(config.to_string(), input);
+        assert_eq!(
+            WgQuickConfig::from_str(&config.to_string()).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn test_parse_ipv6_endpoint() {
+        assert_eq!(
+            parse_endpoint("[2001:db8::1]:51820"),
+            Some(("2001:db8::1".to_owned(), 51820))
+        );
+        assert_eq!(
+            parse_endpoint("192.0.2.1:53"),
+            Some(("192.0.2.1".to_owned(), 53))
+        );
+        assert_eq!(parse_endpoint("2001:db8::1:51820"), None);
+        assert_eq!(parse_endpoint("192.0.2.1"), None);
+    }
+
+    #[test]
+    fn test_unsupported_key() {
+        let input = format!(
+            "[Interface]
+PrivateKey = {PRIVATE_KEY}
+Address = 10.64.10.20/32
+PostUp = iptables -A FORWARD -i wg0 -j ACCEPT
+"
+        );
+        assert_eq!(
+            WgQuickConfig::from_str(&input).unwrap_err(),
+            Error::UnsupportedKey(4, Section::Interface, "PostUp".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_missing_gateway() {
+        let input = format!(
+            "[Interface]
+PrivateKey = {PRIVATE_KEY}
+Address = 10.64.10.20/32
+
+[Peer]
+PublicKey = {PUBLIC_KEY}
+AllowedIPs = 0.0.0.0/0
+Endpoint = 192.0.2.1:51820
+"
+        );
+        assert_eq!(
+            WgQuickConfig::from_str(&input).unwrap_err(),
+            Error::MissingIpv4Gateway
+        );
+        let config = parse(&input, Some(Ipv4Addr::new(10, 64, 0, 1)), None).unwrap();
+        assert_eq!(config.connection.ipv4_gateway, Ipv4Addr::new(10, 64, 0, 1));
+    }
+
+    #[test]
+    fn test_duplicate_key() {
+        let input = format!(
+            "[Interface]
+PrivateKey = {PRIVATE_KEY}
+Address = 10.64.10.20/32
+Address = fc00:bbbb:bbbb:bb01::1:a13/128
+DNS = 10.64.0.1
+
+[Peer]
+PublicKey = {PUBLIC_KEY}
+AllowedIPs = 0.0.0.0/0
+Endpoint = 192.0.2.1:51820
+Endpoint = 192.0.2.2:51820
+"
+        );
+        assert_eq!(
+            WgQuickConfig::from_str(&input).unwrap_err(),
+            Error::DuplicateKey(11, Section::Peer, "Endpoint".to_owned())
+        );
+
+        let input = input.replace("Endpoint = 192.0.2.2:51820\n", "");
+        let config = WgQuickConfig::from_str(&input).unwrap();
+        assert_eq!(config.connection.tunnel.addresses.len(), 2);
+    }
+
+    #[test]
+    fn test_custom_endpoint_psk() {
+        let psk = "FpCyhws9cxwWoV4xELtfJvjJN+zQVRPISllRWgeopVE=";
+        let input = format!(
+            "[Interface]
+PrivateKey = {PRIVATE_KEY}
+Address = 10.64.10.20/32
+DNS = 10.64.0.1
+
+[Peer]
+PublicKey = {PUBLIC_KEY}
+PresharedKey = {psk}
+AllowedIPs = 0.0.0.0/0
+Endpoint = 192.0.2.1:51820
+"
+        );
+        let endpoint = WgQuickConfig::from_str(&input)
+            .unwrap()
+            .custom_tunnel_endpoint();
+        match endpoint.config {
+            ConnectionConfig::Wireguard(connection) => {
+                assert_eq!(connection.peer.psk.unwrap().to_base64(), psk)
+            }
+            ConnectionConfig::OpenVpn(_) => panic!("expected a WireGuard relay"),
+        }
+    }
+}
@@ -14,6 +14,10 @@ pub const DEFAULT_ROTATION_INTERVAL: Duration = if cfg!(target_os = "android") {
     Duration::from_secs(7 * 24 * 60 * 60)
 };
 
+/// Shortest allowed interval between renegotiations of the quantum-resistant PSK. Each exchange
+/// involves generating large Classic McEliece keys, so it should not be done too often.
+pub const MIN_PQ_PSK_ROTATION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 /// Contains account specific wireguard data
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct WireguardData {
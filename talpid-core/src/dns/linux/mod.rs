@@ -72,6 +72,13 @@ impl super::DnsMonitorT for DnsMonitor {
     }
 }
 
+impl DnsMonitor {
+    /// Returns the name of the method used to manage DNS, if DNS is currently being managed.
+    pub fn backend(&self) -> Option<String> {
+        self.inner.as_ref().map(|inner| inner.to_string())
+    }
+}
+
 pub enum DnsMonitorHolder {
     SystemdResolved(SystemdResolved),
     NetworkManager(NetworkManager),
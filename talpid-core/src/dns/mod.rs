@@ -79,6 +79,12 @@ impl DnsMonitor {
         log::info!("Resetting DNS");
         self.inner.reset()
     }
+
+    /// Returns the name of the method used to manage DNS, if DNS is currently being managed.
+    #[cfg(target_os = "linux")]
+    pub fn backend(&self) -> Option<String> {
+        self.inner.backend()
+    }
 }
 
 trait DnsMonitorT: Sized {
@@ -181,7 +181,33 @@ impl Firewall {
         Ok(())
     }
 
+    /// Returns the names of the tables that exist while a policy is applied, but are missing.
+    pub fn missing_tables(&self) -> Result<Vec<String>> {
+        let table_set = Self::list_tables()?;
+        Ok(
+            [&*TABLE_NAME, &*MANGLE_TABLE_NAME_V4, &*MANGLE_TABLE_NAME_V6]
+                .iter()
+                .filter(|table| !table_set.contains(table.as_c_str()))
+                .map(|table| table.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+
     fn verify_tables(&self, expected_tables: &[&CStr]) -> Result<()> {
+        let table_set = Self::list_tables()?;
+        for expected_table in expected_tables {
+            if !table_set.contains(*expected_table) {
+                log::error!(
+                    "Expected '{}' netfilter table to be set, but it is not",
+                    expected_table.to_string_lossy()
+                );
+                return Err(Error::NetfilterTableNotSetError);
+            }
+        }
+        Ok(())
+    }
+
+    fn list_tables() -> Result<std::collections::HashSet<CString>> {
         let socket = mnl::Socket::new(mnl::Bus::Netfilter).map_err(Error::NetlinkOpenError)?;
         let portid = socket.portid();
         let seq = 0;
@@ -205,17 +231,7 @@ impl Firewall {
                 mnl::CbResult::Ok => log::trace!("cb_run OK"),
             }
         }
-
-        for expected_table in expected_tables {
-            if !table_set.contains(*expected_table) {
-                log::error!(
-                    "Expected '{}' netfilter table to be set, but it is not",
-                    expected_table.to_string_lossy()
-                );
-                return Err(Error::NetfilterTableNotSetError);
-            }
-        }
-        Ok(())
+        Ok(table_set)
     }
 
     fn socket_recv<'a>(socket: &mnl::Socket, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>> {
@@ -278,4 +278,11 @@ impl Firewall {
         log::info!("Resetting firewall policy");
         self.inner.reset_policy()
     }
+
+    /// Returns the names of the netfilter tables that should exist while a policy is applied, but
+    /// are missing.
+    #[cfg(target_os = "linux")]
+    pub fn missing_tables(&self) -> Result<Vec<String>, Error> {
+        self.inner.missing_tables()
+    }
 }
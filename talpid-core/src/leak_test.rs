@@ -0,0 +1,377 @@
+//! Leak self-test. Sends probes out of the physical interface, bypassing the routes into the
+//! tunnel, and reports whether the firewall let them leave the host.
+//!
+//! A UDP datagram that is dropped or rejected by the output chain makes `sendto` fail with
+//! `EPERM`, so no response from the target is needed to tell whether a probe was blocked.
+
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::{io, net::SocketAddr};
+use talpid_types::leak_test::{ProbeKind, ProbeOutcome, ProbeResult};
+
+/// A DNS query for the A record of `mullvad.net`.
+const DNS_QUERY: &[u8] = &[
+    0x6d, 0x76, // ID
+    0x01, 0x00, // Flags: recursion desired
+    0x00, 0x01, // QDCOUNT
+    0x00, 0x00, // ANCOUNT
+    0x00, 0x00, // NSCOUNT
+    0x00, 0x00, // ARCOUNT
+    7, b'm', b'u', b'l', b'l', b'v', b'a', b'd', 3, b'n', b'e', b't', 0, // QNAME
+    0x00, 0x01, // QTYPE: A
+    0x00, 0x01, // QCLASS: IN
+];
+
+/// Payload of the probes that are not DNS queries.
+const PROBE_PAYLOAD: &[u8] = b"mullvad leak test";
+
+/// Errors that can occur while preparing a leak test.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Leaks can only be tested while the tunnel is up.
+    #[error(display = "The leak test can only be run in the connected state")]
+    NotConnected,
+
+    /// Failed to look up the route to the relay.
+    #[error(display = "Failed to look up the route to the relay")]
+    FindPhysicalRoute(#[error(source)] talpid_routing::Error),
+
+    /// There is no route to the relay via a physical interface.
+    #[error(display = "Found no route to the relay outside the tunnel")]
+    NoPhysicalRoute,
+}
+
+/// Hosts that the probes are sent to.
+#[derive(Debug, Clone)]
+pub struct LeakTestTargets {
+    /// DNS server that a query is sent to.
+    pub dns: SocketAddr,
+    /// Public IPv4 host.
+    pub ipv4: SocketAddr,
+    /// Public IPv6 host.
+    pub ipv6: SocketAddr,
+    /// Host on the local network, such as the default gateway.
+    pub lan: Option<SocketAddr>,
+}
+
+impl Default for LeakTestTargets {
+    fn default() -> Self {
+        LeakTestTargets {
+            // Documentation addresses (RFC 5737 and RFC 3849). The probes only need a route out of
+            // the physical interface, so there is no reason to send them to real hosts.
+            dns: "198.51.100.53:53".parse().unwrap(),
+            ipv4: "198.51.100.1:443".parse().unwrap(),
+            ipv6: "[2001:db8::1]:443".parse().unwrap(),
+            lan: None,
+        }
+    }
+}
+
+/// Sends one probe to each target out of `interface`. Every probe except the LAN probe should
+/// be blocked, and the LAN probe should only be let through if `allow_lan` is set.
+pub fn run(interface: &str, targets: &LeakTestTargets, allow_lan: bool) -> Vec<ProbeResult> {
+    let mut probes = vec![
+        (ProbeKind::Dns, targets.dns, true, DNS_QUERY),
+        (ProbeKind::Ipv4, targets.ipv4, true, PROBE_PAYLOAD),
+        (ProbeKind::Ipv6, targets.ipv6, true, PROBE_PAYLOAD),
+    ];
+    if let Some(lan) = targets.lan {
+        probes.push((ProbeKind::Lan, lan, !allow_lan, PROBE_PAYLOAD));
+    }
+
+    probes
+        .into_iter()
+        .map(|(kind, target, should_block, payload)| {
+            let outcome = send_probe(interface, target, payload);
+            log::debug!("Leak test {} probe to {}: {:?}", kind, target, outcome);
+            ProbeResult {
+                kind,
+                target,
+                should_block,
+                outcome,
+            }
+        })
+        .collect()
+}
+
+fn send_probe(interface: &str, target: SocketAddr, payload: &[u8]) -> ProbeOutcome {
+    let send = || -> io::Result<()> {
+        let socket = Socket::new(
+            Domain::for_address(target),
+            Type::DGRAM,
+            Some(Protocol::UDP),
+        )?;
+        // Binding to the device makes the lookup in the tunnel routing table fail, since its
+        // routes use the tunnel interface, so the probe is routed like excluded traffic.
+        socket.bind_device(Some(interface.as_bytes()))?;
+        socket.send_to(payload, &SockAddr::from(target))?;
+        Ok(())
+    };
+    match send() {
+        Ok(()) => ProbeOutcome::Sent,
+        Err(error) if error.raw_os_error() == Some(libc::EPERM) => ProbeOutcome::Blocked,
+        Err(error) => ProbeOutcome::Error(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    //! Runs the probes against the connected firewall policy inside a pair of network namespaces
+    //! connected by veth interfaces, with a WireGuard peer in the second namespace that checks
+    //! that traffic through the tunnel still gets through. This requires root, the wireguard
+    //! kernel module, and the `ip` and `wg` tools. Run it with
+    //! `sudo -E cargo test -p talpid-core leak_test -- --ignored`.
+
+    use super::*;
+    use crate::{
+        firewall::{Firewall, FirewallPolicy},
+        tunnel::TunnelMetadata,
+    };
+    use nix::sched::{setns, CloneFlags};
+    use std::{
+        fs::File,
+        io::Write,
+        net::UdpSocket,
+        os::unix::io::AsRawFd,
+        process::{Command, Stdio},
+        time::Duration,
+    };
+    use talpid_types::{
+        net::{Endpoint, TransportProtocol},
+        netns::namespace_path,
+    };
+
+    const CLIENT_NS: &str = "leaktest-client";
+    const PEER_NS: &str = "leaktest-peer";
+    const CLIENT_VETH: &str = "leaktest0";
+    const PEER_VETH: &str = "leaktest1";
+    const TUNNEL_INTERFACE: &str = "wg-leaktest";
+    const PEER_PORT: &str = "51820";
+    const FWMARK: u32 = 0x6d6f6c65;
+    const PEER_TUNNEL_ADDR: &str = "10.64.0.1:9999";
+
+    /// Removes the namespaces, and every interface in them, when dropped.
+    struct Namespaces;
+
+    impl Namespaces {
+        fn create() -> Self {
+            let namespaces = Namespaces;
+            let (client_key, client_pubkey) = wg_keypair();
+            let (peer_key, peer_pubkey) = wg_keypair();
+
+            let commands = [
+                format!("netns add {}", CLIENT_NS),
+                format!("netns add {}", PEER_NS),
+                format!(
+                    "link add {} netns {} type veth peer name {} netns {}",
+                    CLIENT_VETH, CLIENT_NS, PEER_VETH, PEER_NS
+                ),
+                format!(
+                    "-n {} addr add 10.199.0.2/24 dev {}",
+                    CLIENT_NS, CLIENT_VETH
+                ),
+                format!(
+                    "-n {} addr add fd99::2/64 dev {} nodad",
+                    CLIENT_NS, CLIENT_VETH
+                ),
+                format!("-n {} addr add 10.199.0.1/24 dev {}", PEER_NS, PEER_VETH),
+                format!("-n {} addr add fd99::1/64 dev {} nodad", PEER_NS, PEER_VETH),
+                format!("-n {} link set lo up", CLIENT_NS),
+                format!("-n {} link set lo up", PEER_NS),
+                format!("-n {} link set {} up", CLIENT_NS, CLIENT_VETH),
+                format!("-n {} link set {} up", PEER_NS, PEER_VETH),
+                format!("-n {} route add default via 10.199.0.1", CLIENT_NS),
+                format!("-n {} -6 route add default via fd99::1", CLIENT_NS),
+                format!(
+                    "-n {} link add {} type wireguard",
+                    CLIENT_NS, TUNNEL_INTERFACE
+                ),
+                format!(
+                    "-n {} link add {} type wireguard",
+                    PEER_NS, TUNNEL_INTERFACE
+                ),
+                format!(
+                    "-n {} addr add 10.64.0.2/32 dev {}",
+                    CLIENT_NS, TUNNEL_INTERFACE
+                ),
+                format!(
+                    "-n {} addr add 10.64.0.1/32 dev {}",
+                    PEER_NS, TUNNEL_INTERFACE
+                ),
+            ];
+            for command in &commands {
+                let args: Vec<_> = command.split_whitespace().collect();
+                run_command("ip", &args, None);
+            }
+
+            let wg_commands = [
+                (
+                    CLIENT_NS,
+                    format!(
+                        "peer {} endpoint 10.199.0.1:{} allowed-ips 0.0.0.0/0,::/0",
+                        peer_pubkey, PEER_PORT
+                    ),
+                    client_key,
+                ),
+                (
+                    PEER_NS,
+                    format!(
+                        "listen-port {} peer {} allowed-ips 10.64.0.2/32",
+                        PEER_PORT, client_pubkey
+                    ),
+                    peer_key,
+                ),
+            ];
+            for (namespace, config, private_key) in wg_commands {
+                let command = format!(
+                    "netns exec {} wg set {} private-key /dev/stdin {}",
+                    namespace, TUNNEL_INTERFACE, config
+                );
+                let args: Vec<_> = command.split_whitespace().collect();
+                run_command("ip", &args, Some(&private_key));
+                run_command(
+                    "ip",
+                    &["-n", namespace, "link", "set", TUNNEL_INTERFACE, "up"],
+                    None,
+                );
+            }
+
+            namespaces
+        }
+    }
+
+    impl Drop for Namespaces {
+        fn drop(&mut self) {
+            for namespace in [CLIENT_NS, PEER_NS] {
+                let _ = Command::new("ip")
+                    .args(["netns", "delete", namespace])
+                    .status();
+            }
+        }
+    }
+
+    fn run_command(program: &str, args: &[&str], stdin: Option<&str>) -> String {
+        let command = format!("{} {}", program, args.join(" "));
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|error| panic!("Failed to run `{}`: {}", command, error));
+        if let Some(input) = stdin {
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(input.as_bytes())
+                .unwrap();
+        }
+        let output = child.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "`{}` failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).trim().to_owned()
+    }
+
+    fn wg_keypair() -> (String, String) {
+        let private_key = run_command("wg", &["genkey"], None);
+        let public_key = run_command("wg", &["pubkey"], Some(&private_key));
+        (private_key, public_key)
+    }
+
+    fn enter_namespace(namespace: &str) {
+        let netns = File::open(namespace_path(namespace)).unwrap();
+        setns(netns.as_raw_fd(), CloneFlags::CLONE_NEWNET).unwrap();
+    }
+
+    fn connected_policy(allow_lan: bool) -> FirewallPolicy {
+        FirewallPolicy::Connected {
+            peer_endpoint: Endpoint::new(
+                [10, 199, 0, 1],
+                PEER_PORT.parse().unwrap(),
+                TransportProtocol::Udp,
+            ),
+            tunnel: TunnelMetadata {
+                interface: TUNNEL_INTERFACE.to_owned(),
+                ips: vec!["10.64.0.2".parse().unwrap()],
+                ipv4_gateway: "10.64.0.1".parse().unwrap(),
+                ipv6_gateway: None,
+                network_namespace: None,
+            },
+            allow_lan,
+            dns_servers: vec!["10.64.0.1".parse().unwrap()],
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_connected_policy_blocks_leaks() {
+        assert!(
+            nix::unistd::Uid::effective().is_root(),
+            "The leak test must be run as root"
+        );
+        let namespaces = Namespaces::create();
+
+        // Receives the datagrams sent through the tunnel in the peer namespace.
+        let (bound_tx, bound_rx) = std::sync::mpsc::channel();
+        let receiver = std::thread::spawn(move || {
+            enter_namespace(PEER_NS);
+            let socket = UdpSocket::bind(PEER_TUNNEL_ADDR).unwrap();
+            let _ = bound_tx.send(());
+            socket
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0u8; 64];
+            let mut received = 0;
+            while socket.recv(&mut buf).is_ok() {
+                assert_eq!(&buf[..PROBE_PAYLOAD.len()], PROBE_PAYLOAD);
+                received += 1;
+            }
+            received
+        });
+
+        let _ = bound_rx.recv();
+
+        // The default route of the client namespace goes via the veth pair, so the probes would
+        // leave through it unless the firewall stops them. Only this thread enters the namespace.
+        let result = std::thread::spawn(|| {
+            enter_namespace(CLIENT_NS);
+
+            let targets = LeakTestTargets {
+                lan: Some("10.199.0.1:9".parse().unwrap()),
+                ..LeakTestTargets::default()
+            };
+            let tunnel_target = PEER_TUNNEL_ADDR.parse().unwrap();
+
+            for result in run(CLIENT_VETH, &targets, true) {
+                assert_eq!(result.outcome, ProbeOutcome::Sent, "{:?}", result);
+            }
+
+            let mut firewall = Firewall::new(FWMARK).unwrap();
+            for allow_lan in [false, true] {
+                firewall.apply_policy(connected_policy(allow_lan)).unwrap();
+                for result in run(CLIENT_VETH, &targets, allow_lan) {
+                    assert_eq!(result.passed(), Some(true), "{:?}", result);
+                }
+                assert_eq!(
+                    send_probe(TUNNEL_INTERFACE, tunnel_target, PROBE_PAYLOAD),
+                    ProbeOutcome::Sent
+                );
+            }
+            firewall.reset_policy().unwrap();
+        })
+        .join();
+        let received = receiver.join();
+
+        drop(namespaces);
+        if let Err(panic) = result {
+            std::panic::resume_unwind(panic);
+        }
+        let received = received.unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        assert!(received > 0, "No traffic got through the tunnel");
+    }
+}
@@ -39,6 +39,10 @@ pub mod tunnel_state_machine;
 /// Future utilities
 pub mod future_retry;
 
+/// Self-test that checks that the firewall blocks traffic outside the tunnel.
+#[cfg(target_os = "linux")]
+pub mod leak_test;
+
 /// Misc utilities for the Linux platform.
 #[cfg(target_os = "linux")]
 mod linux;
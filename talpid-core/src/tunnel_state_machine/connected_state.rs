@@ -3,6 +3,8 @@ use super::{
     EventResult, SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver, TunnelState,
     TunnelStateTransition, TunnelStateWrapper,
 };
+#[cfg(target_os = "linux")]
+use crate::leak_test;
 use crate::{
     firewall::FirewallPolicy,
     tunnel::{TunnelEvent, TunnelMetadata},
@@ -14,6 +16,10 @@ use futures::{
     StreamExt,
 };
 use std::net::IpAddr;
+#[cfg(target_os = "linux")]
+use std::net::SocketAddr;
+#[cfg(target_os = "linux")]
+use talpid_types::leak_test::ProbeResult;
 use talpid_types::{
     net::TunnelParameters,
     tunnel::{ErrorStateCause, FirewallPolicyError},
@@ -25,6 +31,11 @@ use crate::tunnel::TunnelMonitor;
 
 use super::connecting_state::TunnelCloseEvent;
 
+/// Port of the probe sent to the default gateway by the leak test. It is the discard port, so
+/// the datagram has no effect on the gateway.
+#[cfg(target_os = "linux")]
+const LEAK_TEST_LAN_PORT: u16 = 9;
+
 pub(crate) type TunnelEventsReceiver =
     Fuse<mpsc::UnboundedReceiver<(TunnelEvent, oneshot::Sender<()>)>>;
 
@@ -119,6 +130,38 @@ impl ConnectedState {
         }
     }
 
+    /// Sends probes out of the interface that the relay is reached through, and reports whether
+    /// the firewall blocked them.
+    #[cfg(target_os = "linux")]
+    fn leak_test(
+        &self,
+        shared_values: &SharedTunnelStateValues,
+    ) -> Result<Vec<ProbeResult>, leak_test::Error> {
+        let relay = self.tunnel_parameters.get_next_hop_endpoint().address.ip();
+        let route = shared_values
+            .runtime
+            .block_on(async {
+                shared_values
+                    .route_manager
+                    .handle()?
+                    .get_destination_route(relay, Some(shared_values.fwmark))
+                    .await
+            })
+            .map_err(leak_test::Error::FindPhysicalRoute)?
+            .ok_or(leak_test::Error::NoPhysicalRoute)?;
+        let node = route.get_node();
+        let interface = node.get_device().ok_or(leak_test::Error::NoPhysicalRoute)?;
+
+        let targets = leak_test::LeakTestTargets {
+            lan: node
+                .get_address()
+                .filter(crate::firewall::is_local_address)
+                .map(|gateway| SocketAddr::new(gateway, LEAK_TEST_LAN_PORT)),
+            ..Default::default()
+        };
+        Ok(leak_test::run(interface, &targets, shared_values.allow_lan))
+    }
+
     fn set_dns(&self, shared_values: &mut SharedTunnelStateValues) -> Result<(), BoxedError> {
         let dns_ips = self.get_dns_servers(shared_values);
 
@@ -132,6 +175,13 @@ impl ConnectedState {
             })
             .collect::<Vec<_>>();
 
+        // DNS for an isolated tunnel only applies to processes in its network namespace
+        #[cfg(target_os = "linux")]
+        if let Some(netns) = &self.metadata.network_namespace {
+            return talpid_wireguard::netns::set_dns_servers(netns, &dns_ips)
+                .map_err(BoxedError::new);
+        }
+
         shared_values
             .dns_monitor
             .set(&self.metadata.interface, &dns_ips)
@@ -140,7 +190,15 @@ impl ConnectedState {
         Ok(())
     }
 
-    fn reset_dns(shared_values: &mut SharedTunnelStateValues) {
+    fn reset_dns(&self, shared_values: &mut SharedTunnelStateValues) {
+        #[cfg(target_os = "linux")]
+        if let Some(netns) = &self.metadata.network_namespace {
+            if let Err(error) = talpid_wireguard::netns::reset_dns_servers(netns) {
+                log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
+            }
+            return;
+        }
+
         if let Err(error) = shared_values.dns_monitor.reset() {
             log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
         }
@@ -167,7 +225,7 @@ impl ConnectedState {
         shared_values: &mut SharedTunnelStateValues,
         after_disconnect: AfterDisconnect,
     ) -> EventConsequence {
-        Self::reset_dns(shared_values);
+        self.reset_dns(shared_values);
         Self::reset_routes(shared_values);
 
         EventConsequence::NewState(DisconnectingState::enter(
@@ -276,6 +334,21 @@ impl ConnectedState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            Some(TunnelCommand::Diagnostics(tx)) => {
+                let routing_rules_ipv6 =
+                    Some(self.tunnel_parameters.get_generic_options().enable_ipv6);
+                // An isolated tunnel is routed within its network namespace, without any rules
+                #[cfg(target_os = "linux")]
+                let routing_rules_ipv6 =
+                    routing_rules_ipv6.filter(|_| self.metadata.network_namespace.is_none());
+                let _ = tx.send(shared_values.diagnostics(true, routing_rules_ipv6));
+                SameState(self.into())
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::LeakTest(tx)) => {
+                let _ = tx.send(self.leak_test(shared_values));
+                SameState(self.into())
+            }
         }
     }
 
@@ -302,13 +375,13 @@ impl ConnectedState {
         use self::EventConsequence::*;
 
         if let Some(block_reason) = block_reason {
-            Self::reset_dns(shared_values);
+            self.reset_dns(shared_values);
             Self::reset_routes(shared_values);
             return NewState(ErrorState::enter(shared_values, block_reason));
         }
 
         log::info!("Tunnel closed. Reconnecting.");
-        Self::reset_dns(shared_values);
+        self.reset_dns(shared_values);
         Self::reset_routes(shared_values);
         NewState(ConnectingState::enter(shared_values, 0))
     }
@@ -206,6 +206,17 @@ impl TunnelState for DisconnectedState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            Some(TunnelCommand::Diagnostics(tx)) => {
+                let diagnostics =
+                    shared_values.diagnostics(shared_values.block_when_disconnected, None);
+                let _ = tx.send(diagnostics);
+                SameState(self.into())
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::LeakTest(tx)) => {
+                let _ = tx.send(Err(crate::leak_test::Error::NotConnected));
+                SameState(self.into())
+            }
             None => {
                 Self::reset_dns(shared_values);
                 Finished
@@ -57,6 +57,15 @@ impl DisconnectingState {
                     shared_values.split_tunnel.set_paths(&paths, result_tx);
                     AfterDisconnect::Nothing
                 }
+                Some(TunnelCommand::Diagnostics(tx)) => {
+                    let _ = tx.send(shared_values.diagnostics(true, None));
+                    AfterDisconnect::Nothing
+                }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::LeakTest(tx)) => {
+                    let _ = tx.send(Err(crate::leak_test::Error::NotConnected));
+                    AfterDisconnect::Nothing
+                }
             },
             AfterDisconnect::Block(reason) => match command {
                 Some(TunnelCommand::AllowLan(allow_lan)) => {
@@ -97,6 +106,15 @@ impl DisconnectingState {
                     shared_values.split_tunnel.set_paths(&paths, result_tx);
                     AfterDisconnect::Block(reason)
                 }
+                Some(TunnelCommand::Diagnostics(tx)) => {
+                    let _ = tx.send(shared_values.diagnostics(true, None));
+                    AfterDisconnect::Block(reason)
+                }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::LeakTest(tx)) => {
+                    let _ = tx.send(Err(crate::leak_test::Error::NotConnected));
+                    AfterDisconnect::Block(reason)
+                }
                 None => AfterDisconnect::Block(reason),
             },
             AfterDisconnect::Reconnect(retry_attempt) => match command {
@@ -138,6 +156,15 @@ impl DisconnectingState {
                     shared_values.split_tunnel.set_paths(&paths, result_tx);
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
+                Some(TunnelCommand::Diagnostics(tx)) => {
+                    let _ = tx.send(shared_values.diagnostics(true, None));
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::LeakTest(tx)) => {
+                    let _ = tx.send(Err(crate::leak_test::Error::NotConnected));
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
             },
         };
 
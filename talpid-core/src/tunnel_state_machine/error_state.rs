@@ -209,6 +209,15 @@ impl TunnelState for ErrorState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            Some(TunnelCommand::Diagnostics(tx)) => {
+                let _ = tx.send(shared_values.diagnostics(true, None));
+                SameState(self.into())
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::LeakTest(tx)) => {
+                let _ = tx.send(Err(crate::leak_test::Error::NotConnected));
+                SameState(self.into())
+            }
         }
     }
 }
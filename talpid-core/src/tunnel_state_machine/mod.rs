@@ -11,6 +11,8 @@ use self::{
     disconnecting_state::{AfterDisconnect, DisconnectingState},
     error_state::ErrorState,
 };
+#[cfg(target_os = "linux")]
+use crate::leak_test;
 #[cfg(windows)]
 use crate::split_tunnel;
 use crate::{
@@ -41,7 +43,11 @@ use std::{
     time::Duration,
 };
 #[cfg(target_os = "android")]
-use talpid_types::{android::AndroidContext, ErrorExt};
+use talpid_types::android::AndroidContext;
+#[cfg(target_os = "linux")]
+use talpid_types::leak_test::ProbeResult;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use talpid_types::ErrorExt;
 use talpid_types::{
     net::{AllowedEndpoint, TunnelParameters},
     tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
@@ -219,6 +225,31 @@ pub enum TunnelCommand {
         oneshot::Sender<Result<(), split_tunnel::Error>>,
         Vec<OsString>,
     ),
+    /// Check the system configuration that the current state depends on.
+    Diagnostics(oneshot::Sender<TunnelDiagnostics>),
+    /// Send probes outside the tunnel and report whether the firewall blocked them.
+    #[cfg(target_os = "linux")]
+    LeakTest(oneshot::Sender<Result<Vec<ProbeResult>, leak_test::Error>>),
+}
+
+/// State of the system configuration that is managed by the tunnel state machine. Used to
+/// diagnose problems with the tunnel.
+#[derive(Debug, Clone)]
+pub struct TunnelDiagnostics {
+    /// Whether the current state applies a firewall policy.
+    pub firewall_expected: bool,
+    /// Names of the netfilter tables that are missing, or why they could not be listed.
+    #[cfg(target_os = "linux")]
+    pub missing_firewall_tables: Result<Vec<String>, String>,
+    /// Whether the current state routes traffic through the tunnel.
+    pub routing_rules_expected: bool,
+    /// Descriptions of the routing rules that are missing, or why they could not be listed.
+    /// Always empty if no routing rules are expected.
+    #[cfg(target_os = "linux")]
+    pub missing_routing_rules: Result<Vec<String>, String>,
+    /// Name of the method used to manage DNS, if DNS is currently being managed.
+    #[cfg(target_os = "linux")]
+    pub dns_backend: Option<String>,
 }
 
 type TunnelCommandReceiver = stream::Fuse<mpsc::UnboundedReceiver<TunnelCommand>>;
@@ -379,6 +410,8 @@ impl TunnelStateMachine {
             resource_dir: args.resource_dir,
             #[cfg(target_os = "linux")]
             connectivity_check_was_enabled: None,
+            #[cfg(target_os = "linux")]
+            fwmark: args.linux_ids.fwmark,
             #[cfg(target_os = "macos")]
             filtering_resolver,
             #[cfg(target_os = "macos")]
@@ -475,6 +508,10 @@ struct SharedTunnelStateValues {
     #[cfg(target_os = "linux")]
     connectivity_check_was_enabled: Option<bool>,
 
+    /// Firewall mark of traffic that is routed outside the tunnel.
+    #[cfg(target_os = "linux")]
+    fwmark: u32,
+
     /// Filtering resolver handle
     #[cfg(target_os = "macos")]
     filtering_resolver: crate::resolver::ResolverHandle,
@@ -564,6 +601,38 @@ impl SharedTunnelStateValues {
         }
     }
 
+    /// Checks the firewall, routing and DNS configuration. `routing_rules_ipv6` is `None` if the
+    /// current state does not route traffic through the tunnel, and otherwise says whether IPv6
+    /// is routed as well.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub fn diagnostics(
+        &self,
+        firewall_expected: bool,
+        routing_rules_ipv6: Option<bool>,
+    ) -> TunnelDiagnostics {
+        #[cfg(target_os = "linux")]
+        let missing_routing_rules = match routing_rules_ipv6 {
+            Some(enable_ipv6) => self
+                .runtime
+                .block_on(self.route_manager.missing_routing_rules(enable_ipv6))
+                .map_err(|error| error.display_chain()),
+            None => Ok(vec![]),
+        };
+        TunnelDiagnostics {
+            firewall_expected,
+            #[cfg(target_os = "linux")]
+            missing_firewall_tables: self
+                .firewall
+                .missing_tables()
+                .map_err(|error| error.display_chain()),
+            routing_rules_expected: routing_rules_ipv6.is_some(),
+            #[cfg(target_os = "linux")]
+            missing_routing_rules,
+            #[cfg(target_os = "linux")]
+            dns_backend: self.dns_monitor.backend(),
+        }
+    }
+
     #[cfg(target_os = "android")]
     pub fn bypass_socket(&mut self, fd: RawFd, tx: oneshot::Sender<()>) {
         if let Err(err) = self.tun_provider.lock().unwrap().bypass(fd) {
@@ -1011,6 +1011,8 @@ mod event_server {
                 ips,
                 ipv4_gateway,
                 ipv6_gateway,
+                #[cfg(target_os = "linux")]
+                network_namespace: None,
             })
         }
     }
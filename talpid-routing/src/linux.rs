@@ -84,6 +84,17 @@ fn no_fwmark_rule_v6(fwmark: u32, table: u32) -> RuleMessage {
     v6_rule
 }
 
+/// Returns the first of `rules` that is equivalent to `rule`. Attributes that are not set in
+/// `rule` are ignored.
+fn find_matching_rule<'a>(rules: &'a [RuleMessage], rule: &RuleMessage) -> Option<&'a RuleMessage> {
+    rules.iter().find(|found_rule| {
+        found_rule.header.family == rule.header.family
+            && found_rule.header.action == rule.header.action
+            && (found_rule.header.flags & rule.header.flags) == rule.header.flags
+            && rule.nlas.iter().all(|nla| found_rule.nlas.contains(nla))
+    })
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Errors that can happen in the Linux routing integration
@@ -210,43 +221,47 @@ impl RouteManagerImpl {
     async fn clear_routing_rules(&mut self) -> Result<()> {
         let rules = self.get_rules().await?;
         for rule in all_rules(self.fwmark, self.table_id) {
-            let mut matching_rule = None;
-
             // `RTM_DELRULE` is way too picky about which rules are considered the same.
-            // So iterate over all rules and ignore irrelevant attributes.
-            for found_rule in &rules {
-                // Match header
-                if found_rule.header.family != rule.header.family {
-                    continue;
-                }
-                if found_rule.header.action != rule.header.action {
-                    continue;
-                }
-                if (found_rule.header.flags & rule.header.flags) != rule.header.flags {
-                    continue;
-                }
-                // Match NLAs
-                let mut contains_nlas = true;
-                for nla in &rule.nlas {
-                    if !found_rule.nlas.contains(nla) {
-                        contains_nlas = false;
-                        break;
-                    }
-                }
-                if contains_nlas {
-                    log::trace!("Existing routing rule matched: {:?}", found_rule);
-                    matching_rule = Some(found_rule);
-                    break;
-                }
-            }
-
-            if let Some(rule) = matching_rule {
-                self.delete_rule_if_exists((*rule).clone()).await?;
+            // So find the existing rule and delete that instead.
+            if let Some(rule) = find_matching_rule(&rules, &rule) {
+                log::trace!("Existing routing rule matched: {:?}", rule);
+                self.delete_rule_if_exists(rule.clone()).await?;
             }
         }
         Ok(())
     }
 
+    /// Returns descriptions of the rules created by [Self::create_routing_rules] that do not
+    /// exist.
+    async fn missing_routing_rules(&mut self, enable_ipv6: bool) -> Result<Vec<String>> {
+        use netlink_packet_route::constants::*;
+
+        let rules = self.get_rules().await?;
+        Ok(all_rules(self.fwmark, self.table_id)
+            .iter()
+            .filter(|rule| rule.header.family as u16 == AF_INET || enable_ipv6)
+            .filter(|rule| find_matching_rule(&rules, rule).is_none())
+            .map(|rule| {
+                let family = if rule.header.family as u16 == AF_INET {
+                    "IPv4"
+                } else {
+                    "IPv6"
+                };
+                if rule.nlas.contains(&RuleNla::SuppressPrefixLen(0)) {
+                    format!(
+                        "{} rule that ignores default routes in the main table",
+                        family
+                    )
+                } else {
+                    format!(
+                        "{} rule that routes packets without fwmark {:#x} to table {}",
+                        family, self.fwmark, self.table_id
+                    )
+                }
+            })
+            .collect())
+    }
+
     async fn get_rules(&mut self) -> Result<Vec<RuleMessage>> {
         use netlink_packet_route::constants::*;
 
@@ -384,6 +399,9 @@ impl RouteManagerImpl {
             RouteManagerCommand::ClearRoutingRules(result_tx) => {
                 let _ = result_tx.send(self.clear_routing_rules().await);
             }
+            RouteManagerCommand::MissingRoutingRules(enable_ipv6, result_tx) => {
+                let _ = result_tx.send(self.missing_routing_rules(enable_ipv6).await);
+            }
             RouteManagerCommand::NewChangeListener(result_tx) => {
                 let _ = result_tx.send(self.listen());
             }
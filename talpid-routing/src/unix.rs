@@ -106,6 +106,22 @@ impl RouteManagerHandle {
             .map_err(Error::PlatformError)
     }
 
+    /// Returns descriptions of the rules created by [Self::create_routing_rules] that are missing.
+    #[cfg(target_os = "linux")]
+    pub async fn missing_routing_rules(&self, enable_ipv6: bool) -> Result<Vec<String>, Error> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.tx
+            .unbounded_send(RouteManagerCommand::MissingRoutingRules(
+                enable_ipv6,
+                response_tx,
+            ))
+            .map_err(|_| Error::RouteManagerDown)?;
+        response_rx
+            .await
+            .map_err(|_| Error::ManagerChannelDown)?
+            .map_err(Error::PlatformError)
+    }
+
     /// Listen for route changes.
     #[cfg(target_os = "linux")]
     pub async fn change_listener(&self) -> Result<impl Stream<Item = CallbackMessage>, Error> {
@@ -169,6 +185,8 @@ pub(crate) enum RouteManagerCommand {
     #[cfg(target_os = "linux")]
     ClearRoutingRules(oneshot::Sender<Result<(), PlatformError>>),
     #[cfg(target_os = "linux")]
+    MissingRoutingRules(bool, oneshot::Sender<Result<Vec<String>, PlatformError>>),
+    #[cfg(target_os = "linux")]
     NewChangeListener(oneshot::Sender<mpsc::UnboundedReceiver<CallbackMessage>>),
     #[cfg(target_os = "linux")]
     GetMtuForRoute(IpAddr, oneshot::Sender<Result<u16, PlatformError>>),
@@ -286,6 +304,12 @@ impl RouteManager {
         self.handle()?.clear_routing_rules().await
     }
 
+    /// Returns descriptions of the rules created by [Self::create_routing_rules] that are missing.
+    #[cfg(target_os = "linux")]
+    pub async fn missing_routing_rules(&self, enable_ipv6: bool) -> Result<Vec<String>, Error> {
+        self.handle()?.missing_routing_rules(enable_ipv6).await
+    }
+
     /// Retrieve a sender directly to the command channel.
     pub fn handle(&self) -> Result<RouteManagerHandle, Error> {
         if let Some(tx) = &self.manage_tx {
@@ -2,7 +2,7 @@
 //! Useful to test this crate's implementation.
 
 use std::net::IpAddr;
-use talpid_types::net::wireguard::PublicKey;
+use talpid_types::net::wireguard::{PrivateKey, PublicKey};
 
 #[tokio::main]
 async fn main() {
@@ -17,9 +17,14 @@ async fn main() {
         .expect("Give WireGuard public key as second argument");
     let pubkey = PublicKey::from_base64(pubkey_string.trim()).expect("Invalid public key");
 
-    let (private_key, psk) = talpid_tunnel_config_client::push_pq_key(tuncfg_server_ip, pubkey)
-        .await
-        .unwrap();
+    let private_key = PrivateKey::new_from_random();
+    let psk = talpid_tunnel_config_client::push_pq_key(
+        tuncfg_server_ip,
+        pubkey,
+        private_key.public_key(),
+    )
+    .await
+    .unwrap();
 
     println!("private key: {:?}", private_key);
     println!("psk: {:?}", psk);
@@ -6,6 +6,7 @@ mod proto {
     tonic::include_proto!("tunnel_config");
 }
 use classic_mceliece_rust::{PublicKey, CRYPTO_PUBLICKEYBYTES};
+use ml_kem::{kem::Encapsulate, EncodedSizeUser, KemCore, MlKem1024};
 use proto::{
     post_quantum_secure_server::{PostQuantumSecure, PostQuantumSecureServer},
     PskRequestExperimentalV0, PskRequestExperimentalV1, PskResponseExperimentalV0,
@@ -53,6 +54,16 @@ impl PostQuantumSecure for PostQuantumSecureImpl {
                         classic_mceliece_rust::encapsulate_boxed(&public_key, &mut rng);
                     (ciphertext.as_array().to_vec(), *shared_secret.as_array())
                 }
+                "ML-KEM-1024" => {
+                    type EncapsulationKey = <MlKem1024 as KemCore>::EncapsulationKey;
+                    let key_data = ml_kem::Encoded::<EncapsulationKey>::try_from(
+                        kem_pubkey.key_data.as_slice(),
+                    )
+                    .unwrap();
+                    let public_key = EncapsulationKey::from_bytes(&key_data);
+                    let (ciphertext, shared_secret) = public_key.encapsulate(&mut rng).unwrap();
+                    (ciphertext.to_vec(), shared_secret.into())
+                }
                 name => panic!("Unsupported KEM algorithm: {name}"),
             };
 
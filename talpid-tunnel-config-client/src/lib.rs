@@ -1,8 +1,9 @@
 use std::{fmt, net::IpAddr};
-use talpid_types::net::wireguard::{PresharedKey, PrivateKey, PublicKey};
+use talpid_types::net::wireguard::{PresharedKey, PublicKey};
 use tonic::transport::Channel;
 
 mod classic_mceliece;
+mod ml_kem;
 
 #[allow(clippy::derive_partial_eq_without_eq)]
 mod proto {
@@ -28,7 +29,7 @@ impl std::fmt::Display for Error {
                 "Expected a ciphertext of length {expected}, got {actual} bytes"
             ),
             InvalidCiphertextCount { actual } => {
-                write!(f, "Expected 1 ciphertext in the response, got {actual}")
+                write!(f, "Expected 2 ciphertexts in the response, got {actual}")
             }
         }
     }
@@ -52,37 +53,61 @@ pub const CONFIG_SERVICE_PORT: u16 = 1337;
 /// keys than the larger variants, and is considered safe.
 const CLASSIC_MCELIECE_VARIANT: &str = "Classic-McEliece-460896f";
 
-/// Generates a new WireGuard key pair and negotiates a PSK with the relay in a PQ-safe
-/// manner. This creates a peer on the relay with the new WireGuard pubkey and PSK,
-/// which can then be used to establish a PQ-safe tunnel to the relay.
+/// Negotiates a PSK with the relay in a PQ-safe manner. The PSK is derived from both Classic
+/// McEliece and ML-KEM, so that it remains secure unless both are broken. Responses that do not
+/// contain a ciphertext for both algorithms are rejected. This creates a peer on the relay with
+/// the ephemeral WireGuard pubkey `wg_psk_pubkey` and the PSK, which can then be used to establish
+/// a PQ-safe tunnel to the relay.
+///
+/// The same ephemeral key may be used with several relays, e.g. both hops of a multihop tunnel.
 // TODO: consider binding to the tunnel interface here, on non-windows platforms
 pub async fn push_pq_key(
     service_address: IpAddr,
     wg_pubkey: PublicKey,
-) -> Result<(PrivateKey, PresharedKey), Error> {
-    let wg_psk_privkey = PrivateKey::new_from_random();
+    wg_psk_pubkey: PublicKey,
+) -> Result<PresharedKey, Error> {
     let (cme_kem_pubkey, cme_kem_secret) = classic_mceliece::generate_keys().await;
+    let (ml_kem_pubkey, ml_kem_secret) = ml_kem::generate_keys();
 
     let mut client = new_client(service_address).await?;
     let response = client
         .psk_exchange_experimental_v1(proto::PskRequestExperimentalV1 {
             wg_pubkey: wg_pubkey.as_bytes().to_vec(),
-            wg_psk_pubkey: wg_psk_privkey.public_key().as_bytes().to_vec(),
-            kem_pubkeys: vec![proto::KemPubkeyExperimentalV1 {
-                algorithm_name: CLASSIC_MCELIECE_VARIANT.to_owned(),
-                key_data: cme_kem_pubkey.as_array().to_vec(),
-            }],
+            wg_psk_pubkey: wg_psk_pubkey.as_bytes().to_vec(),
+            kem_pubkeys: vec![
+                proto::KemPubkeyExperimentalV1 {
+                    algorithm_name: CLASSIC_MCELIECE_VARIANT.to_owned(),
+                    key_data: cme_kem_pubkey.as_array().to_vec(),
+                },
+                proto::KemPubkeyExperimentalV1 {
+                    algorithm_name: ml_kem::ALGORITHM_NAME.to_owned(),
+                    key_data: ml_kem::encode_public_key(&ml_kem_pubkey),
+                },
+            ],
         })
         .await
         .map_err(Error::GrpcError)?;
 
     let ciphertexts = response.into_inner().ciphertexts;
-    // Unpack the ciphertexts into one per KEM without needing to access them by index.
-    let [cme_ciphertext] = <&[Vec<u8>; 1]>::try_from(ciphertexts.as_slice()).map_err(|_| {
-        Error::InvalidCiphertextCount {
-            actual: ciphertexts.len(),
+    derive_psk(&cme_kem_secret, &ml_kem_secret, &ciphertexts)
+}
+
+/// Decapsulates the ciphertexts returned by the relay and mixes the shared secrets into a PSK.
+/// The first ciphertext belongs to Classic McEliece and the second one to ML-KEM. Both must be
+/// present, so that a relay or an attacker cannot downgrade the exchange to a single algorithm.
+fn derive_psk(
+    cme_kem_secret: &classic_mceliece::SecretKey<'_>,
+    ml_kem_secret: &ml_kem::DecapsulationKey,
+    ciphertexts: &[Vec<u8>],
+) -> Result<PresharedKey, Error> {
+    let (cme_ciphertext, ml_kem_ciphertext) = match ciphertexts {
+        [cme_ciphertext, ml_kem_ciphertext] => (cme_ciphertext, ml_kem_ciphertext),
+        _ => {
+            return Err(Error::InvalidCiphertextCount {
+                actual: ciphertexts.len(),
+            })
         }
-    })?;
+    };
 
     // Store the PSK data on the heap. So it can be passed around and then zeroized on drop without
     // being stored in a bunch of places on the stack.
@@ -96,11 +121,21 @@ pub async fn push_pq_key(
                 expected: classic_mceliece::CRYPTO_CIPHERTEXTBYTES,
             })?;
         let ciphertext = classic_mceliece::Ciphertext::from(ciphertext_array);
-        let shared_secret = classic_mceliece::decapsulate(&cme_kem_secret, &ciphertext);
+        let shared_secret = classic_mceliece::decapsulate(cme_kem_secret, &ciphertext);
         xor_assign(&mut psk_data, shared_secret.as_array());
     }
+    // Decapsulate ML-KEM and mix into PSK
+    {
+        let shared_secret = ml_kem::decapsulate(ml_kem_secret, ml_kem_ciphertext).ok_or(
+            Error::InvalidCiphertextLength {
+                actual: ml_kem_ciphertext.len(),
+                expected: ml_kem::CIPHERTEXT_LEN,
+            },
+        )?;
+        xor_assign(&mut psk_data, &shared_secret);
+    }
 
-    Ok((wg_psk_privkey, PresharedKey::from(psk_data)))
+    Ok(PresharedKey::from(psk_data))
 }
 
 /// Performs `dst = dst ^ src`.
@@ -115,3 +150,52 @@ async fn new_client(addr: IpAddr) -> Result<RelayConfigService, Error> {
         .await
         .map_err(Error::GrpcConnectError)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::ml_kem::kem::Encapsulate;
+
+    #[test]
+    fn test_derive_psk() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (cme_pubkey, cme_secret) = runtime.block_on(classic_mceliece::generate_keys());
+        let (ml_kem_pubkey, ml_kem_secret) = super::ml_kem::generate_keys();
+
+        let mut rng = rand::thread_rng();
+        let (cme_ciphertext, cme_shared_secret) =
+            classic_mceliece_rust::encapsulate_boxed(&cme_pubkey, &mut rng);
+        let (ml_kem_ciphertext, ml_kem_shared_secret) =
+            ml_kem_pubkey.encapsulate(&mut rng).unwrap();
+        let cme_ciphertext = cme_ciphertext.as_array().to_vec();
+        let ml_kem_ciphertext = ml_kem_ciphertext.to_vec();
+
+        let mut expected = *cme_shared_secret.as_array();
+        xor_assign(&mut expected, &<[u8; 32]>::from(ml_kem_shared_secret));
+        let hybrid = derive_psk(
+            &cme_secret,
+            &ml_kem_secret,
+            &[cme_ciphertext.clone(), ml_kem_ciphertext.clone()],
+        )
+        .unwrap();
+        assert_eq!(hybrid.as_bytes(), &expected);
+
+        assert!(matches!(
+            derive_psk(&cme_secret, &ml_kem_secret, &[]),
+            Err(Error::InvalidCiphertextCount { actual: 0 })
+        ));
+        // A response with only a Classic McEliece ciphertext must not downgrade the exchange
+        assert!(matches!(
+            derive_psk(&cme_secret, &ml_kem_secret, &[cme_ciphertext.clone()]),
+            Err(Error::InvalidCiphertextCount { actual: 1 })
+        ));
+        assert!(matches!(
+            derive_psk(
+                &cme_secret,
+                &ml_kem_secret,
+                &[cme_ciphertext[1..].to_vec(), ml_kem_ciphertext],
+            ),
+            Err(Error::InvalidCiphertextLength { .. })
+        ));
+    }
+}
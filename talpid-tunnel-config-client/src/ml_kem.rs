@@ -0,0 +1,52 @@
+use ml_kem::{kem::Decapsulate, EncodedSizeUser, KemCore, MlKem1024};
+
+/// Name of the ML-KEM variant, using the same format as liboqs.
+pub const ALGORITHM_NAME: &str = "ML-KEM-1024";
+
+/// Size of an ML-KEM-1024 ciphertext in bytes.
+pub const CIPHERTEXT_LEN: usize = 1568;
+
+pub type DecapsulationKey = <MlKem1024 as KemCore>::DecapsulationKey;
+pub type EncapsulationKey = <MlKem1024 as KemCore>::EncapsulationKey;
+
+pub fn generate_keys() -> (EncapsulationKey, DecapsulationKey) {
+    let (decapsulation_key, encapsulation_key) = MlKem1024::generate(&mut rand::thread_rng());
+    (encapsulation_key, decapsulation_key)
+}
+
+/// Returns the serialized form of `key`, suitable for sending to the relay.
+pub fn encode_public_key(key: &EncapsulationKey) -> Vec<u8> {
+    key.as_bytes().to_vec()
+}
+
+/// Decapsulates `ciphertext`. Returns `None` if the ciphertext does not have the length of an
+/// ML-KEM-1024 ciphertext.
+pub fn decapsulate(secret: &DecapsulationKey, ciphertext: &[u8]) -> Option<[u8; 32]> {
+    let ciphertext = ml_kem::Ciphertext::<MlKem1024>::try_from(ciphertext).ok()?;
+    // Decapsulation cannot fail. An invalid ciphertext results in a random shared secret
+    // (implicit rejection).
+    let shared_secret = secret
+        .decapsulate(&ciphertext)
+        .expect("ML-KEM decapsulation failed");
+    Some(shared_secret.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ml_kem::kem::Encapsulate;
+
+    #[test]
+    fn test_encapsulate_decapsulate() {
+        let (public_key, secret_key) = generate_keys();
+
+        let (ciphertext, shared_secret) = public_key.encapsulate(&mut rand::thread_rng()).unwrap();
+        assert_eq!(ciphertext.len(), CIPHERTEXT_LEN);
+        assert_eq!(
+            decapsulate(&secret_key, &ciphertext),
+            Some(<[u8; 32]>::from(shared_secret))
+        );
+
+        assert_eq!(decapsulate(&secret_key, &ciphertext[1..]), None);
+    }
+}
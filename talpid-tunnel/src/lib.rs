@@ -46,6 +46,10 @@ pub struct TunnelMetadata {
     pub ipv4_gateway: Ipv4Addr,
     /// The IP to the IPv6 default gateway on the tunnel interface.
     pub ipv6_gateway: Option<Ipv6Addr>,
+    /// The network namespace that the tunnel interface lives in, if it is not the one of the
+    /// daemon.
+    #[cfg(target_os = "linux")]
+    pub network_namespace: Option<String>,
 }
 
 /// Possible events from the VPN tunnel and the child process managing it.
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::SocketAddr};
+
+/// The kind of traffic sent by a leak test probe.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeKind {
+    /// A DNS query sent outside the tunnel.
+    Dns,
+    /// An IPv4 datagram sent outside the tunnel.
+    Ipv4,
+    /// An IPv6 datagram sent outside the tunnel.
+    Ipv6,
+    /// A datagram sent to a host on the local network.
+    Lan,
+}
+
+impl fmt::Display for ProbeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeKind::Dns => "DNS".fmt(f),
+            ProbeKind::Ipv4 => "IPv4".fmt(f),
+            ProbeKind::Ipv6 => "IPv6".fmt(f),
+            ProbeKind::Lan => "LAN".fmt(f),
+        }
+    }
+}
+
+/// What happened when a probe was sent.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeOutcome {
+    /// The packet was dropped by the firewall before leaving the host.
+    Blocked,
+    /// The packet left the host.
+    Sent,
+    /// The probe could not be sent for some other reason, such as a missing route.
+    Error(String),
+}
+
+/// The result of sending a single probe outside the tunnel.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub kind: ProbeKind,
+    pub target: SocketAddr,
+    /// Whether the firewall is expected to drop the probe.
+    pub should_block: bool,
+    pub outcome: ProbeOutcome,
+}
+
+impl ProbeResult {
+    /// Returns whether the firewall treated the probe as expected, or `None` if the probe could
+    /// not be sent at all.
+    pub fn passed(&self) -> Option<bool> {
+        match self.outcome {
+            ProbeOutcome::Blocked => Some(self.should_block),
+            ProbeOutcome::Sent => Some(!self.should_block),
+            ProbeOutcome::Error(_) => None,
+        }
+    }
+}
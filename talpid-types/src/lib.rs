@@ -4,12 +4,16 @@ use std::{error::Error, fmt, fmt::Write};
 
 #[cfg(target_os = "android")]
 pub mod android;
+pub mod leak_test;
 pub mod net;
 pub mod tunnel;
 
 #[cfg(target_os = "linux")]
 pub mod cgroup;
 
+#[cfg(target_os = "linux")]
+pub mod netns;
+
 /// Used to generate string representations of error chains.
 pub trait ErrorExt {
     /// Creates a string representation of the entire error chain.
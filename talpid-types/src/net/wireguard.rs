@@ -8,6 +8,7 @@ use std::{
     cmp, fmt,
     hash::{Hash, Hasher},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
 };
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
@@ -58,10 +59,10 @@ pub struct PeerConfig {
     pub allowed_ips: Vec<IpNetwork>,
     /// IP address of the WireGuard server.
     pub endpoint: SocketAddr,
-    /// Preshared key (PSK). The PSK should never be persisted, so it does not serialize
-    /// or deserialize. A PSK is only used with quantum-resistant tunnels and are then
-    /// ephemeral and living in memory only.
-    #[serde(skip)]
+    /// Preshared key (PSK). PSKs of quantum-resistant tunnels are ephemeral and only live in
+    /// the tunnel config, so the only PSK that is ever serialized is one that was configured
+    /// for a custom relay.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub psk: Option<PresharedKey>,
 }
 
@@ -89,11 +90,25 @@ pub struct TunnelOptions {
     pub mtu: Option<u16>,
     /// Obtain a PSK using the relay config client.
     pub use_pq_safe_psk: bool,
+    /// Renegotiate the PSK at this interval while the tunnel is up. The PSK is only negotiated
+    /// once per connection if this is `None`.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub pq_psk_rotation_interval: Option<Duration>,
     /// Temporary switch for wireguard-nt
     #[cfg(windows)]
     #[serde(default = "default_wgnt_setting")]
     #[serde(rename = "wireguard_nt")]
     pub use_wireguard_nt: bool,
+    /// Use the built-in userspace implementation (boringtun) instead of kernel WireGuard or
+    /// wireguard-go.
+    #[cfg(target_os = "linux")]
+    #[serde(rename = "boringtun")]
+    pub use_boringtun: bool,
+    /// Move the tunnel interface into a dedicated network namespace, so that only programs
+    /// running inside of it use the tunnel.
+    #[cfg(target_os = "linux")]
+    #[serde(rename = "network_namespace")]
+    pub use_network_namespace: bool,
 }
 
 #[cfg(windows)]
@@ -107,8 +122,13 @@ impl Default for TunnelOptions {
         Self {
             mtu: None,
             use_pq_safe_psk: false,
+            pq_psk_rotation_interval: None,
             #[cfg(windows)]
             use_wireguard_nt: default_wgnt_setting(),
+            #[cfg(target_os = "linux")]
+            use_boringtun: false,
+            #[cfg(target_os = "linux")]
+            use_network_namespace: false,
         }
     }
 }
@@ -135,6 +155,10 @@ impl PrivateKey {
     pub fn to_base64(&self) -> String {
         base64::encode(self.0.to_bytes())
     }
+
+    pub fn from_base64(key: &str) -> Result<Self, InvalidKeyError> {
+        decode_key(key).map(From::from)
+    }
 }
 
 impl From<[u8; 32]> for PrivateKey {
@@ -200,13 +224,7 @@ impl PublicKey {
     }
 
     pub fn from_base64(key: &str) -> Result<Self, InvalidKeyError> {
-        let bytes = base64::decode(key).map_err(|_| InvalidKeyError(()))?;
-        if bytes.len() != 32 {
-            return Err(InvalidKeyError(()));
-        }
-        let mut key = [0u8; 32];
-        key.copy_from_slice(&bytes);
-        Ok(From::from(key))
+        decode_key(key).map(From::from)
     }
 }
 
@@ -277,6 +295,14 @@ impl PresharedKey {
     pub fn as_bytes(&self) -> &[u8; 32] {
         &self.0
     }
+
+    pub fn to_base64(&self) -> String {
+        base64::encode(self.as_bytes())
+    }
+
+    pub fn from_base64(key: &str) -> Result<Self, InvalidKeyError> {
+        decode_key(key).map(|key| PresharedKey(Box::new(key)))
+    }
 }
 
 impl From<Box<[u8; 32]>> for PresharedKey {
@@ -285,12 +311,40 @@ impl From<Box<[u8; 32]>> for PresharedKey {
     }
 }
 
+impl Serialize for PresharedKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_key(self.as_bytes(), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PresharedKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_key::<D, [u8; 32]>(deserializer).map(|key| PresharedKey(Box::new(key)))
+    }
+}
+
 impl fmt::Debug for PresharedKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", &base64::encode(self.as_bytes()))
     }
 }
 
+fn decode_key(key: &str) -> Result<[u8; 32], InvalidKeyError> {
+    let bytes = base64::decode(key).map_err(|_| InvalidKeyError(()))?;
+    if bytes.len() != 32 {
+        return Err(InvalidKeyError(()));
+    }
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&bytes);
+    Ok(key)
+}
+
 fn serialize_key<S>(key: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+/// Name of the network namespace that holds the tunnel interface when namespace isolation is
+/// enabled.
+pub const NETWORK_NAMESPACE_NAME: &str = "mullvad";
+
+/// Directory where named network namespaces are bind-mounted, as used by `ip netns`.
+const NETNS_RUN_DIR: &str = "/var/run/netns";
+
+/// Directory containing per-namespace configuration files, as used by `ip netns exec`.
+const NETNS_ETC_DIR: &str = "/etc/netns";
+
+/// Returns the path of the bind mount that keeps the named network namespace alive.
+pub fn namespace_path(name: &str) -> PathBuf {
+    PathBuf::from(NETNS_RUN_DIR).join(name)
+}
+
+/// Returns the path of the `resolv.conf` that processes in the named network namespace should
+/// see in place of `/etc/resolv.conf`.
+pub fn resolv_conf_path(name: &str) -> PathBuf {
+    PathBuf::from(NETNS_ETC_DIR).join(name).join("resolv.conf")
+}
@@ -6,6 +6,10 @@ fn main() {
     declare_libs_dir("../dist-assets/binaries");
     declare_libs_dir("../build/lib");
 
+    if target_os == "linux" && env::var_os("CARGO_FEATURE_WIREGUARD_GO").is_none() {
+        return;
+    }
+
     let link_type = match target_os.as_str() {
         "android" => "",
         "linux" | "macos" => "=static",
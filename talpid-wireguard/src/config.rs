@@ -2,6 +2,7 @@ use std::{
     borrow::Cow,
     ffi::CString,
     net::{Ipv4Addr, Ipv6Addr},
+    time::Duration,
 };
 use talpid_types::net::{obfuscation::ObfuscatorConfig, wireguard, GenericTunnelOptions};
 
@@ -12,6 +13,9 @@ pub struct Config {
     pub tunnel: wireguard::TunnelConfig,
     /// List of peer configurations
     pub peers: Vec<wireguard::PeerConfig>,
+    /// Private key used with the entry peer of a multihop tunnel instead of `tunnel.private_key`.
+    /// This is only set for tunnel implementations that support a separate key per peer.
+    pub entry_private_key: Option<wireguard::PrivateKey>,
     /// IPv4 gateway
     pub ipv4_gateway: Ipv4Addr,
     /// IPv6 gateway
@@ -27,8 +31,16 @@ pub struct Config {
     /// Temporary switch for wireguard-nt
     #[cfg(target_os = "windows")]
     pub use_wireguard_nt: bool,
+    /// Use the boringtun userspace implementation
+    #[cfg(target_os = "linux")]
+    pub use_boringtun: bool,
+    /// Network namespace to move the tunnel interface into
+    #[cfg(target_os = "linux")]
+    pub network_namespace: Option<String>,
     /// Obfuscator config to be used for reaching the relay.
     pub obfuscator_config: Option<ObfuscatorConfig>,
+    /// Interval at which to renegotiate the PQ-safe PSK, if any
+    pub pq_psk_rotation_interval: Option<Duration>,
 }
 
 #[cfg(not(target_os = "android"))]
@@ -114,6 +126,7 @@ impl Config {
         Ok(Config {
             tunnel,
             peers,
+            entry_private_key: None,
             ipv4_gateway: connection_config.ipv4_gateway,
             ipv6_gateway,
             mtu,
@@ -123,10 +136,25 @@ impl Config {
             enable_ipv6: generic_options.enable_ipv6,
             #[cfg(target_os = "windows")]
             use_wireguard_nt: wg_options.use_wireguard_nt,
+            #[cfg(target_os = "linux")]
+            use_boringtun: wg_options.use_boringtun,
+            #[cfg(target_os = "linux")]
+            network_namespace: wg_options
+                .use_network_namespace
+                .then(|| talpid_types::netns::NETWORK_NAMESPACE_NAME.to_string()),
             obfuscator_config,
+            pq_psk_rotation_interval: wg_options.pq_psk_rotation_interval,
         })
     }
 
+    /// Returns the private key used with the peer at `index`.
+    pub fn peer_private_key(&self, index: usize) -> &wireguard::PrivateKey {
+        match &self.entry_private_key {
+            Some(entry_private_key) if index == 0 && self.peers.len() > 1 => entry_private_key,
+            _ => &self.tunnel.private_key,
+        }
+    }
+
     /// Returns a CString with the appropriate config for WireGuard-go
     // TODO: Consider outputting both overriding and additive configs
     pub fn to_userspace_format(&self) -> CString {
@@ -16,7 +16,7 @@ use std::io;
 use std::{
     borrow::Cow,
     convert::Infallible,
-    net::IpAddr,
+    net::{IpAddr, Ipv4Addr},
     path::Path,
     pin::Pin,
     sync::{mpsc as sync_mpsc, Arc, Mutex},
@@ -32,8 +32,9 @@ use talpid_tunnel::{tun_provider::TunProvider, TunnelArgs, TunnelEvent, TunnelMe
 use talpid_types::BoxedError;
 use talpid_types::{
     net::{
-        obfuscation::ObfuscatorConfig, wireguard::PublicKey, AllowedTunnelTraffic, Endpoint,
-        TransportProtocol,
+        obfuscation::ObfuscatorConfig,
+        wireguard::{PresharedKey, PrivateKey, PublicKey},
+        AllowedTunnelTraffic, Endpoint, TransportProtocol,
     },
     ErrorExt,
 };
@@ -45,15 +46,28 @@ use tunnel_obfuscation::{
 /// WireGuard config data-types
 pub mod config;
 mod connectivity_check;
+#[cfg_attr(
+    all(target_os = "linux", not(feature = "wireguard-go")),
+    allow(dead_code)
+)]
 mod logging;
+/// Network namespace used to isolate the tunnel interface
+#[cfg(target_os = "linux")]
+pub mod netns;
 mod ping_monitor;
 mod stats;
+#[cfg(target_os = "linux")]
+mod wireguard_boringtun;
+#[cfg(any(not(target_os = "linux"), feature = "wireguard-go"))]
 mod wireguard_go;
 #[cfg(target_os = "linux")]
 pub(crate) mod wireguard_kernel;
 #[cfg(windows)]
 mod wireguard_nt;
 
+#[cfg(target_os = "linux")]
+use self::wireguard_boringtun::BoringTunnel;
+#[cfg(any(not(target_os = "linux"), feature = "wireguard-go"))]
 use self::wireguard_go::WgGoTunnel;
 
 type Result<T> = std::result::Result<T, Error>;
@@ -100,6 +114,16 @@ pub enum Error {
     #[cfg(target_os = "windows")]
     #[error(display = "Failed to set IP addresses on WireGuard interface")]
     SetIpAddressesError(#[error(source)] talpid_windows_net::Error),
+
+    /// Failed to set up the network namespace for the tunnel interface
+    #[cfg(target_os = "linux")]
+    #[error(display = "Failed to set up network namespace")]
+    NetworkNamespace(#[error(source)] netns::Error),
+
+    /// PQ PSK negotiation requires the tunnel interface to be in the namespace of the daemon
+    #[cfg(target_os = "linux")]
+    #[error(display = "Quantum-resistant tunnels cannot be used with network namespace isolation")]
+    NetworkNamespacePskUnsupported,
 }
 
 /// Spawns and monitors a wireguard tunnel
@@ -230,7 +254,7 @@ impl WireguardMonitor {
             + 'static,
     >(
         mut config: Config,
-        psk_negotiation: Option<PublicKey>,
+        psk_negotiation: bool,
         log_path: Option<&Path>,
         args: TunnelArgs<'_, F>,
     ) -> Result<WireguardMonitor> {
@@ -240,6 +264,22 @@ impl WireguardMonitor {
             config.peers.iter().map(|peer| peer.endpoint.ip()).collect();
         let (close_msg_sender, close_msg_receiver) = sync_mpsc::channel();
 
+        #[cfg(target_os = "linux")]
+        let netns = config
+            .network_namespace
+            .as_deref()
+            .map(netns::open_or_create)
+            .transpose()
+            .map_err(Error::NetworkNamespace)?;
+        #[cfg(target_os = "linux")]
+        if netns.is_some() && psk_negotiation {
+            return Err(Error::NetworkNamespacePskUnsupported);
+        }
+        #[cfg(target_os = "linux")]
+        let isolated = netns.is_some();
+        #[cfg(not(target_os = "linux"))]
+        let isolated = false;
+
         let obfuscator = args.runtime.block_on(maybe_create_obfuscator(
             &mut config,
             close_msg_sender.clone(),
@@ -250,10 +290,14 @@ impl WireguardMonitor {
 
         let tunnel = Self::open_tunnel(
             args.runtime.clone(),
-            &Self::patch_allowed_ips(&config, psk_negotiation.is_some()),
+            &Self::patch_allowed_ips(&config, psk_negotiation),
             log_path,
             args.resource_dir,
             args.tun_provider.clone(),
+            #[cfg(target_os = "linux")]
+            netns.as_ref(),
+            #[cfg(target_os = "linux")]
+            close_msg_sender.clone(),
             #[cfg(target_os = "windows")]
             args.route_manager.clone(),
             #[cfg(target_os = "windows")]
@@ -282,14 +326,31 @@ impl WireguardMonitor {
         };
 
         let gateway = config.ipv4_gateway;
-        let mut connectivity_monitor = connectivity_check::ConnectivityMonitor::new(
-            gateway,
+        let create_connectivity_monitor = {
             #[cfg(any(target_os = "macos", target_os = "linux"))]
-            iface_name.clone(),
-            Arc::downgrade(&monitor.tunnel),
-            pinger_rx,
-        )
-        .map_err(Error::ConnectivityMonitorError)?;
+            let iface_name = iface_name.clone();
+            let tunnel_handle = Arc::downgrade(&monitor.tunnel);
+            move || {
+                connectivity_check::ConnectivityMonitor::new(
+                    gateway,
+                    #[cfg(any(target_os = "macos", target_os = "linux"))]
+                    iface_name,
+                    tunnel_handle,
+                    pinger_rx,
+                )
+            }
+        };
+        // The pinger socket must be created in the same namespace as the tunnel interface
+        #[cfg(target_os = "linux")]
+        let connectivity_monitor = match &netns {
+            Some(netns) => netns::run_in_namespace(netns, create_connectivity_monitor)
+                .map_err(Error::NetworkNamespace)?,
+            None => create_connectivity_monitor(),
+        };
+        #[cfg(not(target_os = "linux"))]
+        let connectivity_monitor = create_connectivity_monitor();
+        let mut connectivity_monitor =
+            connectivity_monitor.map_err(Error::ConnectivityMonitorError)?;
 
         let metadata = Self::tunnel_metadata(&iface_name, &config);
         let tunnel = monitor.tunnel.clone();
@@ -301,7 +362,7 @@ impl WireguardMonitor {
             Self::add_device_ip_addresses(&iface_name, &config.tunnel.addresses, setup_done_rx)
                 .await?;
 
-            let allowed_traffic = if psk_negotiation.is_some() {
+            let allowed_traffic = if psk_negotiation {
                 AllowedTunnelTraffic::Only(Endpoint::new(
                     config.ipv4_gateway,
                     talpid_tunnel_config_client::CONFIG_SERVICE_PORT,
@@ -312,33 +373,41 @@ impl WireguardMonitor {
             };
             (on_event)(TunnelEvent::InterfaceUp(metadata.clone(), allowed_traffic)).await;
 
-            // Add non-default routes before establishing the tunnel.
-            #[cfg(target_os = "linux")]
-            args.route_manager
-                .create_routing_rules(config.enable_ipv6)
-                .await
-                .map_err(Error::SetupRoutingError)
-                .map_err(CloseMsg::SetupError)?;
+            // Add non-default routes before establishing the tunnel. No routes are needed in the
+            // namespace of the daemon if the tunnel interface lives in a separate one.
+            if !isolated {
+                #[cfg(target_os = "linux")]
+                args.route_manager
+                    .create_routing_rules(config.enable_ipv6)
+                    .await
+                    .map_err(Error::SetupRoutingError)
+                    .map_err(CloseMsg::SetupError)?;
 
-            let routes = Self::get_pre_tunnel_routes(&iface_name, &config)
-                .chain(Self::get_endpoint_routes(&endpoint_addrs))
-                .collect();
-            args.route_manager
-                .add_routes(routes)
-                .await
-                .map_err(Error::SetupRoutingError)
-                .map_err(CloseMsg::SetupError)?;
+                let routes = Self::get_pre_tunnel_routes(&iface_name, &config)
+                    .chain(Self::get_endpoint_routes(&endpoint_addrs))
+                    .collect();
+                args.route_manager
+                    .add_routes(routes)
+                    .await
+                    .map_err(Error::SetupRoutingError)
+                    .map_err(CloseMsg::SetupError)?;
+            }
 
-            if let Some(pubkey) = psk_negotiation {
+            if psk_negotiation {
+                let timeout = Self::psk_exchange_timeout(args.retry_attempt);
+                let gateway = config.ipv4_gateway;
                 Self::perform_psk_negotiation(
-                    tunnel,
-                    obfs_handle,
-                    obfs_close_sender,
-                    args.retry_attempt,
-                    pubkey,
+                    &tunnel,
+                    &obfs_handle,
+                    &obfs_close_sender,
                     &mut config,
+                    true,
+                    |current_pubkey, ephemeral_pubkey| {
+                        Self::negotiate_psk(timeout, gateway, current_pubkey, ephemeral_pubkey)
+                    },
                 )
-                .await?;
+                .await
+                .map_err(|error| error.close_msg)?;
                 (on_event)(TunnelEvent::InterfaceUp(
                     metadata.clone(),
                     AllowedTunnelTraffic::All,
@@ -366,24 +435,42 @@ impl WireguardMonitor {
             .unwrap()?;
 
             // Add any default route(s) that may exist.
-            args.route_manager
-                .add_routes(Self::get_post_tunnel_routes(&iface_name, &config).collect())
-                .await
-                .map_err(Error::SetupRoutingError)
-                .map_err(CloseMsg::SetupError)?;
+            if !isolated {
+                args.route_manager
+                    .add_routes(Self::get_post_tunnel_routes(&iface_name, &config).collect())
+                    .await
+                    .map_err(Error::SetupRoutingError)
+                    .map_err(CloseMsg::SetupError)?;
+            }
 
             (on_event)(TunnelEvent::Up(metadata)).await;
 
-            tokio::task::spawn_blocking(move || {
+            let connectivity_monitor_fut = tokio::task::spawn_blocking(move || {
                 if let Err(error) = connectivity_monitor.run() {
                     log::error!(
                         "{}",
                         error.display_chain_with_msg("Connectivity monitor failed")
                     );
                 }
-            })
-            .await
-            .unwrap();
+            });
+
+            let rotation_interval = config.pq_psk_rotation_interval.filter(|_| psk_negotiation);
+            if let Some(interval) = rotation_interval {
+                let rotation_fut = Self::rotate_psk_periodically(
+                    tunnel,
+                    obfs_handle,
+                    obfs_close_sender,
+                    config,
+                    interval,
+                );
+                futures::pin_mut!(rotation_fut);
+                match futures::future::select(connectivity_monitor_fut, rotation_fut).await {
+                    futures::future::Either::Left((result, _)) => result.unwrap(),
+                    futures::future::Either::Right((result, _)) => return result,
+                }
+            } else {
+                connectivity_monitor_fut.await.unwrap();
+            }
 
             Err::<Infallible, CloseMsg>(CloseMsg::PingErr)
         };
@@ -472,27 +559,231 @@ impl WireguardMonitor {
         Ok(())
     }
 
-    async fn perform_psk_negotiation(
-        tunnel: Arc<Mutex<Option<Box<dyn Tunnel>>>>,
-        obfuscation_handle: Arc<AsyncMutex<Option<ObfuscatorHandle>>>,
-        obfs_close_sender: sync_mpsc::Sender<CloseMsg>,
-        retry_attempt: u32,
-        current_pubkey: PublicKey,
-        config: &mut Config,
-    ) -> std::result::Result<(), CloseMsg> {
-        log::debug!("Performing PQ-safe PSK exchange");
-
-        let timeout = std::cmp::min(
+    fn psk_exchange_timeout(retry_attempt: u32) -> Duration {
+        std::cmp::min(
             MAX_PSK_EXCHANGE_TIMEOUT,
             INITIAL_PSK_EXCHANGE_TIMEOUT
                 .saturating_mul(PSK_EXCHANGE_TIMEOUT_MULTIPLIER.saturating_pow(retry_attempt)),
+        )
+    }
+
+    /// Negotiates a PQ-safe PSK with every relay in `config`, and switches the tunnel over to a
+    /// new ephemeral key. The relays are asked to replace the key that the tunnel is currently
+    /// using, so this works both for the initial exchange and for rotating an ephemeral key.
+    /// For multihop, the exit relay is reached through the entry relay, after which the gateway
+    /// is temporarily routed to the entry relay to negotiate its PSK. Each hop gets its own
+    /// ephemeral key if the tunnel implementation supports it, so that the relays cannot link
+    /// the hops by their keys.
+    ///
+    /// `config` is updated as soon as a relay has accepted a new key. If the entry relay fails
+    /// after the exit relay has accepted its key, the tunnel keeps the current session with the
+    /// entry relay, unless both hops have to share a key. In that case, the tunnel must be
+    /// reconnected.
+    ///
+    /// If `initial_exchange` is set, only traffic to the gateway is routed through the tunnel
+    /// until the negotiation is done, and the obfuscator is restarted afterwards. Rotations keep
+    /// the obfuscator running, since the current session uses its endpoint.
+    async fn perform_psk_negotiation<N, F>(
+        tunnel: &Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+        obfuscation_handle: &Arc<AsyncMutex<Option<ObfuscatorHandle>>>,
+        obfs_close_sender: &sync_mpsc::Sender<CloseMsg>,
+        config: &mut Config,
+        initial_exchange: bool,
+        negotiate: N,
+    ) -> std::result::Result<(), PskNegotiationError>
+    where
+        N: Fn(PublicKey, PublicKey) -> F,
+        F: Future<Output = std::result::Result<PresharedKey, CloseMsg>>,
+    {
+        log::debug!("Performing PQ-safe PSK exchange");
+
+        let ephemeral_private_key = PrivateKey::new_from_random();
+        let exit_psk = negotiate(
+            config.tunnel.private_key.public_key(),
+            ephemeral_private_key.public_key(),
+        )
+        .await
+        .map_err(PskNegotiationError::session_intact)?;
+
+        // The exit relay only accepts the new key from now on
+        let multihop = config.peers.len() > 1;
+        let separate_entry_key = multihop && Self::supports_peer_private_keys(tunnel);
+        let previous_entry_private_key = config.peer_private_key(0).clone();
+        config.tunnel.private_key = ephemeral_private_key;
+        config.entry_private_key = separate_entry_key.then(|| previous_entry_private_key.clone());
+        if let Some(exit_peer) = config.peers.last_mut() {
+            exit_peer.psk = Some(exit_psk);
+        }
+        log::trace!(
+            "Ephemeral pubkey: {}",
+            config.tunnel.private_key.public_key()
         );
 
-        let (private_key, psk) = tokio::time::timeout(
+        if multihop {
+            log::debug!("Performing PQ-safe PSK exchange with entry relay");
+            let mut entry_config =
+                Self::entry_negotiation_config(&Self::patch_allowed_ips(config, initial_exchange));
+            if !separate_entry_key {
+                // Both hops share a key, so keep using the one that the entry relay knows
+                entry_config.tunnel.private_key = previous_entry_private_key.clone();
+            }
+            if let Err(error) = Self::set_tunnel_config(tunnel, entry_config).await {
+                let _ = Self::route_gateway_to_exit(tunnel, config, initial_exchange).await;
+                return Err(PskNegotiationError::session_lost(error));
+            }
+
+            let entry_ephemeral_private_key = if separate_entry_key {
+                PrivateKey::new_from_random()
+            } else {
+                config.tunnel.private_key.clone()
+            };
+            let entry_psk = match negotiate(
+                previous_entry_private_key.public_key(),
+                entry_ephemeral_private_key.public_key(),
+            )
+            .await
+            {
+                Ok(entry_psk) => entry_psk,
+                Err(error) => {
+                    // With a separate entry key, the current session with the entry relay can
+                    // be kept once the gateway is routed back to the exit relay
+                    let restored = Self::route_gateway_to_exit(tunnel, config, initial_exchange)
+                        .await
+                        .is_ok();
+                    return Err(if separate_entry_key && restored {
+                        PskNegotiationError::session_intact(error)
+                    } else {
+                        PskNegotiationError::session_lost(error)
+                    });
+                }
+            };
+
+            if separate_entry_key {
+                log::trace!(
+                    "Entry ephemeral pubkey: {}",
+                    entry_ephemeral_private_key.public_key()
+                );
+                config.entry_private_key = Some(entry_ephemeral_private_key);
+            }
+            config.peers[0].psk = Some(entry_psk);
+        }
+
+        if initial_exchange {
+            // Restart the obfuscation server
+            let mut obfs_guard = obfuscation_handle.lock().await;
+            if let Some(obfs_abort_handle) = obfs_guard.take() {
+                obfs_abort_handle.abort();
+                *obfs_guard = maybe_create_obfuscator(config, obfs_close_sender.clone())
+                    .await
+                    .map_err(CloseMsg::SetupError)
+                    .map_err(PskNegotiationError::session_lost)?;
+            }
+        }
+
+        Self::set_tunnel_config(tunnel, config.clone())
+            .await
+            .map_err(PskNegotiationError::session_lost)
+    }
+
+    /// Undoes [`Self::entry_negotiation_config`] by applying `config` with the gateway routed to
+    /// the exit relay again.
+    async fn route_gateway_to_exit(
+        tunnel: &Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+        config: &Config,
+        gateway_only: bool,
+    ) -> std::result::Result<(), CloseMsg> {
+        Self::set_tunnel_config(
+            tunnel,
+            Self::patch_allowed_ips(config, gateway_only).into_owned(),
+        )
+        .await
+    }
+
+    /// Replaces the PSKs and the ephemeral key every `interval`, without tearing down the tunnel.
+    /// This only returns if the tunnel must be reconnected after a failed rotation.
+    async fn rotate_psk_periodically(
+        tunnel: Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+        obfuscation_handle: Arc<AsyncMutex<Option<ObfuscatorHandle>>>,
+        obfs_close_sender: sync_mpsc::Sender<CloseMsg>,
+        mut config: Config,
+        interval: Duration,
+    ) -> std::result::Result<Infallible, CloseMsg> {
+        let gateway = config.ipv4_gateway;
+        loop {
+            tokio::time::sleep(interval).await;
+            Self::rotate_psk(
+                &tunnel,
+                &obfuscation_handle,
+                &obfs_close_sender,
+                &mut config,
+                |current_pubkey, ephemeral_pubkey| {
+                    Self::negotiate_psk(
+                        MAX_PSK_EXCHANGE_TIMEOUT,
+                        gateway,
+                        current_pubkey,
+                        ephemeral_pubkey,
+                    )
+                },
+            )
+            .await?;
+        }
+    }
+
+    /// Negotiates a new ephemeral key and new PSKs. If that fails, the tunnel keeps using the
+    /// current session with every relay that did not accept a new key, until the next attempt.
+    /// An error is only returned if the tunnel must be reconnected.
+    async fn rotate_psk<N, F>(
+        tunnel: &Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+        obfuscation_handle: &Arc<AsyncMutex<Option<ObfuscatorHandle>>>,
+        obfs_close_sender: &sync_mpsc::Sender<CloseMsg>,
+        config: &mut Config,
+        negotiate: N,
+    ) -> std::result::Result<(), CloseMsg>
+    where
+        N: Fn(PublicKey, PublicKey) -> F,
+        F: Future<Output = std::result::Result<PresharedKey, CloseMsg>>,
+    {
+        log::debug!("Rotating PQ-safe PSK");
+        let result = Self::perform_psk_negotiation(
+            tunnel,
+            obfuscation_handle,
+            obfs_close_sender,
+            config,
+            false,
+            negotiate,
+        )
+        .await;
+        match result {
+            Ok(()) => Ok(()),
+            Err(error) if error.session_intact => {
+                let msg = "Failed to rotate PQ-safe PSK. Keeping the current session";
+                match error.close_msg {
+                    CloseMsg::SetupError(error) => {
+                        log::warn!("{}", error.display_chain_with_msg(msg))
+                    }
+                    _ => log::warn!("{}", msg),
+                }
+                Ok(())
+            }
+            Err(error) => {
+                log::error!("Failed to rotate PQ-safe PSK. Reconnecting");
+                Err(error.close_msg)
+            }
+        }
+    }
+
+    async fn negotiate_psk(
+        timeout: Duration,
+        gateway: Ipv4Addr,
+        current_pubkey: PublicKey,
+        ephemeral_pubkey: PublicKey,
+    ) -> std::result::Result<PresharedKey, CloseMsg> {
+        tokio::time::timeout(
             timeout,
             talpid_tunnel_config_client::push_pq_key(
-                IpAddr::V4(config.ipv4_gateway),
-                config.tunnel.private_key.public_key(),
+                IpAddr::V4(gateway),
+                current_pubkey,
+                ephemeral_pubkey,
             ),
         )
         .await
@@ -501,42 +792,45 @@ impl WireguardMonitor {
             CloseMsg::PskNegotiationTimeout
         })?
         .map_err(Error::PskNegotiationError)
-        .map_err(CloseMsg::SetupError)?;
-
-        config.tunnel.private_key = private_key;
+        .map_err(CloseMsg::SetupError)
+    }
 
-        for peer in &mut config.peers {
-            if current_pubkey == peer.public_key {
-                peer.psk = Some(psk);
-                break;
-            }
+    /// Returns a copy of `config` where the gateway is routed to the entry relay rather than the
+    /// exit relay.
+    fn entry_negotiation_config(config: &Config) -> Config {
+        let mut entry_config = config.clone();
+        let gateway_net = ipnetwork::IpNetwork::from(IpAddr::from(config.ipv4_gateway));
+        for peer in entry_config.peers.iter_mut().skip(1) {
+            peer.allowed_ips
+                .retain(|allowed_ip| *allowed_ip != gateway_net);
         }
+        entry_config.peers[0].allowed_ips.push(gateway_net);
+        entry_config
+    }
 
-        log::trace!(
-            "Ephemeral pubkey: {}",
-            config.tunnel.private_key.public_key()
-        );
-
-        // Restart the obfuscation server
-        let mut obfs_guard = obfuscation_handle.lock().await;
-        if let Some(obfs_abort_handle) = obfs_guard.take() {
-            obfs_abort_handle.abort();
-            *obfs_guard = maybe_create_obfuscator(config, obfs_close_sender)
-                .await
-                .map_err(CloseMsg::SetupError)?;
-        }
+    fn supports_peer_private_keys(tunnel: &Arc<Mutex<Option<Box<dyn Tunnel>>>>) -> bool {
+        tunnel
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|tunnel| tunnel.supports_peer_private_keys())
+            .unwrap_or(false)
+    }
 
+    async fn set_tunnel_config(
+        tunnel: &Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+        config: Config,
+    ) -> std::result::Result<(), CloseMsg> {
         let set_config_future = tunnel
             .lock()
             .unwrap()
             .as_ref()
-            .map(|tunnel| tunnel.set_config(config.clone()));
+            .map(|tunnel| tunnel.set_config(config));
         if let Some(f) = set_config_future {
             f.await
                 .map_err(Error::TunnelError)
                 .map_err(CloseMsg::SetupError)?;
         }
-
         Ok(())
     }
 
@@ -547,9 +841,28 @@ impl WireguardMonitor {
         log_path: Option<&Path>,
         resource_dir: &Path,
         tun_provider: Arc<Mutex<TunProvider>>,
+        #[cfg(target_os = "linux")] netns: Option<&std::fs::File>,
+        #[cfg(target_os = "linux")] close_msg_sender: sync_mpsc::Sender<CloseMsg>,
         #[cfg(windows)] route_manager_handle: crate::routing::RouteManagerHandle,
         #[cfg(windows)] setup_done_tx: mpsc::Sender<std::result::Result<(), BoxedError>>,
     ) -> Result<Box<dyn Tunnel>> {
+        // There is no fallback in this case, since that would leave the tunnel interface outside
+        // of the namespace
+        #[cfg(target_os = "linux")]
+        if let Some(netns) = netns {
+            log::debug!("Using kernel WireGuard implementation in a network namespace");
+            let tunnel = wireguard_kernel::NetlinkTunnel::new_in_namespace(runtime, config, netns)
+                .map_err(TunnelError::NetworkNamespaceError)
+                .map_err(Error::TunnelError)?;
+            return Ok(Box::new(tunnel));
+        }
+
+        #[cfg(target_os = "linux")]
+        if config.use_boringtun {
+            log::debug!("Using boringtun userspace WireGuard implementation");
+            return Self::open_boringtun_tunnel(runtime, config, tun_provider, close_msg_sender);
+        }
+
         #[cfg(target_os = "linux")]
         if !*FORCE_USERSPACE_WIREGUARD {
             if will_nm_manage_dns() {
@@ -606,22 +919,50 @@ impl WireguardMonitor {
             }
         }
 
-        #[cfg(any(target_os = "linux", windows))]
-        log::debug!("Using userspace WireGuard implementation");
+        #[cfg(all(target_os = "linux", not(feature = "wireguard-go")))]
+        {
+            log::debug!("wireguard-go is unavailable, using boringtun userspace implementation");
+            Self::open_boringtun_tunnel(runtime, config, tun_provider, close_msg_sender)
+        }
+
+        #[cfg(any(not(target_os = "linux"), feature = "wireguard-go"))]
+        {
+            #[cfg(any(target_os = "linux", windows))]
+            log::debug!("Using userspace WireGuard implementation");
+            Ok(Box::new(
+                WgGoTunnel::start_tunnel(
+                    config,
+                    log_path,
+                    #[cfg(not(windows))]
+                    tun_provider,
+                    #[cfg(not(windows))]
+                    Self::get_tunnel_destinations(config).flat_map(Self::replace_default_prefixes),
+                    #[cfg(windows)]
+                    route_manager_handle,
+                    #[cfg(windows)]
+                    setup_done_tx,
+                    #[cfg(windows)]
+                    &runtime,
+                )
+                .map_err(Error::TunnelError)?,
+            ))
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn open_boringtun_tunnel(
+        runtime: tokio::runtime::Handle,
+        config: &Config,
+        tun_provider: Arc<Mutex<TunProvider>>,
+        close_msg_sender: sync_mpsc::Sender<CloseMsg>,
+    ) -> Result<Box<dyn Tunnel>> {
         Ok(Box::new(
-            WgGoTunnel::start_tunnel(
+            BoringTunnel::start_tunnel(
+                runtime,
                 config,
-                log_path,
-                #[cfg(not(windows))]
                 tun_provider,
-                #[cfg(not(windows))]
                 Self::get_tunnel_destinations(config).flat_map(Self::replace_default_prefixes),
-                #[cfg(windows)]
-                route_manager_handle,
-                #[cfg(windows)]
-                setup_done_tx,
-                #[cfg(windows)]
-                &runtime,
+                close_msg_sender,
             )
             .map_err(Error::TunnelError)?,
         ))
@@ -634,6 +975,8 @@ impl WireguardMonitor {
             Ok(CloseMsg::Stop) | Ok(CloseMsg::ObfuscatorExpired) => Ok(()),
             Ok(CloseMsg::SetupError(error)) => Err(error),
             Ok(CloseMsg::ObfuscatorFailed(error)) => Err(error),
+            #[cfg(target_os = "linux")]
+            Ok(CloseMsg::TunnelFailed(error)) => Err(error),
             Err(_) => Ok(()),
         };
 
@@ -782,6 +1125,32 @@ impl WireguardMonitor {
             ips: config.tunnel.addresses.clone(),
             ipv4_gateway: config.ipv4_gateway,
             ipv6_gateway: config.ipv6_gateway,
+            #[cfg(target_os = "linux")]
+            network_namespace: config.network_namespace.clone(),
+        }
+    }
+}
+
+/// Error returned when a PSK negotiation fails.
+struct PskNegotiationError {
+    close_msg: CloseMsg,
+    /// Whether the tunnel still uses keys that the relays accept, so that the current session
+    /// can be kept.
+    session_intact: bool,
+}
+
+impl PskNegotiationError {
+    fn session_intact(close_msg: CloseMsg) -> Self {
+        Self {
+            close_msg,
+            session_intact: true,
+        }
+    }
+
+    fn session_lost(close_msg: CloseMsg) -> Self {
+        Self {
+            close_msg,
+            session_intact: false,
         }
     }
 }
@@ -793,6 +1162,9 @@ enum CloseMsg {
     SetupError(Error),
     ObfuscatorExpired,
     ObfuscatorFailed(Error),
+    /// The tunnel stopped moving packets after it was set up.
+    #[cfg(target_os = "linux")]
+    TunnelFailed(Error),
 }
 
 pub(crate) trait Tunnel: Send {
@@ -803,6 +1175,11 @@ pub(crate) trait Tunnel: Send {
         &self,
         _config: Config,
     ) -> Pin<Box<dyn Future<Output = std::result::Result<(), TunnelError>> + Send>>;
+    /// Returns whether the entry peer can use a different private key than the other peers, see
+    /// [`Config::entry_private_key`].
+    fn supports_peer_private_keys(&self) -> bool {
+        false
+    }
 }
 
 /// Errors to be returned from WireGuard implementations, namely implementers of the Tunnel trait
@@ -875,6 +1252,16 @@ pub enum TunnelError {
     /// Failure to set up logging
     #[error(display = "Failed to set up logging")]
     LoggingError(#[error(source)] logging::Error),
+
+    /// Failed to set up the kernel WireGuard device in a network namespace.
+    #[cfg(target_os = "linux")]
+    #[error(display = "Failed to set up WireGuard device in network namespace")]
+    NetworkNamespaceError(#[error(source)] wireguard_kernel::Error),
+
+    /// Failed to set up the boringtun userspace tunnel.
+    #[cfg(target_os = "linux")]
+    #[error(display = "boringtun tunnel error")]
+    BoringtunError(#[error(source)] wireguard_boringtun::Error),
 }
 
 #[cfg(target_os = "linux")]
@@ -892,3 +1279,458 @@ fn will_nm_manage_dns() -> bool {
         })
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use talpid_types::net::wireguard;
+
+    struct MockTunnel {
+        configs: Arc<Mutex<Vec<Config>>>,
+        supports_peer_private_keys: bool,
+        /// Indices of the `set_config` calls that fail.
+        config_failures: &'static [usize],
+    }
+
+    impl Tunnel for MockTunnel {
+        fn get_interface_name(&self) -> String {
+            "wg-mock".to_owned()
+        }
+
+        fn stop(self: Box<Self>) -> std::result::Result<(), TunnelError> {
+            Ok(())
+        }
+
+        fn get_tunnel_stats(&self) -> std::result::Result<stats::StatsMap, TunnelError> {
+            Ok(stats::StatsMap::new())
+        }
+
+        fn set_config(
+            &self,
+            config: Config,
+        ) -> Pin<Box<dyn Future<Output = std::result::Result<(), TunnelError>> + Send>> {
+            let mut configs = self.configs.lock().unwrap();
+            configs.push(config);
+            let result = if self.config_failures.contains(&(configs.len() - 1)) {
+                Err(TunnelError::SetConfigError)
+            } else {
+                Ok(())
+            };
+            Box::pin(async move { result })
+        }
+
+        fn supports_peer_private_keys(&self) -> bool {
+            self.supports_peer_private_keys
+        }
+    }
+
+    /// Returns a mock tunnel and the configs that are applied to it.
+    fn mock_tunnel(
+        supports_peer_private_keys: bool,
+        config_failures: &'static [usize],
+    ) -> (Arc<Mutex<Option<Box<dyn Tunnel>>>>, Arc<Mutex<Vec<Config>>>) {
+        let configs = Arc::new(Mutex::new(vec![]));
+        let tunnel = MockTunnel {
+            configs: configs.clone(),
+            supports_peer_private_keys,
+            config_failures,
+        };
+        (Arc::new(Mutex::new(Some(Box::new(tunnel)))), configs)
+    }
+
+    type Exchanges = Arc<Mutex<Vec<(PublicKey, PublicKey)>>>;
+
+    /// Returns a PSK negotiator that records the current and ephemeral key of every exchange, and
+    /// fails the exchanges whose index is in `failures`.
+    fn mock_negotiator(
+        failures: &'static [usize],
+        exchanges: Exchanges,
+    ) -> impl Fn(
+        PublicKey,
+        PublicKey,
+    ) -> futures::future::Ready<std::result::Result<PresharedKey, CloseMsg>> {
+        move |current_pubkey, ephemeral_pubkey| {
+            let mut exchanges = exchanges.lock().unwrap();
+            exchanges.push((current_pubkey, ephemeral_pubkey));
+            let index = exchanges.len() - 1;
+            let result = if failures.contains(&index) {
+                Err(CloseMsg::PskNegotiationTimeout)
+            } else {
+                Ok(PresharedKey::from(Box::new([index as u8 + 1; 32])))
+            };
+            futures::future::ready(result)
+        }
+    }
+
+    /// Returns a multihop config with a separate entry key and PSKs from a previous exchange.
+    fn negotiated_multihop_config() -> Config {
+        let mut config = multihop_config("10.64.0.1".parse().unwrap());
+        config.peers[0].psk = Some(PresharedKey::from(Box::new([100u8; 32])));
+        config.peers[1].psk = Some(PresharedKey::from(Box::new([101u8; 32])));
+        config.entry_private_key = Some(PrivateKey::new_from_random());
+        config
+    }
+
+    fn peer(allowed_ips: &[&str]) -> wireguard::PeerConfig {
+        wireguard::PeerConfig {
+            public_key: PrivateKey::new_from_random().public_key(),
+            allowed_ips: allowed_ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+            endpoint: "192.0.2.1:51820".parse().unwrap(),
+            psk: None,
+        }
+    }
+
+    fn multihop_config(ipv4_gateway: Ipv4Addr) -> Config {
+        Config {
+            tunnel: wireguard::TunnelConfig {
+                private_key: PrivateKey::new_from_random(),
+                addresses: vec!["10.64.0.2".parse().unwrap()],
+            },
+            peers: vec![peer(&["192.0.2.2/32"]), peer(&["0.0.0.0/0", "::/0"])],
+            entry_private_key: None,
+            ipv4_gateway,
+            ipv6_gateway: None,
+            mtu: 1380,
+            #[cfg(target_os = "linux")]
+            fwmark: None,
+            #[cfg(target_os = "linux")]
+            enable_ipv6: false,
+            #[cfg(target_os = "windows")]
+            use_wireguard_nt: false,
+            #[cfg(target_os = "linux")]
+            use_boringtun: false,
+            #[cfg(target_os = "linux")]
+            network_namespace: None,
+            obfuscator_config: None,
+            pq_psk_rotation_interval: Some(Duration::from_secs(60)),
+        }
+    }
+
+    #[test]
+    fn test_psk_exchange_timeout() {
+        assert_eq!(
+            WireguardMonitor::psk_exchange_timeout(0),
+            INITIAL_PSK_EXCHANGE_TIMEOUT
+        );
+        assert_eq!(
+            WireguardMonitor::psk_exchange_timeout(1),
+            INITIAL_PSK_EXCHANGE_TIMEOUT * PSK_EXCHANGE_TIMEOUT_MULTIPLIER
+        );
+        assert_eq!(
+            WireguardMonitor::psk_exchange_timeout(u32::MAX),
+            MAX_PSK_EXCHANGE_TIMEOUT
+        );
+    }
+
+    #[test]
+    fn test_entry_negotiation_config() {
+        let mut config = multihop_config("10.64.0.1".parse().unwrap());
+        config.peers[1]
+            .allowed_ips
+            .push("10.64.0.1/32".parse().unwrap());
+
+        let entry_config = WireguardMonitor::entry_negotiation_config(&config);
+        let gateway_net: ipnetwork::IpNetwork = "10.64.0.1/32".parse().unwrap();
+
+        assert!(entry_config.peers[0].allowed_ips.contains(&gateway_net));
+        assert!(!entry_config.peers[1].allowed_ips.contains(&gateway_net));
+        assert_eq!(entry_config.tunnel, config.tunnel);
+    }
+
+    #[test]
+    fn test_peer_private_key() {
+        let mut config = multihop_config("10.64.0.1".parse().unwrap());
+        assert_eq!(config.peer_private_key(0), &config.tunnel.private_key);
+
+        let entry_private_key = PrivateKey::new_from_random();
+        config.entry_private_key = Some(entry_private_key.clone());
+        assert_eq!(config.peer_private_key(0), &entry_private_key);
+        assert_eq!(config.peer_private_key(1), &config.tunnel.private_key);
+
+        // The entry key is only used for multihop
+        config.peers.truncate(1);
+        assert_eq!(config.peer_private_key(0), &config.tunnel.private_key);
+    }
+
+    #[test]
+    fn test_patch_allowed_ips() {
+        let config = multihop_config("10.64.0.1".parse().unwrap());
+
+        let unpatched = WireguardMonitor::patch_allowed_ips(&config, false);
+        assert_eq!(unpatched.peers, config.peers);
+
+        let patched = WireguardMonitor::patch_allowed_ips(&config, true);
+        assert_eq!(patched.peers[0].allowed_ips, config.peers[0].allowed_ips);
+        // The default IPv6 route is dropped since there is no IPv6 gateway
+        assert_eq!(
+            patched.peers[1].allowed_ips,
+            vec!["10.64.0.1/32".parse::<ipnetwork::IpNetwork>().unwrap()]
+        );
+    }
+
+    fn rotate_psk(
+        tunnel: &Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+        obfuscation_handle: &Arc<AsyncMutex<Option<ObfuscatorHandle>>>,
+        config: &mut Config,
+        failures: &'static [usize],
+        exchanges: &Exchanges,
+    ) -> std::result::Result<(), CloseMsg> {
+        let (obfs_close_sender, _obfs_close_receiver) = sync_mpsc::channel();
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(WireguardMonitor::rotate_psk(
+                tunnel,
+                obfuscation_handle,
+                &obfs_close_sender,
+                config,
+                mock_negotiator(failures, exchanges.clone()),
+            ))
+    }
+
+    /// A rotation that fails at the exit relay must leave the current keys, PSKs and tunnel
+    /// config in place.
+    #[test]
+    fn test_failed_exit_rotation_keeps_session() {
+        let (tunnel, configs) = mock_tunnel(true, &[]);
+        let exchanges = Exchanges::default();
+        let mut config = negotiated_multihop_config();
+        let previous_config = config.clone();
+
+        rotate_psk(
+            &tunnel,
+            &Arc::new(AsyncMutex::new(None)),
+            &mut config,
+            &[0],
+            &exchanges,
+        )
+        .expect("a failed rotation should not close the tunnel");
+
+        assert_eq!(config.tunnel, previous_config.tunnel);
+        assert_eq!(config.peers, previous_config.peers);
+        assert_eq!(config.entry_private_key, previous_config.entry_private_key);
+        assert!(configs.lock().unwrap().is_empty());
+        assert_eq!(exchanges.lock().unwrap().len(), 1);
+    }
+
+    /// If the exit relay accepts the new key but the entry relay does not, the exit relay must
+    /// use the new key while the entry relay keeps the current session.
+    #[test]
+    fn test_failed_entry_rotation_keeps_entry_session() {
+        let (tunnel, configs) = mock_tunnel(true, &[]);
+        let exchanges = Exchanges::default();
+        let mut config = negotiated_multihop_config();
+        let previous_config = config.clone();
+
+        rotate_psk(
+            &tunnel,
+            &Arc::new(AsyncMutex::new(None)),
+            &mut config,
+            &[1],
+            &exchanges,
+        )
+        .expect("a failed rotation should not close the tunnel");
+
+        let exchanges = exchanges.lock().unwrap();
+        assert_eq!(exchanges.len(), 2);
+        assert_eq!(config.tunnel.private_key.public_key(), exchanges[0].1);
+        assert_eq!(
+            config.peers[1].psk,
+            Some(PresharedKey::from(Box::new([1u8; 32])))
+        );
+        assert_eq!(config.peers[0], previous_config.peers[0]);
+        assert_eq!(
+            config.entry_private_key.as_ref(),
+            Some(previous_config.peer_private_key(0))
+        );
+
+        // The gateway is routed to the exit relay again
+        let configs = configs.lock().unwrap();
+        let last_config = configs.last().expect("no config was applied");
+        assert_eq!(last_config.tunnel, config.tunnel);
+        assert_eq!(last_config.peers, config.peers);
+        assert_eq!(last_config.entry_private_key, config.entry_private_key);
+    }
+
+    /// If both hops share a key, the entry relay cannot keep the current session once the exit
+    /// relay has accepted a new key, so the tunnel must be reconnected.
+    #[test]
+    fn test_failed_entry_rotation_with_shared_key_reconnects() {
+        let (tunnel, _configs) = mock_tunnel(false, &[]);
+        let exchanges = Exchanges::default();
+        let mut config = negotiated_multihop_config();
+        config.entry_private_key = None;
+
+        assert!(rotate_psk(
+            &tunnel,
+            &Arc::new(AsyncMutex::new(None)),
+            &mut config,
+            &[1],
+            &exchanges,
+        )
+        .is_err());
+    }
+
+    /// Rotations must not restart the obfuscator, since the current session uses its endpoint.
+    #[test]
+    fn test_rotation_keeps_obfuscator() {
+        let (tunnel, configs) = mock_tunnel(true, &[]);
+        let exchanges = Exchanges::default();
+        let mut config = negotiated_multihop_config();
+        let previous_config = config.clone();
+
+        let (obfuscator, abort_handle) = abortable(futures::future::pending::<()>());
+        let obfuscation_handle = Arc::new(AsyncMutex::new(Some(ObfuscatorHandle::new(
+            abort_handle,
+            #[cfg(target_os = "android")]
+            -1,
+        ))));
+
+        rotate_psk(&tunnel, &obfuscation_handle, &mut config, &[], &exchanges).unwrap();
+
+        assert!(futures::FutureExt::now_or_never(obfuscator).is_none());
+        assert_eq!(config.peers[0].endpoint, previous_config.peers[0].endpoint);
+
+        let exchanges = exchanges.lock().unwrap();
+        assert_eq!(
+            config
+                .entry_private_key
+                .as_ref()
+                .map(PrivateKey::public_key),
+            Some(exchanges[1].1.clone())
+        );
+        assert_eq!(configs.lock().unwrap().last().unwrap().peers, config.peers);
+    }
+
+    fn initial_psk_negotiation(
+        tunnel: &Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+        config: &mut Config,
+        failures: &'static [usize],
+        exchanges: &Exchanges,
+    ) -> std::result::Result<(), PskNegotiationError> {
+        let (obfs_close_sender, _obfs_close_receiver) = sync_mpsc::channel();
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(WireguardMonitor::perform_psk_negotiation(
+                tunnel,
+                &Arc::new(AsyncMutex::new(None)),
+                &obfs_close_sender,
+                config,
+                true,
+                mock_negotiator(failures, exchanges.clone()),
+            ))
+    }
+
+    fn routes_gateway(peer: &wireguard::PeerConfig, gateway: Ipv4Addr) -> bool {
+        peer.allowed_ips
+            .contains(&ipnetwork::IpNetwork::from(IpAddr::from(gateway)))
+    }
+
+    /// The exit relay is negotiated with first, through the entry relay. The gateway is then
+    /// routed to the entry relay for the second exchange, and finally back to the exit relay.
+    #[test]
+    fn test_multihop_negotiation_order() {
+        let (tunnel, configs) = mock_tunnel(true, &[]);
+        let exchanges = Exchanges::default();
+        let mut config = multihop_config("10.64.0.1".parse().unwrap());
+        let gateway = config.ipv4_gateway;
+        let previous_config = config.clone();
+
+        initial_psk_negotiation(&tunnel, &mut config, &[], &exchanges)
+            .unwrap_or_else(|_| panic!("negotiation failed"));
+
+        let exchanges = exchanges.lock().unwrap();
+        assert_eq!(exchanges.len(), 2);
+        assert_eq!(
+            exchanges[0].0,
+            previous_config.tunnel.private_key.public_key()
+        );
+        assert_eq!(
+            exchanges[1].0,
+            previous_config.tunnel.private_key.public_key()
+        );
+        assert_eq!(config.tunnel.private_key.public_key(), exchanges[0].1);
+        assert_eq!(
+            config
+                .entry_private_key
+                .as_ref()
+                .map(PrivateKey::public_key),
+            Some(exchanges[1].1.clone())
+        );
+
+        let configs = configs.lock().unwrap();
+        assert_eq!(configs.len(), 2);
+        assert!(routes_gateway(&configs[0].peers[0], gateway));
+        assert!(!routes_gateway(&configs[0].peers[1], gateway));
+        // The exit relay must already use the new key during the entry exchange
+        assert_eq!(configs[0].tunnel, config.tunnel);
+        assert_eq!(configs[1].peers, config.peers);
+    }
+
+    /// A failed entry exchange must route the gateway back to the exit relay, also when
+    /// connecting.
+    #[test]
+    fn test_failed_entry_negotiation_restores_routing() {
+        for supports_peer_private_keys in [true, false] {
+            let (tunnel, configs) = mock_tunnel(supports_peer_private_keys, &[]);
+            let exchanges = Exchanges::default();
+            let mut config = multihop_config("10.64.0.1".parse().unwrap());
+            let gateway = config.ipv4_gateway;
+
+            let error = match initial_psk_negotiation(&tunnel, &mut config, &[1], &exchanges) {
+                Ok(()) => panic!("the entry exchange should fail"),
+                Err(error) => error,
+            };
+            assert_eq!(error.session_intact, supports_peer_private_keys);
+
+            let configs = configs.lock().unwrap();
+            assert_eq!(configs.len(), 2);
+            assert!(routes_gateway(&configs[0].peers[0], gateway));
+            let last_config = &configs[1];
+            assert!(!routes_gateway(&last_config.peers[0], gateway));
+            assert_eq!(
+                last_config.peers,
+                WireguardMonitor::patch_allowed_ips(&config, true).peers
+            );
+        }
+    }
+
+    /// If the tunnel cannot be switched to the entry exchange config, the gateway must still be
+    /// routed back to the exit relay and the tunnel reconnected.
+    #[test]
+    fn test_failed_entry_config_restores_routing() {
+        let (tunnel, configs) = mock_tunnel(true, &[0]);
+        let exchanges = Exchanges::default();
+        let mut config = multihop_config("10.64.0.1".parse().unwrap());
+        let gateway = config.ipv4_gateway;
+
+        let error = match initial_psk_negotiation(&tunnel, &mut config, &[], &exchanges) {
+            Ok(()) => panic!("the entry exchange should fail"),
+            Err(error) => error,
+        };
+        assert!(!error.session_intact);
+        assert_eq!(exchanges.lock().unwrap().len(), 1);
+
+        let configs = configs.lock().unwrap();
+        assert_eq!(configs.len(), 2);
+        assert!(!routes_gateway(&configs[1].peers[0], gateway));
+        assert_eq!(
+            configs[1].peers,
+            WireguardMonitor::patch_allowed_ips(&config, true).peers
+        );
+    }
+
+    /// A failed exit exchange happens before the tunnel config is touched.
+    #[test]
+    fn test_failed_exit_negotiation_keeps_config() {
+        let (tunnel, configs) = mock_tunnel(true, &[]);
+        let exchanges = Exchanges::default();
+        let mut config = multihop_config("10.64.0.1".parse().unwrap());
+        let previous_config = config.clone();
+
+        assert!(initial_psk_negotiation(&tunnel, &mut config, &[0], &exchanges).is_err());
+        assert_eq!(exchanges.lock().unwrap().len(), 1);
+        assert!(configs.lock().unwrap().is_empty());
+        assert_eq!(config.tunnel, previous_config.tunnel);
+        assert_eq!(config.peers, previous_config.peers);
+    }
+}
@@ -0,0 +1,187 @@
+//! Management of the network namespace that the tunnel interface can be moved into.
+//!
+//! The namespace is created the same way as `ip netns add` does it, so that it can also be
+//! inspected and entered using `ip netns`.
+
+use nix::{
+    mount::{mount, MsFlags},
+    sched::{setns, unshare, CloneFlags},
+};
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io,
+    net::IpAddr,
+    os::unix::io::AsRawFd,
+    path::Path,
+    thread,
+};
+use talpid_types::netns::{namespace_path, resolv_conf_path};
+
+/// Errors that can occur while managing the network namespace.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to create the directory for the namespace mount point.
+    #[error(display = "Failed to create network namespace directory")]
+    CreateDir(#[error(source)] io::Error),
+
+    /// Failed to create the namespace mount point.
+    #[error(display = "Failed to create network namespace mount point")]
+    CreateMountPoint(#[error(source)] io::Error),
+
+    /// Failed to create a new network namespace.
+    #[error(display = "Failed to create network namespace")]
+    Unshare(#[error(source)] nix::Error),
+
+    /// Failed to bind-mount the new network namespace.
+    #[error(display = "Failed to mount network namespace")]
+    Mount(#[error(source)] nix::Error),
+
+    /// Failed to open the network namespace.
+    #[error(display = "Failed to open network namespace")]
+    Open(#[error(source)] io::Error),
+
+    /// Failed to enter the network namespace.
+    #[error(display = "Failed to enter network namespace")]
+    Enter(#[error(source)] nix::Error),
+
+    /// Failed to write the DNS config of the network namespace.
+    #[error(display = "Failed to write resolv.conf for network namespace")]
+    WriteResolvConf(#[error(source)] io::Error),
+
+    /// Failed to remove the DNS config of the network namespace.
+    #[error(display = "Failed to remove resolv.conf for network namespace")]
+    RemoveResolvConf(#[error(source)] io::Error),
+}
+
+/// Opens the named network namespace, creating it if it does not exist.
+pub fn open_or_create(name: &str) -> Result<File, Error> {
+    let path = namespace_path(name);
+    match File::open(&path) {
+        Ok(file) if is_namespace(&file) => return Ok(file),
+        Ok(_) => (),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+        Err(error) => return Err(Error::Open(error)),
+    }
+
+    log::debug!("Creating network namespace {}", name);
+    create(&path)?;
+    File::open(&path).map_err(Error::Open)
+}
+
+/// Returns whether `file` refers to a network namespace rather than a bare mount point.
+fn is_namespace(file: &File) -> bool {
+    // Entering fails unless the file refers to a network namespace. This is done on a short-lived
+    // thread so that no other thread changes namespace.
+    let fd = match file.try_clone() {
+        Ok(fd) => fd,
+        Err(_) => return false,
+    };
+    thread::spawn(move || setns(fd.as_raw_fd(), CloneFlags::CLONE_NEWNET).is_ok())
+        .join()
+        .unwrap_or(false)
+}
+
+fn create(path: &Path) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(Error::CreateDir)?;
+    }
+    File::create(path).map_err(Error::CreateMountPoint)?;
+
+    let path = path.to_owned();
+    // Unsharing only affects the calling thread, which exits once the namespace is mounted. The
+    // bind mount keeps the namespace alive.
+    thread::spawn(move || {
+        unshare(CloneFlags::CLONE_NEWNET).map_err(Error::Unshare)?;
+        let source = format!("/proc/self/task/{}/ns/net", nix::unistd::gettid());
+        mount(
+            Some(source.as_str()),
+            &path,
+            None::<&str>,
+            MsFlags::MS_BIND,
+            None::<&str>,
+        )
+        .map_err(Error::Mount)
+    })
+    .join()
+    .expect("network namespace thread panicked")
+}
+
+/// Runs `f` on a thread inside the network namespace `netns` and returns the result. Sockets
+/// created by `f` remain in that namespace, regardless of which thread later uses them.
+pub(crate) fn run_in_namespace<T, F>(netns: &File, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let netns = netns.try_clone().map_err(Error::Open)?;
+    thread::spawn(move || {
+        setns(netns.as_raw_fd(), CloneFlags::CLONE_NEWNET).map_err(Error::Enter)?;
+        Ok(f())
+    })
+    .join()
+    .expect("network namespace thread panicked")
+}
+
+/// Sets the DNS servers used by processes in the named network namespace. Like `ip netns exec`,
+/// `mullvad-netns` mounts this file over `/etc/resolv.conf`.
+pub fn set_dns_servers(name: &str, servers: &[IpAddr]) -> Result<(), Error> {
+    let path = resolv_conf_path(name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(Error::WriteResolvConf)?;
+    }
+    fs::write(path, resolv_conf(servers)).map_err(Error::WriteResolvConf)
+}
+
+/// Removes the DNS config of the named network namespace, so that processes in it no longer use
+/// the DNS servers of a tunnel that is down.
+pub fn reset_dns_servers(name: &str) -> Result<(), Error> {
+    remove_if_exists(&resolv_conf_path(name)).map_err(Error::RemoveResolvConf)
+}
+
+fn resolv_conf(servers: &[IpAddr]) -> String {
+    let mut contents = String::from("# Generated by the Mullvad VPN daemon\n");
+    for server in servers {
+        writeln!(contents, "nameserver {}", server).expect("formatting failed");
+    }
+    contents
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolv_conf() {
+        let servers = [
+            "10.64.0.1".parse().unwrap(),
+            "fc00:bbbb:bbbb:bb01::1".parse().unwrap(),
+        ];
+        assert_eq!(
+            resolv_conf(&servers),
+            "# Generated by the Mullvad VPN daemon\n\
+             nameserver 10.64.0.1\n\
+             nameserver fc00:bbbb:bbbb:bb01::1\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_if_exists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("resolv.conf");
+        fs::write(&path, resolv_conf(&[])).unwrap();
+
+        remove_if_exists(&path).unwrap();
+        assert!(!path.exists());
+        // Removing the config when there is none, e.g. when disconnecting twice, is not an error
+        remove_if_exists(&path).unwrap();
+    }
+}
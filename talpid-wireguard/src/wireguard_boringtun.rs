@@ -0,0 +1,1030 @@
+//! Userspace WireGuard implementation built on the noise protocol implementation in `boringtun`.
+//!
+//! Packets are read from a tunnel device obtained from the [`TunProvider`], encrypted, and sent
+//! over UDP sockets that carry the firewall mark. This requires neither the kernel module nor the
+//! wireguard-go library.
+
+use super::{
+    stats::{Stats, StatsMap},
+    CloseMsg, Config, Tunnel, TunnelError,
+};
+use boringtun::{
+    noise::{errors::WireGuardError, Tunn, TunnResult},
+    x25519,
+};
+use futures::future::{BoxFuture, FutureExt};
+use ipnetwork::IpNetwork;
+use parking_lot::{Mutex as PlMutex, RwLock};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4},
+    os::unix::io::{AsRawFd, RawFd},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc as sync_mpsc, Arc, Mutex,
+    },
+    time::Duration,
+};
+use talpid_tunnel::tun_provider::{Tun, TunConfig, TunProvider};
+use talpid_types::{net::wireguard::PresharedKey, ErrorExt};
+use tokio::{io::unix::AsyncFd, net::UdpSocket, task::JoinHandle};
+
+type Result<T> = std::result::Result<T, TunnelError>;
+
+/// Errors that can occur in the boringtun tunnel implementation.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to create a UDP socket for a peer.
+    #[error(display = "Failed to open UDP socket")]
+    OpenSocket(#[error(source)] io::Error),
+
+    /// Failed to register the tunnel device with the async runtime.
+    #[error(display = "Failed to register tunnel device")]
+    RegisterTunnelDevice(#[error(source)] io::Error),
+
+    /// Failed to look up the index of the tunnel interface.
+    #[error(display = "Failed to obtain tunnel interface index")]
+    InterfaceIndex(#[error(source)] nix::Error),
+
+    /// Failed to open a netlink socket.
+    #[error(display = "Failed to open netlink socket")]
+    NetlinkSocket(#[error(source)] io::Error),
+
+    /// Failed to set the MTU of the tunnel interface.
+    #[error(display = "Failed to set tunnel MTU")]
+    SetMtu(#[error(source)] rtnetlink::Error),
+
+    /// Failed to initialize the noise protocol state for a peer.
+    #[error(display = "Failed to initialize peer: {}", _0)]
+    InitializePeer(&'static str),
+
+    /// A peer is routed through another peer, but the tunnel has no IPv4 address to use as the
+    /// source of the inner packets.
+    #[error(display = "Nested peers require an IPv4 tunnel address")]
+    NoIpv4TunnelAddress,
+
+    /// Failed to read a packet from the tunnel device.
+    #[error(display = "Failed to read from tunnel device")]
+    ReadTunnelDevice(#[error(source)] io::Error),
+
+    /// Failed to receive a datagram on a UDP socket.
+    #[error(display = "Failed to receive from UDP socket")]
+    ReceiveDatagram(#[error(source)] io::Error),
+}
+
+/// Largest datagram that may be received on a UDP socket.
+const MAX_UDP_SIZE: usize = (1 << 16) - 1;
+
+/// Size of the packet information header prepended to each packet by the tunnel device.
+const PACKET_INFO_SIZE: usize = 4;
+
+/// Overhead added by WireGuard when encapsulating a packet.
+const WIREGUARD_OVERHEAD: usize = 32;
+
+/// Smallest buffer that can hold a handshake message.
+const MIN_ENCAPSULATE_BUFFER_SIZE: usize = 148;
+
+/// How often the noise protocol timers are driven.
+const TIMER_INTERVAL: Duration = Duration::from_millis(250);
+
+const IPV4_HEADER_SIZE: usize = 20;
+const UDP_HEADER_SIZE: usize = 8;
+const IPPROTO_UDP: u8 = 17;
+const ETH_P_IP: u16 = 0x0800;
+const ETH_P_IPV6: u16 = 0x86dd;
+
+pub struct BoringTunnel {
+    interface_name: String,
+    device: Arc<Device>,
+    tasks: Vec<JoinHandle<()>>,
+    // holding on to the tunnel device ensures that the file descriptor used by the tasks stays
+    // open until the tunnel is stopped
+    _tunnel_device: Tun,
+}
+
+impl BoringTunnel {
+    /// Starts the tunnel. If moving packets fails, the error is sent on `close_msg_sender`, so
+    /// that the tunnel is torn down.
+    pub fn start_tunnel(
+        runtime: tokio::runtime::Handle,
+        config: &Config,
+        tun_provider: Arc<Mutex<TunProvider>>,
+        routes: impl Iterator<Item = IpNetwork>,
+        close_msg_sender: sync_mpsc::Sender<CloseMsg>,
+    ) -> Result<Self> {
+        let tunnel_device = tun_provider
+            .lock()
+            .unwrap()
+            .get_tun(Self::create_tunnel_config(config, routes))
+            .map_err(TunnelError::SetupTunnelDeviceError)?;
+        let interface_name = tunnel_device.interface_name().to_string();
+
+        let device = runtime
+            .block_on(async {
+                set_mtu(&interface_name, config.mtu).await?;
+                Device::new(tunnel_device.as_raw_fd(), config)
+            })
+            .map_err(TunnelError::BoringtunError)?;
+        let device = Arc::new(device);
+
+        let spawn_reader =
+            |reader| runtime.spawn(Self::report_failure(reader, close_msg_sender.clone()));
+        let mut tasks = vec![
+            spawn_reader(device.clone().run_tunnel_reader().boxed()),
+            runtime.spawn(device.clone().run_timers()),
+            spawn_reader(device.clone().run_socket_reader(false).boxed()),
+        ];
+        if device.udp_v6.is_some() {
+            tasks.push(spawn_reader(device.clone().run_socket_reader(true).boxed()));
+        }
+
+        Ok(BoringTunnel {
+            interface_name,
+            device,
+            tasks,
+            _tunnel_device: tunnel_device,
+        })
+    }
+
+    /// Waits for `reader` to fail, and sends the error on `close_msg_sender`.
+    async fn report_failure(
+        reader: BoxFuture<'static, std::result::Result<(), Error>>,
+        close_msg_sender: sync_mpsc::Sender<CloseMsg>,
+    ) {
+        if let Err(error) = reader.await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("boringtun tunnel failed")
+            );
+            let _ = close_msg_sender.send(CloseMsg::TunnelFailed(super::Error::TunnelError(
+                TunnelError::BoringtunError(error),
+            )));
+        }
+    }
+
+    fn create_tunnel_config(config: &Config, routes: impl Iterator<Item = IpNetwork>) -> TunConfig {
+        let mut dns_servers = vec![IpAddr::V4(config.ipv4_gateway)];
+        dns_servers.extend(config.ipv6_gateway.map(IpAddr::V6));
+
+        TunConfig {
+            addresses: config.tunnel.addresses.clone(),
+            dns_servers,
+            routes: routes.collect(),
+            mtu: config.mtu,
+        }
+    }
+
+    fn stop_tunnel(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for BoringTunnel {
+    fn drop(&mut self) {
+        self.stop_tunnel();
+    }
+}
+
+impl Tunnel for BoringTunnel {
+    fn get_interface_name(&self) -> String {
+        self.interface_name.clone()
+    }
+
+    fn get_tunnel_stats(&self) -> Result<StatsMap> {
+        Ok(self
+            .device
+            .peers
+            .read()
+            .iter()
+            .map(|peer| {
+                let (_last_handshake, tx_bytes, rx_bytes, _loss, _rtt) = peer.tunn.lock().stats();
+                let stats = Stats {
+                    tx_bytes: tx_bytes as u64,
+                    rx_bytes: rx_bytes as u64,
+                };
+                (peer.public_key, stats)
+            })
+            .collect())
+    }
+
+    fn stop(mut self: Box<Self>) -> Result<()> {
+        self.stop_tunnel();
+        Ok(())
+    }
+
+    fn supports_peer_private_keys(&self) -> bool {
+        true
+    }
+
+    fn set_config(
+        &self,
+        config: Config,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), TunnelError>> + Send>> {
+        let device = self.device.clone();
+        Box::pin(async move {
+            device.set_config(&config).map_err(|error| {
+                log::error!("Failed to apply boringtun config: {}", error);
+                TunnelError::SetConfigError
+            })
+        })
+    }
+}
+
+struct Peer {
+    public_key: [u8; 32],
+    endpoint: SocketAddr,
+    allowed_ips: Vec<IpNetwork>,
+    /// Keys that the session with the peer was established with.
+    session_keys: SessionKeys,
+    /// Noise protocol state. It is carried over to the updated peer when the config changes, as
+    /// long as the session keys stay the same.
+    tunn: Arc<PlMutex<Tunn>>,
+}
+
+impl Peer {
+    /// Returns whether packets from `source` may be received from the peer. Like in WireGuard,
+    /// the allowed IPs of a peer restrict both where packets are sent and where they come from.
+    fn is_allowed_source(&self, source: IpAddr) -> bool {
+        let allowed = self
+            .allowed_ips
+            .iter()
+            .any(|network| network.contains(source));
+        if !allowed {
+            log::trace!(
+                "Dropping packet from {}, which is not an allowed IP of the peer",
+                source
+            );
+        }
+        allowed
+    }
+}
+
+#[derive(PartialEq, Eq)]
+struct SessionKeys {
+    local_public_key: [u8; 32],
+    psk: Option<PresharedKey>,
+}
+
+/// Creates the peers in `config`. Peers in `current_peers` whose keys are unchanged keep their
+/// sessions, so that changing endpoints or allowed IPs does not interrupt the tunnel.
+fn create_peers(
+    config: &Config,
+    current_peers: &[Arc<Peer>],
+    next_index: &AtomicU32,
+) -> std::result::Result<Vec<Arc<Peer>>, Error> {
+    config
+        .peers
+        .iter()
+        .enumerate()
+        .map(|(index, peer)| {
+            let private_key = config.peer_private_key(index);
+            let local_public_key = *private_key.public_key().as_bytes();
+            let public_key = *peer.public_key.as_bytes();
+            let session_keys = SessionKeys {
+                local_public_key,
+                psk: peer.psk.clone(),
+            };
+            let current_tunn = current_peers
+                .iter()
+                .find(|current| {
+                    current.public_key == public_key && current.session_keys == session_keys
+                })
+                .map(|current| current.tunn.clone());
+
+            let tunn = match current_tunn {
+                Some(tunn) => tunn,
+                None => {
+                    let tunn = Tunn::new(
+                        x25519::StaticSecret::from(private_key.to_bytes()),
+                        x25519::PublicKey::from(public_key),
+                        peer.psk.as_ref().map(|psk| *psk.as_bytes()),
+                        None,
+                        next_index.fetch_add(1, Ordering::Relaxed),
+                        None,
+                    )
+                    .map_err(Error::InitializePeer)?;
+                    Arc::new(PlMutex::new(tunn))
+                }
+            };
+
+            Ok(Arc::new(Peer {
+                public_key,
+                endpoint: peer.endpoint,
+                allowed_ips: peer.allowed_ips.clone(),
+                session_keys,
+                tunn,
+            }))
+        })
+        .collect()
+}
+
+/// State shared between the tasks that move packets between the tunnel device and the peers.
+struct Device {
+    tunnel_fd: AsyncFd<RawFd>,
+    udp_v4: UdpSocket,
+    udp_v6: Option<UdpSocket>,
+    /// Source address of packets sent to a peer that is reached through another peer.
+    nested_source: Option<SocketAddrV4>,
+    peers: RwLock<Vec<Arc<Peer>>>,
+    /// Index of the next session created by the noise protocol. Each session needs a distinct one.
+    next_index: AtomicU32,
+}
+
+impl Device {
+    fn new(tunnel_fd: RawFd, config: &Config) -> std::result::Result<Self, Error> {
+        let udp_v4 = open_socket(Domain::IPV4, config.fwmark)?;
+        let udp_v6 = if config.peers.iter().any(|peer| peer.endpoint.is_ipv6()) {
+            Some(open_socket(Domain::IPV6, config.fwmark)?)
+        } else {
+            None
+        };
+
+        let tunnel_ipv4 = config
+            .tunnel
+            .addresses
+            .iter()
+            .find_map(|address| match address {
+                IpAddr::V4(address) => Some(*address),
+                IpAddr::V6(_) => None,
+            });
+        let local_port = udp_v4.local_addr().map_err(Error::OpenSocket)?.port();
+        let next_index = AtomicU32::new(0);
+        let peers = create_peers(config, &[], &next_index)?;
+
+        let device = Device {
+            tunnel_fd: AsyncFd::new(tunnel_fd).map_err(Error::RegisterTunnelDevice)?,
+            udp_v4,
+            udp_v6,
+            nested_source: tunnel_ipv4.map(|address| SocketAddrV4::new(address, local_port)),
+            peers: RwLock::new(peers),
+            next_index,
+        };
+
+        let has_nested_peer = {
+            let peers = device.peers.read();
+            peers.iter().any(|peer| route_via(&peers, peer).is_some())
+        };
+        if has_nested_peer && device.nested_source.is_none() {
+            return Err(Error::NoIpv4TunnelAddress);
+        }
+
+        Ok(device)
+    }
+
+    /// Replaces the peers with the ones in `config`, keeping the sessions of unchanged peers.
+    fn set_config(&self, config: &Config) -> std::result::Result<(), Error> {
+        let mut peers = self.peers.write();
+        *peers = create_peers(config, &peers, &self.next_index)?;
+        Ok(())
+    }
+
+    /// Reads packets from the tunnel device and sends them to the peer they are routed to. Only
+    /// returns if reading fails.
+    async fn run_tunnel_reader(self: Arc<Self>) -> std::result::Result<(), Error> {
+        let mut packet_buf = vec![0u8; MAX_UDP_SIZE];
+        let mut send_buf = vec![0u8; MAX_UDP_SIZE];
+
+        loop {
+            let size = self
+                .read_tunnel(&mut packet_buf)
+                .await
+                .map_err(Error::ReadTunnelDevice)?;
+            if size <= PACKET_INFO_SIZE {
+                continue;
+            }
+            let packet = &packet_buf[PACKET_INFO_SIZE..size];
+
+            let peer = match packet_destination(packet).and_then(|dest| self.peer_for_address(dest))
+            {
+                Some(peer) => peer,
+                None => continue,
+            };
+            let result = peer.tunn.lock().encapsulate(packet, &mut send_buf);
+            match result {
+                TunnResult::WriteToNetwork(datagram) => self.send_to_peer(&peer, datagram).await,
+                TunnResult::Err(error) => log::trace!("Failed to encapsulate packet: {:?}", error),
+                _ => (),
+            }
+        }
+    }
+
+    /// Receives datagrams from the peers and writes the decrypted packets to the tunnel device.
+    /// Only returns if receiving fails.
+    async fn run_socket_reader(self: Arc<Self>, ipv6: bool) -> std::result::Result<(), Error> {
+        let socket = if ipv6 {
+            self.udp_v6.as_ref().expect("missing IPv6 socket")
+        } else {
+            &self.udp_v4
+        };
+        let mut datagram_buf = vec![0u8; MAX_UDP_SIZE];
+        let mut packet_buf = vec![0u8; MAX_UDP_SIZE];
+
+        loop {
+            let (size, source) = socket
+                .recv_from(&mut datagram_buf)
+                .await
+                .map_err(Error::ReceiveDatagram)?;
+            let peer = match self.peer_for_endpoint(source) {
+                Some(peer) => peer,
+                None => continue,
+            };
+
+            // Datagrams from a peer that is reached through another peer arrive inside the outer
+            // peer's tunnel, so they have to be decapsulated once more.
+            if let Some((inner_peer, inner_datagram)) = self
+                .receive_datagram(&peer, &datagram_buf[..size], &mut packet_buf)
+                .await
+            {
+                if self
+                    .receive_datagram(&inner_peer, &inner_datagram, &mut packet_buf)
+                    .await
+                    .is_some()
+                {
+                    log::trace!("Dropping packet nested more than one level deep");
+                }
+            }
+        }
+    }
+
+    /// Drives the handshake and keepalive timers of all peers.
+    async fn run_timers(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(TIMER_INTERVAL);
+        let mut send_buf = vec![0u8; MAX_UDP_SIZE];
+
+        loop {
+            interval.tick().await;
+            let peers = self.peers.read().clone();
+            for peer in peers {
+                let result = peer.tunn.lock().update_timers(&mut send_buf);
+                match result {
+                    TunnResult::WriteToNetwork(datagram) => {
+                        self.send_to_peer(&peer, datagram).await
+                    }
+                    TunnResult::Err(WireGuardError::ConnectionExpired) => {
+                        log::trace!("Session with peer expired");
+                    }
+                    TunnResult::Err(error) => log::trace!("Timer error: {:?}", error),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// Decapsulates a datagram received from `peer`. If it contains a datagram from another peer
+    /// that is reached through `peer`, that peer and the datagram are returned. Packets whose
+    /// source is not in the allowed IPs of `peer` are dropped.
+    async fn receive_datagram(
+        &self,
+        peer: &Peer,
+        datagram: &[u8],
+        packet_buf: &mut [u8],
+    ) -> Option<(Arc<Peer>, Vec<u8>)> {
+        let mut input = datagram;
+        loop {
+            let result = peer
+                .tunn
+                .lock()
+                .decapsulate(Some(peer.endpoint.ip()), input, packet_buf);
+            // Queued packets are flushed by calling `decapsulate` with an empty datagram
+            input = &[];
+
+            match result {
+                TunnResult::WriteToNetwork(datagram) => self.send_to_peer(peer, datagram).await,
+                TunnResult::WriteToTunnelV4(packet, source) => {
+                    if !peer.is_allowed_source(IpAddr::V4(source)) {
+                        return None;
+                    }
+                    if let Some(nested) = self.nested_datagram(packet) {
+                        return Some(nested);
+                    }
+                    self.write_tunnel(packet, ETH_P_IP);
+                    return None;
+                }
+                TunnResult::WriteToTunnelV6(packet, source) => {
+                    if !peer.is_allowed_source(IpAddr::V6(source)) {
+                        return None;
+                    }
+                    self.write_tunnel(packet, ETH_P_IPV6);
+                    return None;
+                }
+                TunnResult::Err(error) => {
+                    log::trace!("Failed to decapsulate datagram: {:?}", error);
+                    return None;
+                }
+                TunnResult::Done => return None,
+            }
+        }
+    }
+
+    /// Sends an encapsulated datagram to `peer`, either directly or through the peer whose
+    /// allowed IPs contain the endpoint.
+    async fn send_to_peer(&self, peer: &Peer, datagram: &[u8]) {
+        let via = {
+            let peers = self.peers.read();
+            route_via(&peers, peer)
+        };
+
+        let result = match (via, self.nested_source) {
+            (Some(via), Some(source)) => {
+                let destination = match peer.endpoint {
+                    SocketAddr::V4(endpoint) => endpoint,
+                    SocketAddr::V6(_) => {
+                        log::trace!("Nested IPv6 endpoints are not supported");
+                        return;
+                    }
+                };
+                let packet = udp_packet(source, destination, datagram);
+                let mut send_buf =
+                    vec![0u8; (packet.len() + WIREGUARD_OVERHEAD).max(MIN_ENCAPSULATE_BUFFER_SIZE)];
+                let result = via.tunn.lock().encapsulate(&packet, &mut send_buf);
+                match result {
+                    TunnResult::WriteToNetwork(outer) => {
+                        self.socket_for(via.endpoint)
+                            .send_to(outer, via.endpoint)
+                            .await
+                    }
+                    TunnResult::Err(error) => {
+                        log::trace!("Failed to encapsulate nested datagram: {:?}", error);
+                        return;
+                    }
+                    _ => return,
+                }
+            }
+            _ => {
+                self.socket_for(peer.endpoint)
+                    .send_to(datagram, peer.endpoint)
+                    .await
+            }
+        };
+
+        if let Err(error) = result {
+            log::trace!("Failed to send datagram to {}: {}", peer.endpoint, error);
+        }
+    }
+
+    /// Returns the peer and datagram contained in `packet`, if it is a UDP packet from the
+    /// endpoint of a peer that is reached through another peer.
+    fn nested_datagram(&self, packet: &[u8]) -> Option<(Arc<Peer>, Vec<u8>)> {
+        let (source, payload) = parse_udp_packet(packet)?;
+        let peers = self.peers.read();
+        let peer = peers
+            .iter()
+            .find(|peer| peer.endpoint == SocketAddr::V4(source))?;
+        route_via(&peers, peer)?;
+        Some((peer.clone(), payload.to_vec()))
+    }
+
+    fn peer_for_address(&self, address: IpAddr) -> Option<Arc<Peer>> {
+        longest_match(&self.peers.read(), address).map(|(_, peer)| peer.clone())
+    }
+
+    fn peer_for_endpoint(&self, endpoint: SocketAddr) -> Option<Arc<Peer>> {
+        self.peers
+            .read()
+            .iter()
+            .find(|peer| peer.endpoint == endpoint)
+            .cloned()
+    }
+
+    fn socket_for(&self, endpoint: SocketAddr) -> &UdpSocket {
+        match (endpoint, &self.udp_v6) {
+            (SocketAddr::V6(_), Some(udp_v6)) => udp_v6,
+            _ => &self.udp_v4,
+        }
+    }
+
+    async fn read_tunnel(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.tunnel_fd.readable().await?;
+            match guard.try_io(|fd| nix::unistd::read(*fd.get_ref(), buf).map_err(io::Error::from))
+            {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn write_tunnel(&self, packet: &[u8], protocol: u16) {
+        let mut frame = Vec::with_capacity(PACKET_INFO_SIZE + packet.len());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&protocol.to_be_bytes());
+        frame.extend_from_slice(packet);
+
+        if let Err(error) = nix::unistd::write(*self.tunnel_fd.get_ref(), &frame) {
+            log::trace!("Failed to write to tunnel device: {}", error);
+        }
+    }
+}
+
+/// Returns the peer through which `peer` is reached, if it is not reached directly. Endpoints that
+/// are only covered by a default route are reached directly, since encapsulated traffic never
+/// enters the tunnel.
+fn route_via(peers: &[Arc<Peer>], peer: &Peer) -> Option<Arc<Peer>> {
+    longest_match(peers, peer.endpoint.ip())
+        .filter(|(prefix, via)| *prefix > 0 && via.public_key != peer.public_key)
+        .map(|(_, via)| via.clone())
+}
+
+/// Returns the peer with the most specific allowed IP network containing `address`, along with the
+/// prefix length of that network.
+fn longest_match(peers: &[Arc<Peer>], address: IpAddr) -> Option<(u8, &Arc<Peer>)> {
+    peers
+        .iter()
+        .filter_map(|peer| {
+            peer.allowed_ips
+                .iter()
+                .filter(|network| network.contains(address))
+                .map(|network| network.prefix())
+                .max()
+                .map(|prefix| (prefix, peer))
+        })
+        .max_by_key(|(prefix, _)| *prefix)
+}
+
+fn open_socket(domain: Domain, fwmark: Option<u32>) -> std::result::Result<UdpSocket, Error> {
+    let socket =
+        Socket::new(domain, Type::DGRAM, Some(Protocol::UDP)).map_err(Error::OpenSocket)?;
+    if let Some(fwmark) = fwmark {
+        socket.set_mark(fwmark).map_err(Error::OpenSocket)?;
+    }
+    let bind_addr: SocketAddr = if domain == Domain::IPV6 {
+        socket.set_only_v6(true).map_err(Error::OpenSocket)?;
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    };
+    socket.bind(&bind_addr.into()).map_err(Error::OpenSocket)?;
+    socket.set_nonblocking(true).map_err(Error::OpenSocket)?;
+    UdpSocket::from_std(socket.into()).map_err(Error::OpenSocket)
+}
+
+async fn set_mtu(interface_name: &str, mtu: u16) -> std::result::Result<(), Error> {
+    let index = nix::net::if_::if_nametoindex(interface_name).map_err(Error::InterfaceIndex)?;
+    let (connection, handle, _) = rtnetlink::new_connection().map_err(Error::NetlinkSocket)?;
+    let connection = tokio::spawn(connection);
+    let result = handle
+        .link()
+        .set(index)
+        .mtu(u32::from(mtu))
+        .execute()
+        .await
+        .map_err(Error::SetMtu);
+    connection.abort();
+    result
+}
+
+/// Returns the destination address of an IP packet.
+fn packet_destination(packet: &[u8]) -> Option<IpAddr> {
+    match packet.first()? >> 4 {
+        4 if packet.len() >= IPV4_HEADER_SIZE => {
+            let octets: [u8; 4] = packet[16..20].try_into().ok()?;
+            Some(IpAddr::from(octets))
+        }
+        6 if packet.len() >= 40 => {
+            let octets: [u8; 16] = packet[24..40].try_into().ok()?;
+            Some(IpAddr::from(octets))
+        }
+        _ => None,
+    }
+}
+
+/// Builds an IPv4 UDP packet carrying `payload`.
+fn udp_packet(source: SocketAddrV4, destination: SocketAddrV4, payload: &[u8]) -> Vec<u8> {
+    let udp_len = UDP_HEADER_SIZE + payload.len();
+    let total_len = IPV4_HEADER_SIZE + udp_len;
+
+    let mut packet = Vec::with_capacity(total_len);
+    // version and header length, DSCP
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&(total_len as u16).to_be_bytes());
+    // identification, don't fragment
+    packet.extend_from_slice(&[0, 0, 0x40, 0]);
+    // TTL, protocol, checksum
+    packet.extend_from_slice(&[64, IPPROTO_UDP, 0, 0]);
+    packet.extend_from_slice(&source.ip().octets());
+    packet.extend_from_slice(&destination.ip().octets());
+    let checksum = internet_checksum::checksum(&packet);
+    packet[10..12].copy_from_slice(&checksum);
+
+    packet.extend_from_slice(&source.port().to_be_bytes());
+    packet.extend_from_slice(&destination.port().to_be_bytes());
+    packet.extend_from_slice(&(udp_len as u16).to_be_bytes());
+    // The UDP checksum is optional over IPv4
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// Returns the source and payload of an IPv4 UDP packet.
+fn parse_udp_packet(packet: &[u8]) -> Option<(SocketAddrV4, &[u8])> {
+    if packet.len() < IPV4_HEADER_SIZE || packet[0] >> 4 != 4 || packet[9] != IPPROTO_UDP {
+        return None;
+    }
+    let header_len = usize::from(packet[0] & 0x0f) * 4;
+    let total_len = usize::from(u16::from_be_bytes([packet[2], packet[3]])).min(packet.len());
+    if header_len < IPV4_HEADER_SIZE || total_len < header_len + UDP_HEADER_SIZE {
+        return None;
+    }
+
+    let source_ip: [u8; 4] = packet[12..16].try_into().ok()?;
+    let udp = &packet[header_len..total_len];
+    let source_port = u16::from_be_bytes([udp[0], udp[1]]);
+
+    Some((
+        SocketAddrV4::new(source_ip.into(), source_port),
+        &udp[UDP_HEADER_SIZE..],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use talpid_types::net::wireguard::{self, PrivateKey};
+
+    #[test]
+    fn test_udp_packet_roundtrip() {
+        let source = "10.64.0.1:51820".parse().unwrap();
+        let destination = "185.65.135.1:3001".parse().unwrap();
+        let payload = b"handshake";
+
+        let packet = udp_packet(source, destination, payload);
+        assert_eq!(
+            packet.len(),
+            IPV4_HEADER_SIZE + UDP_HEADER_SIZE + payload.len()
+        );
+        assert_eq!(
+            packet_destination(&packet),
+            Some(IpAddr::V4(*destination.ip()))
+        );
+        assert_eq!(
+            internet_checksum::checksum(&packet[..IPV4_HEADER_SIZE]),
+            [0, 0]
+        );
+        assert_eq!(parse_udp_packet(&packet), Some((source, &payload[..])));
+    }
+
+    #[test]
+    fn test_parse_non_udp_packet() {
+        let source = "10.64.0.1:51820".parse().unwrap();
+        let destination = "185.65.135.1:3001".parse().unwrap();
+        let mut packet = udp_packet(source, destination, b"ping");
+        // ICMP
+        packet[9] = 1;
+        assert_eq!(parse_udp_packet(&packet), None);
+    }
+
+    const CLIENT_ADDRESS: &str = "10.64.0.2:1000";
+    const REMOTE_ADDRESS: &str = "10.64.0.1:2000";
+
+    /// A relay that the device under test can establish a session with.
+    struct TestRelay {
+        socket: UdpSocket,
+        tunn: Tunn,
+        device_addr: Option<SocketAddr>,
+    }
+
+    impl TestRelay {
+        /// Receives datagrams from the device until it has sent a packet through the tunnel.
+        async fn receive_packet(&mut self) -> Vec<u8> {
+            let mut datagram_buf = vec![0u8; MAX_UDP_SIZE];
+            let mut packet_buf = vec![0u8; MAX_UDP_SIZE];
+            loop {
+                let (size, source) = self.socket.recv_from(&mut datagram_buf).await.unwrap();
+                self.device_addr = Some(source);
+                let mut input = &datagram_buf[..size];
+                loop {
+                    match self.tunn.decapsulate(None, input, &mut packet_buf) {
+                        TunnResult::WriteToNetwork(datagram) => {
+                            self.socket.send_to(datagram, source).await.unwrap();
+                        }
+                        TunnResult::WriteToTunnelV4(packet, _) => return packet.to_vec(),
+                        _ => break,
+                    }
+                    input = &[];
+                }
+            }
+        }
+
+        async fn send_packet(&mut self, packet: &[u8]) {
+            let mut send_buf = vec![0u8; MAX_UDP_SIZE];
+            match self.tunn.encapsulate(packet, &mut send_buf) {
+                TunnResult::WriteToNetwork(datagram) => {
+                    let device_addr = self.device_addr.expect("device has not connected");
+                    self.socket.send_to(datagram, device_addr).await.unwrap();
+                }
+                _ => panic!("relay has no session"),
+            }
+        }
+    }
+
+    fn test_config(
+        private_key: &PrivateKey,
+        relay_key: &PrivateKey,
+        relay_addr: SocketAddr,
+    ) -> Config {
+        Config {
+            tunnel: wireguard::TunnelConfig {
+                private_key: private_key.clone(),
+                addresses: vec!["10.64.0.2".parse().unwrap()],
+            },
+            peers: vec![wireguard::PeerConfig {
+                public_key: relay_key.public_key(),
+                allowed_ips: vec!["0.0.0.0/0".parse().unwrap()],
+                endpoint: relay_addr,
+                psk: None,
+            }],
+            entry_private_key: None,
+            ipv4_gateway: "10.64.0.1".parse().unwrap(),
+            ipv6_gateway: None,
+            mtu: 1380,
+            fwmark: None,
+            enable_ipv6: false,
+            use_boringtun: true,
+            network_namespace: None,
+            obfuscator_config: None,
+            pq_psk_rotation_interval: None,
+        }
+    }
+
+    #[test]
+    fn test_entry_private_key() {
+        let private_key = PrivateKey::new_from_random();
+        let entry_private_key = PrivateKey::new_from_random();
+        let mut config = test_config(
+            &private_key,
+            &PrivateKey::new_from_random(),
+            "192.0.2.1:51820".parse().unwrap(),
+        );
+        config.peers.insert(
+            0,
+            wireguard::PeerConfig {
+                public_key: PrivateKey::new_from_random().public_key(),
+                allowed_ips: vec!["192.0.2.1/32".parse().unwrap()],
+                endpoint: "192.0.2.2:51820".parse().unwrap(),
+                psk: None,
+            },
+        );
+        config.entry_private_key = Some(entry_private_key.clone());
+
+        let next_index = AtomicU32::new(0);
+        let peers = create_peers(&config, &[], &next_index).unwrap();
+        assert_eq!(
+            peers[0].session_keys.local_public_key,
+            *entry_private_key.public_key().as_bytes()
+        );
+        assert_eq!(
+            peers[1].session_keys.local_public_key,
+            *private_key.public_key().as_bytes()
+        );
+
+        // Replacing the entry key only replaces the session with the entry relay
+        config.entry_private_key = Some(PrivateKey::new_from_random());
+        let updated_peers = create_peers(&config, &peers, &next_index).unwrap();
+        assert!(!Arc::ptr_eq(&peers[0].tunn, &updated_peers[0].tunn));
+        assert!(Arc::ptr_eq(&peers[1].tunn, &updated_peers[1].tunn));
+    }
+
+    #[test]
+    fn test_report_failure() {
+        let (close_msg_sender, close_msg_receiver) = sync_mpsc::channel();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let reader = async {
+            Err::<(), _>(Error::ReadTunnelDevice(io::Error::new(
+                io::ErrorKind::Other,
+                "device removed",
+            )))
+        };
+        runtime.block_on(BoringTunnel::report_failure(
+            reader.boxed(),
+            close_msg_sender,
+        ));
+        assert!(matches!(
+            close_msg_receiver.try_recv(),
+            Ok(CloseMsg::TunnelFailed(_))
+        ));
+    }
+
+    async fn read_tunnel_packet(tunnel_device: &tokio::net::UnixDatagram) -> Vec<u8> {
+        let mut frame = vec![0u8; MAX_UDP_SIZE];
+        let size = tunnel_device.recv(&mut frame).await.unwrap();
+        assert_eq!(&frame[..PACKET_INFO_SIZE], &[0, 0, 0x08, 0x00]);
+        frame[PACKET_INFO_SIZE..size].to_vec()
+    }
+
+    fn device_peer_tunn(device: &Device) -> Arc<PlMutex<Tunn>> {
+        device.peers.read()[0].tunn.clone()
+    }
+
+    /// Sends packets both ways between a tunnel device and a relay, and checks that changing the
+    /// allowed IPs keeps the session while changing the PSK replaces it.
+    #[test]
+    fn test_tunnel_session() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            tokio::time::timeout(Duration::from_secs(10), run_tunnel_session())
+                .await
+                .expect("timed out")
+        });
+    }
+
+    async fn run_tunnel_session() {
+        let client = CLIENT_ADDRESS.parse().unwrap();
+        let remote = REMOTE_ADDRESS.parse().unwrap();
+
+        let private_key = PrivateKey::new_from_random();
+        let relay_key = PrivateKey::new_from_random();
+        let mut relay = TestRelay {
+            socket: UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+            tunn: Tunn::new(
+                x25519::StaticSecret::from(relay_key.to_bytes()),
+                x25519::PublicKey::from(*private_key.public_key().as_bytes()),
+                None,
+                None,
+                0,
+                None,
+            )
+            .unwrap(),
+            device_addr: None,
+        };
+        let mut config = test_config(&private_key, &relay_key, relay.socket.local_addr().unwrap());
+
+        // The device reads and writes packets with a packet information header, like a tun device
+        let (device_end, test_end) = std::os::unix::net::UnixDatagram::pair().unwrap();
+        device_end.set_nonblocking(true).unwrap();
+        test_end.set_nonblocking(true).unwrap();
+        let tunnel_device = tokio::net::UnixDatagram::from_std(test_end).unwrap();
+
+        let device = Arc::new(Device::new(device_end.as_raw_fd(), &config).unwrap());
+        let tasks = [
+            tokio::spawn(device.clone().run_tunnel_reader()),
+            tokio::spawn(device.clone().run_socket_reader(false)),
+        ];
+
+        let mut frame = vec![0, 0, 0x08, 0x00];
+        frame.extend(udp_packet(client, remote, b"ping"));
+        tunnel_device.send(&frame).await.unwrap();
+        assert_eq!(
+            relay.receive_packet().await,
+            udp_packet(client, remote, b"ping")
+        );
+
+        relay
+            .send_packet(&udp_packet(remote, client, b"pong"))
+            .await;
+        assert_eq!(
+            read_tunnel_packet(&tunnel_device).await,
+            udp_packet(remote, client, b"pong")
+        );
+
+        // Changing the allowed IPs keeps the session
+        let tunn = device_peer_tunn(&device);
+        config.peers[0]
+            .allowed_ips
+            .push("10.64.0.1/32".parse().unwrap());
+        device.set_config(&config).unwrap();
+        assert!(Arc::ptr_eq(&tunn, &device_peer_tunn(&device)));
+        assert_eq!(
+            device.peers.read()[0].allowed_ips,
+            config.peers[0].allowed_ips
+        );
+
+        relay
+            .send_packet(&udp_packet(remote, client, b"pong"))
+            .await;
+        assert_eq!(
+            read_tunnel_packet(&tunnel_device).await,
+            udp_packet(remote, client, b"pong")
+        );
+
+        // Packets from sources outside the allowed IPs of the peer are dropped
+        config.peers[0].allowed_ips = vec!["10.64.0.1/32".parse().unwrap()];
+        device.set_config(&config).unwrap();
+        assert!(Arc::ptr_eq(&tunn, &device_peer_tunn(&device)));
+        let spoofed = "10.64.0.3:2000".parse().unwrap();
+        relay
+            .send_packet(&udp_packet(spoofed, client, b"spoofed"))
+            .await;
+        relay
+            .send_packet(&udp_packet(remote, client, b"pong"))
+            .await;
+        assert_eq!(
+            read_tunnel_packet(&tunnel_device).await,
+            udp_packet(remote, client, b"pong")
+        );
+
+        // Changing the PSK requires a new session
+        config.peers[0].psk = Some(PresharedKey::from(Box::new([1u8; 32])));
+        device.set_config(&config).unwrap();
+        assert!(!Arc::ptr_eq(&tunn, &device_peer_tunn(&device)));
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+}
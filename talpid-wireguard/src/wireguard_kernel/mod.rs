@@ -1,4 +1,4 @@
-use super::{Config, Tunnel, TunnelError};
+use super::{netns, Config, Tunnel, TunnelError};
 use futures::future::{abortable, AbortHandle};
 use netlink_packet_core::{constants::*, NetlinkDeserializable};
 use netlink_packet_route::{
@@ -14,7 +14,7 @@ use netlink_proto::{
     sys::{protocols::NETLINK_GENERIC, SocketAddr},
     ConnectionHandle, Error as NetlinkError,
 };
-use std::{ffi::CString, net::IpAddr};
+use std::{ffi::CString, fs::File, net::IpAddr, os::unix::io::AsRawFd};
 use tokio_stream::StreamExt;
 
 mod parsers;
@@ -82,6 +82,21 @@ pub enum Error {
 
     #[error(display = "NetworkManager error")]
     NetworkManager(#[error(source)] nm_tunnel::Error),
+
+    #[error(display = "Network namespace error")]
+    NetworkNamespace(#[error(source)] netns::Error),
+
+    #[error(display = "Failed to move device to network namespace")]
+    SetNamespace(rtnetlink::Error),
+
+    #[error(display = "Failed to look up device")]
+    GetLink(rtnetlink::Error),
+
+    #[error(display = "Failed to bring up device")]
+    SetLinkUp(rtnetlink::Error),
+
+    #[error(display = "Failed to add route")]
+    AddRoute(rtnetlink::Error),
 }
 
 pub(crate) const MULLVAD_INTERFACE_NAME: &str = "wg-mullvad";
@@ -97,6 +112,22 @@ pub struct Handle {
 impl Handle {
     pub async fn connect() -> Result<Self, Error> {
         let message_type = Self::get_wireguard_message_type().await?;
+        Self::open_connections(message_type)
+    }
+
+    /// Connects to netlink from inside the network namespace `netns`. The sockets stay in that
+    /// namespace, so devices that have been moved there can be managed.
+    pub async fn connect_in_namespace(netns: &File) -> Result<Self, Error> {
+        let message_type = Self::get_wireguard_message_type().await?;
+        let runtime = tokio::runtime::Handle::current();
+        netns::run_in_namespace(netns, move || {
+            let _guard = runtime.enter();
+            Self::open_connections(message_type)
+        })
+        .map_err(Error::NetworkNamespace)?
+    }
+
+    fn open_connections(message_type: u16) -> Result<Self, Error> {
         let (conn, wireguard_connection, _messages) =
             netlink_proto::new_connection(NETLINK_GENERIC).map_err(Error::NetlinkSocket)?;
         let wg_handle = WireguardConnection {
@@ -216,6 +247,47 @@ impl Handle {
         Ok(())
     }
 
+    pub async fn get_link_index(&mut self, name: String) -> Result<u32, Error> {
+        let mut links = self.route_handle.link().get().match_name(name).execute();
+        match links.try_next().await {
+            Ok(Some(link)) => Ok(link.header.index),
+            Ok(None) => Err(Error::NoDevice),
+            Err(error) => Err(Error::GetLink(error)),
+        }
+    }
+
+    pub async fn set_link_up(&mut self, index: u32) -> Result<(), Error> {
+        self.route_handle
+            .link()
+            .set(index)
+            .up()
+            .execute()
+            .await
+            .map_err(Error::SetLinkUp)
+    }
+
+    /// Moves a device into the network namespace `netns`. Afterwards, the device can only be
+    /// managed from within that namespace.
+    pub async fn set_link_namespace(&mut self, index: u32, netns: &File) -> Result<(), Error> {
+        self.route_handle
+            .link()
+            .set(index)
+            .setns_by_fd(netns.as_raw_fd())
+            .execute()
+            .await
+            .map_err(Error::SetNamespace)
+    }
+
+    pub async fn add_default_route(&mut self, index: u32, ipv6: bool) -> Result<(), Error> {
+        let request = self.route_handle.route().add().output_interface(index);
+        if ipv6 {
+            request.v6().execute().await
+        } else {
+            request.v4().execute().await
+        }
+        .map_err(Error::AddRoute)
+    }
+
     pub async fn delete_device(&mut self, index: u32) -> Result<(), Error> {
         let mut link_message = LinkMessage::default();
         link_message.header.index = index;
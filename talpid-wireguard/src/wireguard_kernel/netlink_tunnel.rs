@@ -1,4 +1,4 @@
-use std::pin::Pin;
+use std::{fs::File, pin::Pin};
 
 use futures::Future;
 
@@ -46,6 +46,104 @@ impl NetlinkTunnel {
         })
     }
 
+    /// Creates the WireGuard device and moves it into the network namespace `netns`. Since the
+    /// device is created in the namespace of the daemon, encrypted traffic is still sent from
+    /// there, while only processes inside `netns` can use the tunnel.
+    pub fn new_in_namespace(
+        tokio_handle: tokio::runtime::Handle,
+        config: &Config,
+        netns: &File,
+    ) -> Result<Self, Error> {
+        tokio_handle.clone().block_on(async {
+            let mut netlink_connections = Handle::connect_in_namespace(netns).await?;
+
+            // Remove any device left behind in the namespace, since it would prevent a new device
+            // with the same name from being moved there.
+            if let Ok(stale_index) = netlink_connections
+                .get_link_index(MULLVAD_INTERFACE_NAME.to_string())
+                .await
+            {
+                netlink_connections.delete_device(stale_index).await?;
+            }
+
+            let mut init_connections = Handle::connect().await?;
+            let init_index = init_connections
+                .create_device(MULLVAD_INTERFACE_NAME.to_string(), config.mtu as u32)
+                .await?;
+            let move_result = async {
+                init_connections
+                    .wg_handle
+                    .set_config(init_index, config)
+                    .await?;
+                init_connections.set_link_namespace(init_index, netns).await
+            }
+            .await;
+            if let Err(err) = move_result {
+                if let Err(teardown_err) = init_connections.delete_device(init_index).await {
+                    log::error!(
+                        "Failed to tear down WireGuard interface after failing to move it to the network namespace: {}",
+                        teardown_err
+                    );
+                }
+                return Err(err);
+            }
+
+            let interface_index = netlink_connections
+                .get_link_index(MULLVAD_INTERFACE_NAME.to_string())
+                .await?;
+            let mut tunnel = Self {
+                interface_index,
+                netlink_connections,
+                tokio_handle,
+            };
+
+            if let Err(err) = tunnel.setup_namespace(config).await {
+                if let Err(teardown_err) = tunnel
+                    .netlink_connections
+                    .delete_device(interface_index)
+                    .await
+                {
+                    log::error!(
+                        "Failed to tear down WireGuard interface after failing to configure the network namespace: {}",
+                        teardown_err
+                    );
+                }
+                return Err(err);
+            }
+
+            Ok(tunnel)
+        })
+    }
+
+    async fn setup_namespace(&mut self, config: &Config) -> Result<(), Error> {
+        let loopback_index = self
+            .netlink_connections
+            .get_link_index("lo".to_string())
+            .await?;
+        self.netlink_connections.set_link_up(loopback_index).await?;
+
+        // Moving the device brings it down and removes its addresses
+        for tunnel_ip in config.tunnel.addresses.iter() {
+            self.netlink_connections
+                .set_ip_address(self.interface_index, *tunnel_ip)
+                .await?;
+        }
+        self.netlink_connections
+            .set_link_up(self.interface_index)
+            .await?;
+
+        self.netlink_connections
+            .add_default_route(self.interface_index, false)
+            .await?;
+        if config.enable_ipv6 {
+            self.netlink_connections
+                .add_default_route(self.interface_index, true)
+                .await?;
+        }
+
+        Ok(())
+    }
+
     async fn setup(&mut self, config: &Config) -> Result<(), Error> {
         self.netlink_connections
             .wg_handle
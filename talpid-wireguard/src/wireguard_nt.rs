@@ -1020,11 +1020,13 @@ mod tests {
                     endpoint: "1.2.3.4:1234".parse().unwrap(),
                     psk: None,
                 }],
+                entry_private_key: None,
                 ipv4_gateway: "0.0.0.0".parse().unwrap(),
                 ipv6_gateway: None,
                 mtu: 0,
                 use_wireguard_nt: true,
                 obfuscator_config: None,
+                pq_psk_rotation_interval: None,
             }
         };
         static ref WG_STRUCT_CONFIG: Interface = Interface {